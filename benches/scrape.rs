@@ -0,0 +1,63 @@
+//! Tree walker against bytecode VM on the same script and inputs, run with `cargo bench`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use seekr::scrape::{builtins::Registry, eval::eval_with, parse_script, vm::compile, Env, Value};
+
+const SCRIPT: &str = r#"
+let names = split(lower(name), " ") in
+let initials = fold(map(names, |n| len(n)), 0, |acc, n| acc * 10 + n) in
+let handles = filter(names, |n| len(n) > 2 && !contains(n, "x")) in
+if initials > 100 * (2 + 3) then
+  {initials: initials, handles: handles, first: names[0]}
+else
+  {initials: 0, handles: [], first: "-"}
+"#;
+
+fn people() -> Vec<Env> {
+    [
+        "Ada Lovelace",
+        "Grace Brewster Murray Hopper",
+        "Alan Turing",
+        "Max Ex",
+    ]
+    .iter()
+    .cycle()
+    .take(1000)
+    .map(|name| Env::from([("name".to_string(), Value::Str(name.to_string()))]))
+    .collect()
+}
+
+fn bench(c: &mut Criterion) {
+    let registry = Registry::standard();
+    let (expr, _) = parse_script(SCRIPT).unwrap();
+    let people = people();
+    let program = compile(&expr, registry);
+
+    let mut group = c.benchmark_group("1000 people");
+    group.bench_function("tree walker", |b| {
+        b.iter(|| {
+            for env in &people {
+                black_box(eval_with(&expr, env, registry).unwrap());
+            }
+        })
+    });
+    group.bench_function("vm", |b| {
+        b.iter(|| {
+            for env in &people {
+                black_box(program.run(env).unwrap());
+            }
+        })
+    });
+    group.bench_function("vm with compile", |b| {
+        b.iter(|| {
+            let program = compile(&expr, registry);
+            for env in &people {
+                black_box(program.run(env).unwrap());
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
@@ -0,0 +1,21 @@
+-- Scrape scripts, the current version of each.
+create table if not exists scripts
+(
+    id          integer primary key not null,
+    owner       text not null,
+    name        text not null,
+    source      text not null,
+    version     integer not null,
+    created_at  text not null,
+    unique (owner, name)
+);
+
+-- Every version a script ever had, including the current one.
+create table if not exists script_versions
+(
+    script_id   integer not null references scripts (id) on delete cascade,
+    version     integer not null,
+    source      text not null,
+    created_at  text not null,
+    primary key (script_id, version)
+);
@@ -0,0 +1,19 @@
+-- Outputs of scripts run against a person. Failed runs are kept too, with the error instead of
+-- an output.
+create table if not exists scrape_results
+(
+    id              integer primary key not null,
+    owner           text not null,
+    person_id       integer not null references people (id) on delete cascade,
+    script_id       integer not null references scripts (id) on delete cascade,
+    script_version  integer not null,
+    -- the value as JSON
+    output          text,
+    error           text,
+    -- the limits of the run and what it used, as JSON
+    limits          text not null,
+    usage           text not null,
+    created_at      text not null
+);
+
+create index if not exists scrape_results_person on scrape_results (person_id, script_id);
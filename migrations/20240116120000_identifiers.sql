@@ -0,0 +1,16 @@
+-- Typed identifiers of a person: emails, phone numbers, usernames, ... The value is normalized, so
+-- the same identifier written differently is stored once.
+create table if not exists identifiers
+(
+    id          integer primary key not null,
+    owner       text not null,
+    person_id   integer not null references people (id) on delete cascade,
+    kind        text not null,
+    value       text not null,
+    -- the platform of a username or the chain of a crypto wallet, empty for other kinds
+    platform    text not null default '',
+    created_at  text not null,
+    unique (person_id, kind, platform, value)
+);
+
+create index if not exists identifiers_value on identifiers (kind, value);
@@ -0,0 +1,71 @@
+-- Full-text index over the people of every owner and the data attached to them. Triggers keep
+-- it in sync with the tables it covers:
+--
+-- - source 'name': the name of a person
+-- - source 'identifier': the value of an identifier, aliases included, `field` is its kind
+-- - source 'result': the output of a script run as JSON, `field` is the name of the script
+create virtual table if not exists search using fts5
+(
+    text,
+    field,
+    owner unindexed,
+    person_id unindexed,
+    source unindexed,
+    source_id unindexed,
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+
+create trigger if not exists search_people_insert after insert on people
+begin
+    insert into search (text, field, owner, person_id, source, source_id)
+    values (new.name, '', new.owner, new.id, 'name', new.id);
+end;
+
+create trigger if not exists search_people_update after update of name on people
+begin
+    delete from search where source = 'name' and source_id = old.id;
+    insert into search (text, field, owner, person_id, source, source_id)
+    values (new.name, '', new.owner, new.id, 'name', new.id);
+end;
+
+create trigger if not exists search_people_delete after delete on people
+begin
+    delete from search where person_id = old.id;
+end;
+
+create trigger if not exists search_identifiers_insert after insert on identifiers
+begin
+    insert into search (text, field, owner, person_id, source, source_id)
+    values (new.value, trim(new.kind || ' ' || new.platform), new.owner, new.person_id,
+            'identifier', new.id);
+end;
+
+create trigger if not exists search_identifiers_delete after delete on identifiers
+begin
+    delete from search where source = 'identifier' and source_id = old.id;
+end;
+
+create trigger if not exists search_results_insert after insert on scrape_results
+when new.output is not null
+begin
+    insert into search (text, field, owner, person_id, source, source_id)
+    values (new.output, (select name from scripts where id = new.script_id), new.owner,
+            new.person_id, 'result', new.id);
+end;
+
+create trigger if not exists search_results_delete after delete on scrape_results
+begin
+    delete from search where source = 'result' and source_id = old.id;
+end;
+
+insert into search (text, field, owner, person_id, source, source_id)
+select name, '', owner, id, 'name', id from people;
+
+insert into search (text, field, owner, person_id, source, source_id)
+select value, trim(kind || ' ' || platform), owner, person_id, 'identifier', id
+from identifiers;
+
+insert into search (text, field, owner, person_id, source, source_id)
+select r.output, s.name, r.owner, r.person_id, 'result', r.id
+from scrape_results r join scripts s on s.id = r.script_id
+where r.output is not null;
@@ -0,0 +1,34 @@
+-- People merged into another person, with what was needed to undo the merge.
+create table if not exists merges
+(
+    id                  integer primary key not null,
+    owner               text not null,
+    -- the person that was kept, the merged person is gone until the merge is undone
+    kept_id             integer not null,
+    -- the merged person as JSON
+    merged              text not null,
+    -- ids of the identifiers and results moved to the kept person, as JSON arrays
+    moved_identifiers   text not null,
+    moved_results       text not null,
+    -- identifiers the kept person already had, deleted from the merged person, as JSON
+    dropped_identifiers text not null,
+    -- the alias added to the kept person with the name of the merged one
+    alias_id            integer,
+    created_at          text not null,
+    undone_at           text
+);
+
+create index if not exists merges_owner on merges (owner);
+
+-- merges and their undos move identifiers and results between people
+create trigger if not exists search_identifiers_move after update of person_id on identifiers
+begin
+    update search set person_id = new.person_id
+    where source = 'identifier' and source_id = new.id;
+end;
+
+create trigger if not exists search_results_move after update of person_id on scrape_results
+begin
+    update search set person_id = new.person_id
+    where source = 'result' and source_id = new.id;
+end;
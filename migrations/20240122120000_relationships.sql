@@ -0,0 +1,25 @@
+-- Typed connections between two people of the same owner.
+create table if not exists relationships
+(
+    id          integer primary key not null,
+    owner       text not null,
+    from_id     integer not null references people (id) on delete cascade,
+    to_id       integer not null references people (id) on delete cascade,
+    kind        text not null,
+    -- how sure the owner is, from 0 to 1
+    confidence  real not null default 1,
+    -- where the connection is known from
+    source      text not null default '',
+    notes       text not null default '',
+    created_at  text not null,
+    check (from_id != to_id),
+    unique (from_id, to_id, kind)
+);
+
+create index if not exists relationships_from on relationships (from_id);
+create index if not exists relationships_to on relationships (to_id);
+
+-- merges move relationships too, as they were before the merge, as JSON
+alter table merges add column moved_relationships text not null default '[]';
+-- relationships between the two merged people, or that the kept person already had
+alter table merges add column dropped_relationships text not null default '[]';
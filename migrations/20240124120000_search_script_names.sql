@@ -0,0 +1,8 @@
+-- Results in the search index carry the name of their script in `field`, renaming a script
+-- renames them too.
+create trigger if not exists search_scripts_update after update of name on scripts
+begin
+    update search set field = new.name
+    where source = 'result'
+      and source_id in (select id from scrape_results where script_id = new.id);
+end;
@@ -1,57 +1,241 @@
+//! Macros building scrape expressions for tests. The generated code names `Expr`, `Value`,
+//! `Oper`, `Unop` and `parse_script` unqualified, they have to be in scope where a macro is
+//! used.
+
+#[cfg(feature = "dsl")]
 use proc_macro::TokenStream;
+#[cfg(feature = "dsl")]
+use proc_macro2::TokenStream as TokenStream2;
+#[cfg(feature = "dsl")]
 use quote::quote;
-use syn::{parse_macro_input, Expr};
+#[cfg(feature = "dsl")]
+use syn::{parse_macro_input, spanned::Spanned, Expr, LitStr};
 
+/// A literal as a boxed `Expr::Value`: `value!(1)`, `value!(-2)`, `value!(true)` or
+/// `value!("greg")`. Other macros are expanded as they are.
 #[cfg(feature = "dsl")]
 #[proc_macro]
 pub fn value(input: TokenStream) -> TokenStream {
     let expr = parse_macro_input!(input as Expr);
+    TokenStream::from(value_tokens(&expr).unwrap_or_else(syn::Error::into_compile_error))
+}
 
-    let expanded = match &expr {
+#[cfg(feature = "dsl")]
+fn value_tokens(expr: &Expr) -> syn::Result<TokenStream2> {
+    Ok(match expr {
         Expr::Lit(lit) => match &lit.lit {
-            syn::Lit::Int(_) => {
-                quote! {
-                    Box::new(Expr::Value(Value::Number(#expr)))
-                }
-            }
-            // TODO string
-            syn::Lit::Bool(_) => {
-                quote! {
-                    Box::new(Expr::Value(Value::Bool(#expr)))
-                }
-            }
-            _ => {
-                quote! {
-                    compile_error!("Unsupported literal type {}", )
-                }
-            }
+            syn::Lit::Int(_) => quote! {
+                Box::new(Expr::Value(Value::Number(#expr)))
+            },
+            syn::Lit::Str(_) => quote! {
+                Box::new(Expr::Value(Value::Str(String::from(#expr))))
+            },
+            syn::Lit::Bool(_) => quote! {
+                Box::new(Expr::Value(Value::Bool(#expr)))
+            },
+            _ => return Err(syn::Error::new_spanned(expr, "unsupported literal type")),
         },
         Expr::Macro(macro_call) => {
             let macro_ident = &macro_call.mac.path;
             let macro_tokens = &macro_call.mac.tokens;
-
             quote! {
-                #macro_ident!#macro_tokens
+                #macro_ident!(#macro_tokens)
             }
         }
-        Expr::Unary(unary) => match &unary.op {
-            syn::UnOp::Neg(_) => {
-                quote! {
-                    Box::new(Expr::Value(Value::Number(#expr)))
-                }
-            }
-            _ => {
-                quote! {
-                    compile_error!("Unsupported unary expression");
-                }
-            }
-        },
-        _ => {
+        Expr::Unary(syn::ExprUnary {
+            op: syn::UnOp::Neg(_),
+            expr: inner,
+            ..
+        }) if matches!(
+            **inner,
+            Expr::Lit(syn::ExprLit {
+                lit: syn::Lit::Int(_),
+                ..
+            })
+        ) =>
+        {
             quote! {
-                compile_error!("Unsupported expression type")
+                Box::new(Expr::Value(Value::Number(#expr)))
             }
         }
+        _ => return Err(syn::Error::new_spanned(expr, "unsupported expression type")),
+    })
+}
+
+/// A boxed `Expr` written in Rust syntax:
+///
+/// - literals, `null` and `-1` are values, other identifiers variables
+/// - the operators of scrape, `!` and parentheses
+/// - `if c { a } else { b }`, also with `else if`
+/// - `{ let x = e; body }` for `let x = e in body`
+/// - `f(a, b)`, `[a, b]`, `xs[i]` and `profile.name`
+///
+/// Macro calls like `value!(1)` are expanded as they are, they must build a `Box<Expr>` too.
+///
+/// ```ignore
+/// expr!({ let x = 2; if x > 1 { x * 21 } else { len("ab") } })
+/// ```
+#[cfg(feature = "dsl")]
+#[proc_macro]
+pub fn expr(input: TokenStream) -> TokenStream {
+    let expr = parse_macro_input!(input as Expr);
+    TokenStream::from(expr_tokens(&expr).unwrap_or_else(syn::Error::into_compile_error))
+}
+
+#[cfg(feature = "dsl")]
+fn oper(op: &syn::BinOp) -> syn::Result<TokenStream2> {
+    use syn::BinOp;
+    Ok(match op {
+        BinOp::Add(_) => quote!(Oper::Add),
+        BinOp::Sub(_) => quote!(Oper::Sub),
+        BinOp::Mul(_) => quote!(Oper::Mul),
+        BinOp::Div(_) => quote!(Oper::Div),
+        BinOp::Eq(_) => quote!(Oper::Eq),
+        BinOp::Ne(_) => quote!(Oper::Neq),
+        BinOp::Lt(_) => quote!(Oper::Lt),
+        BinOp::Le(_) => quote!(Oper::Le),
+        BinOp::Gt(_) => quote!(Oper::Gt),
+        BinOp::Ge(_) => quote!(Oper::Ge),
+        BinOp::And(_) => quote!(Oper::And),
+        BinOp::Or(_) => quote!(Oper::Or),
+        op => return Err(syn::Error::new_spanned(op, "not a scrape operator")),
+    })
+}
+
+/// The body of a block: `let` statements followed by an expression.
+#[cfg(feature = "dsl")]
+fn block_tokens(block: &syn::Block) -> syn::Result<TokenStream2> {
+    let Some((syn::Stmt::Expr(body, None), lets)) = block.stmts.split_last() else {
+        return Err(syn::Error::new(
+            block.span(),
+            "expected a block ending in an expression",
+        ));
     };
+    let mut tokens = expr_tokens(body)?;
+    for stmt in lets.iter().rev() {
+        let syn::Stmt::Local(syn::Local {
+            pat: syn::Pat::Ident(pat),
+            init: Some(init),
+            ..
+        }) = stmt
+        else {
+            return Err(syn::Error::new_spanned(stmt, "expected let name = expr;"));
+        };
+        let name = pat.ident.to_string();
+        let value = expr_tokens(&init.expr)?;
+        tokens = quote! {
+            Box::new(Expr::Let(String::from(#name), #value, #tokens))
+        };
+    }
+    Ok(tokens)
+}
+
+#[cfg(feature = "dsl")]
+fn expr_tokens(expr: &Expr) -> syn::Result<TokenStream2> {
+    Ok(match expr {
+        Expr::Lit(_) | Expr::Macro(_) => value_tokens(expr)?,
+        Expr::Unary(syn::ExprUnary {
+            op: syn::UnOp::Neg(_),
+            ..
+        }) => value_tokens(expr)?,
+        Expr::Unary(syn::ExprUnary {
+            op: syn::UnOp::Not(_),
+            expr,
+            ..
+        }) => {
+            let e = expr_tokens(expr)?;
+            quote! { Box::new(Expr::Unop(Unop::Not, #e)) }
+        }
+        Expr::Path(path) if path.path.is_ident("null") => quote! {
+            Box::new(Expr::Value(Value::Null))
+        },
+        Expr::Path(path) => {
+            let name = path
+                .path
+                .get_ident()
+                .ok_or_else(|| syn::Error::new_spanned(path, "expected a variable name"))?
+                .to_string();
+            quote! { Box::new(Expr::Var(String::from(#name))) }
+        }
+        Expr::Paren(paren) => expr_tokens(&paren.expr)?,
+        Expr::Binary(binary) => {
+            let op = oper(&binary.op)?;
+            let (e1, e2) = (expr_tokens(&binary.left)?, expr_tokens(&binary.right)?);
+            quote! { Box::new(Expr::Binop(#op, #e1, #e2)) }
+        }
+        Expr::If(syn::ExprIf {
+            cond,
+            then_branch,
+            else_branch: Some((_, else_branch)),
+            ..
+        }) => {
+            let cond = expr_tokens(cond)?;
+            let then_branch = block_tokens(then_branch)?;
+            let else_branch = expr_tokens(else_branch)?;
+            quote! { Box::new(Expr::If(#cond, #then_branch, #else_branch)) }
+        }
+        Expr::Block(block) => block_tokens(&block.block)?,
+        Expr::Call(call) => {
+            let Expr::Path(path) = &*call.func else {
+                return Err(syn::Error::new_spanned(
+                    &call.func,
+                    "expected a function name",
+                ));
+            };
+            let name = path
+                .path
+                .get_ident()
+                .ok_or_else(|| syn::Error::new_spanned(path, "expected a function name"))?
+                .to_string();
+            let args = call
+                .args
+                .iter()
+                .map(expr_tokens)
+                .collect::<syn::Result<Vec<_>>>()?;
+            quote! { Box::new(Expr::Call(String::from(#name), vec![#(*#args),*])) }
+        }
+        Expr::Array(array) => {
+            let elems = array
+                .elems
+                .iter()
+                .map(expr_tokens)
+                .collect::<syn::Result<Vec<_>>>()?;
+            quote! { Box::new(Expr::List(vec![#(*#elems),*])) }
+        }
+        Expr::Index(index) => {
+            let (e, idx) = (expr_tokens(&index.expr)?, expr_tokens(&index.index)?);
+            quote! { Box::new(Expr::Index(#e, #idx)) }
+        }
+        Expr::Field(field) => {
+            let syn::Member::Named(name) = &field.member else {
+                return Err(syn::Error::new_spanned(
+                    &field.member,
+                    "expected a field name",
+                ));
+            };
+            let (e, name) = (expr_tokens(&field.base)?, name.to_string());
+            quote! { Box::new(Expr::Field(#e, String::from(#name))) }
+        }
+        _ => return Err(syn::Error::new_spanned(expr, "unsupported expression type")),
+    })
+}
 
-    TokenStream::from(expanded)
+/// Parse scrape source into an `Expr` when the code runs, panicking with the diagnostic of the
+/// parser on source that does not parse. Only the literal is checked at compile time, the
+/// parser lives in `seekr` and this crate does not depend on it.
+///
+/// ```ignore
+/// assert_eq!(scrape!("let x = 2 in x * 21"), parse_script("let x = 2 in x * 21").unwrap().0);
+/// ```
+#[cfg(feature = "dsl")]
+#[proc_macro]
+pub fn scrape(input: TokenStream) -> TokenStream {
+    let src = parse_macro_input!(input as LitStr);
+    TokenStream::from(quote! {
+        parse_script(#src)
+            .unwrap_or_else(|diagnostic| {
+                panic!("invalid scrape source\n{}", diagnostic.render(#src))
+            })
+            .0
+    })
 }
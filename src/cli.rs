@@ -1,5 +1,8 @@
-use clap::Parser;
-use std::fs::File;
+use clap::{Parser, Subcommand};
+use std::{fs::OpenOptions, path::PathBuf};
+
+use crate::export::Format;
+use crate::scrape::{fetch::Policy, limits::LimitsConfig};
 
 #[derive(Parser, Debug, Clone)]
 #[command(author, version, about)]
@@ -9,12 +12,79 @@ pub struct Args {
 
     #[clap(short = 'b', long = "bind", default_value = "127.0.0.1:3000")]
     pub addr: String,
+
+    /// TOML file with the resource limits of scrape scripts, per user
+    #[clap(long)]
+    limits: Option<PathBuf>,
+
+    /// Host scrape scripts may fetch from, `*.example.com` allows all subdomains. Repeat for
+    /// more hosts
+    #[clap(long = "allow-host")]
+    allowed_hosts: Vec<String>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Without a command the web server is started.
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Evaluate scrape expressions interactively
+    Repl,
+    /// Print a scrape script formatted
+    Fmt {
+        file: PathBuf,
+        /// Write the formatted script back to the file
+        #[clap(short, long)]
+        write: bool,
+    },
+    /// Run a stored script against a person and store the result
+    Run {
+        /// The user owning the script and the person
+        #[clap(long)]
+        owner: String,
+        #[clap(long)]
+        script: u32,
+        #[clap(long)]
+        person: u32,
+    },
+    /// Write people with their identifiers and relationships as a graph, for Gephi, yEd or
+    /// Graphviz
+    Export {
+        /// The user owning the people
+        #[clap(long)]
+        owner: String,
+        #[clap(long, value_enum, default_value = "graphml")]
+        format: Format,
+        /// Only this person and the people around it, all people without
+        #[clap(long)]
+        person: Option<u32>,
+        /// How far from the person to go
+        #[clap(long, default_value_t = 1)]
+        hops: u32,
+        /// Leave out relationships less sure
+        #[clap(long, default_value_t = 0.0)]
+        min_confidence: f64,
+        /// Written to standard output without
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Add the people and relationships of a GraphML file as new people
+    Import {
+        /// The user to own the people
+        #[clap(long)]
+        owner: String,
+        file: PathBuf,
+    },
 }
 
 impl Args {
-    /// touch the database file
+    /// touch the database file, an existing database is kept
     pub fn create_db(&self) -> Result<&Self, std::io::Error> {
-        let _ = File::create(&self.db_path)?;
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.db_path)?;
         Ok(self)
     }
 
@@ -22,6 +92,21 @@ impl Args {
         tracing::debug!("database file: {}", self.db_path);
         format!("sqlite:{}", self.db_path)
     }
+
+    /// the configured script limits, the defaults without `--limits`
+    pub fn limits(&self) -> anyhow::Result<LimitsConfig> {
+        self.limits
+            .as_ref()
+            .map_or_else(|| Ok(LimitsConfig::default()), LimitsConfig::load)
+    }
+
+    /// what scripts may fetch
+    pub fn policy(&self) -> Policy {
+        Policy {
+            allowed_hosts: self.allowed_hosts.clone(),
+            ..Policy::default()
+        }
+    }
 }
 /// Parse cli arguments using clap
 pub fn parse() -> Args {
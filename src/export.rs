@@ -0,0 +1,249 @@
+//! Networks of people, their identifiers and relationships for graph tools: GraphML for yEd
+//! and Gephi, GEXF for Gephi and DOT for Graphviz. GraphML can be imported back.
+
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::collections::BTreeSet;
+use thiserror::Error;
+use tracing::instrument;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::people::{Identifier, IdentifierBuilder, IdentifierKind, Person};
+use crate::relationships::{Graph, GraphQuery, Relationship, RelationshipError};
+
+mod dot;
+mod gexf;
+mod graphml;
+pub use graphml::import_graphml;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Graphml,
+    Gexf,
+    Dot,
+}
+
+impl Format {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Graphml => "graphml",
+            Self::Gexf => "gexf",
+            Self::Dot => "dot",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Graphml | Self::Gexf => "application/xml",
+            Self::Dot => "text/vnd.graphviz",
+        }
+    }
+}
+
+/// What to export. All people of the user without a person.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportQuery {
+    #[param(inline)]
+    pub format: Format,
+
+    /// Only this person and the people around it.
+    #[param(example = 4)]
+    pub person: Option<u32>,
+
+    /// How far from the person to go, see [`GraphQuery::hops`].
+    #[serde(default = "ExportQuery::default_hops")]
+    #[param(example = 1, minimum = 1, maximum = 6)]
+    pub hops: u32,
+
+    /// Leave out relationships less sure.
+    #[serde(default)]
+    #[param(example = 0.5, minimum = 0, maximum = 1)]
+    pub min_confidence: f64,
+}
+
+impl ExportQuery {
+    fn default_hops() -> u32 {
+        GraphQuery::default().hops
+    }
+
+    pub fn new(format: Format) -> Self {
+        Self {
+            format,
+            person: None,
+            hops: Self::default_hops(),
+            min_confidence: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ExportError {
+    #[error(transparent)]
+    Relationship(#[from] RelationshipError),
+
+    #[error("invalid GraphML: {0}")]
+    Invalid(String),
+
+    #[error("invalid GraphML: {0}")]
+    Xml(#[from] roxmltree::Error),
+
+    #[error("sqlx error")]
+    Sqlx(#[from] sqlx::Error),
+
+    #[error("not authenticated")]
+    Auth,
+}
+
+/// People with their identifiers and the relationships between them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Network {
+    pub people: Vec<Person>,
+
+    pub identifiers: Vec<Identifier>,
+
+    /// Only those between the people.
+    pub relationships: Vec<Relationship>,
+}
+
+/// Attributes of relationships in exports, with their GraphML and GEXF types.
+const EDGE_ATTRIBUTES: [(&str, &str); 4] = [
+    ("kind", "string"),
+    ("confidence", "double"),
+    ("source", "string"),
+    ("notes", "string"),
+];
+
+impl Network {
+    /// Load the people of `owner` the query asks for.
+    #[instrument(skip(db))]
+    pub async fn load(
+        db: &SqlitePool,
+        owner: &str,
+        query: &ExportQuery,
+    ) -> Result<Self, ExportError> {
+        let graph = Graph::load(db, owner, query.min_confidence).await?;
+        let ids = match query.person {
+            Some(id) => std::iter::once(id)
+                .chain(
+                    graph
+                        .neighbours(owner, id, query.hops)?
+                        .into_iter()
+                        .map(|n| n.person.id),
+                )
+                .collect(),
+            None => graph.people().map(|p| p.id).collect(),
+        };
+        Self::of(db, owner, &graph, &ids).await
+    }
+
+    /// The people of `graph` in `ids`.
+    async fn of(
+        db: &SqlitePool,
+        owner: &str,
+        graph: &Graph,
+        ids: &BTreeSet<u32>,
+    ) -> Result<Self, ExportError> {
+        let identifiers: Vec<Identifier> = sqlx::query_as(
+            "select * from identifiers where owner = ? order by person_id, kind, platform, value",
+        )
+        .bind(owner)
+        .fetch_all(db)
+        .await?;
+        let mut relationships: Vec<Relationship> = graph
+            .relationships()
+            .filter(|r| ids.contains(&r.from_id) && ids.contains(&r.to_id))
+            .cloned()
+            .collect();
+        relationships.sort_by_key(|r| r.id);
+        Ok(Self {
+            people: graph
+                .people()
+                .filter(|p| ids.contains(&p.id))
+                .cloned()
+                .collect(),
+            identifiers: identifiers
+                .into_iter()
+                .filter(|i| ids.contains(&i.person_id))
+                .collect(),
+            relationships,
+        })
+    }
+
+    pub fn export(&self, format: Format) -> String {
+        match format {
+            Format::Graphml => self.graphml(),
+            Format::Gexf => self.gexf(),
+            Format::Dot => self.dot(),
+        }
+    }
+
+    /// The identifiers of a person as node attributes named after their kind, one identifier
+    /// per line with the platform in parentheses after the value.
+    fn node_attributes(&self, person_id: u32) -> Vec<(IdentifierKind, String)> {
+        IdentifierKind::ALL
+            .into_iter()
+            .filter_map(|kind| {
+                let values: Vec<String> = self
+                    .identifiers
+                    .iter()
+                    .filter(|i| i.person_id == person_id && i.kind == kind)
+                    .map(|i| match i.platform.as_str() {
+                        "" => i.value.clone(),
+                        platform => format!("{} ({})", i.value, platform),
+                    })
+                    .collect();
+                (!values.is_empty()).then(|| (kind, values.join("\n")))
+            })
+            .collect()
+    }
+}
+
+/// The attributes of a relationship in [`EDGE_ATTRIBUTES`], empty ones left out.
+fn edge_attributes(relationship: &Relationship) -> Vec<(&'static str, String)> {
+    [
+        relationship.kind.to_string(),
+        relationship.confidence.to_string(),
+        relationship.source.clone(),
+        relationship.notes.clone(),
+    ]
+    .into_iter()
+    .zip(EDGE_ATTRIBUTES)
+    .filter(|(value, _)| !value.is_empty())
+    .map(|(value, (name, _))| (name, value))
+    .collect()
+}
+
+/// An identifier from a line of a node attribute, see [`Network::node_attributes`].
+fn parse_identifier(kind: IdentifierKind, line: &str) -> IdentifierBuilder {
+    // only usernames and wallets have platforms, and neither contains spaces
+    let platform = matches!(kind, IdentifierKind::Username | IdentifierKind::Wallet)
+        .then(|| line.strip_suffix(')')?.rsplit_once(" ("))
+        .flatten();
+    match platform {
+        Some((value, platform)) => IdentifierBuilder::new(kind, value).platform(platform),
+        None => IdentifierBuilder::new(kind, line),
+    }
+}
+
+/// Escape text for XML elements and attributes. Line breaks are kept in attributes too.
+fn escape_xml(s: &str) -> String {
+    let mut xml = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => xml.push_str("&amp;"),
+            '<' => xml.push_str("&lt;"),
+            '>' => xml.push_str("&gt;"),
+            '"' => xml.push_str("&quot;"),
+            '\'' => xml.push_str("&apos;"),
+            '\n' => xml.push_str("&#10;"),
+            '\r' => xml.push_str("&#13;"),
+            '\t' => xml.push_str("&#9;"),
+            c => xml.push(c),
+        }
+    }
+    xml
+}
+
+mod test;
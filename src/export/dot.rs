@@ -0,0 +1,54 @@
+use super::*;
+
+/// Quote a DOT string. Line breaks become `\n`, which Graphviz shows as line breaks in labels.
+fn quote(s: &str) -> String {
+    let mut dot = String::with_capacity(s.len() + 2);
+    dot.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => dot.push_str("\\\""),
+            '\\' => dot.push_str("\\\\"),
+            '\n' => dot.push_str("\\n"),
+            '\r' => {}
+            c => dot.push(c),
+        }
+    }
+    dot.push('"');
+    dot
+}
+
+impl Network {
+    /// A Graphviz digraph, people labeled with their names and relationships with their kinds.
+    /// Identifiers and the [`EDGE_ATTRIBUTES`] are attributes Graphviz ignores.
+    pub fn dot(&self) -> String {
+        let mut dot = String::from("digraph seekr {\n");
+        for person in &self.people {
+            let attributes: Vec<String> = std::iter::once(format!("label={}", quote(&person.name)))
+                .chain(
+                    self.node_attributes(person.id)
+                        .into_iter()
+                        .map(|(kind, values)| format!("{kind}={}", quote(&values))),
+                )
+                .collect();
+            dot.push_str(&format!("  p{} [{}];\n", person.id, attributes.join(", ")));
+        }
+        for relationship in &self.relationships {
+            let attributes: Vec<String> =
+                std::iter::once(format!("label={}", quote(relationship.kind.as_str())))
+                    .chain(
+                        edge_attributes(relationship)
+                            .into_iter()
+                            .map(|(name, value)| format!("{name}={}", quote(&value))),
+                    )
+                    .collect();
+            dot.push_str(&format!(
+                "  p{} -> p{} [{}];\n",
+                relationship.from_id,
+                relationship.to_id,
+                attributes.join(", ")
+            ));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
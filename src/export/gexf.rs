@@ -0,0 +1,59 @@
+use super::*;
+
+impl Network {
+    /// GEXF 1.3 with the same attributes as [`Network::graphml`]. Edges are weighted by their
+    /// confidence.
+    pub fn gexf(&self) -> String {
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml.push_str("<gexf xmlns=\"http://gexf.net/1.3\" version=\"1.3\">\n");
+        xml.push_str("  <meta>\n    <creator>seekr</creator>\n  </meta>\n");
+        xml.push_str("  <graph defaultedgetype=\"directed\" mode=\"static\">\n");
+        xml.push_str("    <attributes class=\"node\">\n");
+        for kind in IdentifierKind::ALL {
+            xml.push_str(&format!(
+                "      <attribute id=\"{kind}\" title=\"{kind}\" type=\"string\"/>\n"
+            ));
+        }
+        xml.push_str("    </attributes>\n    <attributes class=\"edge\">\n");
+        for (name, ty) in EDGE_ATTRIBUTES {
+            xml.push_str(&format!(
+                "      <attribute id=\"{name}\" title=\"{name}\" type=\"{ty}\"/>\n"
+            ));
+        }
+        xml.push_str("    </attributes>\n    <nodes>\n");
+        for person in &self.people {
+            xml.push_str(&format!(
+                "      <node id=\"p{}\" label=\"{}\">\n        <attvalues>\n",
+                person.id,
+                escape_xml(&person.name)
+            ));
+            for (kind, values) in self.node_attributes(person.id) {
+                xml.push_str(&format!(
+                    "          <attvalue for=\"{kind}\" value=\"{}\"/>\n",
+                    escape_xml(&values)
+                ));
+            }
+            xml.push_str("        </attvalues>\n      </node>\n");
+        }
+        xml.push_str("    </nodes>\n    <edges>\n");
+        for relationship in &self.relationships {
+            xml.push_str(&format!(
+                "      <edge id=\"r{}\" source=\"p{}\" target=\"p{}\" label=\"{}\" weight=\"{}\">\n        <attvalues>\n",
+                relationship.id,
+                relationship.from_id,
+                relationship.to_id,
+                relationship.kind,
+                relationship.confidence
+            ));
+            for (name, value) in edge_attributes(relationship) {
+                xml.push_str(&format!(
+                    "          <attvalue for=\"{name}\" value=\"{}\"/>\n",
+                    escape_xml(&value)
+                ));
+            }
+            xml.push_str("        </attvalues>\n      </edge>\n");
+        }
+        xml.push_str("    </edges>\n  </graph>\n</gexf>\n");
+        xml
+    }
+}
@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+
+use super::*;
+use crate::relationships::RelationshipKind;
+use crate::NOW;
+
+impl Network {
+    /// GraphML with a `label` and an attribute per identifier kind for nodes, and the
+    /// [`EDGE_ATTRIBUTES`] for edges.
+    pub fn graphml(&self) -> String {
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml.push_str("<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n");
+        xml.push_str(
+            "  <key id=\"label\" for=\"node\" attr.name=\"label\" attr.type=\"string\"/>\n",
+        );
+        for kind in IdentifierKind::ALL {
+            xml.push_str(&format!(
+                "  <key id=\"{kind}\" for=\"node\" attr.name=\"{kind}\" attr.type=\"string\"/>\n"
+            ));
+        }
+        for (name, ty) in EDGE_ATTRIBUTES {
+            xml.push_str(&format!(
+                "  <key id=\"{name}\" for=\"edge\" attr.name=\"{name}\" attr.type=\"{ty}\"/>\n"
+            ));
+        }
+        xml.push_str("  <graph id=\"seekr\" edgedefault=\"directed\">\n");
+        for person in &self.people {
+            xml.push_str(&format!("    <node id=\"p{}\">\n", person.id));
+            xml.push_str(&format!(
+                "      <data key=\"label\">{}</data>\n",
+                escape_xml(&person.name)
+            ));
+            for (kind, values) in self.node_attributes(person.id) {
+                xml.push_str(&format!(
+                    "      <data key=\"{kind}\">{}</data>\n",
+                    escape_xml(&values)
+                ));
+            }
+            xml.push_str("    </node>\n");
+        }
+        for relationship in &self.relationships {
+            xml.push_str(&format!(
+                "    <edge id=\"r{}\" source=\"p{}\" target=\"p{}\">\n",
+                relationship.id, relationship.from_id, relationship.to_id
+            ));
+            for (name, value) in edge_attributes(relationship) {
+                xml.push_str(&format!(
+                    "      <data key=\"{name}\">{}</data>\n",
+                    escape_xml(&value)
+                ));
+            }
+            xml.push_str("    </edge>\n");
+        }
+        xml.push_str("  </graph>\n</graphml>\n");
+        xml
+    }
+}
+
+/// The data of a node or edge by attribute name.
+fn data(keys: &HashMap<String, String>, element: roxmltree::Node) -> HashMap<String, String> {
+    element
+        .children()
+        .filter(|d| d.has_tag_name("data"))
+        .filter_map(|d| {
+            let name = keys.get(d.attribute("key")?)?;
+            Some((name.clone(), d.text().unwrap_or_default().to_string()))
+        })
+        .collect()
+}
+
+/// A person to import, with the id of its node.
+struct Node {
+    id: String,
+    name: String,
+    identifiers: Vec<IdentifierBuilder>,
+}
+
+/// A relationship to import between two nodes.
+struct Edge {
+    from: String,
+    to: String,
+    kind: RelationshipKind,
+    confidence: f64,
+    source: String,
+    notes: String,
+}
+
+fn parse(xml: &str) -> Result<(Vec<Node>, Vec<Edge>), ExportError> {
+    let document = roxmltree::Document::parse(xml)?;
+    let graphml = document.root_element();
+    if !graphml.has_tag_name("graphml") {
+        return Err(ExportError::Invalid("not a GraphML document".to_string()));
+    }
+    let keys: HashMap<String, String> = graphml
+        .children()
+        .filter(|k| k.has_tag_name("key"))
+        .filter_map(|k| {
+            let id = k.attribute("id")?;
+            Some((
+                id.to_string(),
+                k.attribute("attr.name").unwrap_or(id).to_string(),
+            ))
+        })
+        .collect();
+    let graph = graphml
+        .children()
+        .find(|g| g.has_tag_name("graph"))
+        .ok_or_else(|| ExportError::Invalid("no graph".to_string()))?;
+
+    let mut nodes: Vec<Node> = vec![];
+    for element in graph.children().filter(|n| n.has_tag_name("node")) {
+        let id = element
+            .attribute("id")
+            .ok_or_else(|| ExportError::Invalid("node without id".to_string()))?;
+        let invalid = |e: &dyn std::fmt::Display| ExportError::Invalid(format!("node {id}: {e}"));
+        if nodes.iter().any(|n| n.id == id) {
+            return Err(invalid(&"more than one node with the id"));
+        }
+        let data = data(&keys, element);
+        // Gephi names the label `label`, other tools `name`
+        let name = data
+            .get("label")
+            .or_else(|| data.get("name"))
+            .map(String::as_str)
+            .unwrap_or_default();
+        let name = crate::people::validate_name(name).map_err(|e| invalid(&e))?;
+        let mut identifiers = vec![];
+        for kind in IdentifierKind::ALL {
+            for line in data.get(kind.as_str()).into_iter().flat_map(|v| v.lines()) {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let identifier = parse_identifier(kind, line.trim())
+                    .normalize()
+                    .map_err(|e| invalid(&e))?;
+                identifiers.push(identifier);
+            }
+        }
+        nodes.push(Node {
+            id: id.to_string(),
+            name,
+            identifiers,
+        });
+    }
+
+    let mut edges = vec![];
+    for element in graph.children().filter(|e| e.has_tag_name("edge")) {
+        let (from, to) = match (element.attribute("source"), element.attribute("target")) {
+            (Some(from), Some(to)) => (from, to),
+            _ => {
+                return Err(ExportError::Invalid(
+                    "edge without source or target".to_string(),
+                ))
+            }
+        };
+        let invalid =
+            |e: &dyn std::fmt::Display| ExportError::Invalid(format!("edge {from} -> {to}: {e}"));
+        if from == to {
+            return Err(invalid(&"a person can not be related to itself"));
+        }
+        for end in [from, to] {
+            if !nodes.iter().any(|n| n.id == end) {
+                return Err(invalid(&format!("no node {end}")));
+            }
+        }
+        let mut data = data(&keys, element);
+        let kind = data
+            .remove("kind")
+            .ok_or_else(|| invalid(&"no kind"))
+            .and_then(|kind| RelationshipKind::try_from(kind).map_err(|e| invalid(&e)))?;
+        let confidence = match data.get("confidence") {
+            Some(confidence) => confidence
+                .trim()
+                .parse()
+                .map_err(|_| invalid(&"confidence is not a number"))?,
+            None => 1.0,
+        };
+        if !(0.0..=1.0).contains(&confidence) {
+            return Err(invalid(&"confidence must be from 0 to 1"));
+        }
+        edges.push(Edge {
+            from: from.to_string(),
+            to: to.to_string(),
+            kind,
+            confidence,
+            source: data.remove("source").unwrap_or_default(),
+            notes: data.remove("notes").unwrap_or_default(),
+        });
+    }
+    Ok((nodes, edges))
+}
+
+/// Add the nodes and edges of a GraphML document to the people of `owner` as new people and
+/// relationships between them. Node attributes named after identifier kinds are identifiers,
+/// as [`Network::graphml`] writes them. Nothing is added if anything is invalid. Returns what
+/// was added.
+#[instrument(skip(db, xml))]
+pub async fn import_graphml(
+    db: &SqlitePool,
+    owner: &str,
+    xml: &str,
+) -> Result<Network, ExportError> {
+    let (nodes, edges) = parse(xml)?;
+
+    let mut tx = db.begin().await?;
+    let mut people = HashMap::new();
+    for node in &nodes {
+        let person: Person =
+            sqlx::query_as("insert into people (owner, name) values (?, ?) returning *")
+                .bind(owner)
+                .bind(&node.name)
+                .fetch_one(&mut *tx)
+                .await?;
+        // the same identifier twice is added once
+        for identifier in &node.identifiers {
+            sqlx::query(&format!(
+                "insert or ignore into identifiers
+                 (owner, person_id, kind, value, platform, created_at)
+                 values (?, ?, ?, ?, ?, {NOW})"
+            ))
+            .bind(owner)
+            .bind(person.id)
+            .bind(identifier.kind.as_str())
+            .bind(&identifier.value)
+            .bind(&identifier.platform)
+            .execute(&mut *tx)
+            .await?;
+        }
+        people.insert(node.id.as_str(), person.id);
+    }
+    for edge in &edges {
+        sqlx::query(&format!(
+            "insert or ignore into relationships
+             (owner, from_id, to_id, kind, confidence, source, notes, created_at)
+             values (?, ?, ?, ?, ?, ?, ?, {NOW})"
+        ))
+        .bind(owner)
+        .bind(people[edge.from.as_str()])
+        .bind(people[edge.to.as_str()])
+        .bind(edge.kind.as_str())
+        .bind(edge.confidence)
+        .bind(edge.source.trim())
+        .bind(edge.notes.trim())
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+
+    let graph = Graph::load(db, owner, 0.0).await?;
+    Network::of(db, owner, &graph, &people.into_values().collect()).await
+}
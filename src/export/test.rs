@@ -0,0 +1,314 @@
+#![cfg(test)]
+use super::*;
+use crate::people::{add_identifier, insert_person, PersonBuilder};
+use crate::relationships::{add_relationship, RelationshipBuilder, RelationshipKind};
+use crate::test_db;
+use IdentifierKind::*;
+
+/// greg and ada colleagues, ada and bob family, carl alone.
+async fn investigation(db: &SqlitePool, owner: &str) -> Vec<Person> {
+    let mut people = vec![];
+    for name in ["Greg \"G\" Smith", "ada", "bob", "carl"] {
+        let builder = PersonBuilder {
+            name: name.to_string(),
+        };
+        people.push(insert_person(db, owner, builder).await.unwrap());
+    }
+    let (greg, ada, bob) = (people[0].id, people[1].id, people[2].id);
+    for identifier in [
+        IdentifierBuilder::new(Email, "greg@example.com"),
+        IdentifierBuilder::new(Email, "g@smith.dev"),
+        IdentifierBuilder::new(Username, "@greg").platform("github"),
+        IdentifierBuilder::new(Address, "1 Main St & Co <b>"),
+    ] {
+        add_identifier(db, owner, greg, identifier).await.unwrap();
+    }
+    add_identifier(db, owner, ada, IdentifierBuilder::new(Alias, "Ada L."))
+        .await
+        .unwrap();
+    add_relationship(
+        db,
+        owner,
+        RelationshipBuilder {
+            confidence: 0.75,
+            source: "https://example.com/team?a=1&b=2".to_string(),
+            notes: "worked together".to_string(),
+            ..RelationshipBuilder::new(greg, ada, RelationshipKind::Colleague)
+        },
+    )
+    .await
+    .unwrap();
+    add_relationship(
+        db,
+        owner,
+        RelationshipBuilder::new(bob, ada, RelationshipKind::Family),
+    )
+    .await
+    .unwrap();
+    people
+}
+
+/// Names, identifiers and relationships of a network, without ids.
+type Shape = (
+    Vec<String>,
+    Vec<(String, String, String, String)>,
+    Vec<String>,
+);
+
+/// A network without ids, to compare networks of different owners.
+fn shape(network: &Network) -> Shape {
+    let name = |id: u32| {
+        network
+            .people
+            .iter()
+            .find(|p| p.id == id)
+            .unwrap()
+            .name
+            .clone()
+    };
+    let mut people: Vec<String> = network.people.iter().map(|p| p.name.clone()).collect();
+    let mut identifiers: Vec<_> = network
+        .identifiers
+        .iter()
+        .map(|i| {
+            (
+                name(i.person_id),
+                i.kind.to_string(),
+                i.value.clone(),
+                i.platform.clone(),
+            )
+        })
+        .collect();
+    let mut relationships: Vec<String> = network
+        .relationships
+        .iter()
+        .map(|r| {
+            format!(
+                "{} -{}-> {} {} {:?} {:?}",
+                r.from_name, r.kind, r.to_name, r.confidence, r.source, r.notes
+            )
+        })
+        .collect();
+    people.sort();
+    identifiers.sort();
+    relationships.sort();
+    (people, identifiers, relationships)
+}
+
+#[tokio::test]
+async fn test_load() {
+    let db = test_db().await;
+    let people = investigation(&db, "ferris").await;
+    investigation(&db, "crab").await;
+
+    let all = Network::load(&db, "ferris", &ExportQuery::new(Format::Graphml))
+        .await
+        .unwrap();
+    assert_eq!(all.people, people);
+    assert_eq!(all.identifiers.len(), 5);
+    assert_eq!(all.relationships.len(), 2);
+
+    // a person and the people around it
+    let query = ExportQuery {
+        person: Some(people[0].id),
+        ..ExportQuery::new(Format::Graphml)
+    };
+    let around = Network::load(&db, "ferris", &query).await.unwrap();
+    assert_eq!(around.people, people[..2]);
+    assert_eq!(around.relationships.len(), 1);
+    assert!(around
+        .identifiers
+        .iter()
+        .all(|i| i.person_id == people[0].id || i.person_id == people[1].id));
+    let query = ExportQuery { hops: 2, ..query };
+    assert_eq!(
+        Network::load(&db, "ferris", &query).await.unwrap().people,
+        people[..3]
+    );
+    let query = ExportQuery {
+        min_confidence: 0.8,
+        ..query
+    };
+    assert_eq!(
+        Network::load(&db, "ferris", &query).await.unwrap().people,
+        people[..1]
+    );
+
+    assert!(matches!(
+        Network::load(
+            &db,
+            "crab",
+            &ExportQuery {
+                person: Some(people[0].id),
+                ..ExportQuery::new(Format::Graphml)
+            }
+        )
+        .await,
+        Err(ExportError::Relationship(RelationshipError::Person(_)))
+    ));
+}
+
+#[tokio::test]
+async fn test_export_formats() {
+    let db = test_db().await;
+    investigation(&db, "ferris").await;
+    let network = Network::load(&db, "ferris", &ExportQuery::new(Format::Graphml))
+        .await
+        .unwrap();
+
+    let graphml = network.export(Format::Graphml);
+    roxmltree::Document::parse(&graphml).unwrap();
+    for expected in [
+        r#"<key id="email" for="node" attr.name="email" attr.type="string"/>"#,
+        r#"<key id="confidence" for="edge" attr.name="confidence" attr.type="double"/>"#,
+        r#"<data key="label">Greg &quot;G&quot; Smith</data>"#,
+        r#"<data key="email">g@smith.dev&#10;greg@example.com</data>"#,
+        r#"<data key="username">greg (github)</data>"#,
+        r#"<data key="address">1 Main St &amp; Co &lt;b&gt;</data>"#,
+        r#"<edge id="r1" source="p1" target="p2">"#,
+        r#"<data key="confidence">0.75</data>"#,
+        r#"<data key="source">https://example.com/team?a=1&amp;b=2</data>"#,
+    ] {
+        assert!(graphml.contains(expected), "{expected}\n{graphml}");
+    }
+    // empty attributes are left out
+    assert!(!graphml.contains(r#"<data key="notes"></data>"#));
+
+    let gexf = network.export(Format::Gexf);
+    roxmltree::Document::parse(&gexf).unwrap();
+    for expected in [
+        r#"<attribute id="wallet" title="wallet" type="string"/>"#,
+        r#"<node id="p1" label="Greg &quot;G&quot; Smith">"#,
+        r#"<attvalue for="email" value="g@smith.dev&#10;greg@example.com"/>"#,
+        r#"<edge id="r1" source="p1" target="p2" label="colleague" weight="0.75">"#,
+        r#"<attvalue for="notes" value="worked together"/>"#,
+    ] {
+        assert!(gexf.contains(expected), "{expected}\n{gexf}");
+    }
+
+    assert_eq!(
+        network.export(Format::Dot),
+        r#"digraph seekr {
+  p1 [label="Greg \"G\" Smith", email="g@smith.dev\ngreg@example.com", username="greg (github)", address="1 Main St & Co <b>"];
+  p2 [label="ada", alias="Ada L."];
+  p3 [label="bob"];
+  p4 [label="carl"];
+  p1 -> p2 [label="colleague", kind="colleague", confidence="0.75", source="https://example.com/team?a=1&b=2", notes="worked together"];
+  p3 -> p2 [label="family", kind="family", confidence="1"];
+}
+"#
+    );
+}
+
+#[tokio::test]
+async fn test_graphml_roundtrip() {
+    let db = test_db().await;
+    investigation(&db, "ferris").await;
+    let exported = Network::load(&db, "ferris", &ExportQuery::new(Format::Graphml))
+        .await
+        .unwrap();
+
+    let imported = import_graphml(&db, "crab", &exported.graphml())
+        .await
+        .unwrap();
+    assert_eq!(shape(&imported), shape(&exported));
+    assert!(imported.people.iter().all(|p| p.owner == "crab"));
+    // what was imported is stored
+    let stored = Network::load(&db, "crab", &ExportQuery::new(Format::Graphml))
+        .await
+        .unwrap();
+    assert_eq!(stored, imported);
+    // and exports the same again
+    let again = import_graphml(&db, "bob", &stored.graphml()).await.unwrap();
+    assert_eq!(shape(&again), shape(&exported));
+
+    // importing again adds new people
+    import_graphml(&db, "crab", &exported.graphml())
+        .await
+        .unwrap();
+    let twice = Network::load(&db, "crab", &ExportQuery::new(Format::Graphml))
+        .await
+        .unwrap();
+    assert_eq!(twice.people.len(), 8);
+    assert_eq!(twice.relationships.len(), 4);
+}
+
+#[tokio::test]
+async fn test_graphml_import() {
+    let db = test_db().await;
+    // from another tool, keys named differently and a name instead of a label
+    let imported = import_graphml(
+        &db,
+        "ferris",
+        r#"<?xml version="1.0"?>
+        <graphml xmlns="http://graphml.graphdrawing.org/xmlns">
+          <key id="d0" for="node" attr.name="name" attr.type="string"/>
+          <key id="d1" for="node" attr.name="email" attr.type="string"/>
+          <key id="d2" for="edge" attr.name="kind" attr.type="string"/>
+          <key id="d3" for="node" attr.name="x" attr.type="double"/>
+          <graph edgedefault="undirected">
+            <node id="a"><data key="d0">Ada</data><data key="d1">Ada@Example.COM
+              Ada@example.com</data><data key="d3">1.5</data></node>
+            <node id="b"><data key="d0">Bob</data></node>
+            <edge source="a" target="b"><data key="d2">friend</data></edge>
+            <edge source="a" target="b"><data key="d2">friend</data></edge>
+          </graph>
+        </graphml>"#,
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        shape(&imported),
+        (
+            vec!["Ada".to_string(), "Bob".to_string()],
+            vec![(
+                "Ada".to_string(),
+                "email".to_string(),
+                "Ada@example.com".to_string(),
+                String::new()
+            )],
+            vec![r#"Ada -friend-> Bob 1 "" """#.to_string()]
+        )
+    );
+
+    for (xml, error) in [
+        ("<graph/>", "invalid GraphML: not a GraphML document"),
+        ("<graphml><graph>", "invalid GraphML: "),
+        (
+            r#"<graphml><graph><node id="a"/></graph></graphml>"#,
+            "invalid GraphML: node a: invalid person: name is empty",
+        ),
+        (
+            r#"<graphml><key id="e" attr.name="email"/><key id="l" attr.name="label"/><graph>
+               <node id="a"><data key="l">a</data><data key="e">nope</data></node>
+               </graph></graphml>"#,
+            "invalid GraphML: node a: invalid email",
+        ),
+        (
+            r#"<graphml><key id="l" attr.name="label"/><graph>
+               <node id="a"><data key="l">a</data></node>
+               <edge source="a" target="b"/>
+               </graph></graphml>"#,
+            "invalid GraphML: edge a -> b: no node b",
+        ),
+        (
+            r#"<graphml><key id="l" attr.name="label"/><key id="k" attr.name="kind"/><graph>
+               <node id="a"><data key="l">a</data></node>
+               <node id="b"><data key="l">b</data></node>
+               <edge source="a" target="b"><data key="k">enemy</data></edge>
+               </graph></graphml>"#,
+            "invalid GraphML: edge a -> b: unknown relationship kind",
+        ),
+    ] {
+        let e = import_graphml(&db, "crab", xml).await.unwrap_err();
+        assert!(e.to_string().starts_with(error), "{e}");
+    }
+    // nothing of an invalid import is added
+    assert!(
+        Network::load(&db, "crab", &ExportQuery::new(Format::Graphml))
+            .await
+            .unwrap()
+            .people
+            .is_empty()
+    );
+}
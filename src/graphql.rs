@@ -0,0 +1,389 @@
+//! The GraphQL API at `/graphql`. Every resolver only sees the people of the logged in user, the
+//! [`Owner`] the handler adds to each request.
+
+use async_graphql::{
+    Context, EmptySubscription, Enum, InputObject, Json, Object, Result, Schema, SimpleObject,
+};
+use sqlx::SqlitePool;
+
+use crate::people::{
+    self, Identifier, IdentifierBuilder, IdentifierKind, Person, PersonBuilder, PersonPatch,
+};
+use crate::relationships::{self, Relationship};
+use crate::scripts::{self, ScrapeResult};
+
+pub type SeekrSchema = Schema<Query, Mutation, EmptySubscription>;
+
+/// The name of the user a request is made by.
+#[derive(Debug, Clone)]
+pub struct Owner(pub String);
+
+/// How deep queries may nest. Identifiers, relationships and results link back to people, so
+/// without a limit one query could run any number of database queries.
+pub const MAX_DEPTH: usize = 10;
+
+/// How many fields a query may select in all, lists counting once.
+pub const MAX_COMPLEXITY: usize = 200;
+
+pub fn schema(db: SqlitePool) -> SeekrSchema {
+    Schema::build(Query, Mutation, EmptySubscription)
+        .data(db)
+        .limit_depth(MAX_DEPTH)
+        .limit_complexity(MAX_COMPLEXITY)
+        .finish()
+}
+
+/// The database and the owner of the request. Requests without an owner are rejected.
+fn owner<'a>(ctx: &Context<'a>) -> Result<(&'a SqlitePool, &'a str)> {
+    let owner = ctx
+        .data_opt::<Owner>()
+        .ok_or(people::GetPersonError::Auth)?;
+    Ok((ctx.data::<SqlitePool>()?, &owner.0))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Enum)]
+#[graphql(name = "IdentifierKind", remote = "crate::people::IdentifierKind")]
+enum Kind {
+    Email,
+    Phone,
+    Username,
+    Url,
+    Address,
+    Birthdate,
+    Alias,
+    Wallet,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Enum)]
+#[graphql(
+    name = "RelationshipKind",
+    remote = "crate::relationships::RelationshipKind"
+)]
+enum RelationshipKind {
+    Family,
+    Partner,
+    Friend,
+    Colleague,
+    Associate,
+    /// The two are names of one person, `from` being the alias.
+    AliasOf,
+    /// The two use the same online account.
+    SameAccount,
+}
+
+struct PersonObject(Person);
+
+#[Object(name = "Person")]
+impl PersonObject {
+    async fn id(&self) -> u32 {
+        self.0.id
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn owner(&self) -> &str {
+        &self.0.owner
+    }
+
+    /// Identifiers of the person by kind, only those of `kind` if given.
+    async fn identifiers(
+        &self,
+        ctx: &Context<'_>,
+        kind: Option<Kind>,
+    ) -> Result<Vec<IdentifierObject>> {
+        let (db, owner) = owner(ctx)?;
+        let kind = kind.map(IdentifierKind::from);
+        Ok(people::list_identifiers(db, owner, self.0.id)
+            .await?
+            .into_iter()
+            .filter(|i| kind.is_none_or(|kind| i.kind == kind))
+            .map(IdentifierObject)
+            .collect())
+    }
+
+    /// Relationships from and to the person, by kind.
+    async fn relationships(&self, ctx: &Context<'_>) -> Result<Vec<RelationshipObject>> {
+        let (db, owner) = owner(ctx)?;
+        Ok(relationships::list_relationships(db, owner, self.0.id)
+            .await?
+            .into_iter()
+            .map(RelationshipObject)
+            .collect())
+    }
+
+    /// The latest result of every script run against the person.
+    async fn results(&self, ctx: &Context<'_>) -> Result<Vec<ScrapeResultObject>> {
+        let (db, owner) = owner(ctx)?;
+        Ok(scripts::latest_results(db, owner, self.0.id)
+            .await?
+            .into_iter()
+            .map(ScrapeResultObject)
+            .collect())
+    }
+}
+
+struct IdentifierObject(Identifier);
+
+#[Object(name = "Identifier")]
+impl IdentifierObject {
+    async fn id(&self) -> u32 {
+        self.0.id
+    }
+
+    async fn kind(&self) -> Kind {
+        self.0.kind.into()
+    }
+
+    /// The normalized value.
+    async fn value(&self) -> &str {
+        &self.0.value
+    }
+
+    /// The platform of a username or the chain of a wallet, empty for other kinds.
+    async fn platform(&self) -> &str {
+        &self.0.platform
+    }
+
+    async fn created_at(&self) -> &str {
+        &self.0.created_at
+    }
+
+    async fn person(&self, ctx: &Context<'_>) -> Result<PersonObject> {
+        let (db, owner) = owner(ctx)?;
+        Ok(PersonObject(
+            people::get_person(db, owner, self.0.person_id).await?,
+        ))
+    }
+}
+
+struct RelationshipObject(Relationship);
+
+#[Object(name = "Relationship")]
+impl RelationshipObject {
+    async fn id(&self) -> u32 {
+        self.0.id
+    }
+
+    async fn kind(&self) -> RelationshipKind {
+        self.0.kind.into()
+    }
+
+    /// From 0 to 1.
+    async fn confidence(&self) -> f64 {
+        self.0.confidence
+    }
+
+    /// Where the connection is known from.
+    async fn source(&self) -> &str {
+        &self.0.source
+    }
+
+    async fn notes(&self) -> &str {
+        &self.0.notes
+    }
+
+    async fn created_at(&self) -> &str {
+        &self.0.created_at
+    }
+
+    async fn from(&self, ctx: &Context<'_>) -> Result<PersonObject> {
+        let (db, owner) = owner(ctx)?;
+        Ok(PersonObject(
+            people::get_person(db, owner, self.0.from_id).await?,
+        ))
+    }
+
+    async fn to(&self, ctx: &Context<'_>) -> Result<PersonObject> {
+        let (db, owner) = owner(ctx)?;
+        Ok(PersonObject(
+            people::get_person(db, owner, self.0.to_id).await?,
+        ))
+    }
+}
+
+struct ScrapeResultObject(ScrapeResult);
+
+#[Object(name = "ScrapeResult")]
+impl ScrapeResultObject {
+    async fn id(&self) -> u32 {
+        self.0.id
+    }
+
+    async fn script_id(&self) -> u32 {
+        self.0.script_id
+    }
+
+    async fn script_name(&self) -> &str {
+        &self.0.script_name
+    }
+
+    async fn script_version(&self) -> u32 {
+        self.0.script_version
+    }
+
+    /// The value the script returned, null if it failed.
+    async fn output(&self) -> Option<Json<&serde_json::Value>> {
+        self.0.output.as_ref().map(|output| Json(&output.0))
+    }
+
+    async fn error(&self) -> Option<&str> {
+        self.0.error.as_deref()
+    }
+
+    async fn created_at(&self) -> &str {
+        &self.0.created_at
+    }
+
+    async fn person(&self, ctx: &Context<'_>) -> Result<PersonObject> {
+        let (db, owner) = owner(ctx)?;
+        Ok(PersonObject(
+            people::get_person(db, owner, self.0.person_id).await?,
+        ))
+    }
+}
+
+/// Which people to list, see [`people::PeopleFilter`].
+#[derive(Default, InputObject)]
+struct PeopleFilter {
+    /// Only people whose name contains this, ignoring ASCII case.
+    name: Option<String>,
+}
+
+impl From<PeopleFilter> for people::PeopleFilter {
+    fn from(filter: PeopleFilter) -> Self {
+        people::PeopleFilter { name: filter.name }
+    }
+}
+
+/// Which page of people to list, see [`people::Pagination`].
+#[derive(InputObject)]
+struct Pagination {
+    #[graphql(default = 1)]
+    page: u32,
+    /// At most [`people::Pagination::MAX_PER_PAGE`].
+    #[graphql(default = 50)]
+    per_page: u32,
+}
+
+impl Default for Pagination {
+    fn default() -> Self {
+        let people::Pagination { page, per_page } = people::Pagination::default();
+        Pagination { page, per_page }
+    }
+}
+
+impl From<Pagination> for people::Pagination {
+    fn from(pagination: Pagination) -> Self {
+        people::Pagination {
+            page: pagination.page,
+            per_page: pagination.per_page,
+        }
+    }
+}
+
+/// A page of people, see [`people::PeoplePage`].
+#[derive(SimpleObject)]
+struct PeoplePage {
+    people: Vec<PersonObject>,
+    page: u32,
+    per_page: u32,
+    total: u32,
+}
+
+pub struct Query;
+
+#[Object]
+impl Query {
+    /// People by id, a page at a time, with the filters and pagination of `/api/v1/people`.
+    async fn people(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] filter: PeopleFilter,
+        #[graphql(default)] pagination: Pagination,
+    ) -> Result<PeoplePage> {
+        let (db, owner) = owner(ctx)?;
+        let found = people::list_people(db, owner, &filter.into(), pagination.into()).await?;
+        Ok(PeoplePage {
+            people: found.people.into_iter().map(PersonObject).collect(),
+            page: found.page,
+            per_page: found.per_page,
+            total: found.total,
+        })
+    }
+
+    /// A person by id, null if the user has no such person.
+    async fn person(&self, ctx: &Context<'_>, id: u32) -> Result<Option<PersonObject>> {
+        let (db, owner) = owner(ctx)?;
+        match people::get_person(db, owner, id).await {
+            Ok(person) => Ok(Some(PersonObject(person))),
+            Err(people::GetPersonError::NotFound { .. }) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[derive(InputObject)]
+struct IdentifierInput {
+    kind: Kind,
+    value: String,
+    /// Required for usernames, detected for wallets.
+    #[graphql(default)]
+    platform: String,
+}
+
+pub struct Mutation;
+
+#[Object]
+impl Mutation {
+    async fn create_person(&self, ctx: &Context<'_>, name: String) -> Result<PersonObject> {
+        let (db, owner) = owner(ctx)?;
+        Ok(PersonObject(
+            people::insert_person(db, owner, PersonBuilder { name }).await?,
+        ))
+    }
+
+    /// Change the fields that are given.
+    async fn update_person(
+        &self,
+        ctx: &Context<'_>,
+        id: u32,
+        name: Option<String>,
+    ) -> Result<PersonObject> {
+        let (db, owner) = owner(ctx)?;
+        Ok(PersonObject(
+            people::update_person(db, owner, id, PersonPatch { name }).await?,
+        ))
+    }
+
+    /// Delete a person with its identifiers and results, returns true.
+    async fn delete_person(&self, ctx: &Context<'_>, id: u32) -> Result<bool> {
+        let (db, owner) = owner(ctx)?;
+        people::delete_person(db, owner, id).await?;
+        Ok(true)
+    }
+
+    async fn add_identifier(
+        &self,
+        ctx: &Context<'_>,
+        person_id: u32,
+        identifier: IdentifierInput,
+    ) -> Result<IdentifierObject> {
+        let (db, owner) = owner(ctx)?;
+        let builder = IdentifierBuilder::new(identifier.kind.into(), &identifier.value)
+            .platform(&identifier.platform);
+        Ok(IdentifierObject(
+            people::add_identifier(db, owner, person_id, builder).await?,
+        ))
+    }
+
+    /// Remove an identifier from a person, returns true.
+    async fn remove_identifier(&self, ctx: &Context<'_>, person_id: u32, id: u32) -> Result<bool> {
+        let (db, owner) = owner(ctx)?;
+        people::remove_identifier(db, owner, person_id, id).await?;
+        Ok(true)
+    }
+}
+
+mod test;
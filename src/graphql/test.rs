@@ -0,0 +1,326 @@
+#![cfg(test)]
+use super::*;
+use crate::test_db;
+use async_graphql::{value, Request};
+
+async fn execute(
+    schema: &SeekrSchema,
+    owner: Option<&str>,
+    query: &str,
+) -> async_graphql::Response {
+    let request = Request::new(query);
+    let request = match owner {
+        Some(owner) => request.data(Owner(owner.to_string())),
+        None => request,
+    };
+    schema.execute(request).await
+}
+
+/// The data of a response without errors.
+async fn data(schema: &SeekrSchema, owner: &str, query: &str) -> async_graphql::Value {
+    let response = execute(schema, Some(owner), query).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    response.data
+}
+
+async fn errors(schema: &SeekrSchema, owner: Option<&str>, query: &str) -> Vec<String> {
+    execute(schema, owner, query)
+        .await
+        .errors
+        .into_iter()
+        .map(|e| e.message)
+        .collect()
+}
+
+#[tokio::test]
+async fn test_graphql() {
+    let db = test_db().await;
+    let schema = schema(db.clone());
+
+    let created = data(
+        &schema,
+        "ferris",
+        r#"mutation {
+            greg: createPerson(name: "greg") { id name owner }
+            ada: createPerson(name: "ada") { id }
+        }"#,
+    )
+    .await;
+    assert_eq!(
+        created,
+        value!({"greg": {"id": 1, "name": "greg", "owner": "ferris"}, "ada": {"id": 2}})
+    );
+    let added = data(
+        &schema,
+        "ferris",
+        r#"mutation {
+            addIdentifier(personId: 1, identifier: {kind: EMAIL, value: "Greg@Example.com"}) {
+                kind value person { name }
+            }
+        }"#,
+    )
+    .await;
+    assert_eq!(
+        added,
+        value!({"addIdentifier": {"kind": "EMAIL", "value": "Greg@example.com", "person": {"name": "greg"}}})
+    );
+    data(
+        &schema,
+        "ferris",
+        r#"mutation {
+            addIdentifier(personId: 1, identifier: {kind: USERNAME, value: "@greg", platform: "github"}) { id }
+        }"#,
+    )
+    .await;
+    assert_eq!(
+        errors(
+            &schema,
+            Some("ferris"),
+            r#"mutation { addIdentifier(personId: 1, identifier: {kind: PHONE, value: "x"}) { id } }"#
+        )
+        .await,
+        ["invalid phone: only digits, spaces, dashes, dots, slashes and parentheses"]
+    );
+
+    // nested relations with filters
+    assert_eq!(
+        data(
+            &schema,
+            "ferris",
+            r#"{
+                person(id: 1) {
+                    name
+                    all: identifiers { kind }
+                    usernames: identifiers(kind: USERNAME) { value platform }
+                    results { id }
+                }
+            }"#,
+        )
+        .await,
+        value!({"person": {
+            "name": "greg",
+            "all": [{"kind": "EMAIL"}, {"kind": "USERNAME"}],
+            "usernames": [{"value": "greg", "platform": "github"}],
+            "results": [],
+        }})
+    );
+    assert_eq!(
+        data(
+            &schema,
+            "ferris",
+            "{ people(pagination: {perPage: 1, page: 2}) { people { name } page perPage total } }"
+        )
+        .await,
+        value!({"people": {"people": [{"name": "ada"}], "page": 2, "perPage": 1, "total": 2}})
+    );
+    assert_eq!(
+        data(
+            &schema,
+            "ferris",
+            r#"{ people(filter: {name: "GR"}) { total } }"#
+        )
+        .await,
+        value!({"people": {"total": 1}})
+    );
+    // pages are clamped like those of /api/v1/people
+    assert_eq!(
+        data(
+            &schema,
+            "ferris",
+            "{ people(pagination: {perPage: 1000, page: 0}) { page perPage } }"
+        )
+        .await,
+        value!({"people": {"page": 1, "perPage": 100}})
+    );
+
+    assert_eq!(
+        data(
+            &schema,
+            "ferris",
+            r#"mutation { updatePerson(id: 1, name: "gregory") { name } }"#
+        )
+        .await,
+        value!({"updatePerson": {"name": "gregory"}})
+    );
+    assert_eq!(
+        data(&schema, "ferris", "mutation { deletePerson(id: 2) }").await,
+        value!({"deletePerson": true})
+    );
+    assert_eq!(
+        data(&schema, "ferris", "{ person(id: 2) { name } }").await,
+        value!({"person": null})
+    );
+}
+
+#[tokio::test]
+async fn test_graphql_relationships() {
+    use crate::relationships::{add_relationship, RelationshipBuilder, RelationshipKind};
+
+    let db = test_db().await;
+    let schema = schema(db.clone());
+    for name in ["greg", "ada", "bob"] {
+        people::insert_person(&db, "ferris", PersonBuilder { name: name.into() })
+            .await
+            .unwrap();
+    }
+    add_relationship(
+        &db,
+        "ferris",
+        RelationshipBuilder {
+            confidence: 0.5,
+            source: "https://example.com/team".into(),
+            ..RelationshipBuilder::new(1, 2, RelationshipKind::Colleague)
+        },
+    )
+    .await
+    .unwrap();
+    add_relationship(
+        &db,
+        "ferris",
+        RelationshipBuilder::new(3, 1, RelationshipKind::AliasOf),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        data(
+            &schema,
+            "ferris",
+            r#"{
+                person(id: 1) {
+                    relationships { kind confidence source from { name } to { name } }
+                }
+            }"#,
+        )
+        .await,
+        value!({"person": {"relationships": [
+            {
+                "kind": "ALIAS_OF",
+                "confidence": 1.0,
+                "source": "",
+                "from": {"name": "bob"},
+                "to": {"name": "greg"},
+            },
+            {
+                "kind": "COLLEAGUE",
+                "confidence": 0.5,
+                "source": "https://example.com/team",
+                "from": {"name": "greg"},
+                "to": {"name": "ada"},
+            },
+        ]}})
+    );
+    assert_eq!(
+        data(
+            &schema,
+            "ferris",
+            "{ people(filter: {name: \"ada\"}) { people { relationships { to { name } } } } }",
+        )
+        .await,
+        value!({"people": {"people": [{"relationships": [{"to": {"name": "ada"}}]}]}})
+    );
+}
+
+#[tokio::test]
+async fn test_graphql_owner_scoping() {
+    let db = test_db().await;
+    let schema = schema(db.clone());
+    data(
+        &schema,
+        "ferris",
+        r#"mutation {
+            createPerson(name: "greg") { id }
+            addIdentifier(personId: 1, identifier: {kind: ALIAS, value: "crabby"}) { id }
+        }"#,
+    )
+    .await;
+
+    // another user sees none of it
+    assert_eq!(
+        data(&schema, "crab", "{ person(id: 1) { name } }").await,
+        value!({"person": null})
+    );
+    assert_eq!(
+        data(&schema, "crab", "{ people { total people { name } } }").await,
+        value!({"people": {"total": 0, "people": []}})
+    );
+    // and can change none of it
+    for mutation in [
+        r#"mutation { updatePerson(id: 1, name: "bob") { name } }"#,
+        "mutation { deletePerson(id: 1) }",
+        r#"mutation { addIdentifier(personId: 1, identifier: {kind: ALIAS, value: "x"}) { id } }"#,
+        "mutation { removeIdentifier(personId: 1, id: 1) }",
+    ] {
+        assert_eq!(
+            errors(&schema, Some("crab"), mutation).await.len(),
+            1,
+            "{}",
+            mutation
+        );
+    }
+    assert_eq!(
+        data(
+            &schema,
+            "ferris",
+            "{ person(id: 1) { name identifiers { value } } }"
+        )
+        .await,
+        value!({"person": {"name": "greg", "identifiers": [{"value": "crabby"}]}})
+    );
+
+    // requests without a user are rejected
+    assert_eq!(
+        errors(&schema, None, "{ people { total } }").await,
+        ["not authenticated"]
+    );
+    assert_eq!(
+        errors(
+            &schema,
+            None,
+            r#"mutation { createPerson(name: "bob") { id } }"#
+        )
+        .await,
+        ["not authenticated"]
+    );
+}
+
+#[tokio::test]
+async fn test_graphql_limits() {
+    let db = test_db().await;
+    let schema = schema(db.clone());
+    data(
+        &schema,
+        "ferris",
+        r#"mutation {
+            createPerson(name: "greg") { id }
+            addIdentifier(personId: 1, identifier: {kind: ALIAS, value: "crabby"}) { id }
+        }"#,
+    )
+    .await;
+
+    let nested = |levels: usize| {
+        format!(
+            "{{ person(id: 1) {{ {}name{} }} }}",
+            "identifiers { person { ".repeat(levels),
+            " } }".repeat(levels)
+        )
+    };
+    data(&schema, "ferris", &nested(3)).await;
+    assert_eq!(
+        errors(&schema, Some("ferris"), &nested(MAX_DEPTH)).await,
+        ["Query is nested too deep."]
+    );
+
+    // wide queries are limited too
+    let wide = format!(
+        "{{ {} }}",
+        (0..MAX_COMPLEXITY)
+            .map(|i| format!("p{i}: person(id: 1) {{ name }}"))
+            .collect::<Vec<_>>()
+            .join(" ")
+    );
+    assert_eq!(
+        errors(&schema, Some("ferris"), &wide).await,
+        ["Query is too complex."]
+    );
+}
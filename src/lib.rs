@@ -1,12 +1,18 @@
 pub mod cli;
+pub mod export;
+pub mod graphql;
 pub mod people;
+pub mod relationships;
 pub mod routes;
 pub mod scrape;
+pub mod scripts;
+pub mod search;
 pub mod users;
 pub mod web;
 
 use anyhow::Result;
-use cli::Args;
+use cli::{Args, Command};
+use std::path::PathBuf;
 use tower_http::trace::TraceLayer;
 use tracing::{info, instrument};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
@@ -15,6 +21,83 @@ use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 pub async fn run(args: Args) -> Result<()> {
     setup_tracing();
 
+    match args.command {
+        Some(Command::Repl) => {
+            let session = scrape::repl::Session::new(
+                scrape::builtins::Registry::standard().clone(),
+                args.limits()?.default,
+            );
+            let history =
+                std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".seekr_history"));
+            return scrape::repl::run(session, history);
+        }
+        Some(Command::Fmt { ref file, write }) => {
+            let src = std::fs::read_to_string(file)?;
+            let formatted = scrape::format_script(&src)
+                .map_err(|diagnostic| anyhow::anyhow!("{}", diagnostic.render(&src)))?;
+            match write {
+                true => std::fs::write(file, formatted)?,
+                false => print!("{formatted}"),
+            }
+            return Ok(());
+        }
+        Some(Command::Run {
+            ref owner,
+            script,
+            person,
+        }) => {
+            let db = open_db(&args).await?;
+            let (limits, policy) = (args.limits()?, args.policy());
+            let runner =
+                tokio::task::spawn_blocking(move || scripts::Runner::http(limits, policy)).await?;
+            let result = scripts::run_script(&db, &runner, owner, script, person).await;
+            // neither may the http client be dropped in the runtime
+            tokio::task::spawn_blocking(move || drop(runner)).await?;
+            println!("{}", serde_json::to_string_pretty(&result?)?);
+            return Ok(());
+        }
+        Some(Command::Export {
+            ref owner,
+            format,
+            person,
+            hops,
+            min_confidence,
+            ref output,
+        }) => {
+            let db = open_db(&args).await?;
+            let query = export::ExportQuery {
+                format,
+                person,
+                hops,
+                min_confidence,
+            };
+            let exported = export::Network::load(&db, owner, &query)
+                .await?
+                .export(format);
+            match output {
+                Some(path) => std::fs::write(path, exported)?,
+                None => print!("{exported}"),
+            }
+            return Ok(());
+        }
+        Some(Command::Import {
+            ref owner,
+            ref file,
+        }) => {
+            let db = open_db(&args).await?;
+            let imported =
+                export::import_graphml(&db, owner, &std::fs::read_to_string(file)?).await?;
+            println!(
+                "imported {} people, {} identifiers and {} relationships",
+                imported.people.len(),
+                imported.identifiers.len(),
+                imported.relationships.len()
+            );
+            return Ok(());
+        }
+        None => {}
+    }
+
     let app = routes::get_router(&args)
         .await?
         .layer(TraceLayer::new_for_http());
@@ -24,6 +107,29 @@ pub async fn run(args: Args) -> Result<()> {
     Ok(axum::serve(listener, app).await?)
 }
 
+/// The current time in UTC in SQL, as the `created_at` columns store it.
+pub(crate) const NOW: &str = "strftime('%Y-%m-%dT%H:%M:%SZ', 'now')";
+
+/// The database of the server and the commands, created if missing and migrated.
+pub(crate) async fn open_db(args: &Args) -> Result<sqlx::SqlitePool> {
+    let db = sqlx::SqlitePool::connect(&args.create_db()?.get_pool()).await?;
+    sqlx::migrate!().run(&db).await?;
+    Ok(db)
+}
+
+/// A migrated in-memory database. A single connection, every connection would open its own
+/// database.
+#[cfg(test)]
+pub(crate) async fn test_db() -> sqlx::SqlitePool {
+    let db = sqlx::sqlite::SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    sqlx::migrate!().run(&db).await.unwrap();
+    db
+}
+
 fn setup_tracing() {
     tracing_subscriber::registry()
         .with(
@@ -40,3 +146,5 @@ fn setup_tracing() {
         )
         .init();
 }
+
+mod test;
@@ -4,5 +4,5 @@ use seekr::{cli, run};
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = cli::parse();
-    Ok(run(args).await?)
+    run(args).await
 }
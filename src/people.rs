@@ -1,12 +1,24 @@
 use serde::{Deserialize, Serialize};
-use sqlx::FromRow;
-use sqlxinsert::SqliteInsert;
+use sqlx::{FromRow, SqlitePool};
 use thiserror::Error;
-use utoipa::ToSchema;
+use tracing::instrument;
+use utoipa::{IntoParams, ToSchema};
 
-use crate::users::AuthSession;
+mod duplicates;
+mod identifiers;
+pub use duplicates::{
+    find_duplicates, list_merges, merge_people, name_tokens, undo_merge, DuplicateCandidate,
+    DuplicatesQuery, Merge, MergeError, MergeRequest,
+};
+pub use identifiers::{
+    add_identifier, list_identifiers, remove_identifier, Identifier, IdentifierBuilder,
+    IdentifierError, IdentifierKind,
+};
 
-#[derive(Clone, Serialize, Deserialize, FromRow, ToSchema, SqliteInsert)]
+/// Longest name of a person, in characters.
+pub const MAX_NAME_LEN: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromRow, ToSchema)]
 pub struct Person {
     #[schema(example = 4u32)]
     pub id: u32,
@@ -18,49 +30,190 @@ pub struct Person {
     pub owner: String,
 }
 
-// /// Used in requests creating a person
-// #[derive(Clone, Serialize, Deserialize, FromRow, ToSchema)]
-// pub struct PersonBuilder {
-//     #[schema(example = "greg")]
-//     pub name: String,
-// }
+/// Used in requests creating a person
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PersonBuilder {
+    #[schema(example = "greg")]
+    pub name: String,
+}
+
+/// Used in requests updating a person, fields left out are not changed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct PersonPatch {
+    #[schema(example = "greg")]
+    pub name: Option<String>,
+}
+
+/// Which page of people to list. Pages start at 1.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct Pagination {
+    #[serde(default = "Pagination::first")]
+    #[param(example = 1, minimum = 1)]
+    pub page: u32,
+
+    /// At most [`Pagination::MAX_PER_PAGE`].
+    #[serde(default = "Pagination::default_per_page")]
+    #[param(example = 50, minimum = 1, maximum = 100)]
+    pub per_page: u32,
+}
+
+impl Pagination {
+    pub const MAX_PER_PAGE: u32 = 100;
+
+    fn first() -> u32 {
+        1
+    }
+
+    fn default_per_page() -> u32 {
+        50
+    }
+}
+
+impl Default for Pagination {
+    fn default() -> Self {
+        Self {
+            page: Self::first(),
+            per_page: Self::default_per_page(),
+        }
+    }
+}
+
+/// Which people to list.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PeopleFilter {
+    /// Only people whose name contains this, ignoring ASCII case.
+    #[param(example = "gr")]
+    pub name: Option<String>,
+}
 
+/// A page of people with the number of people in all pages.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct PeoplePage {
+    pub people: Vec<Person>,
+
+    #[schema(example = 1u32)]
+    pub page: u32,
+
+    #[schema(example = 50u32)]
+    pub per_page: u32,
+
+    #[schema(example = 1u32)]
+    pub total: u32,
+}
+
+/// Trimmed, not empty and at most [`MAX_NAME_LEN`] characters.
+pub(crate) fn validate_name(name: &str) -> Result<String, InsertPersonError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(InsertPersonError::Invalid("name is empty".to_string()));
+    }
+    if name.chars().count() > MAX_NAME_LEN {
+        return Err(InsertPersonError::Invalid(format!(
+            "name is longer than {MAX_NAME_LEN} characters"
+        )));
+    }
+    Ok(name.to_string())
+}
+
+#[instrument(skip(db))]
 pub async fn insert_person(
-    auth_session: AuthSession,
-    person: Person,
+    db: &SqlitePool,
+    owner: &str,
+    person: PersonBuilder,
 ) -> Result<Person, InsertPersonError> {
-    match auth_session.user {
-        Some(user) => {
-            let mut person = person;
-            person.owner = user.username;
-            let db = auth_session.backend.get_pool();
-            let _ = person.insert_raw(&db, "people").await?;
-            Ok(person)
-        }
-        None => Err(InsertPersonError::Auth),
+    let name = validate_name(&person.name)?;
+    Ok(
+        sqlx::query_as("insert into people (owner, name) values (?, ?) returning *")
+            .bind(owner)
+            .bind(name)
+            .fetch_one(db)
+            .await?,
+    )
+}
+
+pub async fn get_person(db: &SqlitePool, owner: &str, id: u32) -> Result<Person, GetPersonError> {
+    sqlx::query_as("select * from people where id = ? and owner = ?")
+        .bind(id)
+        .bind(owner)
+        .fetch_optional(db)
+        .await?
+        .ok_or_else(|| GetPersonError::NotFound {
+            id,
+            owner: owner.to_string(),
+        })
+}
+
+/// The people of `owner` matching `filter`, ordered by id, a page at a time.
+pub async fn list_people(
+    db: &SqlitePool,
+    owner: &str,
+    filter: &PeopleFilter,
+    pagination: Pagination,
+) -> Result<PeoplePage, GetPersonError> {
+    const MATCHES: &str = "owner = ? and instr(lower(name), lower(?)) > 0";
+    let page = pagination.page.max(1);
+    let per_page = pagination.per_page.clamp(1, Pagination::MAX_PER_PAGE);
+    let name = filter.name.as_deref().unwrap_or_default();
+    let people = sqlx::query_as(&format!(
+        "select * from people where {MATCHES} order by id limit ? offset ?"
+    ))
+    .bind(owner)
+    .bind(name)
+    .bind(per_page)
+    .bind(i64::from(page - 1) * i64::from(per_page))
+    .fetch_all(db)
+    .await?;
+    let (total,): (u32,) = sqlx::query_as(&format!("select count(*) from people where {MATCHES}"))
+        .bind(owner)
+        .bind(name)
+        .fetch_one(db)
+        .await?;
+    Ok(PeoplePage {
+        people,
+        page,
+        per_page,
+        total,
+    })
+}
+
+#[instrument(skip(db))]
+pub async fn update_person(
+    db: &SqlitePool,
+    owner: &str,
+    id: u32,
+    patch: PersonPatch,
+) -> Result<Person, InsertPersonError> {
+    let mut person = get_person(db, owner, id).await?;
+    if let Some(name) = patch.name {
+        person.name = validate_name(&name)?;
     }
+    Ok(
+        sqlx::query_as("update people set name = ? where id = ? and owner = ? returning *")
+            .bind(&person.name)
+            .bind(id)
+            .bind(owner)
+            .fetch_one(db)
+            .await?,
+    )
 }
 
-pub async fn get_person(auth_session: AuthSession, id: u32) -> Result<Person, GetPersonError> {
-    match auth_session.user {
-        Some(user) => {
-            let db = auth_session.backend.get_pool();
-            if let Some(person) = sqlx::query_as("select * from people where id = ? and owner = ?")
-                .bind(id)
-                .bind(&user.username)
-                .fetch_optional(&db)
-                .await?
-            {
-                Ok(person)
-            } else {
-                Err(GetPersonError::NotFound {
-                    id,
-                    owner: user.username,
-                })
-            }
-        }
-        None => Err(GetPersonError::Auth),
+/// Delete a person with everything attached to it, like identifiers and scrape results.
+#[instrument(skip(db))]
+pub async fn delete_person(db: &SqlitePool, owner: &str, id: u32) -> Result<(), GetPersonError> {
+    let deleted = sqlx::query("delete from people where id = ? and owner = ?")
+        .bind(id)
+        .bind(owner)
+        .execute(db)
+        .await?;
+    if deleted.rows_affected() == 0 {
+        return Err(GetPersonError::NotFound {
+            id,
+            owner: owner.to_string(),
+        });
     }
+    Ok(())
 }
 
 #[derive(Debug, Error)]
@@ -77,8 +230,12 @@ pub enum GetPersonError {
 
 #[derive(Debug, Error)]
 pub enum InsertPersonError {
-    #[error("sqlx error")]
-    Sqlxinsert(#[from] eyre::ErrReport),
+    #[error("invalid person: {0}")]
+    Invalid(String),
+
+    /// The person to update could not be read.
+    #[error(transparent)]
+    Get(#[from] GetPersonError),
 
     #[error("sqlx error")]
     Sqlx(#[from] sqlx::Error),
@@ -86,3 +243,5 @@ pub enum InsertPersonError {
     #[error("not authenticated")]
     Auth,
 }
+
+mod test;
@@ -0,0 +1,626 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{types::Json, FromRow, SqlitePool};
+use std::collections::HashMap;
+use thiserror::Error;
+use tracing::instrument;
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+use utoipa::{IntoParams, ToSchema};
+
+use super::{GetPersonError, Identifier, IdentifierBuilder, IdentifierKind, Person};
+use crate::relationships::Relationship;
+use crate::NOW;
+
+/// Two people that may be the same.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct DuplicateCandidate {
+    /// The older of the two, suggested to be kept.
+    pub keep: Person,
+
+    pub duplicate: Person,
+
+    /// From 0 to 1, higher is more likely the same person.
+    #[schema(example = 0.95)]
+    pub score: f64,
+
+    /// Why the two look the same.
+    #[schema(example = json!(["same name in another order", "both have email greg@example.com"]))]
+    pub reasons: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DuplicatesQuery {
+    /// Leave out pairs scoring less.
+    #[serde(default = "DuplicatesQuery::default_min_score")]
+    #[param(example = 0.75, minimum = 0, maximum = 1)]
+    pub min_score: f64,
+}
+
+impl DuplicatesQuery {
+    fn default_min_score() -> f64 {
+        0.75
+    }
+}
+
+impl Default for DuplicatesQuery {
+    fn default() -> Self {
+        Self {
+            min_score: Self::default_min_score(),
+        }
+    }
+}
+
+/// A person merged into another, with what is needed to undo it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct Merge {
+    #[schema(example = 1u32)]
+    pub id: u32,
+
+    #[schema(example = "seekr")]
+    pub owner: String,
+
+    /// The person that was kept.
+    #[schema(example = 4u32)]
+    pub kept_id: u32,
+
+    /// The person that was merged and deleted.
+    #[schema(value_type = Person)]
+    pub merged: Json<Person>,
+
+    /// Identifiers moved to the kept person.
+    #[schema(value_type = Vec<u32>)]
+    pub moved_identifiers: Json<Vec<u32>>,
+
+    /// Scrape results moved to the kept person.
+    #[schema(value_type = Vec<u32>)]
+    pub moved_results: Json<Vec<u32>>,
+
+    /// Identifiers the kept person already had, they were deleted.
+    #[schema(value_type = Vec<Identifier>)]
+    pub dropped_identifiers: Json<Vec<Identifier>>,
+
+    /// Relationships of the merged person moved to the kept one, as they were before.
+    #[schema(value_type = Vec<Relationship>)]
+    pub moved_relationships: Json<Vec<Relationship>>,
+
+    /// Relationships between the two people or that the kept person already had, they were
+    /// deleted.
+    #[schema(value_type = Vec<Relationship>)]
+    pub dropped_relationships: Json<Vec<Relationship>>,
+
+    /// The alias with the name of the merged person added to the kept one.
+    #[schema(example = 12u32)]
+    pub alias_id: Option<u32>,
+
+    #[schema(example = "2024-01-20T12:00:00Z")]
+    pub created_at: String,
+
+    #[schema(example = json!(null))]
+    pub undone_at: Option<String>,
+}
+
+/// Used in requests merging two people.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct MergeRequest {
+    #[schema(example = 4u32)]
+    pub keep: u32,
+
+    /// Moved into `keep` and deleted.
+    #[schema(example = 7u32)]
+    pub merge: u32,
+}
+
+#[derive(Debug, Error)]
+pub enum MergeError {
+    #[error("can not merge person {0} into itself")]
+    SamePerson(u32),
+
+    #[error(transparent)]
+    Person(#[from] GetPersonError),
+
+    #[error("merge not found. ID: {id:?} owner: {owner:?}")]
+    NotFound { id: u32, owner: String },
+
+    #[error("merge {0} was already undone")]
+    AlreadyUndone(u32),
+
+    #[error("sqlx error")]
+    Sqlx(#[from] sqlx::Error),
+
+    #[error("not authenticated")]
+    Auth,
+}
+
+/// Latin spellings of letters that do not decompose into a base letter and marks.
+fn transliterate(c: char) -> Option<&'static str> {
+    Some(match c {
+        'ß' => "ss",
+        'æ' => "ae",
+        'œ' => "oe",
+        'ø' => "o",
+        'ł' => "l",
+        'đ' | 'ð' => "d",
+        'þ' => "th",
+        'ı' => "i",
+        'а' => "a",
+        'б' => "b",
+        'в' => "v",
+        'г' | 'ґ' => "g",
+        'д' => "d",
+        'е' | 'ё' | 'э' => "e",
+        'є' => "ye",
+        'ж' => "zh",
+        'з' => "z",
+        'и' | 'і' => "i",
+        'ї' => "yi",
+        'й' | 'ы' => "y",
+        'к' => "k",
+        'л' => "l",
+        'м' => "m",
+        'н' => "n",
+        'о' => "o",
+        'п' => "p",
+        'р' => "r",
+        'с' => "s",
+        'т' => "t",
+        'у' => "u",
+        'ф' => "f",
+        'х' => "kh",
+        'ц' => "ts",
+        'ч' => "ch",
+        'ш' => "sh",
+        'щ' => "shch",
+        'ъ' | 'ь' => "",
+        'ю' => "yu",
+        'я' => "ya",
+        _ => return None,
+    })
+}
+
+/// The words of a name in lowercase latin letters and digits, without marks.
+///
+/// ```
+/// use seekr::people::name_tokens;
+///
+/// assert_eq!(name_tokens("Grüßer, G."), ["grusser", "g"]);
+/// assert_eq!(name_tokens("Ада Лавлейс"), ["ada", "lavleys"]);
+/// ```
+pub fn name_tokens(name: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut token = String::new();
+    for c in name.chars().flat_map(char::to_lowercase) {
+        // before decomposing, й is not и with a mark
+        if let Some(latin) = transliterate(c) {
+            token.push_str(latin);
+        } else if c.is_alphanumeric() {
+            token.extend(std::iter::once(c).nfd().filter(|c| !is_combining_mark(*c)));
+        } else if !token.is_empty() {
+            tokens.push(std::mem::take(&mut token));
+        }
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+    tokens
+}
+
+/// How alike two names are, from 0 to 1, with the reason.
+fn name_score(a: &[String], b: &[String]) -> (f64, String) {
+    if a.is_empty() || b.is_empty() {
+        return (0.0, String::new());
+    }
+    if a == b {
+        return (1.0, "same name".to_string());
+    }
+    let (mut sorted_a, mut sorted_b) = (a.to_vec(), b.to_vec());
+    sorted_a.sort();
+    sorted_b.sort();
+    if sorted_a == sorted_b {
+        return (0.95, "same name in another order".to_string());
+    }
+    // "G. Smith" and "Greg Smith"
+    let initial = |x: &String, y: &String| x.chars().count() == 1 && y.starts_with(x.as_str());
+    if a.len() == b.len()
+        && a.iter()
+            .zip(b)
+            .any(|(x, y)| x == y && x.chars().count() > 1)
+        && a.iter()
+            .zip(b)
+            .all(|(x, y)| x == y || initial(x, y) || initial(y, x))
+    {
+        return (0.85, "same name with initials".to_string());
+    }
+    // typos and other spellings, kept below the cases above
+    let similarity = strsim::jaro_winkler(&a.join(" "), &b.join(" ")).max(strsim::jaro_winkler(
+        &sorted_a.join(" "),
+        &sorted_b.join(" "),
+    ));
+    (
+        0.9 * similarity,
+        format!("similar names ({:.2})", similarity),
+    )
+}
+
+/// Kinds of identifiers that point to one person when two people share them.
+const IDENTIFYING: [IdentifierKind; 5] = [
+    IdentifierKind::Email,
+    IdentifierKind::Phone,
+    IdentifierKind::Username,
+    IdentifierKind::Url,
+    IdentifierKind::Wallet,
+];
+
+/// Each shared identifier takes away this much of the remaining doubt.
+const SHARED_WEIGHT: f64 = 0.6;
+
+/// Score two people by their names and aliases and the identifiers they share.
+fn score(
+    a: &Person,
+    b: &Person,
+    identifiers: &HashMap<u32, Vec<Identifier>>,
+) -> (f64, Vec<String>) {
+    let none = vec![];
+    let (ids_a, ids_b) = (
+        identifiers.get(&a.id).unwrap_or(&none),
+        identifiers.get(&b.id).unwrap_or(&none),
+    );
+    let names = |person: &Person, ids: &[Identifier]| -> Vec<Vec<String>> {
+        std::iter::once(person.name.as_str())
+            .chain(
+                ids.iter()
+                    .filter(|i| i.kind == IdentifierKind::Alias)
+                    .map(|i| i.value.as_str()),
+            )
+            .map(name_tokens)
+            .collect()
+    };
+    let (names_a, names_b) = (names(a, ids_a), names(b, ids_b));
+    let (mut score, reason) = names_a
+        .iter()
+        .flat_map(|x| names_b.iter().map(move |y| name_score(x, y)))
+        .max_by(|(x, _), (y, _)| x.total_cmp(y))
+        .unwrap_or_default();
+    let mut reasons = vec![];
+    if score > 0.0 {
+        reasons.push(reason);
+    }
+    for shared in ids_a.iter().filter(|x| {
+        IDENTIFYING.contains(&x.kind)
+            && ids_b
+                .iter()
+                .any(|y| (x.kind, &x.platform, &x.value) == (y.kind, &y.platform, &y.value))
+    }) {
+        score += (1.0 - score) * SHARED_WEIGHT;
+        reasons.push(match shared.platform.as_str() {
+            "" => format!("both have {} {}", shared.kind, shared.value),
+            platform => format!("both have {} {} on {}", shared.kind, shared.value, platform),
+        });
+    }
+    (score, reasons)
+}
+
+/// Pairs of people of `owner` that may be the same, the most likely first. Compares every pair.
+#[instrument(skip(db))]
+pub async fn find_duplicates(
+    db: &SqlitePool,
+    owner: &str,
+    query: DuplicatesQuery,
+) -> Result<Vec<DuplicateCandidate>, GetPersonError> {
+    let people: Vec<Person> = sqlx::query_as("select * from people where owner = ? order by id")
+        .bind(owner)
+        .fetch_all(db)
+        .await?;
+    let mut identifiers: HashMap<u32, Vec<Identifier>> = HashMap::new();
+    let all: Vec<Identifier> = sqlx::query_as("select * from identifiers where owner = ?")
+        .bind(owner)
+        .fetch_all(db)
+        .await?;
+    for identifier in all {
+        identifiers
+            .entry(identifier.person_id)
+            .or_default()
+            .push(identifier);
+    }
+
+    let mut candidates = vec![];
+    for (i, keep) in people.iter().enumerate() {
+        for duplicate in &people[i + 1..] {
+            let (score, reasons) = score(keep, duplicate, &identifiers);
+            if score >= query.min_score {
+                candidates.push(DuplicateCandidate {
+                    keep: keep.clone(),
+                    duplicate: duplicate.clone(),
+                    score,
+                    reasons,
+                });
+            }
+        }
+    }
+    candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+    Ok(candidates)
+}
+
+async fn fetch_person(
+    tx: &mut sqlx::SqliteConnection,
+    owner: &str,
+    id: u32,
+) -> Result<Person, GetPersonError> {
+    sqlx::query_as("select * from people where id = ? and owner = ?")
+        .bind(id)
+        .bind(owner)
+        .fetch_optional(tx)
+        .await?
+        .ok_or_else(|| GetPersonError::NotFound {
+            id,
+            owner: owner.to_string(),
+        })
+}
+
+/// Move the identifiers, scrape results and relationships of `merge` to `keep` and delete
+/// `merge`. Identifiers and relationships `keep` already has are dropped, and the name of
+/// `merge` becomes an alias of `keep`. The merge is recorded, see [`undo_merge`].
+#[instrument(skip(db))]
+pub async fn merge_people(
+    db: &SqlitePool,
+    owner: &str,
+    keep: u32,
+    merge: u32,
+) -> Result<Merge, MergeError> {
+    if keep == merge {
+        return Err(MergeError::SamePerson(keep));
+    }
+    let mut tx = db.begin().await?;
+    let kept = fetch_person(&mut tx, owner, keep).await?;
+    let merged = fetch_person(&mut tx, owner, merge).await?;
+
+    let kept_identifiers: Vec<Identifier> =
+        sqlx::query_as("select * from identifiers where person_id = ?")
+            .bind(keep)
+            .fetch_all(&mut *tx)
+            .await?;
+    let identifiers: Vec<Identifier> =
+        sqlx::query_as("select * from identifiers where person_id = ? order by id")
+            .bind(merge)
+            .fetch_all(&mut *tx)
+            .await?;
+    let (mut moved_identifiers, mut dropped_identifiers) = (vec![], vec![]);
+    for identifier in identifiers {
+        let known = kept_identifiers.iter().any(|k| {
+            (k.kind, &k.platform, &k.value)
+                == (identifier.kind, &identifier.platform, &identifier.value)
+        });
+        if known {
+            sqlx::query("delete from identifiers where id = ?")
+                .bind(identifier.id)
+                .execute(&mut *tx)
+                .await?;
+            dropped_identifiers.push(identifier);
+        } else {
+            sqlx::query("update identifiers set person_id = ? where id = ?")
+                .bind(keep)
+                .bind(identifier.id)
+                .execute(&mut *tx)
+                .await?;
+            moved_identifiers.push(identifier.id);
+        }
+    }
+    let moved_results: Vec<u32> = sqlx::query_scalar(
+        "update scrape_results set person_id = ? where person_id = ? returning id",
+    )
+    .bind(keep)
+    .bind(merge)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let relationships: Vec<Relationship> = sqlx::query_as(
+        "select r.*, f.name as from_name, t.name as to_name from relationships r
+         join people f on f.id = r.from_id
+         join people t on t.id = r.to_id
+         where r.from_id = ? or r.to_id = ? order by r.id",
+    )
+    .bind(merge)
+    .bind(merge)
+    .fetch_all(&mut *tx)
+    .await?;
+    let (mut moved_relationships, mut dropped_relationships) = (vec![], vec![]);
+    for relationship in relationships {
+        let moved = |id| if id == merge { keep } else { id };
+        let (from_id, to_id) = (moved(relationship.from_id), moved(relationship.to_id));
+        let known: Option<u32> = sqlx::query_scalar(
+            "select id from relationships where from_id = ? and to_id = ? and kind = ?",
+        )
+        .bind(from_id)
+        .bind(to_id)
+        .bind(relationship.kind.as_str())
+        .fetch_optional(&mut *tx)
+        .await?;
+        if from_id == to_id || known.is_some() {
+            sqlx::query("delete from relationships where id = ?")
+                .bind(relationship.id)
+                .execute(&mut *tx)
+                .await?;
+            dropped_relationships.push(relationship);
+        } else {
+            sqlx::query("update relationships set from_id = ?, to_id = ? where id = ?")
+                .bind(from_id)
+                .bind(to_id)
+                .bind(relationship.id)
+                .execute(&mut *tx)
+                .await?;
+            moved_relationships.push(relationship);
+        }
+    }
+
+    let alias = IdentifierBuilder::new(IdentifierKind::Alias, &merged.name).normalize();
+    let alias_id = match alias {
+        Ok(alias)
+            if alias.value != kept.name
+                && !kept_identifiers
+                    .iter()
+                    .chain(&dropped_identifiers)
+                    .any(|k| k.kind == IdentifierKind::Alias && k.value == alias.value) =>
+        {
+            let id: u32 = sqlx::query_scalar(&format!(
+                "insert into identifiers (owner, person_id, kind, value, platform, created_at)
+                 values (?, ?, ?, ?, '', {NOW}) returning id"
+            ))
+            .bind(owner)
+            .bind(keep)
+            .bind(IdentifierKind::Alias.as_str())
+            .bind(&alias.value)
+            .fetch_one(&mut *tx)
+            .await?;
+            Some(id)
+        }
+        _ => None,
+    };
+
+    sqlx::query("delete from people where id = ?")
+        .bind(merge)
+        .execute(&mut *tx)
+        .await?;
+    let recorded: Merge = sqlx::query_as(&format!(
+        "insert into merges (owner, kept_id, merged, moved_identifiers, moved_results,
+                             dropped_identifiers, moved_relationships, dropped_relationships,
+                             alias_id, created_at)
+         values (?, ?, ?, ?, ?, ?, ?, ?, ?, {NOW}) returning *"
+    ))
+    .bind(owner)
+    .bind(keep)
+    .bind(Json(&merged))
+    .bind(Json(&moved_identifiers))
+    .bind(Json(&moved_results))
+    .bind(Json(&dropped_identifiers))
+    .bind(Json(&moved_relationships))
+    .bind(Json(&dropped_relationships))
+    .bind(alias_id)
+    .fetch_one(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(recorded)
+}
+
+/// Bring back the merged person of a merge with what it had. Its id is kept unless another
+/// person got it since. Returns the restored person.
+#[instrument(skip(db))]
+pub async fn undo_merge(db: &SqlitePool, owner: &str, id: u32) -> Result<Person, MergeError> {
+    let mut tx = db.begin().await?;
+    let merge: Merge = sqlx::query_as("select * from merges where id = ? and owner = ?")
+        .bind(id)
+        .bind(owner)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| MergeError::NotFound {
+            id,
+            owner: owner.to_string(),
+        })?;
+    if merge.undone_at.is_some() {
+        return Err(MergeError::AlreadyUndone(id));
+    }
+
+    let merged = &merge.merged.0;
+    let taken: Option<u32> = sqlx::query_scalar("select id from people where id = ?")
+        .bind(merged.id)
+        .fetch_optional(&mut *tx)
+        .await?;
+    let restored: Person =
+        sqlx::query_as("insert into people (id, owner, name) values (?, ?, ?) returning *")
+            .bind(taken.is_none().then_some(merged.id))
+            .bind(owner)
+            .bind(&merged.name)
+            .fetch_one(&mut *tx)
+            .await?;
+
+    // what was deleted or moved away from the kept person since stays where it is
+    for (table, ids) in [
+        ("identifiers", &merge.moved_identifiers),
+        ("scrape_results", &merge.moved_results),
+    ] {
+        sqlx::query(&format!(
+            "update {table} set person_id = ?
+             where person_id = ? and id in (select value from json_each(?))"
+        ))
+        .bind(restored.id)
+        .bind(merge.kept_id)
+        .bind(ids)
+        .execute(&mut *tx)
+        .await?;
+    }
+    for identifier in merge.dropped_identifiers.iter() {
+        sqlx::query(
+            "insert or ignore into identifiers
+             (owner, person_id, kind, value, platform, created_at) values (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(owner)
+        .bind(restored.id)
+        .bind(identifier.kind.as_str())
+        .bind(&identifier.value)
+        .bind(&identifier.platform)
+        .bind(&identifier.created_at)
+        .execute(&mut *tx)
+        .await?;
+    }
+    let restore = |id| if id == merged.id { restored.id } else { id };
+    for relationship in merge.moved_relationships.iter() {
+        // only the end that was the merged person moves back
+        let (column, other, other_id) = if relationship.from_id == merged.id {
+            ("from_id", "to_id", relationship.to_id)
+        } else {
+            ("to_id", "from_id", relationship.from_id)
+        };
+        sqlx::query(&format!(
+            "update or ignore relationships set {column} = ?
+             where id = ? and {column} = ? and {other} = ?"
+        ))
+        .bind(restored.id)
+        .bind(relationship.id)
+        .bind(merge.kept_id)
+        .bind(other_id)
+        .execute(&mut *tx)
+        .await?;
+    }
+    for relationship in merge.dropped_relationships.iter() {
+        // unless the other person was deleted since
+        sqlx::query(
+            "insert or ignore into relationships
+             (owner, from_id, to_id, kind, confidence, source, notes, created_at)
+             select ?, ?, ?, ?, ?, ?, ?, ?
+             where (select count(*) from people where id in (?, ?)) = 2",
+        )
+        .bind(owner)
+        .bind(restore(relationship.from_id))
+        .bind(restore(relationship.to_id))
+        .bind(relationship.kind.as_str())
+        .bind(relationship.confidence)
+        .bind(&relationship.source)
+        .bind(&relationship.notes)
+        .bind(&relationship.created_at)
+        .bind(restore(relationship.from_id))
+        .bind(restore(relationship.to_id))
+        .execute(&mut *tx)
+        .await?;
+    }
+    if let Some(alias_id) = merge.alias_id {
+        sqlx::query("delete from identifiers where id = ? and person_id = ?")
+            .bind(alias_id)
+            .bind(merge.kept_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    sqlx::query(&format!("update merges set undone_at = {NOW} where id = ?"))
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(restored)
+}
+
+/// The merges of `owner`, the newest first, undone ones included.
+pub async fn list_merges(db: &SqlitePool, owner: &str) -> Result<Vec<Merge>, MergeError> {
+    Ok(
+        sqlx::query_as("select * from merges where owner = ? order by id desc")
+            .bind(owner)
+            .fetch_all(db)
+            .await?,
+    )
+}
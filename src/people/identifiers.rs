@@ -0,0 +1,427 @@
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, SqlitePool};
+use std::fmt;
+use thiserror::Error;
+use time::{Date, Month};
+use tracing::instrument;
+use utoipa::ToSchema;
+
+use crate::NOW;
+
+/// Longest value accepted for any kind, after normalizing.
+pub const MAX_VALUE_LEN: usize = 512;
+
+/// What an identifier of a person is. Stored as its snake case name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum IdentifierKind {
+    Email,
+    Phone,
+    /// A username on the platform of the identifier.
+    Username,
+    Url,
+    /// A physical address.
+    Address,
+    /// A date of birth, `YYYY-MM-DD`.
+    Birthdate,
+    /// Another name the person is known by.
+    Alias,
+    /// A crypto wallet address. The platform is the chain, `bitcoin` or `ethereum`.
+    Wallet,
+}
+
+impl IdentifierKind {
+    pub const ALL: [IdentifierKind; 8] = [
+        Self::Email,
+        Self::Phone,
+        Self::Username,
+        Self::Url,
+        Self::Address,
+        Self::Birthdate,
+        Self::Alias,
+        Self::Wallet,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Email => "email",
+            Self::Phone => "phone",
+            Self::Username => "username",
+            Self::Url => "url",
+            Self::Address => "address",
+            Self::Birthdate => "birthdate",
+            Self::Alias => "alias",
+            Self::Wallet => "wallet",
+        }
+    }
+}
+
+impl TryFrom<String> for IdentifierKind {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == s)
+            .ok_or_else(|| format!("unknown identifier kind {s:?}"))
+    }
+}
+
+impl fmt::Display for IdentifierKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// An identifier attached to a person.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct Identifier {
+    #[schema(example = 1u32)]
+    pub id: u32,
+
+    #[schema(example = "seekr")]
+    pub owner: String,
+
+    #[schema(example = 4u32)]
+    pub person_id: u32,
+
+    #[sqlx(try_from = "String")]
+    pub kind: IdentifierKind,
+
+    /// The normalized value, see [`IdentifierBuilder::normalize`].
+    #[schema(example = "greg")]
+    pub value: String,
+
+    /// The platform of a username or the chain of a wallet, empty for other kinds.
+    #[schema(example = "github")]
+    pub platform: String,
+
+    #[schema(example = "2024-01-16T12:00:00Z")]
+    pub created_at: String,
+}
+
+/// Used in requests adding an identifier to a person.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct IdentifierBuilder {
+    pub kind: IdentifierKind,
+
+    #[schema(example = "@Greg")]
+    pub value: String,
+
+    /// Required for usernames, detected for wallets and not allowed for other kinds.
+    #[serde(default)]
+    #[schema(example = "GitHub")]
+    pub platform: String,
+}
+
+#[derive(Debug, Error)]
+pub enum IdentifierError {
+    #[error("invalid {kind}: {reason}")]
+    Invalid {
+        kind: IdentifierKind,
+        reason: String,
+    },
+
+    #[error("person {person_id} already has this {kind}")]
+    Duplicate {
+        person_id: u32,
+        kind: IdentifierKind,
+    },
+
+    #[error("identifier not found. ID: {id:?} owner: {owner:?}")]
+    NotFound { id: u32, owner: String },
+
+    #[error("person not found. ID: {id:?} owner: {owner:?}")]
+    PersonNotFound { id: u32, owner: String },
+
+    #[error("sqlx error")]
+    Sqlx(#[from] sqlx::Error),
+
+    #[error("not authenticated")]
+    Auth,
+}
+
+/// Trim and replace every run of whitespace by a single space.
+fn collapse_whitespace(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn email(value: &str) -> Result<String, &'static str> {
+    let (local, domain) = value.rsplit_once('@').ok_or("missing @")?;
+    if local.is_empty() || local.contains(char::is_whitespace) || local.contains('@') {
+        return Err("invalid local part");
+    }
+    let labels: Vec<&str> = domain.split('.').collect();
+    let label_ok = |l: &&str| {
+        !l.is_empty()
+            && !l.starts_with('-')
+            && !l.ends_with('-')
+            && l.chars().all(|c| c.is_alphanumeric() || c == '-')
+    };
+    if labels.len() < 2 || !labels.iter().all(label_ok) {
+        return Err("invalid domain");
+    }
+    // the local part may be case sensitive, the domain is not
+    Ok(format!("{local}@{}", domain.to_lowercase()))
+}
+
+/// Digits with a leading `+` if the number has a country code. `00` is the same as `+`.
+fn phone(value: &str) -> Result<String, &'static str> {
+    let (plus, rest) = match value.strip_prefix('+') {
+        Some(rest) => (true, rest),
+        None => match value.strip_prefix("00") {
+            Some(rest) => (true, rest),
+            None => (false, value),
+        },
+    };
+    let mut digits = String::new();
+    for c in rest.chars() {
+        match c {
+            '0'..='9' => digits.push(c),
+            ' ' | '-' | '.' | '(' | ')' | '/' => (),
+            _ => return Err("only digits, spaces, dashes, dots, slashes and parentheses"),
+        }
+    }
+    // E.164 numbers have at most 15 digits
+    if !(4..=15).contains(&digits.len()) {
+        return Err("expected 4 to 15 digits");
+    }
+    Ok(if plus { format!("+{digits}") } else { digits })
+}
+
+fn username(value: &str) -> Result<String, &'static str> {
+    let name = value.strip_prefix('@').unwrap_or(value);
+    if name.is_empty() {
+        return Err("empty");
+    }
+    if name.contains(char::is_whitespace) {
+        return Err("contains whitespace");
+    }
+    Ok(name.to_string())
+}
+
+fn url(value: &str) -> Result<String, String> {
+    let url = Url::parse(value).map_err(|e| e.to_string())?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err("only http and https".to_string());
+    }
+    if url.host_str().is_none() {
+        return Err("missing host".to_string());
+    }
+    Ok(url.to_string())
+}
+
+fn birthdate(value: &str) -> Result<String, &'static str> {
+    const FORMAT: &str = "expected YYYY-MM-DD";
+    let mut parts = value.splitn(3, '-');
+    let mut part = |len: usize| {
+        parts
+            .next()
+            .filter(|p| p.len() == len && p.chars().all(|c| c.is_ascii_digit()))
+            .and_then(|p| p.parse::<u16>().ok())
+            .ok_or(FORMAT)
+    };
+    let (year, month, day) = (part(4)?, part(2)?, part(2)?);
+    let month = Month::try_from(month as u8).map_err(|_| "invalid month")?;
+    let date =
+        Date::from_calendar_date(year.into(), month, day as u8).map_err(|_| "invalid day")?;
+    if date > time::OffsetDateTime::now_utc().date() {
+        return Err("in the future");
+    }
+    Ok(format!(
+        "{:04}-{:02}-{:02}",
+        date.year(),
+        date.month() as u8,
+        date.day()
+    ))
+}
+
+/// The chain of a wallet address and the address. Bitcoin bech32 and ethereum addresses are
+/// case insensitive and lowercased, base58 bitcoin addresses are kept as they are.
+fn wallet(value: &str) -> Result<(String, &'static str), &'static str> {
+    const BASE58: &str = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+    const BECH32: &str = "qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+    let lower = value.to_lowercase();
+    if let Some(hex) = lower.strip_prefix("0x") {
+        if hex.len() == 40 && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Ok((lower, "ethereum"));
+        }
+        return Err("ethereum addresses are 0x and 40 hex digits");
+    }
+    if let Some(data) = lower.strip_prefix("bc1") {
+        // mixed case is not allowed in bech32
+        if value != lower && value != value.to_uppercase() {
+            return Err("mixed case bech32 address");
+        }
+        if (39..=59).contains(&data.len()) && data.chars().all(|c| BECH32.contains(c)) {
+            return Ok((lower, "bitcoin"));
+        }
+        return Err("invalid bech32 bitcoin address");
+    }
+    if (value.starts_with('1') || value.starts_with('3'))
+        && (26..=35).contains(&value.len())
+        && value.chars().all(|c| BASE58.contains(c))
+    {
+        return Ok((value.to_string(), "bitcoin"));
+    }
+    Err("not a bitcoin or ethereum address")
+}
+
+impl IdentifierBuilder {
+    pub fn new(kind: IdentifierKind, value: &str) -> Self {
+        Self {
+            kind,
+            value: value.to_string(),
+            platform: String::new(),
+        }
+    }
+
+    pub fn platform(mut self, platform: &str) -> Self {
+        self.platform = platform.to_string();
+        self
+    }
+
+    /// Validate the value for its kind and bring it to the form it is stored in, so equal
+    /// identifiers compare equal:
+    ///
+    /// - emails: the domain is lowercased
+    /// - phone numbers: only digits, and `+` for a country code
+    /// - usernames: without a leading `@`, the platform lowercased
+    /// - urls: http(s) only, as the `url` crate prints them
+    /// - addresses and aliases: whitespace collapsed
+    /// - birthdates: `YYYY-MM-DD`, not in the future
+    /// - wallets: the platform is the chain, detected from the address
+    ///
+    /// ```
+    /// use seekr::people::{IdentifierBuilder, IdentifierKind};
+    ///
+    /// let phone = IdentifierBuilder::new(IdentifierKind::Phone, "0049 (30) 123-456");
+    /// assert_eq!(phone.normalize().unwrap().value, "+4930123456");
+    /// ```
+    pub fn normalize(self) -> Result<Self, IdentifierError> {
+        let kind = self.kind;
+        let invalid = |reason: &str| IdentifierError::Invalid {
+            kind,
+            reason: reason.to_string(),
+        };
+        let value = collapse_whitespace(&self.value);
+        if value.is_empty() {
+            return Err(invalid("empty"));
+        }
+        let mut platform = self.platform.trim().to_lowercase();
+        match (kind, platform.is_empty()) {
+            (IdentifierKind::Username, true) => return Err(invalid("usernames need a platform")),
+            // a wallet may name its chain, it is checked against the address
+            (IdentifierKind::Username | IdentifierKind::Wallet, _) | (_, true) => (),
+            _ => return Err(invalid("only usernames and wallets have a platform")),
+        }
+        let value = match kind {
+            IdentifierKind::Email => email(&value).map_err(invalid)?,
+            IdentifierKind::Phone => phone(&value).map_err(invalid)?,
+            IdentifierKind::Username => username(&value).map_err(invalid)?,
+            IdentifierKind::Url => url(&value).map_err(|e| invalid(&e))?,
+            IdentifierKind::Address | IdentifierKind::Alias => value,
+            IdentifierKind::Birthdate => birthdate(&value).map_err(invalid)?,
+            IdentifierKind::Wallet => {
+                let (address, chain) = wallet(&value).map_err(invalid)?;
+                if !platform.is_empty() && platform != chain {
+                    return Err(invalid(&format!("a {chain} address, not {platform}")));
+                }
+                platform = chain.to_string();
+                address
+            }
+        };
+        if value.len() > MAX_VALUE_LEN {
+            return Err(invalid("too long"));
+        }
+        Ok(Self {
+            kind,
+            value,
+            platform,
+        })
+    }
+}
+
+async fn check_person(db: &SqlitePool, owner: &str, person_id: u32) -> Result<(), IdentifierError> {
+    sqlx::query("select 1 from people where id = ? and owner = ?")
+        .bind(person_id)
+        .bind(owner)
+        .fetch_optional(db)
+        .await?
+        .map(|_| ())
+        .ok_or_else(|| IdentifierError::PersonNotFound {
+            id: person_id,
+            owner: owner.to_string(),
+        })
+}
+
+/// Normalize an identifier and attach it to a person of `owner`.
+#[instrument(skip(db))]
+pub async fn add_identifier(
+    db: &SqlitePool,
+    owner: &str,
+    person_id: u32,
+    identifier: IdentifierBuilder,
+) -> Result<Identifier, IdentifierError> {
+    let identifier = identifier.normalize()?;
+    check_person(db, owner, person_id).await?;
+    sqlx::query_as(&format!(
+        "insert into identifiers (owner, person_id, kind, value, platform, created_at)
+         values (?, ?, ?, ?, ?, {NOW}) returning *"
+    ))
+    .bind(owner)
+    .bind(person_id)
+    .bind(identifier.kind.as_str())
+    .bind(&identifier.value)
+    .bind(&identifier.platform)
+    .fetch_one(db)
+    .await
+    .map_err(|e| match &e {
+        sqlx::Error::Database(db) if db.is_unique_violation() => IdentifierError::Duplicate {
+            person_id,
+            kind: identifier.kind,
+        },
+        _ => e.into(),
+    })
+}
+
+/// The identifiers of a person, by kind and platform.
+pub async fn list_identifiers(
+    db: &SqlitePool,
+    owner: &str,
+    person_id: u32,
+) -> Result<Vec<Identifier>, IdentifierError> {
+    check_person(db, owner, person_id).await?;
+    Ok(sqlx::query_as(
+        "select * from identifiers where person_id = ? and owner = ?
+         order by kind, platform, value",
+    )
+    .bind(person_id)
+    .bind(owner)
+    .fetch_all(db)
+    .await?)
+}
+
+#[instrument(skip(db))]
+pub async fn remove_identifier(
+    db: &SqlitePool,
+    owner: &str,
+    person_id: u32,
+    id: u32,
+) -> Result<(), IdentifierError> {
+    let deleted =
+        sqlx::query("delete from identifiers where id = ? and person_id = ? and owner = ?")
+            .bind(id)
+            .bind(person_id)
+            .bind(owner)
+            .execute(db)
+            .await?;
+    if deleted.rows_affected() == 0 {
+        return Err(IdentifierError::NotFound {
+            id,
+            owner: owner.to_string(),
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,616 @@
+#![cfg(test)]
+use super::*;
+use crate::test_db;
+use std::collections::HashMap;
+use IdentifierKind::*;
+
+fn normalized(
+    kind: IdentifierKind,
+    value: &str,
+    platform: &str,
+) -> Result<(String, String), String> {
+    IdentifierBuilder::new(kind, value)
+        .platform(platform)
+        .normalize()
+        .map(|i| (i.value, i.platform))
+        .map_err(|e| e.to_string())
+}
+
+#[test]
+fn test_identifiers_normalize() {
+    let ok = |value: &str| Ok((value.to_string(), String::new()));
+    let on = |value: &str, platform: &str| Ok((value.to_string(), platform.to_string()));
+
+    assert_eq!(
+        normalized(Email, " Greg@Example.COM ", ""),
+        ok("Greg@example.com")
+    );
+    assert_eq!(
+        normalized(Email, "a+b@münchen.de", ""),
+        ok("a+b@münchen.de")
+    );
+    for invalid in [
+        "greg",
+        "@example.com",
+        "greg@localhost",
+        "greg@-x.com",
+        "g reg@x.com",
+    ] {
+        assert!(normalized(Email, invalid, "").is_err(), "{}", invalid);
+    }
+
+    assert_eq!(normalized(Phone, "+49 30 123-456", ""), ok("+4930123456"));
+    assert_eq!(
+        normalized(Phone, "0049 (30) 123.456", ""),
+        ok("+4930123456")
+    );
+    assert_eq!(normalized(Phone, "030/123456", ""), ok("030123456"));
+    assert!(normalized(Phone, "12", "").is_err());
+    assert!(normalized(Phone, "+1 234 567 890 123 456", "").is_err());
+    assert!(normalized(Phone, "call me", "").is_err());
+
+    assert_eq!(
+        normalized(Username, "@Greg", " GitHub "),
+        on("Greg", "github")
+    );
+    assert_eq!(
+        normalized(Username, "greg", ""),
+        Err("invalid username: usernames need a platform".to_string())
+    );
+    assert!(normalized(Username, "gr eg", "github").is_err());
+    assert_eq!(
+        normalized(Email, "greg@example.com", "github"),
+        Err("invalid email: only usernames and wallets have a platform".to_string())
+    );
+
+    assert_eq!(
+        normalized(Url, "HTTPS://Example.com", ""),
+        ok("https://example.com/")
+    );
+    assert!(normalized(Url, "ftp://example.com", "").is_err());
+    assert!(normalized(Url, "example.com", "").is_err());
+
+    assert_eq!(
+        normalized(Address, "  221B Baker Street\n London ", ""),
+        ok("221B Baker Street London")
+    );
+    assert_eq!(normalized(Alias, "Greg  the Crab", ""), ok("Greg the Crab"));
+    assert_eq!(
+        normalized(Alias, "   ", ""),
+        Err("invalid alias: empty".to_string())
+    );
+    assert!(normalized(Alias, &"a".repeat(identifiers::MAX_VALUE_LEN + 1), "").is_err());
+
+    assert_eq!(normalized(Birthdate, "1990-02-28", ""), ok("1990-02-28"));
+    assert_eq!(normalized(Birthdate, "2000-02-29", ""), ok("2000-02-29"));
+    for invalid in [
+        "1990-02-30",
+        "1990-13-01",
+        "90-01-01",
+        "1990-1-1",
+        "3000-01-01",
+        "yesterday",
+    ] {
+        assert!(normalized(Birthdate, invalid, "").is_err(), "{}", invalid);
+    }
+
+    let eth = "0x52908400098527886E0F7030069857D2E4169EE7";
+    assert_eq!(
+        normalized(Wallet, eth, ""),
+        on(&eth.to_lowercase(), "ethereum")
+    );
+    assert_eq!(
+        normalized(
+            Wallet,
+            "BC1QW508D6QEJXTDG4Y5R3ZARVARY0C5XW7KV8F3T4",
+            "bitcoin"
+        ),
+        on("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4", "bitcoin")
+    );
+    assert_eq!(
+        normalized(Wallet, "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2", ""),
+        on("1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2", "bitcoin")
+    );
+    assert_eq!(
+        normalized(Wallet, eth, "bitcoin"),
+        Err("invalid wallet: a ethereum address, not bitcoin".to_string())
+    );
+    for invalid in [
+        "0x1234",
+        "bc1Qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4",
+        "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN0",
+    ] {
+        assert!(normalized(Wallet, invalid, "").is_err(), "{}", invalid);
+    }
+}
+
+#[tokio::test]
+async fn test_identifiers_crud() {
+    let db = test_db().await;
+    sqlx::query("insert into people (id, owner, name) values (4, 'ferris', 'greg')")
+        .execute(&db)
+        .await
+        .unwrap();
+
+    let email = add_identifier(
+        &db,
+        "ferris",
+        4,
+        IdentifierBuilder::new(Email, "greg@EXAMPLE.com"),
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        (email.kind, email.value.as_str()),
+        (Email, "greg@example.com")
+    );
+    assert_eq!((email.owner.as_str(), email.person_id), ("ferris", 4));
+
+    // stored normalized, so the same email written differently is a duplicate
+    assert!(matches!(
+        add_identifier(
+            &db,
+            "ferris",
+            4,
+            IdentifierBuilder::new(Email, "greg@example.COM")
+        )
+        .await,
+        Err(IdentifierError::Duplicate {
+            person_id: 4,
+            kind: Email
+        })
+    ));
+    // the same username on another platform is not
+    for platform in ["github", "gitlab"] {
+        add_identifier(
+            &db,
+            "ferris",
+            4,
+            IdentifierBuilder::new(Username, "greg").platform(platform),
+        )
+        .await
+        .unwrap();
+    }
+    assert!(matches!(
+        add_identifier(&db, "ferris", 4, IdentifierBuilder::new(Phone, "phone")).await,
+        Err(IdentifierError::Invalid { kind: Phone, .. })
+    ));
+
+    // people of other owners are not found
+    assert!(matches!(
+        add_identifier(&db, "crab", 4, IdentifierBuilder::new(Alias, "greg")).await,
+        Err(IdentifierError::PersonNotFound { id: 4, .. })
+    ));
+    assert!(matches!(
+        list_identifiers(&db, "crab", 4).await,
+        Err(IdentifierError::PersonNotFound { .. })
+    ));
+
+    let listed: Vec<(IdentifierKind, String)> = list_identifiers(&db, "ferris", 4)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|i| (i.kind, i.platform))
+        .collect();
+    assert_eq!(
+        listed,
+        [
+            (Email, String::new()),
+            (Username, "github".to_string()),
+            (Username, "gitlab".to_string())
+        ]
+    );
+
+    assert!(matches!(
+        remove_identifier(&db, "crab", 4, email.id).await,
+        Err(IdentifierError::NotFound { .. })
+    ));
+    remove_identifier(&db, "ferris", 4, email.id).await.unwrap();
+    assert!(matches!(
+        remove_identifier(&db, "ferris", 4, email.id).await,
+        Err(IdentifierError::NotFound { .. })
+    ));
+
+    // every kind is stored as its name and read back
+    for kind in IdentifierKind::ALL {
+        assert_eq!(IdentifierKind::try_from(kind.to_string()), Ok(kind));
+        assert_eq!(serde_json::to_value(kind).unwrap(), kind.as_str());
+    }
+    assert!(IdentifierKind::try_from("ssn".to_string()).is_err());
+
+    // identifiers go with their person
+    sqlx::query("delete from people where id = 4")
+        .execute(&db)
+        .await
+        .unwrap();
+    let (left,): (u32,) = sqlx::query_as("select count(*) from identifiers")
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(left, 0);
+}
+
+#[tokio::test]
+async fn test_people_crud() {
+    let db = test_db().await;
+    let builder = |name: &str| PersonBuilder {
+        name: name.to_string(),
+    };
+
+    let greg = insert_person(&db, "ferris", builder(" greg "))
+        .await
+        .unwrap();
+    assert_eq!(
+        (greg.name.as_str(), greg.owner.as_str()),
+        ("greg", "ferris")
+    );
+    assert_eq!(get_person(&db, "ferris", greg.id).await.unwrap(), greg);
+    assert!(matches!(
+        get_person(&db, "crab", greg.id).await,
+        Err(GetPersonError::NotFound { .. })
+    ));
+    for invalid in ["", "  ", &"x".repeat(MAX_NAME_LEN + 1)] {
+        assert!(matches!(
+            insert_person(&db, "ferris", builder(invalid)).await,
+            Err(InsertPersonError::Invalid(_))
+        ));
+    }
+
+    for name in ["ada", "grace", "linus", "ken"] {
+        insert_person(&db, "ferris", builder(name)).await.unwrap();
+    }
+    insert_person(&db, "crab", builder("bob")).await.unwrap();
+    let page = |page, per_page| Pagination { page, per_page };
+    let all = PeopleFilter::default();
+    let names =
+        |page: PeoplePage| -> Vec<String> { page.people.into_iter().map(|p| p.name).collect() };
+
+    let second = list_people(&db, "ferris", &all, page(2, 2)).await.unwrap();
+    assert_eq!((second.page, second.per_page, second.total), (2, 2, 5));
+    assert_eq!(names(second), ["grace", "linus"]);
+    assert_eq!(
+        names(list_people(&db, "ferris", &all, page(3, 2)).await.unwrap()),
+        ["ken"]
+    );
+    assert!(list_people(&db, "ferris", &all, page(4, 2))
+        .await
+        .unwrap()
+        .people
+        .is_empty());
+    // out of range values are clamped
+    let clamped = list_people(&db, "ferris", &all, page(0, 1000))
+        .await
+        .unwrap();
+    assert_eq!(
+        (clamped.page, clamped.per_page),
+        (1, Pagination::MAX_PER_PAGE)
+    );
+    assert_eq!(clamped.people.len(), 5);
+    let named = |name: &str| PeopleFilter {
+        name: Some(name.to_string()),
+    };
+    let filtered = list_people(&db, "ferris", &named("N"), page(1, 50))
+        .await
+        .unwrap();
+    assert_eq!(filtered.total, 2);
+    assert_eq!(names(filtered), ["linus", "ken"]);
+    assert!(
+        list_people(&db, "ferris", &named("bob"), Pagination::default())
+            .await
+            .unwrap()
+            .people
+            .is_empty()
+    );
+    assert_eq!(
+        serde_json::from_str::<Pagination>("{}").unwrap(),
+        Pagination::default()
+    );
+
+    // fields left out are kept
+    let same = update_person(&db, "ferris", greg.id, PersonPatch::default())
+        .await
+        .unwrap();
+    assert_eq!(same, greg);
+    let renamed = update_person(
+        &db,
+        "ferris",
+        greg.id,
+        PersonPatch {
+            name: Some("gregory".to_string()),
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(renamed.name, "gregory");
+    assert!(matches!(
+        update_person(
+            &db,
+            "ferris",
+            greg.id,
+            PersonPatch {
+                name: Some(String::new())
+            }
+        )
+        .await,
+        Err(InsertPersonError::Invalid(_))
+    ));
+    assert!(matches!(
+        update_person(&db, "crab", greg.id, PersonPatch::default()).await,
+        Err(InsertPersonError::Get(GetPersonError::NotFound { .. }))
+    ));
+
+    add_identifier(
+        &db,
+        "ferris",
+        greg.id,
+        IdentifierBuilder::new(Alias, "greg"),
+    )
+    .await
+    .unwrap();
+    assert!(matches!(
+        delete_person(&db, "crab", greg.id).await,
+        Err(GetPersonError::NotFound { .. })
+    ));
+    delete_person(&db, "ferris", greg.id).await.unwrap();
+    assert!(matches!(
+        get_person(&db, "ferris", greg.id).await,
+        Err(GetPersonError::NotFound { .. })
+    ));
+    let (identifiers,): (u32,) = sqlx::query_as("select count(*) from identifiers")
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(identifiers, 0);
+}
+
+#[test]
+fn test_name_tokens() {
+    assert_eq!(name_tokens("  Greg   SMITH "), ["greg", "smith"]);
+    assert_eq!(name_tokens("G. Smith-Jones"), ["g", "smith", "jones"]);
+    assert_eq!(name_tokens("Zoë Ångström"), ["zoe", "angstrom"]);
+    assert_eq!(name_tokens("Łukasz Øster"), ["lukasz", "oster"]);
+    assert_eq!(name_tokens("Григорий Смит"), ["grigoriy", "smit"]);
+    assert!(name_tokens(" .,- ").is_empty());
+}
+
+#[tokio::test]
+async fn test_find_duplicates() {
+    let db = test_db().await;
+    let mut ids = HashMap::new();
+    for name in [
+        "Greg Smith",
+        "greg  smith",
+        "Smith, Greg",
+        "G. Smith",
+        "Gregg Smith",
+        "Ada Lovelace",
+        "A. Lovelace",
+        "Grace Hopper",
+        "Amazing Grace",
+    ] {
+        let person = insert_person(
+            &db,
+            "ferris",
+            PersonBuilder {
+                name: name.to_string(),
+            },
+        )
+        .await
+        .unwrap();
+        ids.insert(name, person.id);
+    }
+    let pairs = |candidates: Vec<DuplicateCandidate>| -> Vec<(String, String, f64)> {
+        candidates
+            .into_iter()
+            .map(|c| {
+                (
+                    c.keep.name,
+                    c.duplicate.name,
+                    (c.score * 100.0).round() / 100.0,
+                )
+            })
+            .collect()
+    };
+    let found = find_duplicates(&db, "ferris", DuplicatesQuery { min_score: 0.84 })
+        .await
+        .unwrap();
+    assert_eq!(found[0].reasons, ["same name"]);
+    let found = pairs(found);
+    let pair = |a: &str, b: &str, score| (a.to_string(), b.to_string(), score);
+    for expected in [
+        pair("Greg Smith", "greg  smith", 1.0),
+        pair("Greg Smith", "Smith, Greg", 0.95),
+        pair("Greg Smith", "G. Smith", 0.85),
+        pair("Ada Lovelace", "A. Lovelace", 0.85),
+    ] {
+        assert!(found.contains(&expected), "{:?} in {:?}", expected, found);
+    }
+    assert!(found
+        .iter()
+        .all(|(a, b, _)| a != "Grace Hopper" && b != "Grace Hopper"));
+    // sorted by score
+    assert!(found.windows(2).all(|w| w[0].2 >= w[1].2));
+    // typos score lower
+    let typo = pairs(
+        find_duplicates(&db, "ferris", DuplicatesQuery { min_score: 0.5 })
+            .await
+            .unwrap(),
+    )
+    .into_iter()
+    .find(|(a, b, _)| a == "Greg Smith" && b == "Gregg Smith")
+    .unwrap();
+    assert!((0.8..0.9).contains(&typo.2), "{:?}", typo);
+
+    // shared identifiers count for people with different names
+    for name in ["Grace Hopper", "Amazing Grace"] {
+        add_identifier(
+            &db,
+            "ferris",
+            ids[name],
+            IdentifierBuilder::new(Username, "grace").platform("github"),
+        )
+        .await
+        .unwrap();
+    }
+    let grace = find_duplicates(&db, "ferris", DuplicatesQuery { min_score: 0.75 })
+        .await
+        .unwrap()
+        .into_iter()
+        .find(|c| c.keep.name == "Grace Hopper")
+        .unwrap();
+    assert_eq!(grace.duplicate.name, "Amazing Grace");
+    assert_eq!(
+        grace.reasons.last().unwrap(),
+        "both have username grace on github"
+    );
+    // and so do aliases
+    add_identifier(
+        &db,
+        "ferris",
+        ids["Ada Lovelace"],
+        IdentifierBuilder::new(Alias, "Grace Hopper"),
+    )
+    .await
+    .unwrap();
+    assert!(
+        find_duplicates(&db, "ferris", DuplicatesQuery { min_score: 0.99 })
+            .await
+            .unwrap()
+            .iter()
+            .any(|c| c.keep.name == "Ada Lovelace" && c.duplicate.name == "Grace Hopper")
+    );
+
+    // only the people of the owner are compared
+    assert!(find_duplicates(&db, "crab", DuplicatesQuery::default())
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn test_merge_people() {
+    let db = test_db().await;
+    let person = |name: &str| PersonBuilder {
+        name: name.to_string(),
+    };
+    let greg = insert_person(&db, "ferris", person("Greg Smith"))
+        .await
+        .unwrap();
+    let dup = insert_person(&db, "ferris", person("G. Smith"))
+        .await
+        .unwrap();
+    let email = |value: &str| IdentifierBuilder::new(Email, value);
+    add_identifier(&db, "ferris", greg.id, email("greg@example.com"))
+        .await
+        .unwrap();
+    let shared = add_identifier(&db, "ferris", dup.id, email("greg@example.com"))
+        .await
+        .unwrap();
+    let own = add_identifier(&db, "ferris", dup.id, email("g@smith.dev"))
+        .await
+        .unwrap();
+    sqlx::query(
+        "insert into scripts (id, owner, name, source, version, created_at)
+         values (1, 'ferris', 'bio', '1', 1, '')",
+    )
+    .execute(&db)
+    .await
+    .unwrap();
+    let (result,): (u32,) = sqlx::query_as(
+        "insert into scrape_results
+         (owner, person_id, script_id, script_version, output, limits, usage, created_at)
+         values ('ferris', ?, 1, 1, '\"smith\"', '{}', '{}', '') returning id",
+    )
+    .bind(dup.id)
+    .fetch_one(&db)
+    .await
+    .unwrap();
+
+    assert!(matches!(
+        merge_people(&db, "ferris", greg.id, greg.id).await,
+        Err(MergeError::SamePerson(_))
+    ));
+    assert!(matches!(
+        merge_people(&db, "crab", greg.id, dup.id).await,
+        Err(MergeError::Person(GetPersonError::NotFound { .. }))
+    ));
+
+    let merge = merge_people(&db, "ferris", greg.id, dup.id).await.unwrap();
+    assert_eq!(merge.kept_id, greg.id);
+    assert_eq!(merge.merged.0, dup);
+    assert_eq!(merge.moved_identifiers.0, [own.id]);
+    assert_eq!(merge.moved_results.0, [result]);
+    assert_eq!(merge.dropped_identifiers.0, std::slice::from_ref(&shared));
+    assert!(matches!(
+        get_person(&db, "ferris", dup.id).await,
+        Err(GetPersonError::NotFound { .. })
+    ));
+    let values = |identifiers: Vec<Identifier>| -> Vec<String> {
+        identifiers.into_iter().map(|i| i.value).collect()
+    };
+    assert_eq!(
+        values(list_identifiers(&db, "ferris", greg.id).await.unwrap()),
+        ["G. Smith", "g@smith.dev", "greg@example.com"]
+    );
+    let (moved,): (u32,) = sqlx::query_as("select person_id from scrape_results where id = ?")
+        .bind(result)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(moved, greg.id);
+    // the search follows the moved data
+    let hits = crate::search::search(&db, "ferris", &crate::search::SearchQuery::new("smith"))
+        .await
+        .unwrap();
+    assert!(
+        hits.iter().all(|hit| hit.person_id == greg.id),
+        "{:?}",
+        hits
+    );
+
+    assert_eq!(
+        list_merges(&db, "ferris").await.unwrap(),
+        std::slice::from_ref(&merge)
+    );
+    assert!(list_merges(&db, "crab").await.unwrap().is_empty());
+    assert!(matches!(
+        undo_merge(&db, "crab", merge.id).await,
+        Err(MergeError::NotFound { .. })
+    ));
+
+    let restored = undo_merge(&db, "ferris", merge.id).await.unwrap();
+    assert_eq!(restored, dup);
+    assert_eq!(
+        values(list_identifiers(&db, "ferris", greg.id).await.unwrap()),
+        ["greg@example.com"]
+    );
+    assert_eq!(
+        values(list_identifiers(&db, "ferris", dup.id).await.unwrap()),
+        ["g@smith.dev", "greg@example.com"]
+    );
+    let (back,): (u32,) = sqlx::query_as("select person_id from scrape_results where id = ?")
+        .bind(result)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(back, dup.id);
+    assert!(matches!(
+        undo_merge(&db, "ferris", merge.id).await,
+        Err(MergeError::AlreadyUndone(_))
+    ));
+    assert!(list_merges(&db, "ferris").await.unwrap()[0]
+        .undone_at
+        .is_some());
+
+    // a merged person whose id was taken since comes back with a new one
+    let merge = merge_people(&db, "ferris", greg.id, dup.id).await.unwrap();
+    let newer = insert_person(&db, "ferris", person("Ada")).await.unwrap();
+    assert_eq!(newer.id, dup.id);
+    let restored = undo_merge(&db, "ferris", merge.id).await.unwrap();
+    assert_ne!(restored.id, dup.id);
+    assert_eq!(restored.name, dup.name);
+    assert_eq!(
+        values(list_identifiers(&db, "ferris", restored.id).await.unwrap()),
+        ["g@smith.dev", "greg@example.com"]
+    );
+}
@@ -0,0 +1,540 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, SqlitePool};
+use std::{
+    collections::{hash_map::Entry, BTreeMap, HashMap, HashSet, VecDeque},
+    fmt,
+};
+use thiserror::Error;
+use tracing::instrument;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::people::{GetPersonError, Person};
+use crate::NOW;
+
+/// How two people are connected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RelationshipKind {
+    Family,
+    Partner,
+    Friend,
+    Colleague,
+    Associate,
+    /// The two are names of one person, `from` being the alias.
+    AliasOf,
+    /// The two use the same online account.
+    SameAccount,
+}
+
+impl RelationshipKind {
+    pub const ALL: [RelationshipKind; 7] = [
+        Self::Family,
+        Self::Partner,
+        Self::Friend,
+        Self::Colleague,
+        Self::Associate,
+        Self::AliasOf,
+        Self::SameAccount,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Family => "family",
+            Self::Partner => "partner",
+            Self::Friend => "friend",
+            Self::Colleague => "colleague",
+            Self::Associate => "associate",
+            Self::AliasOf => "alias_of",
+            Self::SameAccount => "same_account",
+        }
+    }
+}
+
+impl TryFrom<String> for RelationshipKind {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == s)
+            .ok_or_else(|| format!("unknown relationship kind {s:?}"))
+    }
+}
+
+impl fmt::Display for RelationshipKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A connection from one person to another. Graph queries follow it both ways.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct Relationship {
+    #[schema(example = 1u32)]
+    pub id: u32,
+
+    #[schema(example = "seekr")]
+    pub owner: String,
+
+    #[schema(example = 4u32)]
+    pub from_id: u32,
+
+    #[schema(example = "greg")]
+    pub from_name: String,
+
+    #[schema(example = 7u32)]
+    pub to_id: u32,
+
+    #[schema(example = "ada")]
+    pub to_name: String,
+
+    #[sqlx(try_from = "String")]
+    pub kind: RelationshipKind,
+
+    /// From 0 to 1.
+    #[schema(example = 0.8)]
+    pub confidence: f64,
+
+    /// Where the connection is known from.
+    #[schema(example = "https://example.com/team")]
+    pub source: String,
+
+    #[schema(example = "worked together 2019-2021")]
+    pub notes: String,
+
+    #[schema(example = "2024-01-22T12:00:00Z")]
+    pub created_at: String,
+}
+
+/// Used in requests creating or updating a relationship.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct RelationshipBuilder {
+    #[schema(example = 4u32)]
+    pub from_id: u32,
+
+    #[schema(example = 7u32)]
+    pub to_id: u32,
+
+    pub kind: RelationshipKind,
+
+    /// From 0 to 1, 1 if left out.
+    #[serde(default = "RelationshipBuilder::certain")]
+    #[schema(example = 0.8)]
+    pub confidence: f64,
+
+    #[serde(default)]
+    #[schema(example = "https://example.com/team")]
+    pub source: String,
+
+    #[serde(default)]
+    #[schema(example = "worked together 2019-2021")]
+    pub notes: String,
+}
+
+impl RelationshipBuilder {
+    pub fn new(from_id: u32, to_id: u32, kind: RelationshipKind) -> Self {
+        Self {
+            from_id,
+            to_id,
+            kind,
+            confidence: Self::certain(),
+            source: String::new(),
+            notes: String::new(),
+        }
+    }
+
+    fn certain() -> f64 {
+        1.0
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum RelationshipError {
+    #[error("invalid relationship: {0}")]
+    Invalid(String),
+
+    #[error("people {from_id} and {to_id} already have a {kind} relationship")]
+    Duplicate {
+        from_id: u32,
+        to_id: u32,
+        kind: RelationshipKind,
+    },
+
+    #[error("relationship not found. ID: {id:?} owner: {owner:?}")]
+    NotFound { id: u32, owner: String },
+
+    #[error("people {from_id} and {to_id} are not connected")]
+    NotConnected { from_id: u32, to_id: u32 },
+
+    #[error(transparent)]
+    Person(#[from] GetPersonError),
+
+    #[error("sqlx error")]
+    Sqlx(#[from] sqlx::Error),
+
+    #[error("not authenticated")]
+    Auth,
+}
+
+const SELECT_RELATIONSHIPS: &str =
+    "select r.*, f.name as from_name, t.name as to_name from relationships r
+     join people f on f.id = r.from_id
+     join people t on t.id = r.to_id";
+
+/// Check a relationship and that both people belong to `owner`.
+async fn validate(
+    db: &SqlitePool,
+    owner: &str,
+    relationship: &RelationshipBuilder,
+) -> Result<(), RelationshipError> {
+    if relationship.from_id == relationship.to_id {
+        return Err(RelationshipError::Invalid(
+            "a person can not be related to itself".to_string(),
+        ));
+    }
+    if !(0.0..=1.0).contains(&relationship.confidence) {
+        return Err(RelationshipError::Invalid(
+            "confidence must be from 0 to 1".to_string(),
+        ));
+    }
+    for id in [relationship.from_id, relationship.to_id] {
+        crate::people::get_person(db, owner, id).await?;
+    }
+    Ok(())
+}
+
+fn duplicate(e: sqlx::Error, relationship: &RelationshipBuilder) -> RelationshipError {
+    match &e {
+        sqlx::Error::Database(db) if db.is_unique_violation() => RelationshipError::Duplicate {
+            from_id: relationship.from_id,
+            to_id: relationship.to_id,
+            kind: relationship.kind,
+        },
+        _ => e.into(),
+    }
+}
+
+#[instrument(skip(db))]
+pub async fn add_relationship(
+    db: &SqlitePool,
+    owner: &str,
+    relationship: RelationshipBuilder,
+) -> Result<Relationship, RelationshipError> {
+    validate(db, owner, &relationship).await?;
+    let (id,): (u32,) = sqlx::query_as(&format!(
+        "insert into relationships
+         (owner, from_id, to_id, kind, confidence, source, notes, created_at)
+         values (?, ?, ?, ?, ?, ?, ?, {NOW}) returning id"
+    ))
+    .bind(owner)
+    .bind(relationship.from_id)
+    .bind(relationship.to_id)
+    .bind(relationship.kind.as_str())
+    .bind(relationship.confidence)
+    .bind(relationship.source.trim())
+    .bind(relationship.notes.trim())
+    .fetch_one(db)
+    .await
+    .map_err(|e| duplicate(e, &relationship))?;
+    get_relationship(db, owner, id).await
+}
+
+pub async fn get_relationship(
+    db: &SqlitePool,
+    owner: &str,
+    id: u32,
+) -> Result<Relationship, RelationshipError> {
+    sqlx::query_as(&format!(
+        "{SELECT_RELATIONSHIPS} where r.id = ? and r.owner = ?"
+    ))
+    .bind(id)
+    .bind(owner)
+    .fetch_optional(db)
+    .await?
+    .ok_or_else(|| RelationshipError::NotFound {
+        id,
+        owner: owner.to_string(),
+    })
+}
+
+/// The relationships of a person, both from and to it.
+pub async fn list_relationships(
+    db: &SqlitePool,
+    owner: &str,
+    person_id: u32,
+) -> Result<Vec<Relationship>, RelationshipError> {
+    crate::people::get_person(db, owner, person_id).await?;
+    Ok(sqlx::query_as(&format!(
+        "{SELECT_RELATIONSHIPS} where (r.from_id = ? or r.to_id = ?) and r.owner = ?
+         order by r.kind, r.id"
+    ))
+    .bind(person_id)
+    .bind(person_id)
+    .bind(owner)
+    .fetch_all(db)
+    .await?)
+}
+
+#[instrument(skip(db))]
+pub async fn update_relationship(
+    db: &SqlitePool,
+    owner: &str,
+    id: u32,
+    relationship: RelationshipBuilder,
+) -> Result<Relationship, RelationshipError> {
+    get_relationship(db, owner, id).await?;
+    validate(db, owner, &relationship).await?;
+    sqlx::query(
+        "update relationships
+         set from_id = ?, to_id = ?, kind = ?, confidence = ?, source = ?, notes = ?
+         where id = ? and owner = ?",
+    )
+    .bind(relationship.from_id)
+    .bind(relationship.to_id)
+    .bind(relationship.kind.as_str())
+    .bind(relationship.confidence)
+    .bind(relationship.source.trim())
+    .bind(relationship.notes.trim())
+    .bind(id)
+    .bind(owner)
+    .execute(db)
+    .await
+    .map_err(|e| duplicate(e, &relationship))?;
+    get_relationship(db, owner, id).await
+}
+
+#[instrument(skip(db))]
+pub async fn delete_relationship(
+    db: &SqlitePool,
+    owner: &str,
+    id: u32,
+) -> Result<(), RelationshipError> {
+    let deleted = sqlx::query("delete from relationships where id = ? and owner = ?")
+        .bind(id)
+        .bind(owner)
+        .execute(db)
+        .await?;
+    if deleted.rows_affected() == 0 {
+        return Err(RelationshipError::NotFound {
+            id,
+            owner: owner.to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Which relationships graph queries follow.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct GraphQuery {
+    /// How far neighbours go from the person, at most [`GraphQuery::MAX_HOPS`].
+    #[serde(default = "GraphQuery::default_hops")]
+    #[param(example = 2, minimum = 1, maximum = 6)]
+    pub hops: u32,
+
+    /// Only follow relationships at least this sure.
+    #[serde(default)]
+    #[param(example = 0.5, minimum = 0, maximum = 1)]
+    pub min_confidence: f64,
+}
+
+impl GraphQuery {
+    pub const MAX_HOPS: u32 = 6;
+
+    fn default_hops() -> u32 {
+        1
+    }
+}
+
+impl Default for GraphQuery {
+    fn default() -> Self {
+        Self {
+            hops: Self::default_hops(),
+            min_confidence: 0.0,
+        }
+    }
+}
+
+/// A person reached from another in a number of hops.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Neighbour {
+    pub person: Person,
+
+    #[schema(example = 1u32)]
+    pub distance: u32,
+
+    /// The relationship it was first reached by.
+    #[schema(example = 3u32)]
+    pub via: u32,
+}
+
+/// A way from one person to another, the relationships in order. Empty from a person to itself.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct RelationshipPath {
+    /// The people along the path, both ends included.
+    pub people: Vec<Person>,
+
+    pub relationships: Vec<Relationship>,
+}
+
+/// The people of `owner` and their relationships, without direction.
+pub struct Graph {
+    people: BTreeMap<u32, Person>,
+    relationships: HashMap<u32, Relationship>,
+    /// Person to (relationship, other person).
+    edges: HashMap<u32, Vec<(u32, u32)>>,
+}
+
+impl Graph {
+    /// Load the people of `owner` and the relationships at least `min_confidence` sure.
+    pub async fn load(
+        db: &SqlitePool,
+        owner: &str,
+        min_confidence: f64,
+    ) -> Result<Self, RelationshipError> {
+        let people: Vec<Person> = sqlx::query_as("select * from people where owner = ?")
+            .bind(owner)
+            .fetch_all(db)
+            .await?;
+        let relationships: Vec<Relationship> = sqlx::query_as(&format!(
+            "{SELECT_RELATIONSHIPS} where r.owner = ? and r.confidence >= ? order by r.id"
+        ))
+        .bind(owner)
+        .bind(min_confidence)
+        .fetch_all(db)
+        .await?;
+        let mut edges: HashMap<u32, Vec<(u32, u32)>> = HashMap::new();
+        for r in &relationships {
+            edges.entry(r.from_id).or_default().push((r.id, r.to_id));
+            edges.entry(r.to_id).or_default().push((r.id, r.from_id));
+        }
+        Ok(Self {
+            people: people.into_iter().map(|p| (p.id, p)).collect(),
+            relationships: relationships.into_iter().map(|r| (r.id, r)).collect(),
+            edges,
+        })
+    }
+
+    /// The people by id.
+    pub fn people(&self) -> impl Iterator<Item = &Person> {
+        self.people.values()
+    }
+
+    /// The relationships, in no order.
+    pub fn relationships(&self) -> impl Iterator<Item = &Relationship> {
+        self.relationships.values()
+    }
+
+    fn person(&self, owner: &str, id: u32) -> Result<&Person, RelationshipError> {
+        self.people.get(&id).ok_or_else(|| {
+            GetPersonError::NotFound {
+                id,
+                owner: owner.to_string(),
+            }
+            .into()
+        })
+    }
+
+    /// Breadth first from `start` up to `hops` away, the relationship each person was reached by.
+    fn search(&self, start: u32, hops: u32) -> HashMap<u32, (u32, Option<u32>)> {
+        // person to (distance, relationship)
+        let mut reached = HashMap::from([(start, (0, None))]);
+        let mut queue = VecDeque::from([start]);
+        while let Some(id) = queue.pop_front() {
+            let distance = reached[&id].0;
+            if distance == hops {
+                continue;
+            }
+            for &(relationship, other) in self.edges.get(&id).into_iter().flatten() {
+                if let Entry::Vacant(entry) = reached.entry(other) {
+                    entry.insert((distance + 1, Some(relationship)));
+                    queue.push_back(other);
+                }
+            }
+        }
+        reached
+    }
+
+    /// The people up to `hops` relationships away from a person, the nearest first.
+    pub fn neighbours(
+        &self,
+        owner: &str,
+        id: u32,
+        hops: u32,
+    ) -> Result<Vec<Neighbour>, RelationshipError> {
+        self.person(owner, id)?;
+        let mut neighbours: Vec<Neighbour> = self
+            .search(id, hops.clamp(1, GraphQuery::MAX_HOPS))
+            .into_iter()
+            .filter_map(|(other, (distance, via))| {
+                Some(Neighbour {
+                    person: self.people[&other].clone(),
+                    distance,
+                    via: via?,
+                })
+            })
+            .collect();
+        neighbours.sort_by_key(|n| (n.distance, n.person.id));
+        Ok(neighbours)
+    }
+
+    /// A path with the fewest relationships between two people, `None` if they are not
+    /// connected.
+    pub fn shortest_path(
+        &self,
+        owner: &str,
+        from: u32,
+        to: u32,
+    ) -> Result<Option<RelationshipPath>, RelationshipError> {
+        self.person(owner, from)?;
+        self.person(owner, to)?;
+        let reached = self.search(from, u32::MAX);
+        if !reached.contains_key(&to) {
+            return Ok(None);
+        }
+        let (mut people, mut relationships) = (vec![self.people[&to].clone()], vec![]);
+        let mut id = to;
+        while let (_, Some(via)) = reached[&id] {
+            let relationship = &self.relationships[&via];
+            id = if relationship.to_id == id {
+                relationship.from_id
+            } else {
+                relationship.to_id
+            };
+            relationships.push(relationship.clone());
+            people.push(self.people[&id].clone());
+        }
+        people.reverse();
+        relationships.reverse();
+        Ok(Some(RelationshipPath {
+            people,
+            relationships,
+        }))
+    }
+
+    /// Groups of people connected to each other, the largest first. People without
+    /// relationships are a group of their own.
+    pub fn components(&self) -> Vec<Vec<Person>> {
+        let mut seen = HashSet::new();
+        let mut components = vec![];
+        for &id in self.people.keys() {
+            if seen.contains(&id) {
+                continue;
+            }
+            let mut component: Vec<Person> = self
+                .search(id, u32::MAX)
+                .into_keys()
+                .map(|other| {
+                    seen.insert(other);
+                    self.people[&other].clone()
+                })
+                .collect();
+            component.sort_by_key(|p| p.id);
+            components.push(component);
+        }
+        components.sort_by_key(|c| std::cmp::Reverse(c.len()));
+        components
+    }
+}
+
+mod test;
@@ -0,0 +1,319 @@
+#![cfg(test)]
+use super::*;
+use crate::people::{insert_person, merge_people, undo_merge, PersonBuilder};
+use crate::test_db;
+use RelationshipKind::*;
+
+async fn people(db: &SqlitePool, owner: &str, names: &[&str]) -> Vec<Person> {
+    let mut people = vec![];
+    for name in names {
+        let builder = PersonBuilder {
+            name: name.to_string(),
+        };
+        people.push(insert_person(db, owner, builder).await.unwrap());
+    }
+    people
+}
+
+#[test]
+fn test_relationship_kinds() {
+    for kind in RelationshipKind::ALL {
+        assert_eq!(RelationshipKind::try_from(kind.to_string()), Ok(kind));
+        assert_eq!(
+            serde_json::to_string(&kind).unwrap(),
+            format!("\"{}\"", kind)
+        );
+    }
+    assert!(RelationshipKind::try_from("enemy".to_string()).is_err());
+}
+
+#[tokio::test]
+async fn test_relationships() {
+    let db = test_db().await;
+    let [greg, ada, bob] = &people(&db, "ferris", &["greg", "ada", "bob"]).await[..] else {
+        unreachable!()
+    };
+    let other = &people(&db, "crab", &["crab"]).await[0];
+
+    let mut builder = RelationshipBuilder::new(greg.id, ada.id, Colleague);
+    builder.confidence = 0.8;
+    builder.source = " https://example.com/team ".to_string();
+    let colleagues = add_relationship(&db, "ferris", builder.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        (
+            colleagues.from_name.as_str(),
+            colleagues.to_name.as_str(),
+            colleagues.kind,
+            colleagues.confidence,
+            colleagues.source.as_str(),
+        ),
+        ("greg", "ada", Colleague, 0.8, "https://example.com/team")
+    );
+    assert!(matches!(
+        add_relationship(&db, "ferris", builder.clone()).await,
+        Err(RelationshipError::Duplicate { .. })
+    ));
+    // the other way or of another kind is not a duplicate
+    add_relationship(
+        &db,
+        "ferris",
+        RelationshipBuilder::new(ada.id, greg.id, Colleague),
+    )
+    .await
+    .unwrap();
+    let friends = add_relationship(
+        &db,
+        "ferris",
+        RelationshipBuilder::new(bob.id, greg.id, Friend),
+    )
+    .await
+    .unwrap();
+
+    for invalid in [
+        RelationshipBuilder::new(greg.id, greg.id, Family),
+        RelationshipBuilder {
+            confidence: 1.5,
+            ..RelationshipBuilder::new(greg.id, bob.id, Family)
+        },
+    ] {
+        assert!(matches!(
+            add_relationship(&db, "ferris", invalid).await,
+            Err(RelationshipError::Invalid(_))
+        ));
+    }
+    // only between people of the owner
+    assert!(matches!(
+        add_relationship(
+            &db,
+            "ferris",
+            RelationshipBuilder::new(greg.id, other.id, Family)
+        )
+        .await,
+        Err(RelationshipError::Person(GetPersonError::NotFound { .. }))
+    ));
+    assert!(matches!(
+        add_relationship(
+            &db,
+            "crab",
+            RelationshipBuilder::new(other.id, greg.id, Family)
+        )
+        .await,
+        Err(RelationshipError::Person(GetPersonError::NotFound { .. }))
+    ));
+    assert!(matches!(
+        get_relationship(&db, "crab", colleagues.id).await,
+        Err(RelationshipError::NotFound { .. })
+    ));
+
+    let ids = |relationships: Vec<Relationship>| -> Vec<u32> {
+        relationships.into_iter().map(|r| r.id).collect()
+    };
+    assert_eq!(
+        ids(list_relationships(&db, "ferris", greg.id).await.unwrap()),
+        [colleagues.id, colleagues.id + 1, friends.id]
+    );
+    assert_eq!(
+        ids(list_relationships(&db, "ferris", bob.id).await.unwrap()),
+        [friends.id]
+    );
+    assert!(list_relationships(&db, "crab", greg.id).await.is_err());
+
+    let updated = update_relationship(
+        &db,
+        "ferris",
+        friends.id,
+        RelationshipBuilder {
+            notes: "school".to_string(),
+            ..RelationshipBuilder::new(bob.id, ada.id, Family)
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        (
+            updated.to_name.as_str(),
+            updated.kind,
+            updated.notes.as_str()
+        ),
+        ("ada", Family, "school")
+    );
+    assert!(matches!(
+        update_relationship(&db, "ferris", friends.id, builder).await,
+        Err(RelationshipError::Duplicate { .. })
+    ));
+    assert!(matches!(
+        update_relationship(
+            &db,
+            "crab",
+            friends.id,
+            RelationshipBuilder::new(other.id, other.id, Family)
+        )
+        .await,
+        Err(RelationshipError::NotFound { .. })
+    ));
+
+    assert!(matches!(
+        delete_relationship(&db, "crab", friends.id).await,
+        Err(RelationshipError::NotFound { .. })
+    ));
+    delete_relationship(&db, "ferris", friends.id)
+        .await
+        .unwrap();
+    assert!(list_relationships(&db, "ferris", bob.id)
+        .await
+        .unwrap()
+        .is_empty());
+
+    // deleting a person deletes its relationships
+    crate::people::delete_person(&db, "ferris", ada.id)
+        .await
+        .unwrap();
+    assert!(list_relationships(&db, "ferris", greg.id)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn test_graph() {
+    let db = test_db().await;
+    // a - b - c - d, a - e - d, f alone, g - h
+    let p = people(&db, "ferris", &["a", "b", "c", "d", "e", "f", "g", "h"]).await;
+    let id = |i: usize| p[i].id;
+    for (from, to, confidence) in [
+        (0, 1, 1.0),
+        (2, 1, 1.0),
+        (2, 3, 1.0),
+        (0, 4, 0.3),
+        (4, 3, 0.3),
+        (6, 7, 1.0),
+    ] {
+        let builder = RelationshipBuilder {
+            confidence,
+            ..RelationshipBuilder::new(id(from), id(to), Associate)
+        };
+        add_relationship(&db, "ferris", builder).await.unwrap();
+    }
+    let names =
+        |people: Vec<Person>| -> Vec<String> { people.into_iter().map(|p| p.name).collect() };
+
+    let graph = Graph::load(&db, "ferris", 0.0).await.unwrap();
+    let neighbours = |graph: &Graph, hops| -> Vec<(String, u32)> {
+        graph
+            .neighbours("ferris", id(0), hops)
+            .unwrap()
+            .into_iter()
+            .map(|n| (n.person.name, n.distance))
+            .collect()
+    };
+    let expected = |pairs: &[(&str, u32)]| -> Vec<(String, u32)> {
+        pairs.iter().map(|(n, d)| (n.to_string(), *d)).collect()
+    };
+    assert_eq!(neighbours(&graph, 1), expected(&[("b", 1), ("e", 1)]));
+    assert_eq!(
+        neighbours(&graph, 2),
+        expected(&[("b", 1), ("e", 1), ("c", 2), ("d", 2)])
+    );
+    assert_eq!(
+        neighbours(&graph, 0),
+        neighbours(&graph, 1),
+        "at least one hop"
+    );
+
+    let path = graph
+        .shortest_path("ferris", id(0), id(3))
+        .unwrap()
+        .unwrap();
+    assert_eq!(names(path.people), ["a", "e", "d"]);
+    assert_eq!(path.relationships.len(), 2);
+    let path = graph
+        .shortest_path("ferris", id(2), id(2))
+        .unwrap()
+        .unwrap();
+    assert_eq!(names(path.people), ["c"]);
+    assert!(path.relationships.is_empty());
+    assert_eq!(graph.shortest_path("ferris", id(0), id(5)).unwrap(), None);
+    assert!(matches!(
+        graph.shortest_path("ferris", id(0), 1000),
+        Err(RelationshipError::Person(GetPersonError::NotFound { .. }))
+    ));
+
+    assert_eq!(
+        graph
+            .components()
+            .into_iter()
+            .map(names)
+            .collect::<Vec<_>>(),
+        [vec!["a", "b", "c", "d", "e"], vec!["g", "h"], vec!["f"]]
+    );
+
+    // unsure relationships left out
+    let sure = Graph::load(&db, "ferris", 0.5).await.unwrap();
+    assert_eq!(neighbours(&sure, 1), expected(&[("b", 1)]));
+    assert_eq!(
+        names(
+            sure.shortest_path("ferris", id(0), id(3))
+                .unwrap()
+                .unwrap()
+                .people
+        ),
+        ["a", "b", "c", "d"]
+    );
+
+    // only the people of the owner
+    let graph = Graph::load(&db, "crab", 0.0).await.unwrap();
+    assert!(graph.components().is_empty());
+    assert!(graph.neighbours("crab", id(0), 1).is_err());
+}
+
+#[tokio::test]
+async fn test_merge_relationships() {
+    let db = test_db().await;
+    let [greg, dup, ada, bob] = &people(&db, "ferris", &["greg", "g", "ada", "bob"]).await[..]
+    else {
+        unreachable!()
+    };
+    let add = |from: u32, to: u32, kind| {
+        let db = db.clone();
+        async move {
+            add_relationship(&db, "ferris", RelationshipBuilder::new(from, to, kind))
+                .await
+                .unwrap()
+        }
+    };
+    add(greg.id, ada.id, Colleague).await;
+    let between = add(dup.id, greg.id, SameAccount).await;
+    let known = add(dup.id, ada.id, Colleague).await;
+    let moved = add(dup.id, bob.id, Friend).await;
+    let edges = |relationships: Vec<Relationship>| -> Vec<(u32, u32, RelationshipKind)> {
+        relationships
+            .into_iter()
+            .map(|r| (r.from_id, r.to_id, r.kind))
+            .collect()
+    };
+
+    let merge = merge_people(&db, "ferris", greg.id, dup.id).await.unwrap();
+    assert_eq!(merge.moved_relationships.0, std::slice::from_ref(&moved));
+    // between the two, and one greg already has
+    assert_eq!(merge.dropped_relationships.0, [between, known]);
+    assert_eq!(
+        edges(list_relationships(&db, "ferris", greg.id).await.unwrap()),
+        [(greg.id, ada.id, Colleague), (greg.id, bob.id, Friend),]
+    );
+
+    undo_merge(&db, "ferris", merge.id).await.unwrap();
+    assert_eq!(
+        edges(list_relationships(&db, "ferris", dup.id).await.unwrap()),
+        [
+            (dup.id, ada.id, Colleague),
+            (dup.id, bob.id, Friend),
+            (dup.id, greg.id, SameAccount),
+        ]
+    );
+    assert_eq!(
+        edges(list_relationships(&db, "ferris", greg.id).await.unwrap()),
+        [(greg.id, ada.id, Colleague), (dup.id, greg.id, SameAccount),]
+    );
+}
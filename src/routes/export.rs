@@ -0,0 +1,98 @@
+use axum::{
+    extract::Query,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use tracing::{error, instrument};
+
+use super::{owner, ErrorMessage, NotAuthenticated};
+use crate::export::{self, ExportError, ExportQuery, Network};
+use crate::users::AuthSession;
+
+impl From<NotAuthenticated> for ExportError {
+    fn from(_: NotAuthenticated) -> Self {
+        ExportError::Auth
+    }
+}
+
+impl IntoResponse for ExportError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            ExportError::Relationship(_) => StatusCode::NOT_FOUND,
+            ExportError::Invalid(_) | ExportError::Xml(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ExportError::Auth => StatusCode::UNAUTHORIZED,
+            ExportError::Sqlx(e) => {
+                error!("export: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+        match self {
+            ExportError::Relationship(e) => e.into_response(),
+            e => (
+                status,
+                Json(ErrorMessage {
+                    message: e.to_string(),
+                }),
+            )
+                .into_response(),
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/export",
+    params(ExportQuery),
+    responses(
+        (status = 200, description = "The people, their identifiers and relationships as a file", body = String),
+        (status = 404, description = "Person not found", body = ErrorMessage),
+    )
+)]
+#[instrument(skip(auth_session))]
+/// Export Network
+pub async fn export_handler(
+    auth_session: AuthSession,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, ExportError> {
+    let (db, owner) = owner(auth_session)?;
+    let network = Network::load(&db, &owner, &query).await?;
+    let filename = match query.person {
+        Some(id) => format!("person-{id}.{}", query.format.extension()),
+        None => format!("seekr.{}", query.format.extension()),
+    };
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                query.format.content_type().to_string(),
+            ),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{filename}\""),
+            ),
+        ],
+        network.export(query.format),
+    )
+        .into_response())
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/import/graphml",
+    request_body(content = String, content_type = "application/xml"),
+    responses(
+        (status = 201, description = "The people, identifiers and relationships added", body = Network),
+        (status = 422, description = "Invalid GraphML", body = ErrorMessage),
+    )
+)]
+#[instrument(skip(auth_session, body))]
+/// Import GraphML
+pub async fn import_graphml_handler(
+    auth_session: AuthSession,
+    body: String,
+) -> Result<(StatusCode, Json<Network>), ExportError> {
+    let (db, owner) = owner(auth_session)?;
+    let network = export::import_graphml(&db, &owner, &body).await?;
+    Ok((StatusCode::CREATED, Json(network)))
+}
@@ -0,0 +1,25 @@
+use async_graphql::http::GraphiQLSource;
+use axum::{extract::Extension, response::Html, Json};
+use tracing::instrument;
+
+use crate::graphql::{Owner, SeekrSchema};
+use crate::users::AuthSession;
+
+/// Run a GraphQL query or mutation as the logged in user.
+#[instrument(skip_all)]
+pub async fn graphql_handler(
+    auth_session: AuthSession,
+    Extension(schema): Extension<SeekrSchema>,
+    Json(request): Json<async_graphql::Request>,
+) -> Json<async_graphql::Response> {
+    let request = match auth_session.user {
+        Some(user) => request.data(Owner(user.username)),
+        None => request,
+    };
+    Json(schema.execute(request).await)
+}
+
+/// The GraphiQL playground for `/graphql`.
+pub async fn graphiql_handler() -> Html<String> {
+    Html(GraphiQLSource::build().endpoint("/graphql").finish())
+}
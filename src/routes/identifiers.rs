@@ -0,0 +1,107 @@
+use axum::{
+    extract::Path,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use tracing::{error, instrument};
+
+use super::{owner, ErrorMessage, NotAuthenticated};
+use crate::people::{self, Identifier, IdentifierBuilder, IdentifierError};
+use crate::users::AuthSession;
+
+impl From<NotAuthenticated> for IdentifierError {
+    fn from(_: NotAuthenticated) -> Self {
+        IdentifierError::Auth
+    }
+}
+
+impl IntoResponse for IdentifierError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            IdentifierError::NotFound { .. } | IdentifierError::PersonNotFound { .. } => {
+                StatusCode::NOT_FOUND
+            }
+            IdentifierError::Invalid { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            IdentifierError::Duplicate { .. } => StatusCode::CONFLICT,
+            IdentifierError::Auth => StatusCode::UNAUTHORIZED,
+            IdentifierError::Sqlx(e) => {
+                error!("identifiers: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+        (
+            status,
+            Json(ErrorMessage {
+                message: self.to_string(),
+            }),
+        )
+            .into_response()
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/people/{id}/identifiers",
+    params(("id" = u32, Path, description = "Person id")),
+    responses(
+        (status = 200, description = "Identifiers of the person, by kind", body = [Identifier]),
+        (status = 404, description = "Person not found", body = ErrorMessage),
+    )
+)]
+#[instrument(skip(auth_session))]
+/// List Identifiers
+pub async fn list_identifiers_handler(
+    auth_session: AuthSession,
+    Path(id): Path<u32>,
+) -> Result<Json<Vec<Identifier>>, IdentifierError> {
+    let (db, owner) = owner(auth_session)?;
+    Ok(Json(people::list_identifiers(&db, &owner, id).await?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/people/{id}/identifiers",
+    params(("id" = u32, Path, description = "Person id")),
+    request_body = IdentifierBuilder,
+    responses(
+        (status = 201, description = "Identifier added with its normalized value", body = Identifier),
+        (status = 404, description = "Person not found", body = ErrorMessage),
+        (status = 409, description = "The person already has the identifier", body = ErrorMessage),
+        (status = 422, description = "Invalid value for the kind", body = ErrorMessage),
+    )
+)]
+#[instrument(skip(auth_session))]
+/// Add Identifier
+pub async fn add_identifier_handler(
+    auth_session: AuthSession,
+    Path(id): Path<u32>,
+    Json(identifier): Json<IdentifierBuilder>,
+) -> Result<(StatusCode, Json<Identifier>), IdentifierError> {
+    let (db, owner) = owner(auth_session)?;
+    let identifier = people::add_identifier(&db, &owner, id, identifier).await?;
+    Ok((StatusCode::CREATED, Json(identifier)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/people/{id}/identifiers/{identifier_id}",
+    params(
+        ("id" = u32, Path, description = "Person id"),
+        ("identifier_id" = u32, Path, description = "Identifier id"),
+    ),
+    responses(
+        (status = 204, description = "Identifier removed"),
+        (status = 404, description = "Not found", body = ErrorMessage),
+    )
+)]
+#[instrument(skip(auth_session))]
+/// Remove Identifier
+pub async fn remove_identifier_handler(
+    auth_session: AuthSession,
+    Path((id, identifier_id)): Path<(u32, u32)>,
+) -> Result<StatusCode, IdentifierError> {
+    let (db, owner) = owner(auth_session)?;
+    people::remove_identifier(&db, &owner, id, identifier_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,120 @@
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use tracing::{error, instrument};
+
+use super::{owner, ErrorMessage, NotAuthenticated};
+use crate::people::{
+    self, DuplicateCandidate, DuplicatesQuery, Merge, MergeError, MergeRequest, Person,
+};
+use crate::users::AuthSession;
+
+impl From<NotAuthenticated> for MergeError {
+    fn from(_: NotAuthenticated) -> Self {
+        MergeError::Auth
+    }
+}
+
+impl IntoResponse for MergeError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            MergeError::Person(_) => StatusCode::NOT_FOUND,
+            MergeError::NotFound { .. } => StatusCode::NOT_FOUND,
+            MergeError::SamePerson(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            MergeError::AlreadyUndone(_) => StatusCode::CONFLICT,
+            MergeError::Auth => StatusCode::UNAUTHORIZED,
+            MergeError::Sqlx(e) => {
+                error!("merges: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+        match self {
+            MergeError::Person(e) => e.into_response(),
+            e => (
+                status,
+                Json(ErrorMessage {
+                    message: e.to_string(),
+                }),
+            )
+                .into_response(),
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/people/duplicates",
+    params(DuplicatesQuery),
+    responses(
+        (status = 200, description = "Pairs of people that may be the same, the most likely first", body = [DuplicateCandidate]),
+    )
+)]
+#[instrument(skip(auth_session))]
+/// Find Duplicates
+pub async fn find_duplicates_handler(
+    auth_session: AuthSession,
+    Query(query): Query<DuplicatesQuery>,
+) -> Result<Json<Vec<DuplicateCandidate>>, MergeError> {
+    let (db, owner) = owner(auth_session)?;
+    Ok(Json(people::find_duplicates(&db, &owner, query).await?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/merges",
+    request_body = MergeRequest,
+    responses(
+        (status = 201, description = "Merged, the merge can be undone", body = Merge),
+        (status = 404, description = "A person was not found", body = ErrorMessage),
+        (status = 422, description = "A person can not be merged into itself", body = ErrorMessage),
+    )
+)]
+#[instrument(skip(auth_session))]
+/// Merge People
+pub async fn merge_handler(
+    auth_session: AuthSession,
+    Json(request): Json<MergeRequest>,
+) -> Result<(StatusCode, Json<Merge>), MergeError> {
+    let (db, owner) = owner(auth_session)?;
+    let merge = people::merge_people(&db, &owner, request.keep, request.merge).await?;
+    Ok((StatusCode::CREATED, Json(merge)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/merges",
+    responses(
+        (status = 200, description = "Merges of the user, newest first", body = [Merge]),
+    )
+)]
+#[instrument(skip(auth_session))]
+/// List Merges
+pub async fn list_merges_handler(
+    auth_session: AuthSession,
+) -> Result<Json<Vec<Merge>>, MergeError> {
+    let (db, owner) = owner(auth_session)?;
+    Ok(Json(people::list_merges(&db, &owner).await?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/merges/{id}/undo",
+    params(("id" = u32, Path, description = "Merge id")),
+    responses(
+        (status = 200, description = "The merged person, restored", body = Person),
+        (status = 404, description = "Not found", body = ErrorMessage),
+        (status = 409, description = "Already undone", body = ErrorMessage),
+    )
+)]
+#[instrument(skip(auth_session))]
+/// Undo Merge
+pub async fn undo_merge_handler(
+    auth_session: AuthSession,
+    Path(id): Path<u32>,
+) -> Result<Json<Person>, MergeError> {
+    let (db, owner) = owner(auth_session)?;
+    Ok(Json(people::undo_merge(&db, &owner, id).await?))
+}
@@ -1,28 +1,40 @@
 pub mod embed;
-pub mod get_person;
+pub mod export;
+pub mod graphql;
+pub mod identifiers;
 pub mod language_detection;
-pub mod list_people;
+pub mod merges;
 pub mod not_found;
-pub mod post_person;
+pub mod people;
+pub mod relationships;
+pub mod scrape;
+pub mod scripts;
+pub mod search;
 
 use crate::cli::Args;
+use crate::users::AuthSession;
 use axum::{
-    error_handling::HandleErrorLayer,
     // extract::State,
+    extract::Request,
     http::StatusCode,
-    BoxError,
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{delete, get, post},
+    Extension,
+    Json,
     Router,
 };
-use utoipa::OpenApi;
+use serde::{Deserialize, Serialize};
+use utoipa::{OpenApi, ToSchema};
 // use tracing::info;
 use sqlx::SqlitePool;
 use time::Duration;
-use tower::ServiceBuilder;
 use utoipa_rapidoc::RapiDoc;
 use utoipa_redoc::{Redoc, Servable};
 use utoipa_swagger_ui::SwaggerUi;
 
 use crate::{
+    scripts::Runner,
     users::Backend,
     web::{auth, protected},
 };
@@ -33,19 +45,118 @@ use axum_login::{
     AuthManagerLayerBuilder,
 };
 
+/// The body of error responses.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ErrorMessage {
+    #[schema(example = "script not found. ID: 4 owner: \"seekr\"")]
+    pub message: String,
+}
+
+/// A request to the API without a logged in user. The error of every handler converts it into
+/// its `Auth` variant, so `?` turns it into a 401.
+#[derive(Debug)]
+pub struct NotAuthenticated;
+
+/// The database and the name of the logged in user, who owns everything a request reads and
+/// changes.
+pub fn owner(auth_session: AuthSession) -> Result<(SqlitePool, String), NotAuthenticated> {
+    match auth_session.user {
+        Some(user) => Ok((auth_session.backend.get_pool(), user.username)),
+        None => Err(NotAuthenticated),
+    }
+}
+
+/// Answer requests to the API without a logged in user with a 401, instead of the redirect to
+/// the login page the web pages get.
+async fn api_login_required(auth_session: AuthSession, request: Request, next: Next) -> Response {
+    match auth_session.user {
+        Some(_) => next.run(request).await,
+        None => (
+            StatusCode::UNAUTHORIZED,
+            Json(ErrorMessage {
+                message: "not authenticated".to_string(),
+            }),
+        )
+            .into_response(),
+    }
+}
+
 pub async fn get_router(args: &Args) -> anyhow::Result<Router<()>> {
     #[derive(OpenApi)]
     #[openapi(
         paths(
             language_detection::detect_language_handler,
-            // list_people::list_people_handler,
-            // get_person::get_person_handler,
-            // post_person::post_person_handler,
+            scrape::check_handler,
+            scripts::create_script_handler,
+            scripts::list_scripts_handler,
+            scripts::get_script_handler,
+            scripts::update_script_handler,
+            scripts::delete_script_handler,
+            scripts::list_versions_handler,
+            scripts::get_version_handler,
+            scripts::restore_version_handler,
+            scripts::run_script_handler,
+            scripts::list_results_handler,
+            search::search_handler,
+            merges::find_duplicates_handler,
+            merges::merge_handler,
+            merges::list_merges_handler,
+            merges::undo_merge_handler,
+            relationships::add_relationship_handler,
+            relationships::get_relationship_handler,
+            relationships::update_relationship_handler,
+            relationships::delete_relationship_handler,
+            relationships::list_relationships_handler,
+            relationships::neighbours_handler,
+            relationships::shortest_path_handler,
+            relationships::components_handler,
+            export::export_handler,
+            export::import_graphml_handler,
+            identifiers::list_identifiers_handler,
+            identifiers::add_identifier_handler,
+            identifiers::remove_identifier_handler,
+            people::list_people_handler,
+            people::create_person_handler,
+            people::get_person_handler,
+            people::update_person_handler,
+            people::delete_person_handler,
         ),
         components(schemas(
             language_detection::DetectLanguageQuery,
             language_detection::LanguageDetectionResult,
             language_detection::Language,
+            scrape::ScrapeCheckQuery,
+            scrape::ScrapeCheckResult,
+            crate::scrape::ParseDiagnostic,
+            crate::scrape::Span,
+            crate::scrape::Position,
+            crate::scrape::TypeRepr,
+            crate::scripts::Script,
+            crate::scripts::ScriptVersion,
+            crate::scripts::ScriptBuilder,
+            crate::scripts::ScrapeResult,
+            crate::scrape::limits::Limits,
+            crate::scrape::limits::Usage,
+            crate::people::Person,
+            crate::people::PersonBuilder,
+            crate::people::PersonPatch,
+            crate::people::PeoplePage,
+            crate::search::Hit,
+            crate::people::DuplicateCandidate,
+            crate::people::Merge,
+            crate::people::MergeRequest,
+            crate::search::HitSource,
+            crate::relationships::Relationship,
+            crate::relationships::RelationshipBuilder,
+            crate::relationships::RelationshipKind,
+            crate::relationships::Neighbour,
+            crate::relationships::RelationshipPath,
+            crate::export::Network,
+            crate::export::Format,
+            crate::people::Identifier,
+            crate::people::IdentifierBuilder,
+            crate::people::IdentifierKind,
+            ErrorMessage,
             // Model,
         ))
     )]
@@ -56,23 +167,123 @@ pub async fn get_router(args: &Args) -> anyhow::Result<Router<()>> {
         .with_secure(false)
         .with_expiry(Expiry::OnInactivity(Duration::days(1)));
 
-    let sqlx_db = SqlitePool::connect(&args.create_db()?.get_pool()).await?;
-    sqlx::migrate!().run(&sqlx_db).await?;
+    let sqlx_db = crate::open_db(args).await?;
+    let schema = crate::graphql::schema(sqlx_db.clone());
     let backend = Backend::new(sqlx_db);
 
-    let auth_service = ServiceBuilder::new()
-        .layer(HandleErrorLayer::new(|_: BoxError| async {
-            StatusCode::BAD_REQUEST
-        }))
-        .layer(AuthManagerLayerBuilder::new(backend, session_layer).build());
+    let auth_service = AuthManagerLayerBuilder::new(backend, session_layer).build();
+
+    // the http client blocks, it is created outside of the runtime
+    let (limits, policy) = (args.limits()?, args.policy());
+    let runner = tokio::task::spawn_blocking(move || Runner::http(limits, policy)).await?;
+
+    let api = Router::new()
+        .route(
+            "/graphql",
+            get(graphql::graphiql_handler).post(graphql::graphql_handler),
+        )
+        .route("/api/v1/scrape/check", post(scrape::check_handler))
+        .route("/api/v1/search", get(search::search_handler))
+        .route(
+            "/api/v1/people/duplicates",
+            get(merges::find_duplicates_handler),
+        )
+        .route(
+            "/api/v1/merges",
+            get(merges::list_merges_handler).post(merges::merge_handler),
+        )
+        .route("/api/v1/merges/:id/undo", post(merges::undo_merge_handler))
+        .route(
+            "/api/v1/people",
+            get(people::list_people_handler).post(people::create_person_handler),
+        )
+        .route(
+            "/api/v1/people/:id",
+            get(people::get_person_handler)
+                .patch(people::update_person_handler)
+                .delete(people::delete_person_handler),
+        )
+        .route(
+            "/api/v1/scripts",
+            get(scripts::list_scripts_handler).post(scripts::create_script_handler),
+        )
+        .route(
+            "/api/v1/scripts/:id",
+            get(scripts::get_script_handler)
+                .put(scripts::update_script_handler)
+                .delete(scripts::delete_script_handler),
+        )
+        .route(
+            "/api/v1/scripts/:id/versions",
+            get(scripts::list_versions_handler),
+        )
+        .route(
+            "/api/v1/scripts/:id/versions/:version",
+            get(scripts::get_version_handler),
+        )
+        .route(
+            "/api/v1/scripts/:id/versions/:version/restore",
+            post(scripts::restore_version_handler),
+        )
+        .route(
+            "/api/v1/people/:id/scripts/:script_id/run",
+            post(scripts::run_script_handler),
+        )
+        .route(
+            "/api/v1/people/:id/results",
+            get(scripts::list_results_handler),
+        )
+        .route(
+            "/api/v1/people/:id/identifiers",
+            get(identifiers::list_identifiers_handler).post(identifiers::add_identifier_handler),
+        )
+        .route(
+            "/api/v1/people/:id/identifiers/:identifier_id",
+            delete(identifiers::remove_identifier_handler),
+        )
+        .route(
+            "/api/v1/relationships",
+            post(relationships::add_relationship_handler),
+        )
+        .route(
+            "/api/v1/relationships/:id",
+            get(relationships::get_relationship_handler)
+                .put(relationships::update_relationship_handler)
+                .delete(relationships::delete_relationship_handler),
+        )
+        .route(
+            "/api/v1/people/:id/relationships",
+            get(relationships::list_relationships_handler),
+        )
+        .route(
+            "/api/v1/people/:id/neighbours",
+            get(relationships::neighbours_handler),
+        )
+        .route(
+            "/api/v1/people/:id/path/:other_id",
+            get(relationships::shortest_path_handler),
+        )
+        .route(
+            "/api/v1/graph/components",
+            get(relationships::components_handler),
+        )
+        .route("/api/v1/export", get(export::export_handler))
+        .route(
+            "/api/v1/import/graphml",
+            post(export::import_graphml_handler),
+        )
+        .route_layer(middleware::from_fn(api_login_required));
 
     let app = protected::router()
         .route_layer(login_required!(Backend, login_url = "/login"))
+        .merge(api)
         .merge(auth::router())
         .merge(RapiDoc::with_openapi("/api-docs/openapi2.json", ApiDoc::openapi()).path("/rapidoc"))
         .merge(SwaggerUi::new("/swagger-ui").url("/api-docs/openapi.json", ApiDoc::openapi()))
         .merge(Redoc::with_url("/redoc", ApiDoc::openapi()))
-        .layer(auth_service);
+        .layer(auth_service)
+        .layer(Extension(runner))
+        .layer(Extension(schema));
     Ok(app)
 
     // Ok(Router::new()
@@ -83,15 +294,11 @@ pub async fn get_router(args: &Args) -> anyhow::Result<Router<()>> {
     //         post(language_detection::detect_language_handler),
     //     )
     //     // .route("/api/v1/db", get(db_test))
-    //     .route("/api/v1/list_people", get(list_people::list_people_handler))
-    //     .route("/api/v1/get_person", get(get_person::get_person_handler))
-    //     .route(
-    //         "/api/v1/post_person",
-    //         post(post_person::post_person_handler),
-    //     )
     //     .merge(RapiDoc::with_openapi("/api-docs/openapi2.json", ApiDoc::openapi()).path("/rapidoc"))
     //     .merge(SwaggerUi::new("/swagger-ui").url("/api-docs/openapi.json", ApiDoc::openapi()))
     //     .merge(Redoc::with_url("/redoc", ApiDoc::openapi()))
     //     .fallback(not_found::not_found_handler)
     //     .with_state(db))
 }
+
+mod test;
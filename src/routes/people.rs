@@ -0,0 +1,164 @@
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use tracing::{error, instrument};
+
+use super::{owner, ErrorMessage, NotAuthenticated};
+use crate::people::{
+    self, GetPersonError, InsertPersonError, Pagination, PeopleFilter, PeoplePage, Person,
+    PersonBuilder, PersonPatch,
+};
+use crate::users::AuthSession;
+
+fn error_response(status: StatusCode, message: String) -> Response {
+    (status, Json(ErrorMessage { message })).into_response()
+}
+
+impl From<NotAuthenticated> for GetPersonError {
+    fn from(_: NotAuthenticated) -> Self {
+        GetPersonError::Auth
+    }
+}
+
+impl From<NotAuthenticated> for InsertPersonError {
+    fn from(_: NotAuthenticated) -> Self {
+        InsertPersonError::Auth
+    }
+}
+
+impl IntoResponse for GetPersonError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            GetPersonError::NotFound { .. } => StatusCode::NOT_FOUND,
+            GetPersonError::Auth => StatusCode::UNAUTHORIZED,
+            GetPersonError::Sqlx(e) => {
+                error!("people: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+        error_response(status, self.to_string())
+    }
+}
+
+impl IntoResponse for InsertPersonError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            InsertPersonError::Get(_) => StatusCode::NOT_FOUND,
+            InsertPersonError::Invalid(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            InsertPersonError::Auth => StatusCode::UNAUTHORIZED,
+            InsertPersonError::Sqlx(e) => {
+                error!("people: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+        match self {
+            InsertPersonError::Get(e) => e.into_response(),
+            e => error_response(status, e.to_string()),
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/people",
+    params(PeopleFilter, Pagination),
+    responses(
+        (status = 200, description = "A page of the people of the user, by id", body = PeoplePage),
+    )
+)]
+#[instrument(skip(auth_session))]
+/// List People
+pub async fn list_people_handler(
+    auth_session: AuthSession,
+    Query(filter): Query<PeopleFilter>,
+    Query(pagination): Query<Pagination>,
+) -> Result<Json<PeoplePage>, GetPersonError> {
+    let (db, owner) = owner(auth_session)?;
+    Ok(Json(
+        people::list_people(&db, &owner, &filter, pagination).await?,
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/people",
+    request_body = PersonBuilder,
+    responses(
+        (status = 201, description = "Person created", body = Person),
+        (status = 422, description = "Invalid person", body = ErrorMessage),
+    )
+)]
+#[instrument(skip(auth_session))]
+/// Create Person
+pub async fn create_person_handler(
+    auth_session: AuthSession,
+    Json(person): Json<PersonBuilder>,
+) -> Result<(StatusCode, Json<Person>), InsertPersonError> {
+    let (db, owner) = owner(auth_session)?;
+    let person = people::insert_person(&db, &owner, person).await?;
+    Ok((StatusCode::CREATED, Json(person)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/people/{id}",
+    params(("id" = u32, Path, description = "Person id")),
+    responses(
+        (status = 200, description = "The person", body = Person),
+        (status = 404, description = "Not found", body = ErrorMessage),
+    )
+)]
+#[instrument(skip(auth_session))]
+/// Get Person
+pub async fn get_person_handler(
+    auth_session: AuthSession,
+    Path(id): Path<u32>,
+) -> Result<Json<Person>, GetPersonError> {
+    let (db, owner) = owner(auth_session)?;
+    Ok(Json(people::get_person(&db, &owner, id).await?))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/v1/people/{id}",
+    params(("id" = u32, Path, description = "Person id")),
+    request_body = PersonPatch,
+    responses(
+        (status = 200, description = "The updated person", body = Person),
+        (status = 404, description = "Not found", body = ErrorMessage),
+        (status = 422, description = "Invalid person", body = ErrorMessage),
+    )
+)]
+#[instrument(skip(auth_session))]
+/// Update Person
+pub async fn update_person_handler(
+    auth_session: AuthSession,
+    Path(id): Path<u32>,
+    Json(patch): Json<PersonPatch>,
+) -> Result<Json<Person>, InsertPersonError> {
+    let (db, owner) = owner(auth_session)?;
+    Ok(Json(people::update_person(&db, &owner, id, patch).await?))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/people/{id}",
+    params(("id" = u32, Path, description = "Person id")),
+    responses(
+        (status = 204, description = "Person deleted with its identifiers and results"),
+        (status = 404, description = "Not found", body = ErrorMessage),
+    )
+)]
+#[instrument(skip(auth_session))]
+/// Delete Person
+pub async fn delete_person_handler(
+    auth_session: AuthSession,
+    Path(id): Path<u32>,
+) -> Result<StatusCode, GetPersonError> {
+    let (db, owner) = owner(auth_session)?;
+    people::delete_person(&db, &owner, id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
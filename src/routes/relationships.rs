@@ -0,0 +1,228 @@
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use tracing::{error, instrument};
+
+use super::{owner, ErrorMessage, NotAuthenticated};
+use crate::people::Person;
+use crate::relationships::{
+    self, Graph, GraphQuery, Neighbour, Relationship, RelationshipBuilder, RelationshipError,
+    RelationshipPath,
+};
+use crate::users::AuthSession;
+
+impl From<NotAuthenticated> for RelationshipError {
+    fn from(_: NotAuthenticated) -> Self {
+        RelationshipError::Auth
+    }
+}
+
+impl IntoResponse for RelationshipError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            RelationshipError::Person(_) => StatusCode::NOT_FOUND,
+            RelationshipError::NotFound { .. } => StatusCode::NOT_FOUND,
+            RelationshipError::NotConnected { .. } => StatusCode::NOT_FOUND,
+            RelationshipError::Invalid(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            RelationshipError::Duplicate { .. } => StatusCode::CONFLICT,
+            RelationshipError::Auth => StatusCode::UNAUTHORIZED,
+            RelationshipError::Sqlx(e) => {
+                error!("relationships: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+        match self {
+            RelationshipError::Person(e) => e.into_response(),
+            e => (
+                status,
+                Json(ErrorMessage {
+                    message: e.to_string(),
+                }),
+            )
+                .into_response(),
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/relationships",
+    request_body = RelationshipBuilder,
+    responses(
+        (status = 201, description = "Added", body = Relationship),
+        (status = 404, description = "A person was not found", body = ErrorMessage),
+        (status = 409, description = "The people already have a relationship of the kind", body = ErrorMessage),
+        (status = 422, description = "Invalid relationship", body = ErrorMessage),
+    )
+)]
+#[instrument(skip(auth_session))]
+/// Add Relationship
+pub async fn add_relationship_handler(
+    auth_session: AuthSession,
+    Json(relationship): Json<RelationshipBuilder>,
+) -> Result<(StatusCode, Json<Relationship>), RelationshipError> {
+    let (db, owner) = owner(auth_session)?;
+    let relationship = relationships::add_relationship(&db, &owner, relationship).await?;
+    Ok((StatusCode::CREATED, Json(relationship)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/relationships/{id}",
+    params(("id" = u32, Path, description = "Relationship id")),
+    responses(
+        (status = 200, description = "Found", body = Relationship),
+        (status = 404, description = "Not found", body = ErrorMessage),
+    )
+)]
+#[instrument(skip(auth_session))]
+/// Get Relationship
+pub async fn get_relationship_handler(
+    auth_session: AuthSession,
+    Path(id): Path<u32>,
+) -> Result<Json<Relationship>, RelationshipError> {
+    let (db, owner) = owner(auth_session)?;
+    Ok(Json(
+        relationships::get_relationship(&db, &owner, id).await?,
+    ))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/relationships/{id}",
+    params(("id" = u32, Path, description = "Relationship id")),
+    request_body = RelationshipBuilder,
+    responses(
+        (status = 200, description = "Updated", body = Relationship),
+        (status = 404, description = "Not found", body = ErrorMessage),
+        (status = 409, description = "The people already have a relationship of the kind", body = ErrorMessage),
+        (status = 422, description = "Invalid relationship", body = ErrorMessage),
+    )
+)]
+#[instrument(skip(auth_session))]
+/// Update Relationship
+pub async fn update_relationship_handler(
+    auth_session: AuthSession,
+    Path(id): Path<u32>,
+    Json(relationship): Json<RelationshipBuilder>,
+) -> Result<Json<Relationship>, RelationshipError> {
+    let (db, owner) = owner(auth_session)?;
+    Ok(Json(
+        relationships::update_relationship(&db, &owner, id, relationship).await?,
+    ))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/relationships/{id}",
+    params(("id" = u32, Path, description = "Relationship id")),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = 404, description = "Not found", body = ErrorMessage),
+    )
+)]
+#[instrument(skip(auth_session))]
+/// Delete Relationship
+pub async fn delete_relationship_handler(
+    auth_session: AuthSession,
+    Path(id): Path<u32>,
+) -> Result<StatusCode, RelationshipError> {
+    let (db, owner) = owner(auth_session)?;
+    relationships::delete_relationship(&db, &owner, id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/people/{id}/relationships",
+    params(("id" = u32, Path, description = "Person id")),
+    responses(
+        (status = 200, description = "Relationships from and to the person", body = [Relationship]),
+        (status = 404, description = "Person not found", body = ErrorMessage),
+    )
+)]
+#[instrument(skip(auth_session))]
+/// List Relationships
+pub async fn list_relationships_handler(
+    auth_session: AuthSession,
+    Path(id): Path<u32>,
+) -> Result<Json<Vec<Relationship>>, RelationshipError> {
+    let (db, owner) = owner(auth_session)?;
+    Ok(Json(
+        relationships::list_relationships(&db, &owner, id).await?,
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/people/{id}/neighbours",
+    params(("id" = u32, Path, description = "Person id"), GraphQuery),
+    responses(
+        (status = 200, description = "People up to `hops` relationships away, the nearest first", body = [Neighbour]),
+        (status = 404, description = "Person not found", body = ErrorMessage),
+    )
+)]
+#[instrument(skip(auth_session))]
+/// List Neighbours
+pub async fn neighbours_handler(
+    auth_session: AuthSession,
+    Path(id): Path<u32>,
+    Query(query): Query<GraphQuery>,
+) -> Result<Json<Vec<Neighbour>>, RelationshipError> {
+    let (db, owner) = owner(auth_session)?;
+    let graph = Graph::load(&db, &owner, query.min_confidence).await?;
+    Ok(Json(graph.neighbours(&owner, id, query.hops)?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/people/{id}/path/{other_id}",
+    params(
+        ("id" = u32, Path, description = "Person id"),
+        ("other_id" = u32, Path, description = "Person id"),
+        GraphQuery,
+    ),
+    responses(
+        (status = 200, description = "A path with the fewest relationships", body = RelationshipPath),
+        (status = 404, description = "A person was not found or the two are not connected", body = ErrorMessage),
+    )
+)]
+#[instrument(skip(auth_session))]
+/// Shortest Path
+pub async fn shortest_path_handler(
+    auth_session: AuthSession,
+    Path((id, other_id)): Path<(u32, u32)>,
+    Query(query): Query<GraphQuery>,
+) -> Result<Json<RelationshipPath>, RelationshipError> {
+    let (db, owner) = owner(auth_session)?;
+    let graph = Graph::load(&db, &owner, query.min_confidence).await?;
+    graph
+        .shortest_path(&owner, id, other_id)?
+        .map(Json)
+        .ok_or(RelationshipError::NotConnected {
+            from_id: id,
+            to_id: other_id,
+        })
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/graph/components",
+    params(GraphQuery),
+    responses(
+        (status = 200, description = "Groups of connected people, the largest first", body = [Vec<Person>]),
+    )
+)]
+#[instrument(skip(auth_session))]
+/// Connected Components
+pub async fn components_handler(
+    auth_session: AuthSession,
+    Query(query): Query<GraphQuery>,
+) -> Result<Json<Vec<Vec<Person>>>, RelationshipError> {
+    let (db, owner) = owner(auth_session)?;
+    let graph = Graph::load(&db, &owner, query.min_confidence).await?;
+    Ok(Json(graph.components()))
+}
@@ -0,0 +1,61 @@
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+use utoipa::ToSchema;
+
+use super::owner;
+use crate::scrape::{ParseDiagnostic, Span, TypeError, TypeRepr};
+use crate::scripts::{self, ScriptError};
+use crate::users::AuthSession;
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ScrapeCheckQuery {
+    #[schema(example = "let foo = 2 foo")]
+    source: String,
+    /// The name the script is saved under, an import of it is a cycle
+    #[serde(default)]
+    name: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub enum ScrapeCheckResult {
+    /// The script is well formed, this is the type of its result.
+    Type(TypeRepr),
+    ParseError(ParseDiagnostic),
+    TypeError {
+        message: String,
+        span: Option<Span>,
+    },
+}
+
+impl From<TypeError> for ScrapeCheckResult {
+    fn from(e: TypeError) -> Self {
+        ScrapeCheckResult::TypeError {
+            message: e.to_string(),
+            span: e.span(),
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/scrape/check",
+    request_body = ScrapeCheckQuery,
+    responses(
+        (status = 200, description = "Script is valid", body = ScrapeCheckResult, example = json!(ScrapeCheckResult::Type(TypeRepr::Number))),
+
+        (status = 422, description = "Script does not parse or typecheck", body = ScrapeCheckResult),
+        (status = 401, description = "Not logged in", body = ErrorMessage),
+    )
+)]
+#[instrument(skip(auth_session))]
+/// Check Script
+pub async fn check_handler(
+    auth_session: AuthSession,
+    Json(payload): Json<ScrapeCheckQuery>,
+) -> Result<Json<ScrapeCheckResult>, ScriptError> {
+    let (db, owner) = owner(auth_session)?;
+    let name = payload.name.unwrap_or_default();
+    let t = scripts::check(&db, &owner, &name, &payload.source).await?;
+    Ok(Json(ScrapeCheckResult::Type(t)))
+}
@@ -0,0 +1,263 @@
+use axum::{
+    extract::{Extension, Path},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use tracing::{error, instrument};
+
+use super::scrape::ScrapeCheckResult;
+use super::{owner, ErrorMessage, NotAuthenticated};
+use crate::scripts::{
+    self, Runner, ScrapeResult, Script, ScriptBuilder, ScriptError, ScriptVersion,
+};
+use crate::users::AuthSession;
+
+impl From<NotAuthenticated> for ScriptError {
+    fn from(_: NotAuthenticated) -> Self {
+        ScriptError::Auth
+    }
+}
+
+impl IntoResponse for ScriptError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            ScriptError::NotFound { .. }
+            | ScriptError::VersionNotFound { .. }
+            | ScriptError::PersonNotFound { .. } => StatusCode::NOT_FOUND,
+            ScriptError::NameTaken(_) | ScriptError::Imported { .. } => StatusCode::CONFLICT,
+            ScriptError::Parse(_) | ScriptError::Type(_) | ScriptError::Module(_) => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            ScriptError::Auth => StatusCode::UNAUTHORIZED,
+            ScriptError::Sqlx(e) => {
+                error!("scripts: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+            ScriptError::Join(e) => {
+                error!("scripts: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+        match self {
+            ScriptError::Parse(e) => {
+                (status, Json(ScrapeCheckResult::ParseError(e))).into_response()
+            }
+            ScriptError::Type(e) => (status, Json(ScrapeCheckResult::from(e))).into_response(),
+            e => (
+                status,
+                Json(ErrorMessage {
+                    message: e.to_string(),
+                }),
+            )
+                .into_response(),
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/scripts",
+    request_body = ScriptBuilder,
+    responses(
+        (status = 201, description = "Script saved as version 1", body = Script),
+        (status = 409, description = "A script with this name exists", body = ErrorMessage),
+        (status = 422, description = "Script does not parse or typecheck", body = ScrapeCheckResult),
+    )
+)]
+#[instrument(skip(auth_session))]
+/// Create Script
+pub async fn create_script_handler(
+    auth_session: AuthSession,
+    Json(script): Json<ScriptBuilder>,
+) -> Result<(StatusCode, Json<Script>), ScriptError> {
+    let (db, owner) = owner(auth_session)?;
+    let script = scripts::insert_script(&db, &owner, script).await?;
+    Ok((StatusCode::CREATED, Json(script)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/scripts",
+    responses(
+        (status = 200, description = "Scripts of the user, by name", body = [Script]),
+    )
+)]
+#[instrument(skip(auth_session))]
+/// List Scripts
+pub async fn list_scripts_handler(
+    auth_session: AuthSession,
+) -> Result<Json<Vec<Script>>, ScriptError> {
+    let (db, owner) = owner(auth_session)?;
+    Ok(Json(scripts::list_scripts(&db, &owner).await?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/scripts/{id}",
+    params(("id" = u32, Path, description = "Script id")),
+    responses(
+        (status = 200, description = "Current version of the script", body = Script),
+        (status = 404, description = "Not found", body = ErrorMessage),
+    )
+)]
+#[instrument(skip(auth_session))]
+/// Get Script
+pub async fn get_script_handler(
+    auth_session: AuthSession,
+    Path(id): Path<u32>,
+) -> Result<Json<Script>, ScriptError> {
+    let (db, owner) = owner(auth_session)?;
+    Ok(Json(scripts::get_script(&db, &owner, id).await?))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/scripts/{id}",
+    params(("id" = u32, Path, description = "Script id")),
+    request_body = ScriptBuilder,
+    responses(
+        (status = 200, description = "Saved as a new version", body = Script),
+        (status = 404, description = "Not found", body = ErrorMessage),
+        (status = 409, description = "A script with this name exists, or the script is imported and can not be renamed", body = ErrorMessage),
+        (status = 422, description = "Script does not parse or typecheck", body = ScrapeCheckResult),
+    )
+)]
+#[instrument(skip(auth_session))]
+/// Update Script
+pub async fn update_script_handler(
+    auth_session: AuthSession,
+    Path(id): Path<u32>,
+    Json(script): Json<ScriptBuilder>,
+) -> Result<Json<Script>, ScriptError> {
+    let (db, owner) = owner(auth_session)?;
+    Ok(Json(scripts::update_script(&db, &owner, id, script).await?))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/scripts/{id}",
+    params(("id" = u32, Path, description = "Script id")),
+    responses(
+        (status = 204, description = "Script and its history deleted"),
+        (status = 404, description = "Not found", body = ErrorMessage),
+    )
+)]
+#[instrument(skip(auth_session))]
+/// Delete Script
+pub async fn delete_script_handler(
+    auth_session: AuthSession,
+    Path(id): Path<u32>,
+) -> Result<StatusCode, ScriptError> {
+    let (db, owner) = owner(auth_session)?;
+    scripts::delete_script(&db, &owner, id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/scripts/{id}/versions",
+    params(("id" = u32, Path, description = "Script id")),
+    responses(
+        (status = 200, description = "History of the script, newest first", body = [ScriptVersion]),
+        (status = 404, description = "Not found", body = ErrorMessage),
+    )
+)]
+#[instrument(skip(auth_session))]
+/// List Script Versions
+pub async fn list_versions_handler(
+    auth_session: AuthSession,
+    Path(id): Path<u32>,
+) -> Result<Json<Vec<ScriptVersion>>, ScriptError> {
+    let (db, owner) = owner(auth_session)?;
+    Ok(Json(scripts::list_versions(&db, &owner, id).await?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/scripts/{id}/versions/{version}",
+    params(
+        ("id" = u32, Path, description = "Script id"),
+        ("version" = u32, Path, description = "Version of the script"),
+    ),
+    responses(
+        (status = 200, description = "The version", body = ScriptVersion),
+        (status = 404, description = "Not found", body = ErrorMessage),
+    )
+)]
+#[instrument(skip(auth_session))]
+/// Get Script Version
+pub async fn get_version_handler(
+    auth_session: AuthSession,
+    Path((id, version)): Path<(u32, u32)>,
+) -> Result<Json<ScriptVersion>, ScriptError> {
+    let (db, owner) = owner(auth_session)?;
+    Ok(Json(scripts::get_version(&db, &owner, id, version).await?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/scripts/{id}/versions/{version}/restore",
+    params(
+        ("id" = u32, Path, description = "Script id"),
+        ("version" = u32, Path, description = "Version to restore"),
+    ),
+    responses(
+        (status = 200, description = "The old source saved as a new version", body = Script),
+        (status = 404, description = "Not found", body = ErrorMessage),
+        (status = 422, description = "The old source no longer typechecks", body = ScrapeCheckResult),
+    )
+)]
+#[instrument(skip(auth_session))]
+/// Restore Script Version
+pub async fn restore_version_handler(
+    auth_session: AuthSession,
+    Path((id, version)): Path<(u32, u32)>,
+) -> Result<Json<Script>, ScriptError> {
+    let (db, owner) = owner(auth_session)?;
+    Ok(Json(
+        scripts::restore_version(&db, &owner, id, version).await?,
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/people/{id}/scripts/{script_id}/run",
+    params(
+        ("id" = u32, Path, description = "Person id"),
+        ("script_id" = u32, Path, description = "Script id"),
+    ),
+    responses(
+        (status = 201, description = "The stored result, also if the script failed", body = ScrapeResult),
+        (status = 404, description = "Person or script not found", body = ErrorMessage),
+    )
+)]
+#[instrument(skip(auth_session, runner))]
+/// Run Script
+pub async fn run_script_handler(
+    auth_session: AuthSession,
+    Extension(runner): Extension<Runner>,
+    Path((id, script_id)): Path<(u32, u32)>,
+) -> Result<(StatusCode, Json<ScrapeResult>), ScriptError> {
+    let (db, owner) = owner(auth_session)?;
+    let result = scripts::run_script(&db, &runner, &owner, script_id, id).await?;
+    Ok((StatusCode::CREATED, Json(result)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/people/{id}/results",
+    params(("id" = u32, Path, description = "Person id")),
+    responses(
+        (status = 200, description = "The latest result of every script run against the person", body = [ScrapeResult]),
+    )
+)]
+#[instrument(skip(auth_session))]
+/// List Scrape Results
+pub async fn list_results_handler(
+    auth_session: AuthSession,
+    Path(id): Path<u32>,
+) -> Result<Json<Vec<ScrapeResult>>, ScriptError> {
+    let (db, owner) = owner(auth_session)?;
+    Ok(Json(scripts::latest_results(&db, &owner, id).await?))
+}
@@ -0,0 +1,56 @@
+use axum::{
+    extract::Query,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use tracing::{error, instrument};
+
+use super::{owner, ErrorMessage, NotAuthenticated};
+use crate::search::{self, Hit, SearchError, SearchQuery};
+use crate::users::AuthSession;
+
+impl From<NotAuthenticated> for SearchError {
+    fn from(_: NotAuthenticated) -> Self {
+        SearchError::Auth
+    }
+}
+
+impl IntoResponse for SearchError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            SearchError::InvalidQuery(_) => StatusCode::BAD_REQUEST,
+            SearchError::Auth => StatusCode::UNAUTHORIZED,
+            SearchError::Sqlx(e) => {
+                error!("search: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+        (
+            status,
+            Json(ErrorMessage {
+                message: self.to_string(),
+            }),
+        )
+            .into_response()
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/search",
+    params(SearchQuery),
+    responses(
+        (status = 200, description = "Hits in the people of the user, the best first", body = [Hit]),
+        (status = 400, description = "Nothing to search for", body = ErrorMessage),
+    )
+)]
+#[instrument(skip(auth_session))]
+/// Search
+pub async fn search_handler(
+    auth_session: AuthSession,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<Vec<Hit>>, SearchError> {
+    let (db, owner) = owner(auth_session)?;
+    Ok(Json(search::search(&db, &owner, &query).await?))
+}
@@ -0,0 +1,123 @@
+#![cfg(test)]
+use super::*;
+use axum::{body::Body, http::Request};
+use clap::Parser;
+use serde_json::json;
+use tower::ServiceExt;
+
+/// The app on a fresh database in its own directory, removed by the caller.
+async fn app(test: &str) -> (Router, std::path::PathBuf) {
+    let dir = std::env::temp_dir().join(format!("seekr-{test}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("seekr.db");
+    let _ = std::fs::remove_file(&path);
+    let args = Args::parse_from(["seekr", "--db-path", path.to_str().unwrap()]);
+    (get_router(&args).await.unwrap(), dir)
+}
+
+/// Log in as the default user, returns the session cookie.
+async fn login(app: &Router) -> String {
+    let request = Request::builder()
+        .method("POST")
+        .uri("/login")
+        .header("content-type", "application/x-www-form-urlencoded")
+        .body(Body::from("username=ferris&password=hunter42"))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    let cookie = response.headers()["set-cookie"].to_str().unwrap();
+    cookie.split(';').next().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn test_api_without_session() {
+    let (app, dir) = app("api-without-session").await;
+
+    // JSON clients get a 401 instead of the redirect to the login page
+    for (method, uri) in [
+        ("GET", "/api/v1/people"),
+        ("GET", "/api/v1/scripts/1"),
+        ("POST", "/api/v1/scrape/check"),
+        ("POST", "/graphql"),
+    ] {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(
+            response.status(),
+            StatusCode::UNAUTHORIZED,
+            "{method} {uri}"
+        );
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let error: ErrorMessage = serde_json::from_slice(&body).unwrap();
+        assert_eq!(error.message, "not authenticated");
+    }
+
+    // the web pages still redirect
+    let request = Request::builder().uri("/").body(Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Send a JSON request as the logged in user, returns the status and the JSON body.
+async fn send(
+    app: &Router,
+    cookie: &str,
+    uri: &str,
+    body: serde_json::Value,
+) -> (StatusCode, serde_json::Value) {
+    let request = Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header("content-type", "application/json")
+        .header("cookie", cookie)
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn test_check_script() {
+    let (app, dir) = app("check-script").await;
+    let cookie = login(&app).await;
+    let check = "/api/v1/scrape/check";
+
+    // scripts are checked against the fields of a person
+    let (status, result) = send(&app, &cookie, check, json!({ "source": "len(name) * 2" })).await;
+    assert_eq!(status, StatusCode::OK, "{result}");
+    assert_eq!(result, json!({ "Type": "Number" }));
+
+    // with defs and the scripts of the user they import
+    let util = json!({ "name": "util", "source": "def double(x) = x * 2" });
+    let (status, _) = send(&app, &cookie, "/api/v1/scripts", util).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let source = "import \"util\"\ndef twice(s) = util.double(len(s))\ntwice(name)";
+    let (status, result) = send(&app, &cookie, check, json!({ "source": source })).await;
+    assert_eq!(status, StatusCode::OK, "{result}");
+    assert_eq!(result, json!({ "Type": "Number" }));
+
+    // an import of the script itself is a cycle
+    let query = json!({ "source": source, "name": "util" });
+    let (status, result) = send(&app, &cookie, check, query).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{result}");
+
+    // errors keep their format
+    let (status, result) = send(&app, &cookie, check, json!({ "source": "len(name) +" })).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(result.get("ParseError").is_some(), "{result}");
+    let (status, result) = send(&app, &cookie, check, json!({ "source": "name * 2" })).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(result.get("TypeError").is_some(), "{result}");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
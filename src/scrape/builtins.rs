@@ -0,0 +1,255 @@
+use md5::Md5;
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, fmt, sync::Arc, sync::OnceLock};
+
+use super::{EvalError, TypeRepr, Value};
+
+/// Type of a parameter of a native function.
+#[derive(Debug, PartialEq, Clone)]
+pub enum ParamType {
+    Is(TypeRepr),
+    /// Accepts a value of every type, e.g. the argument of `to_str`.
+    Any,
+}
+
+impl ParamType {
+    pub fn accepts(&self, t: &TypeRepr) -> bool {
+        match self {
+            Self::Is(expected) => expected.unify(t).is_some(),
+            Self::Any => true,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Signature {
+    pub params: Vec<ParamType>,
+    pub ret: TypeRepr,
+}
+
+/// Functions taking a lambda, e.g. `map(xs, |x| x + 1)`. Lambdas are not values, so these are
+/// built into the evaluator and the type checker instead of being registered. They shadow
+/// registered functions of the same name.
+pub const HIGHER_ORDER: [&str; 3] = ["map", "filter", "fold"];
+
+/// Implementation of a native function. The arguments have already been checked against the
+/// signature of the function.
+pub type NativeFn = Arc<dyn Fn(&[Value]) -> Result<Value, EvalError> + Send + Sync>;
+
+#[derive(Clone)]
+pub struct Builtin {
+    pub signature: Signature,
+    pub f: NativeFn,
+    /// Calls count against [`Limits::max_fetches`](super::limits::Limits::max_fetches).
+    pub network: bool,
+}
+
+impl fmt::Debug for Builtin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Builtin")
+            .field("signature", &self.signature)
+            .field("network", &self.network)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The functions a scrape script can call. Both the evaluator and the type checker look up
+/// calls here, so a function registered from Rust is usable and typechecked like the standard
+/// library.
+///
+/// # Example
+///
+/// ```rust
+/// use seekr::scrape::{
+///     builtins::{ParamType, Registry},
+///     eval::eval_with,
+///     parse_expr, Env, TypeEnv, TypeRepr, Value,
+/// };
+/// let mut registry = Registry::standard().clone();
+/// registry.register(
+///     "gravatar",
+///     &[ParamType::Is(TypeRepr::Str)],
+///     TypeRepr::Str,
+///     |args| match args {
+///         [Value::Str(hash)] => Ok(Value::Str(format!("https://gravatar.com/avatar/{hash}"))),
+///         _ => Err("invalid arguments".to_string()),
+///     },
+/// );
+/// let (_, expr) = parse_expr("gravatar(md5(\"foo@example.com\"))").unwrap();
+/// assert_eq!(expr.infer_with(None, &TypeEnv::new(), &registry), Ok(TypeRepr::Str));
+/// assert_eq!(
+///     eval_with(&expr, &Env::new(), &registry),
+///     Ok(Value::Str("https://gravatar.com/avatar/b48def645758b95537d4424c84d1a9ff".to_string()))
+/// );
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Registry {
+    functions: HashMap<String, Builtin>,
+}
+
+impl Registry {
+    /// A registry without any functions.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The standard library, shared by every script that does not bring its own registry.
+    pub fn standard() -> &'static Self {
+        static STANDARD: OnceLock<Registry> = OnceLock::new();
+        STANDARD.get_or_init(|| {
+            let mut registry = Self::new();
+            register_standard(&mut registry);
+            super::html::register(&mut registry);
+            registry
+        })
+    }
+
+    /// Add a function, replacing a previously registered one with the same name. Errors are
+    /// messages for the analyst and end up in [`EvalError::Builtin`].
+    pub fn register<F>(&mut self, name: &str, params: &[ParamType], ret: TypeRepr, f: F)
+    where
+        F: Fn(&[Value]) -> Result<Value, String> + Send + Sync + 'static,
+    {
+        let owned = name.to_string();
+        self.register_native(name, params, ret, move |args| {
+            f(args).map_err(|message| EvalError::Builtin {
+                name: owned.clone(),
+                message,
+            })
+        });
+    }
+
+    /// Like [`Registry::register`] for functions that fail with their own [`EvalError`], e.g.
+    /// `fetch` with [`EvalError::Fetch`].
+    pub fn register_native<F>(&mut self, name: &str, params: &[ParamType], ret: TypeRepr, f: F)
+    where
+        F: Fn(&[Value]) -> Result<Value, EvalError> + Send + Sync + 'static,
+    {
+        self.functions.insert(
+            name.to_string(),
+            Builtin {
+                signature: Signature {
+                    params: params.to_vec(),
+                    ret,
+                },
+                f: Arc::new(f),
+                network: false,
+            },
+        );
+    }
+
+    /// Mark a registered function as doing network requests.
+    pub fn mark_network(&mut self, name: &str) {
+        if let Some(builtin) = self.functions.get_mut(name) {
+            builtin.network = true;
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Builtin> {
+        self.functions.get(name)
+    }
+
+    /// Names of all registered functions, sorted.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.functions.keys().map(String::as_str).collect();
+        names.sort();
+        names
+    }
+}
+
+fn register_standard(r: &mut Registry) {
+    use ParamType::Any;
+    const STR: ParamType = ParamType::Is(TypeRepr::Str);
+
+    fn invalid() -> String {
+        "invalid arguments".to_string()
+    }
+
+    r.register("len", &[STR], TypeRepr::Number, |args| match args {
+        [Value::Str(s)] => Ok(Value::Number(s.chars().count() as i64)),
+        _ => Err(invalid()),
+    });
+    r.register("lower", &[STR], TypeRepr::Str, |args| match args {
+        [Value::Str(s)] => Ok(Value::Str(s.to_lowercase())),
+        _ => Err(invalid()),
+    });
+    r.register("upper", &[STR], TypeRepr::Str, |args| match args {
+        [Value::Str(s)] => Ok(Value::Str(s.to_uppercase())),
+        _ => Err(invalid()),
+    });
+    r.register("trim", &[STR], TypeRepr::Str, |args| match args {
+        [Value::Str(s)] => Ok(Value::Str(s.trim().to_string())),
+        _ => Err(invalid()),
+    });
+    // split("a,b,c", ",") == ["a", "b", "c"]
+    r.register(
+        "split",
+        &[STR, STR],
+        TypeRepr::List(Box::new(TypeRepr::Str)),
+        |args| match args {
+            [Value::Str(s), Value::Str(sep)] => Ok(Value::List(
+                s.split(sep.as_str())
+                    .map(|field| Value::Str(field.to_string()))
+                    .collect(),
+            )),
+            _ => Err(invalid()),
+        },
+    );
+    r.register("contains", &[STR, STR], TypeRepr::Bool, |args| match args {
+        [Value::Str(s), Value::Str(needle)] => Ok(Value::Bool(s.contains(needle.as_str()))),
+        _ => Err(invalid()),
+    });
+    r.register(
+        "replace",
+        &[STR, STR, STR],
+        TypeRepr::Str,
+        |args| match args {
+            [Value::Str(s), Value::Str(from), Value::Str(to)] => {
+                Ok(Value::Str(s.replace(from.as_str(), to)))
+            }
+            _ => Err(invalid()),
+        },
+    );
+    r.register(
+        "regex_match",
+        &[STR, STR],
+        TypeRepr::Bool,
+        |args| match args {
+            [Value::Str(s), Value::Str(pattern)] => Regex::new(pattern)
+                .map(|re| Value::Bool(re.is_match(s)))
+                .map_err(|e| e.to_string()),
+            _ => Err(invalid()),
+        },
+    );
+    r.register("md5", &[STR], TypeRepr::Str, |args| match args {
+        [Value::Str(s)] => Ok(Value::Str(hex::encode(Md5::digest(s)))),
+        _ => Err(invalid()),
+    });
+    r.register("sha256", &[STR], TypeRepr::Str, |args| match args {
+        [Value::Str(s)] => Ok(Value::Str(hex::encode(Sha256::digest(s)))),
+        _ => Err(invalid()),
+    });
+    r.register("url_encode", &[STR], TypeRepr::Str, |args| match args {
+        [Value::Str(s)] => Ok(Value::Str(urlencoding::encode(s).into_owned())),
+        _ => Err(invalid()),
+    });
+    r.register("url_decode", &[STR], TypeRepr::Str, |args| match args {
+        [Value::Str(s)] => urlencoding::decode(s)
+            .map(|s| Value::Str(s.into_owned()))
+            .map_err(|e| e.to_string()),
+        _ => Err(invalid()),
+    });
+    r.register("to_str", &[Any], TypeRepr::Str, |args| match args {
+        [v] => Ok(Value::Str(v.to_string())),
+        _ => Err(invalid()),
+    });
+    r.register("to_num", &[STR], TypeRepr::Number, |args| match args {
+        [Value::Str(s)] => s
+            .trim()
+            .parse()
+            .map(Value::Number)
+            .map_err(|_| format!("not a number: {:?}", s)),
+        _ => Err(invalid()),
+    });
+}
@@ -0,0 +1,223 @@
+use nom::error::{VerboseError, VerboseErrorKind};
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Write};
+use thiserror::Error;
+use utoipa::ToSchema;
+
+/// Position in the scrape source, both 1-based. Columns count characters, not bytes.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Serialize, Deserialize, ToSchema)]
+pub struct Position {
+    #[schema(example = 1u32)]
+    pub line: u32,
+    #[schema(example = 5u32)]
+    pub column: u32,
+}
+
+/// Region of the scrape source, `end` is exclusive.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, ToSchema)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+/// Spans of an expression and all of its subexpressions. `children` mirrors the boxed
+/// subexpressions of the [`super::Expr`] node in order, e.g. `[condition, then, else]` for `If`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Spans {
+    pub span: Span,
+    pub children: Vec<Spans>,
+}
+
+/// Spans recorded while parsing. Positions are kept as the length of the remaining input, that
+/// way a parser does not need to know where its input starts in the source. [`RawSpans::resolve`]
+/// turns them into line and column numbers once the whole source is parsed.
+#[derive(Debug, Clone)]
+pub(super) struct RawSpans {
+    pub(super) start: usize,
+    pub(super) end: usize,
+    pub(super) children: Vec<RawSpans>,
+}
+
+impl RawSpans {
+    pub(super) fn resolve(&self, src: &str) -> Spans {
+        self.resolve_with(&LineIndex::new(src))
+    }
+
+    fn resolve_with(&self, index: &LineIndex) -> Spans {
+        Spans {
+            span: Span {
+                start: index.position(index.len - self.start),
+                end: index.position(index.len - self.end),
+            },
+            children: self
+                .children
+                .iter()
+                .map(|c| c.resolve_with(index))
+                .collect(),
+        }
+    }
+}
+
+/// Byte offsets of the line starts of a source, to turn offsets into positions.
+struct LineIndex<'a> {
+    src: &'a str,
+    len: usize,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    fn new(src: &'a str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(src.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self {
+            src,
+            len: src.len(),
+            line_starts,
+        }
+    }
+
+    fn position(&self, offset: usize) -> Position {
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let column = self.src[self.line_starts[line]..offset].chars().count();
+        Position {
+            line: line as u32 + 1,
+            column: column as u32 + 1,
+        }
+    }
+
+    fn line(&self, line: u32) -> &'a str {
+        let start = self.line_starts[line as usize - 1];
+        let end = self
+            .line_starts
+            .get(line as usize)
+            .map_or(self.len, |next| next - 1);
+        self.src[start..end].trim_end_matches('\r')
+    }
+}
+
+/// A parse error pointing at the place in the source where parsing failed.
+#[derive(Debug, PartialEq, Clone, Error, Serialize, Deserialize, ToSchema)]
+#[error("{}: {message}", span.start)]
+pub struct ParseDiagnostic {
+    #[schema(example = "expected in")]
+    pub message: String,
+    /// The token that could not be parsed, empty at the end of the source.
+    pub span: Span,
+    /// What the parser would have accepted instead, e.g. `in`, `')'` or `expression`.
+    #[schema(example = json!(["in"]))]
+    pub expected: Vec<String>,
+    /// The constructs that were being parsed, innermost first, e.g. `["if", "let"]`.
+    #[schema(example = json!(["let"]))]
+    pub context: Vec<String>,
+}
+
+impl ParseDiagnostic {
+    /// Build a diagnostic from the error stack nom collected. The first entry is the innermost
+    /// one, it decides where the error is reported.
+    pub fn from_verbose_error(src: &str, e: VerboseError<&str>) -> Self {
+        let index = LineIndex::new(src);
+        // contexts are recorded before the whitespace the parsers skip
+        let offset = |input: &str| src.len() - input.trim_start().len();
+        let error_offset = e.errors.first().map_or(src.len(), |(i, _)| offset(i));
+
+        let mut expected = vec![];
+        let mut context = vec![];
+        // the outermost context at the error position describes best what was expected, the
+        // inner ones are the alternatives that were tried
+        let mut outermost = None;
+        for (input, kind) in &e.errors {
+            match kind {
+                VerboseErrorKind::Char(c) if offset(input) == error_offset => {
+                    expected.push(format!("{:?}", c))
+                }
+                VerboseErrorKind::Context(ctx) if offset(input) == error_offset => {
+                    outermost = Some(ctx.to_string())
+                }
+                VerboseErrorKind::Context(ctx) if *ctx != "expression" => {
+                    context.push(ctx.to_string())
+                }
+                VerboseErrorKind::Nom(nom::error::ErrorKind::Eof) => {
+                    expected.push("end of input".to_string())
+                }
+                _ => (),
+            }
+        }
+        expected.extend(outermost);
+        expected.dedup();
+        context.dedup();
+
+        // underline the word at the error position
+        let token_len = src[error_offset..]
+            .find(char::is_whitespace)
+            .unwrap_or(src.len() - error_offset);
+        let span = Span {
+            start: index.position(error_offset),
+            end: index.position(error_offset + token_len),
+        };
+
+        let message = match expected.as_slice() {
+            [] => "invalid syntax".to_string(),
+            [e] => format!("expected {}", e),
+            es => format!("expected one of: {}", es.join(", ")),
+        };
+
+        Self {
+            message,
+            span,
+            expected,
+            context,
+        }
+    }
+
+    /// Whether parsing failed at the end of the source, so more input could complete it, e.g.
+    /// `let foo = 2 in`.
+    pub fn is_at_end(&self) -> bool {
+        self.span.start == self.span.end
+    }
+
+    /// Render the diagnostic with the offending source line and a caret under the token.
+    ///
+    /// ```text
+    /// error: expected in
+    ///  --> 1:13
+    ///   |
+    /// 1 | let foo = 2 foo
+    ///   |             ^^^
+    ///   = while parsing let
+    /// ```
+    pub fn render(&self, src: &str) -> String {
+        let index = LineIndex::new(src);
+        let Span { start, end } = self.span;
+        let line_no = start.line.to_string();
+        let gutter = " ".repeat(line_no.len());
+        let carets = if end.line == start.line && end.column > start.column {
+            end.column - start.column
+        } else {
+            1
+        };
+
+        let mut out = String::new();
+        let _ = writeln!(out, "error: {}", self.message);
+        let _ = writeln!(out, "{}--> {}:{}", gutter, start.line, start.column);
+        let _ = writeln!(out, "{} |", gutter);
+        let _ = writeln!(out, "{} | {}", line_no, index.line(start.line));
+        let _ = writeln!(
+            out,
+            "{} | {}{}",
+            gutter,
+            " ".repeat(start.column as usize - 1),
+            "^".repeat(carets as usize)
+        );
+        for ctx in &self.context {
+            let _ = writeln!(out, "{} = while parsing {}", gutter, ctx);
+        }
+        out
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
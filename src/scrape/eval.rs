@@ -0,0 +1,401 @@
+use std::{cmp::Ordering, collections::HashMap};
+use thiserror::Error;
+use tracing::instrument;
+
+use super::{
+    builtins::{Builtin, Registry, HIGHER_ORDER},
+    fetch::FetchError,
+    limits::{size, LimitKind, Limits, Meter, Usage},
+    Expr, Oper, Typed, Unop, Value,
+};
+
+/// Variables visible to an expression. `Let` never mutates the env of its caller, it evaluates
+/// the body in an extended copy, which gives lexical scoping and shadowing for free.
+pub type Env = HashMap<String, Value>;
+
+#[derive(Debug, PartialEq, Clone, Error)]
+pub enum EvalError {
+    #[error("division by zero")]
+    DivisionByZero,
+
+    #[error("integer overflow in {0:?}")]
+    Overflow(Oper),
+
+    #[error("invalid operands for {op:?}: {lhs:?} and {rhs:?}")]
+    InvalidOperands { op: Oper, lhs: Value, rhs: Value },
+
+    #[error("invalid operand for {op:?}: {value:?}")]
+    InvalidOperand { op: Unop, value: Value },
+
+    #[error("unbound variable: {0}")]
+    UnboundVariable(String),
+
+    #[error("condition is not a bool: {0:?}")]
+    NonBoolCondition(Value),
+
+    #[error("unknown function: {0}")]
+    UnknownFunction(String),
+
+    #[error("invalid arguments for {name}: {args:?}")]
+    InvalidArguments { name: String, args: Vec<Value> },
+
+    #[error("{name}: {message}")]
+    Builtin { name: String, message: String },
+
+    #[error("{name} takes {expected} arguments, found {found}")]
+    Arity {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+
+    #[error("index {index} out of range for list of length {len}")]
+    IndexOutOfRange { index: i64, len: usize },
+
+    #[error("can not index {value:?} with {index:?}")]
+    InvalidIndex { value: Value, index: Value },
+
+    #[error("no field {name} in {value:?}")]
+    NoField { name: String, value: Value },
+
+    #[error("{name} expects a lambda with {params} parameters")]
+    ExpectedLambda { name: String, params: usize },
+
+    #[error("a lambda can only be passed to map, filter or fold")]
+    UnexpectedLambda,
+
+    #[error(transparent)]
+    Fetch(#[from] FetchError),
+
+    #[error("{kind} limit exceeded")]
+    LimitExceeded { kind: LimitKind },
+}
+
+/// Evaluate an expression.
+///
+/// # Example
+///
+/// ```rust
+/// use seekr::scrape::{eval, parse_expr, Env, Value};
+/// let (_, expr) = parse_expr("let foo=2 in foo*21").unwrap();
+/// assert_eq!(eval(&expr, &Env::new()), Ok(Value::Number(42)));
+/// ```
+pub fn eval(expr: &Expr, env: &Env) -> Result<Value, EvalError> {
+    eval_with(expr, env, Registry::standard())
+}
+
+/// Evaluate an expression, calling the functions of `registry`.
+#[instrument(skip(registry))]
+pub fn eval_with(expr: &Expr, env: &Env, registry: &Registry) -> Result<Value, EvalError> {
+    eval_limited(expr, env, registry, &Limits::unlimited()).0
+}
+
+/// Evaluate an untrusted expression within `limits`. Returns what the run used, also when it
+/// failed, e.g. with [`EvalError::LimitExceeded`].
+///
+/// # Example
+///
+/// ```rust
+/// use seekr::scrape::{
+///     builtins::Registry,
+///     eval::eval_limited,
+///     limits::{LimitKind, Limits},
+///     parse_expr, Env, EvalError,
+/// };
+/// let (_, expr) = parse_expr("fold(split(\"a,b,c\", \",\"), 0, |n, x| n + 1)").unwrap();
+/// let limits = Limits { fuel: 10, ..Limits::default() };
+/// let (result, usage) = eval_limited(&expr, &Env::new(), Registry::standard(), &limits);
+/// assert_eq!(result, Err(EvalError::LimitExceeded { kind: LimitKind::Fuel }));
+/// assert_eq!(usage.fuel, 10);
+/// ```
+#[instrument(skip(registry))]
+pub fn eval_limited(
+    expr: &Expr,
+    env: &Env,
+    registry: &Registry,
+    limits: &Limits,
+) -> (Result<Value, EvalError>, Usage) {
+    let meter = Meter::new(limits.clone());
+    let result = Evaluator {
+        registry,
+        meter: &meter,
+    }
+    .eval(expr, env);
+    (result, meter.usage())
+}
+
+struct Evaluator<'a> {
+    registry: &'a Registry,
+    meter: &'a Meter,
+}
+
+impl Evaluator<'_> {
+    fn eval(&self, expr: &Expr, env: &Env) -> Result<Value, EvalError> {
+        let _guard = self.meter.enter()?;
+        let eval = |e: &Expr, env: &Env| self.eval(e, env);
+        match expr {
+            Expr::Value(v) => Ok(v.clone()),
+            // && and || only evaluate their right operand if the left one does not decide the
+            // result
+            Expr::Binop(op @ (Oper::And | Oper::Or), e1, e2) => match (op, eval(e1, env)?) {
+                (Oper::And, Value::Bool(false)) => Ok(Value::Bool(false)),
+                (Oper::Or, Value::Bool(true)) => Ok(Value::Bool(true)),
+                (_, lhs) => eval_binop(op, lhs, eval(e2, env)?),
+            },
+            Expr::Binop(op, e1, e2) => {
+                let value = eval_binop(op, eval(e1, env)?, eval(e2, env)?)?;
+                if let Value::Str(s) = &value {
+                    self.meter.alloc(s.len())?;
+                }
+                Ok(value)
+            }
+            Expr::Unop(Unop::Not, e) => match eval(e, env)? {
+                Value::Bool(b) => Ok(Value::Bool(!b)),
+                value => Err(EvalError::InvalidOperand {
+                    op: Unop::Not,
+                    value,
+                }),
+            },
+            Expr::Var(name) => env
+                .get(name)
+                .cloned()
+                .ok_or_else(|| EvalError::UnboundVariable(name.to_string())),
+            Expr::Let(name, e1, e2) => {
+                let value = eval(e1, env)?;
+                let mut inner = env.clone();
+                inner.insert(name.to_string(), value);
+                eval(e2, &inner)
+            }
+            // only the branch that is taken gets evaluated
+            Expr::If(condition, e1, e2) => match eval(condition, env)? {
+                Value::Bool(true) => eval(e1, env),
+                Value::Bool(false) => eval(e2, env),
+                v => Err(EvalError::NonBoolCondition(v)),
+            },
+            Expr::Call(name, args) if HIGHER_ORDER.contains(&name.as_str()) => {
+                self.eval_higher_order(name, args, env)
+            }
+            Expr::Call(name, args) => {
+                let builtin = self
+                    .registry
+                    .get(name)
+                    .ok_or_else(|| EvalError::UnknownFunction(name.to_string()))?;
+                let args = args
+                    .iter()
+                    .map(|arg| eval(arg, env))
+                    .collect::<Result<Vec<_>, _>>()?;
+                check_args(name, builtin, &args)?;
+                let value = if builtin.network {
+                    self.meter.network(|| (builtin.f)(&args))?
+                } else {
+                    (builtin.f)(&args)?
+                };
+                self.meter.alloc(size(&value))?;
+                Ok(value)
+            }
+            Expr::List(elems) => {
+                self.meter.alloc(elems.len())?;
+                elems
+                    .iter()
+                    .map(|e| eval(e, env))
+                    .collect::<Result<_, _>>()
+                    .map(Value::List)
+            }
+            Expr::Record(fields) => {
+                self.meter.alloc(fields.len())?;
+                fields
+                    .iter()
+                    .map(|(k, e)| Ok((k.clone(), eval(e, env)?)))
+                    .collect::<Result<_, _>>()
+                    .map(|fields| Value::Record(Box::new(fields)))
+            }
+            Expr::Index(e, idx) => index(eval(e, env)?, eval(idx, env)?),
+            Expr::Field(e, name) => field(eval(e, env)?, name),
+            Expr::Lambda(..) => Err(EvalError::UnexpectedLambda),
+        }
+    }
+
+    /// `map(xs, |x| ...)`, `filter(xs, |x| ...)` and `fold(xs, init, |acc, x| ...)`.
+    fn eval_higher_order(&self, name: &str, args: &[Expr], env: &Env) -> Result<Value, EvalError> {
+        let HigherOrder {
+            xs,
+            init,
+            params: names,
+            body,
+        } = higher_order(name, args)?;
+        let xs = match self.eval(xs, env)? {
+            Value::List(xs) => xs,
+            value => {
+                return Err(EvalError::InvalidArguments {
+                    name: name.to_string(),
+                    args: vec![value],
+                })
+            }
+        };
+        let apply = |bindings: Vec<Value>| {
+            self.meter.step()?;
+            let mut inner = env.clone();
+            inner.extend(names.iter().cloned().zip(bindings));
+            self.eval(body, &inner)
+        };
+        match (name, init) {
+            ("map", _) => {
+                self.meter.alloc(xs.len())?;
+                xs.into_iter()
+                    .map(|x| apply(vec![x]))
+                    .collect::<Result<_, _>>()
+                    .map(Value::List)
+            }
+            ("filter", _) => {
+                let mut kept = vec![];
+                for x in xs {
+                    match apply(vec![x.clone()])? {
+                        Value::Bool(true) => kept.push(x),
+                        Value::Bool(false) => (),
+                        v => return Err(EvalError::NonBoolCondition(v)),
+                    }
+                }
+                self.meter.alloc(kept.len())?;
+                Ok(Value::List(kept))
+            }
+            (_, init) => {
+                let init = init.map_or(Ok(Value::Null), |init| self.eval(init, env))?;
+                xs.into_iter().try_fold(init, |acc, x| apply(vec![acc, x]))
+            }
+        }
+    }
+}
+
+/// The arguments of a call of one of [`HIGHER_ORDER`].
+pub(super) struct HigherOrder<'e> {
+    pub(super) xs: &'e Expr,
+    /// The initial accumulator of `fold`.
+    pub(super) init: Option<&'e Expr>,
+    pub(super) params: &'e [String],
+    pub(super) body: &'e Expr,
+}
+
+/// Check the number of arguments of a higher order function and that the last one is a lambda
+/// with the right number of parameters.
+pub(super) fn higher_order<'e>(name: &str, args: &'e [Expr]) -> Result<HigherOrder<'e>, EvalError> {
+    let (xs, init, lambda) = match args {
+        [xs, init, lambda] if name == "fold" => (xs, Some(init), lambda),
+        [xs, lambda] if name != "fold" => (xs, None, lambda),
+        _ => {
+            return Err(EvalError::Arity {
+                name: name.to_string(),
+                expected: if name == "fold" { 3 } else { 2 },
+                found: args.len(),
+            })
+        }
+    };
+    let params = if init.is_some() { 2 } else { 1 };
+    match lambda {
+        Expr::Lambda(names, body) if names.len() == params => Ok(HigherOrder {
+            xs,
+            init,
+            params: names,
+            body,
+        }),
+        _ => Err(EvalError::ExpectedLambda {
+            name: name.to_string(),
+            params,
+        }),
+    }
+}
+
+/// Fails with [`EvalError::InvalidArguments`] unless `args` match the signature of `builtin`.
+pub(super) fn check_args(name: &str, builtin: &Builtin, args: &[Value]) -> Result<(), EvalError> {
+    let params = &builtin.signature.params;
+    let valid = params.len() == args.len()
+        && params.iter().zip(args).all(|(param, arg)| {
+            param.accepts(&Value::get_type(arg.clone()).unwrap_or_else(|e| match e {}))
+        });
+    if valid {
+        Ok(())
+    } else {
+        Err(EvalError::InvalidArguments {
+            name: name.to_string(),
+            args: args.to_vec(),
+        })
+    }
+}
+
+/// `xs[0]` and `record["name"]`.
+pub(super) fn index(value: Value, index: Value) -> Result<Value, EvalError> {
+    match (value, index) {
+        (Value::List(xs), Value::Number(index)) => usize::try_from(index)
+            .ok()
+            .and_then(|i| xs.get(i))
+            .cloned()
+            .ok_or(EvalError::IndexOutOfRange {
+                index,
+                len: xs.len(),
+            }),
+        (Value::Record(mut fields), Value::Str(name)) => {
+            fields.shift_remove(&name).ok_or(EvalError::NoField {
+                name,
+                value: Value::Record(fields),
+            })
+        }
+        (value, index) => Err(EvalError::InvalidIndex { value, index }),
+    }
+}
+
+/// `record.name`.
+pub(super) fn field(value: Value, name: &str) -> Result<Value, EvalError> {
+    match value {
+        Value::Record(mut fields) => fields.shift_remove(name).ok_or(EvalError::NoField {
+            name: name.to_string(),
+            value: Value::Record(fields),
+        }),
+        value => Err(EvalError::NoField {
+            name: name.to_string(),
+            value,
+        }),
+    }
+}
+
+pub(super) fn eval_binop(op: &Oper, lhs: Value, rhs: Value) -> Result<Value, EvalError> {
+    match (op, lhs, rhs) {
+        (Oper::Add, Value::Str(a), Value::Str(b)) => Ok(Value::Str(a + &b)),
+        (Oper::Div, Value::Number(_), Value::Number(0)) => Err(EvalError::DivisionByZero),
+        (Oper::Add | Oper::Sub | Oper::Mul | Oper::Div, Value::Number(a), Value::Number(b)) => {
+            let result = match op {
+                Oper::Add => a.checked_add(b),
+                Oper::Sub => a.checked_sub(b),
+                Oper::Mul => a.checked_mul(b),
+                Oper::Div => a.checked_div(b),
+                _ => unreachable!(),
+            };
+            result
+                .map(Value::Number)
+                .ok_or_else(|| EvalError::Overflow(op.clone()))
+        }
+        (Oper::Eq, a, b) => Ok(Value::Bool(a == b)),
+        (Oper::Neq, a, b) => Ok(Value::Bool(a != b)),
+        (Oper::Lt | Oper::Le | Oper::Gt | Oper::Ge, Value::Number(a), Value::Number(b)) => {
+            Ok(Value::Bool(compare(op, a.cmp(&b))))
+        }
+        (Oper::Lt | Oper::Le | Oper::Gt | Oper::Ge, Value::Str(a), Value::Str(b)) => {
+            Ok(Value::Bool(compare(op, a.cmp(&b))))
+        }
+        (Oper::And, Value::Bool(a), Value::Bool(b)) => Ok(Value::Bool(a && b)),
+        (Oper::Or, Value::Bool(a), Value::Bool(b)) => Ok(Value::Bool(a || b)),
+        (op, lhs, rhs) => Err(EvalError::InvalidOperands {
+            op: op.clone(),
+            lhs,
+            rhs,
+        }),
+    }
+}
+
+fn compare(op: &Oper, ordering: Ordering) -> bool {
+    match op {
+        Oper::Lt => ordering.is_lt(),
+        Oper::Le => ordering.is_le(),
+        Oper::Gt => ordering.is_gt(),
+        Oper::Ge => ordering.is_ge(),
+        _ => unreachable!(),
+    }
+}
@@ -0,0 +1,536 @@
+use indexmap::IndexMap;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    io::Read,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+use tracing::instrument;
+
+use super::{
+    builtins::{ParamType, Registry},
+    limits::take_fetch,
+    EvalError, TypeRepr, Value,
+};
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Request {
+    pub method: String,
+    pub url: String,
+    pub headers: IndexMap<String, String>,
+    pub body: Option<String>,
+    /// Set by [`PolicyTransport`], not part of recorded fixtures.
+    #[serde(skip)]
+    pub timeout: Option<Duration>,
+    /// Transports stop reading the body after this many bytes. Set by [`PolicyTransport`].
+    #[serde(skip)]
+    pub max_body: Option<usize>,
+}
+
+impl Request {
+    pub fn get(url: &str) -> Self {
+        Self {
+            method: "GET".to_string(),
+            url: url.to_string(),
+            headers: IndexMap::new(),
+            body: None,
+            timeout: None,
+            max_body: None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Response {
+    pub status: u16,
+    pub headers: IndexMap<String, String>,
+    pub body: String,
+}
+
+#[derive(Debug, PartialEq, Clone, Error)]
+pub enum FetchError {
+    #[error("host {host} is not allowed")]
+    DeniedHost { host: String },
+
+    #[error("invalid url {url}: {reason}")]
+    InvalidUrl { url: String, reason: String },
+
+    #[error("invalid fetch options: {0}")]
+    InvalidOptions(String),
+
+    #[error("more than {limit} redirects")]
+    TooManyRedirects { limit: usize },
+
+    /// Following a redirect would exceed the fetches the script may make.
+    #[error("fetch limit exceeded following a redirect")]
+    FetchLimit,
+
+    #[error("response body is larger than {limit} bytes")]
+    BodyTooLarge { limit: usize },
+
+    #[error("request to {url} timed out")]
+    Timeout { url: String },
+
+    #[error("no recorded response for {method} {url}")]
+    NotRecorded { method: String, url: String },
+
+    #[error("fixture {path}: {reason}")]
+    Fixture { path: PathBuf, reason: String },
+
+    #[error("http error: {0}")]
+    Http(String),
+}
+
+/// Sends requests for `fetch`. Scripts run synchronously, so transports block. Implementations
+/// are stacked, e.g. a [`PolicyTransport`] around a [`Recorder`] around the [`HttpTransport`].
+pub trait Transport: Send + Sync {
+    fn send(&self, request: &Request) -> Result<Response, FetchError>;
+}
+
+impl<T: Transport + ?Sized> Transport for Arc<T> {
+    fn send(&self, request: &Request) -> Result<Response, FetchError> {
+        (**self).send(request)
+    }
+}
+
+/// Talks to the network. Must not be used from an async context, run scripts with
+/// `tokio::task::spawn_blocking` there. Redirects are returned as they are, [`PolicyTransport`]
+/// follows them within its policy.
+#[derive(Debug)]
+pub struct HttpTransport {
+    client: reqwest::blocking::Client,
+}
+
+impl Default for HttpTransport {
+    fn default() -> Self {
+        let client = reqwest::blocking::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .expect("the HTTP client can be created");
+        Self { client }
+    }
+}
+
+impl HttpTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Transport for HttpTransport {
+    #[instrument(skip(self))]
+    fn send(&self, request: &Request) -> Result<Response, FetchError> {
+        let method = reqwest::Method::from_bytes(request.method.as_bytes())
+            .map_err(|e| FetchError::InvalidOptions(e.to_string()))?;
+        let mut builder = self.client.request(method, &request.url);
+        for (name, value) in &request.headers {
+            builder = builder.header(name, value);
+        }
+        if let Some(body) = &request.body {
+            builder = builder.body(body.clone());
+        }
+        if let Some(timeout) = request.timeout {
+            builder = builder.timeout(timeout);
+        }
+        let http_error = |e: reqwest::Error| {
+            if e.is_timeout() {
+                FetchError::Timeout {
+                    url: request.url.clone(),
+                }
+            } else {
+                FetchError::Http(e.to_string())
+            }
+        };
+        let response = builder.send().map_err(http_error)?;
+        let status = response.status().as_u16();
+        let headers = response
+            .headers()
+            .iter()
+            .map(|(k, v)| {
+                (
+                    k.to_string(),
+                    String::from_utf8_lossy(v.as_bytes()).into_owned(),
+                )
+            })
+            .collect();
+        // read one byte more than allowed to notice oversized bodies without buffering them
+        let limit = request.max_body.map_or(u64::MAX, |max| max as u64 + 1);
+        let mut body = vec![];
+        response
+            .take(limit)
+            .read_to_end(&mut body)
+            .map_err(|e| FetchError::Http(e.to_string()))?;
+        Ok(Response {
+            status,
+            headers,
+            body: String::from_utf8_lossy(&body).into_owned(),
+        })
+    }
+}
+
+/// A request and the response it got, the unit of a fixture file.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Exchange {
+    pub request: Request,
+    pub response: Response,
+}
+
+fn write_fixture(path: &Path, exchanges: &[Exchange]) -> Result<(), FetchError> {
+    let fixture_error = |reason: String| FetchError::Fixture {
+        path: path.to_path_buf(),
+        reason,
+    };
+    let json = serde_json::to_string_pretty(exchanges).map_err(|e| fixture_error(e.to_string()))?;
+    std::fs::write(path, json).map_err(|e| fixture_error(e.to_string()))
+}
+
+/// Passes requests on to `inner` and writes every exchange to a fixture file that [`Replay`]
+/// can serve later.
+pub struct Recorder<T> {
+    inner: T,
+    path: PathBuf,
+    exchanges: Mutex<Vec<Exchange>>,
+}
+
+impl<T: Transport> Recorder<T> {
+    pub fn new(inner: T, path: impl Into<PathBuf>) -> Self {
+        Self {
+            inner,
+            path: path.into(),
+            exchanges: Mutex::new(vec![]),
+        }
+    }
+}
+
+impl<T: Transport> Transport for Recorder<T> {
+    fn send(&self, request: &Request) -> Result<Response, FetchError> {
+        let response = self.inner.send(request)?;
+        let mut exchanges = self.exchanges.lock().unwrap_or_else(|e| e.into_inner());
+        exchanges.push(Exchange {
+            request: request.clone(),
+            response: response.clone(),
+        });
+        // rewritten on every request so an aborted script still leaves a usable fixture
+        write_fixture(&self.path, &exchanges)?;
+        Ok(response)
+    }
+}
+
+/// Answers requests from a fixture file written by [`Recorder`], without touching the network.
+/// Requests match on method, url and body.
+#[derive(Debug, Default)]
+pub struct Replay {
+    exchanges: Vec<Exchange>,
+}
+
+impl Replay {
+    pub fn new(exchanges: Vec<Exchange>) -> Self {
+        Self { exchanges }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, FetchError> {
+        let path = path.as_ref();
+        let fixture_error = |reason: String| FetchError::Fixture {
+            path: path.to_path_buf(),
+            reason,
+        };
+        let json = std::fs::read_to_string(path).map_err(|e| fixture_error(e.to_string()))?;
+        let exchanges = serde_json::from_str(&json).map_err(|e| fixture_error(e.to_string()))?;
+        Ok(Self::new(exchanges))
+    }
+}
+
+impl Transport for Replay {
+    fn send(&self, request: &Request) -> Result<Response, FetchError> {
+        self.exchanges
+            .iter()
+            .find(|e| {
+                e.request.method == request.method
+                    && e.request.url == request.url
+                    && e.request.body == request.body
+            })
+            .map(|e| e.response.clone())
+            .ok_or_else(|| FetchError::NotRecorded {
+                method: request.method.clone(),
+                url: request.url.clone(),
+            })
+    }
+}
+
+/// What scripts are allowed to fetch.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Policy {
+    /// Hosts scripts may talk to, `*.example.com` allows all subdomains. Nothing is allowed if
+    /// empty.
+    pub allowed_hosts: Vec<String>,
+    /// Minimum time between two requests to the same host.
+    pub rate_limit: Option<Duration>,
+    /// Maximum size of a response body in bytes.
+    pub max_body: usize,
+    pub timeout: Duration,
+    /// Sent unless the script sets its own `User-Agent` header.
+    pub user_agent: String,
+    /// Redirects followed for one request. Every one counts as a fetch of the script.
+    pub max_redirects: usize,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self {
+            allowed_hosts: vec![],
+            rate_limit: Some(Duration::from_secs(1)),
+            max_body: 5 * 1024 * 1024,
+            timeout: Duration::from_secs(30),
+            user_agent: concat!("seekr/", env!("CARGO_PKG_VERSION")).to_string(),
+            max_redirects: 10,
+        }
+    }
+}
+
+impl Policy {
+    pub fn allows(&self, host: &str) -> bool {
+        self.allowed_hosts
+            .iter()
+            .any(|allowed| match allowed.strip_prefix("*.") {
+                Some(domain) => host
+                    .strip_suffix(domain)
+                    .is_some_and(|sub| sub.ends_with('.')),
+                None => allowed == host,
+            })
+    }
+}
+
+/// Headers not sent on when a redirect leads to another host.
+const CREDENTIAL_HEADERS: [&str; 3] = ["authorization", "cookie", "proxy-authorization"];
+
+/// Where a response redirects to, if it is a redirect.
+fn redirect_location(response: &Response) -> Option<&str> {
+    if !matches!(response.status, 301 | 302 | 303 | 307 | 308) {
+        return None;
+    }
+    response
+        .headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case("location"))
+        .map(|(_, v)| v.as_str())
+}
+
+/// Enforces a [`Policy`] before requests reach `inner`. Redirects are followed here, so every
+/// hop is checked against the policy.
+pub struct PolicyTransport<T> {
+    policy: Policy,
+    inner: T,
+    /// Earliest time the next request to a host may be sent.
+    next_slot: Mutex<HashMap<String, Instant>>,
+}
+
+impl<T: Transport> PolicyTransport<T> {
+    pub fn new(policy: Policy, inner: T) -> Self {
+        Self {
+            policy,
+            inner,
+            next_slot: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The url of a request, if the policy allows requests to it.
+    fn check(&self, url: &str) -> Result<Url, FetchError> {
+        let invalid = |reason: &str| FetchError::InvalidUrl {
+            url: url.to_string(),
+            reason: reason.to_string(),
+        };
+        let parsed = Url::parse(url).map_err(|e| invalid(&e.to_string()))?;
+        if !matches!(parsed.scheme(), "http" | "https") {
+            return Err(invalid("only http and https are supported"));
+        }
+        let host = parsed.host_str().ok_or_else(|| invalid("no host"))?;
+        if !self.policy.allows(host) {
+            return Err(FetchError::DeniedHost {
+                host: host.to_string(),
+            });
+        }
+        Ok(parsed)
+    }
+
+    /// Block until a request to `host` is allowed by the rate limit.
+    fn wait_for(&self, host: &str) {
+        let Some(interval) = self.policy.rate_limit else {
+            return;
+        };
+        let now = Instant::now();
+        let slot = {
+            let mut next_slot = self.next_slot.lock().unwrap_or_else(|e| e.into_inner());
+            let slot = next_slot.get(host).map_or(now, |&next| next.max(now));
+            next_slot.insert(host.to_string(), slot + interval);
+            slot
+        };
+        thread::sleep(slot - now);
+    }
+}
+
+impl<T: Transport> Transport for PolicyTransport<T> {
+    #[instrument(skip(self))]
+    fn send(&self, request: &Request) -> Result<Response, FetchError> {
+        let mut url = self.check(&request.url)?;
+        let mut request = request.clone();
+        if !request
+            .headers
+            .keys()
+            .any(|k| k.eq_ignore_ascii_case("user-agent"))
+        {
+            request
+                .headers
+                .insert("User-Agent".to_string(), self.policy.user_agent.clone());
+        }
+        request.timeout = Some(self.policy.timeout);
+        request.max_body = Some(self.policy.max_body);
+
+        let mut redirects = 0;
+        loop {
+            self.wait_for(url.host_str().unwrap_or_default());
+            let response = self.inner.send(&request)?;
+            if response.body.len() > self.policy.max_body {
+                return Err(FetchError::BodyTooLarge {
+                    limit: self.policy.max_body,
+                });
+            }
+            let Some(location) = redirect_location(&response) else {
+                return Ok(response);
+            };
+            if redirects == self.policy.max_redirects {
+                return Err(FetchError::TooManyRedirects {
+                    limit: self.policy.max_redirects,
+                });
+            }
+            redirects += 1;
+            let next = url.join(location).map_err(|e| FetchError::InvalidUrl {
+                url: location.to_string(),
+                reason: e.to_string(),
+            })?;
+            let next = self.check(next.as_str())?;
+            if !take_fetch() {
+                return Err(FetchError::FetchLimit);
+            }
+            // like browsers, 303 and a 301 or 302 after a POST continue with a GET
+            if response.status == 303
+                || (matches!(response.status, 301 | 302) && request.method == "POST")
+            {
+                request.method = "GET".to_string();
+                request.body = None;
+            }
+            if next.host_str() != url.host_str() {
+                request
+                    .headers
+                    .retain(|k, _| !CREDENTIAL_HEADERS.iter().any(|h| k.eq_ignore_ascii_case(h)));
+            }
+            request.url = next.to_string();
+            url = next;
+        }
+    }
+}
+
+impl From<Response> for Value {
+    fn from(response: Response) -> Self {
+        let headers = response
+            .headers
+            .into_iter()
+            .map(|(k, v)| (k, Value::Str(v)))
+            .collect();
+        Value::Record(Box::new(IndexMap::from([
+            ("status".to_string(), Value::Number(response.status.into())),
+            ("headers".to_string(), Value::Record(Box::new(headers))),
+            ("body".to_string(), Value::Str(response.body)),
+        ])))
+    }
+}
+
+/// Build a request from the arguments of `fetch(url, {headers, method, body})`. `options` may
+/// be `null` or leave out fields.
+fn request(url: &str, options: &Value) -> Result<Request, FetchError> {
+    let mut request = Request::get(url);
+    let fields = match options {
+        Value::Null => return Ok(request),
+        Value::Record(fields) => fields,
+        v => return Err(FetchError::InvalidOptions(format!("not a record: {}", v))),
+    };
+    for (key, value) in fields.iter() {
+        match (key.as_str(), value) {
+            ("method", Value::Str(method)) => request.method = method.to_uppercase(),
+            ("body", Value::Str(body)) => request.body = Some(body.clone()),
+            ("body", Value::Null) => request.body = None,
+            ("headers", Value::Record(headers)) => {
+                for (name, value) in headers.iter() {
+                    let Value::Str(value) = value else {
+                        return Err(FetchError::InvalidOptions(format!(
+                            "header {} is not a string",
+                            name
+                        )));
+                    };
+                    request.headers.insert(name.clone(), value.clone());
+                }
+            }
+            (key, value) => return Err(FetchError::InvalidOptions(format!("{}: {}", key, value))),
+        }
+    }
+    Ok(request)
+}
+
+/// Register `fetch(url, options)`, sending requests through `transport`. Not part of the
+/// standard library, only scripts that are given a transport can reach the network.
+///
+/// # Example
+///
+/// ```rust
+/// use seekr::scrape::{
+///     builtins::Registry,
+///     eval::eval_with,
+///     fetch::{self, Exchange, Policy, PolicyTransport, Replay, Request, Response},
+///     parse_expr, Env, EvalError, Value,
+/// };
+/// use std::sync::Arc;
+///
+/// let replay = Replay::new(vec![Exchange {
+///     request: Request::get("https://example.com/ada"),
+///     response: Response { status: 200, headers: Default::default(), body: "hi".to_string() },
+/// }]);
+/// let policy = Policy { allowed_hosts: vec!["example.com".to_string()], ..Policy::default() };
+/// let mut registry = Registry::standard().clone();
+/// fetch::register(&mut registry, Arc::new(PolicyTransport::new(policy, replay)));
+///
+/// let (_, expr) = parse_expr(r#"fetch("https://example.com/ada", {}).body"#).unwrap();
+/// assert_eq!(eval_with(&expr, &Env::new(), &registry), Ok(Value::Str("hi".to_string())));
+///
+/// let (_, expr) = parse_expr(r#"fetch("https://evil.example/", null)"#).unwrap();
+/// assert_eq!(
+///     eval_with(&expr, &Env::new(), &registry),
+///     Err(EvalError::Fetch(fetch::FetchError::DeniedHost { host: "evil.example".to_string() }))
+/// );
+/// ```
+pub fn register(r: &mut Registry, transport: Arc<dyn Transport>) {
+    let ret = TypeRepr::Record(Box::new(IndexMap::from([
+        ("status".to_string(), TypeRepr::Number),
+        ("headers".to_string(), TypeRepr::Any),
+        ("body".to_string(), TypeRepr::Str),
+    ])));
+    r.register_native(
+        "fetch",
+        &[ParamType::Is(TypeRepr::Str), ParamType::Any],
+        ret,
+        move |args| match args {
+            [Value::Str(url), options] => {
+                let request = request(url, options)?;
+                Ok(transport.send(&request)?.into())
+            }
+            _ => Err(EvalError::InvalidArguments {
+                name: "fetch".to_string(),
+                args: args.to_vec(),
+            }),
+        },
+    );
+    r.mark_network("fetch");
+}
@@ -0,0 +1,94 @@
+use jsonpath_rust::{path::config::JsonPathConfig, JsonPathInst};
+use scraper::{Html, Selector};
+use std::str::FromStr;
+
+use super::{
+    builtins::{ParamType, Registry},
+    TypeRepr, Value,
+};
+
+/// Register the document functions. HTML nodes are passed around as their outer HTML, so a
+/// node returned by `select` can be handed to `select`, `attr` or `text` again and results
+/// stay plain strings that can be stored.
+///
+/// - `select(html, "div.bio > p")`: every element matching the CSS selector
+/// - `attr(node, "href")`: attribute of the outermost element, `null` if it is missing
+/// - `text(node)`: text content with whitespace collapsed
+/// - `json_path(body, "$.data.user")`: every match of the JSONPath, `body` is JSON text or a
+///   value
+pub fn register(r: &mut Registry) {
+    const STR: ParamType = ParamType::Is(TypeRepr::Str);
+
+    r.register(
+        "select",
+        &[STR, STR],
+        TypeRepr::List(Box::new(TypeRepr::Str)),
+        |args| match args {
+            [Value::Str(html), Value::Str(selector)] => select(html, selector),
+            _ => Err("invalid arguments".to_string()),
+        },
+    );
+    // `Any` because a missing attribute is `null`
+    r.register("attr", &[STR, STR], TypeRepr::Any, |args| match args {
+        [Value::Str(node), Value::Str(name)] => Ok(attr(node, name)),
+        _ => Err("invalid arguments".to_string()),
+    });
+    r.register("text", &[STR], TypeRepr::Str, |args| match args {
+        [Value::Str(node)] => Ok(Value::Str(text(node))),
+        _ => Err("invalid arguments".to_string()),
+    });
+    r.register(
+        "json_path",
+        &[ParamType::Any, STR],
+        TypeRepr::List(Box::new(TypeRepr::Any)),
+        |args| match args {
+            [body, Value::Str(path)] => json_path(body, path),
+            _ => Err("invalid arguments".to_string()),
+        },
+    );
+}
+
+fn select(html: &str, selector: &str) -> Result<Value, String> {
+    let selector = Selector::parse(selector).map_err(|e| e.to_string())?;
+    Ok(Value::List(
+        Html::parse_document(html)
+            .select(&selector)
+            .map(|element| Value::Str(element.html()))
+            .collect(),
+    ))
+}
+
+fn attr(node: &str, name: &str) -> Value {
+    let fragment = Html::parse_fragment(node);
+    // the root of a fragment is a synthetic <html> element wrapping the node
+    let element = fragment
+        .root_element()
+        .children()
+        .find_map(scraper::ElementRef::wrap);
+    element
+        .and_then(|e| e.value().attr(name))
+        .map_or(Value::Null, |v| Value::Str(v.to_string()))
+}
+
+fn text(node: &str) -> String {
+    Html::parse_fragment(node)
+        .root_element()
+        .text()
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn json_path(body: &Value, path: &str) -> Result<Value, String> {
+    let json = match body {
+        Value::Str(s) => serde_json::from_str(s).map_err(|e| e.to_string())?,
+        v => serde_json::Value::from(v.clone()),
+    };
+    let path = JsonPathInst::from_str(path)?;
+    path.find_slice(&json, JsonPathConfig::default())
+        .into_iter()
+        .map(|found| Value::try_from((*found).clone()).map_err(|e| e.to_string()))
+        .collect::<Result<_, _>>()
+        .map(Value::List)
+}
@@ -0,0 +1,268 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    fmt,
+    path::Path,
+    time::{Duration, Instant},
+};
+use utoipa::ToSchema;
+
+use super::{EvalError, Value};
+
+/// Resources a single script run may use. Missing fields in a configuration file take the
+/// defaults.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(default)]
+pub struct Limits {
+    /// Evaluation steps, every evaluated node and lambda call costs one.
+    pub fuel: u64,
+    /// Nesting of evaluated expressions.
+    pub max_depth: usize,
+    /// Bytes of strings plus elements of lists and records created by the script.
+    pub max_alloc: usize,
+    pub max_fetches: usize,
+    pub timeout_ms: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            fuel: 1_000_000,
+            max_depth: 256,
+            max_alloc: 64 * 1024 * 1024,
+            max_fetches: 20,
+            timeout_ms: 60_000,
+        }
+    }
+}
+
+impl Limits {
+    /// No limits, for trusted expressions.
+    pub fn unlimited() -> Self {
+        Self {
+            fuel: u64::MAX,
+            max_depth: usize::MAX,
+            max_alloc: usize::MAX,
+            max_fetches: usize::MAX,
+            timeout_ms: u64::MAX,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, ToSchema)]
+pub enum LimitKind {
+    Fuel,
+    Depth,
+    Alloc,
+    Fetches,
+    Time,
+}
+
+impl fmt::Display for LimitKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LimitKind::Fuel => "fuel",
+            LimitKind::Depth => "recursion depth",
+            LimitKind::Alloc => "allocation",
+            LimitKind::Fetches => "fetches",
+            LimitKind::Time => "time",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// What a run used, stored along with its result and the limits it ran under.
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct Usage {
+    pub fuel: u64,
+    /// Deepest nesting reached.
+    pub depth: usize,
+    pub alloc: usize,
+    pub fetches: usize,
+    pub elapsed_ms: u64,
+}
+
+/// Limits per user, read from a TOML file:
+///
+/// ```toml
+/// [default]
+/// fuel = 100000
+///
+/// [users.alice]
+/// max_fetches = 100
+/// ```
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LimitsConfig {
+    pub default: Limits,
+    pub users: HashMap<String, Limits>,
+}
+
+impl LimitsConfig {
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Ok(toml::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    pub fn for_user(&self, username: &str) -> &Limits {
+        self.users.get(username).unwrap_or(&self.default)
+    }
+}
+
+/// Counts the resources of one run against its [`Limits`]. The evaluator only holds shared
+/// references, so counters are cells.
+#[derive(Debug)]
+pub(super) struct Meter {
+    limits: Limits,
+    started: Instant,
+    fuel: Cell<u64>,
+    depth: Cell<usize>,
+    max_depth: Cell<usize>,
+    alloc: Cell<usize>,
+    fetches: Cell<usize>,
+}
+
+/// Leaves a level of nesting when dropped, see [`Meter::enter`].
+pub(super) struct DepthGuard<'a>(&'a Meter);
+
+impl Drop for DepthGuard<'_> {
+    fn drop(&mut self) {
+        self.0.depth.set(self.0.depth.get() - 1);
+    }
+}
+
+/// Requests the network builtin being called may still send after its first, e.g. to follow
+/// redirects, and what it used of them. See [`Meter::network`] and [`take_fetch`].
+#[derive(Debug, Clone, Copy, Default)]
+struct FetchBudget {
+    remaining: usize,
+    used: usize,
+    exceeded: bool,
+}
+
+thread_local! {
+    /// The budget of the network builtin called on this thread, `None` outside of a call.
+    static FETCH_BUDGET: Cell<Option<FetchBudget>> = const { Cell::new(None) };
+}
+
+/// Count one more request of the network builtin being called, e.g. a redirect a transport
+/// follows. `false` if that would exceed [`Limits::max_fetches`] of the run. Always `true` outside
+/// of evaluation, e.g. when a transport is used directly.
+pub fn take_fetch() -> bool {
+    FETCH_BUDGET.with(|cell| {
+        let Some(mut budget) = cell.get() else {
+            return true;
+        };
+        let taken = budget.remaining > 0;
+        if taken {
+            budget.remaining -= 1;
+            budget.used += 1;
+        } else {
+            budget.exceeded = true;
+        }
+        cell.set(Some(budget));
+        taken
+    })
+}
+
+fn exceeded(kind: LimitKind) -> EvalError {
+    EvalError::LimitExceeded { kind }
+}
+
+impl Meter {
+    pub(super) fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            started: Instant::now(),
+            fuel: Cell::new(0),
+            depth: Cell::new(0),
+            max_depth: Cell::new(0),
+            alloc: Cell::new(0),
+            fetches: Cell::new(0),
+        }
+    }
+
+    /// Spend one unit of fuel and enter a level of nesting, until the guard is dropped.
+    pub(super) fn enter(&self) -> Result<DepthGuard<'_>, EvalError> {
+        self.step()?;
+        let depth = self.depth.get() + 1;
+        if depth > self.limits.max_depth {
+            return Err(exceeded(LimitKind::Depth));
+        }
+        self.depth.set(depth);
+        self.max_depth.set(self.max_depth.get().max(depth));
+        Ok(DepthGuard(self))
+    }
+
+    pub(super) fn step(&self) -> Result<(), EvalError> {
+        let fuel = self.fuel.get() + 1;
+        if fuel > self.limits.fuel {
+            return Err(exceeded(LimitKind::Fuel));
+        }
+        self.fuel.set(fuel);
+        if self.started.elapsed() > Duration::from_millis(self.limits.timeout_ms) {
+            return Err(exceeded(LimitKind::Time));
+        }
+        Ok(())
+    }
+
+    /// Account for `amount` bytes or elements created by the script.
+    pub(super) fn alloc(&self, amount: usize) -> Result<(), EvalError> {
+        let alloc = self.alloc.get().saturating_add(amount);
+        if alloc > self.limits.max_alloc {
+            return Err(exceeded(LimitKind::Alloc));
+        }
+        self.alloc.set(alloc);
+        Ok(())
+    }
+
+    pub(super) fn fetch(&self) -> Result<(), EvalError> {
+        let fetches = self.fetches.get() + 1;
+        if fetches > self.limits.max_fetches {
+            return Err(exceeded(LimitKind::Fetches));
+        }
+        self.fetches.set(fetches);
+        Ok(())
+    }
+
+    /// Call a network builtin. The call counts as one fetch, and every further request it sends,
+    /// see [`take_fetch`], as one more.
+    pub(super) fn network<R>(
+        &self,
+        call: impl FnOnce() -> Result<R, EvalError>,
+    ) -> Result<R, EvalError> {
+        self.fetch()?;
+        let budget = FetchBudget {
+            remaining: self.limits.max_fetches - self.fetches.get(),
+            ..FetchBudget::default()
+        };
+        let outer = FETCH_BUDGET.replace(Some(budget));
+        let result = call();
+        let budget = FETCH_BUDGET.replace(outer).unwrap_or_default();
+        self.fetches.set(self.fetches.get() + budget.used);
+        if budget.exceeded {
+            return Err(exceeded(LimitKind::Fetches));
+        }
+        result
+    }
+
+    pub(super) fn usage(&self) -> Usage {
+        Usage {
+            fuel: self.fuel.get(),
+            depth: self.max_depth.get(),
+            alloc: self.alloc.get(),
+            fetches: self.fetches.get(),
+            elapsed_ms: self.started.elapsed().as_millis() as u64,
+        }
+    }
+}
+
+/// Bytes of strings plus the number of list and record elements, recursively.
+pub(super) fn size(value: &Value) -> usize {
+    match value {
+        Value::Str(s) => s.len(),
+        Value::List(xs) => xs.len() + xs.iter().map(size).sum::<usize>(),
+        Value::Record(fields) => fields.iter().map(|(k, v)| 1 + k.len() + size(v)).sum(),
+        Value::Number(_) | Value::Bool(_) | Value::Null => 0,
+    }
+}
@@ -1,41 +1,91 @@
 use nom::{
     branch::alt,
-    bytes::complete::{escaped_transform, tag},
-    character::complete::{alpha1, char, multispace0, multispace1},
-    character::complete::{digit1, one_of},
-    combinator::{cut, fail, map, map_res, opt, value},
-    error::{context, ContextError, ParseError},
+    bytes::complete::{is_not, tag, take_until, take_while_m_n},
+    character::complete::digit1,
+    character::complete::{alpha1, alphanumeric1, char, multispace0, multispace1, satisfy},
+    combinator::{cut, eof, fail, map, map_opt, not, opt, recognize, value, verify},
+    error::{context, ContextError, ParseError, VerboseError},
+    multi::{fold_many0, many0_count, separated_list0, separated_list1},
     sequence::{delimited, pair, preceded, terminated},
     IResult, Parser,
 };
-use std::{collections::HashMap, str::FromStr};
+use serde::{Deserialize, Serialize};
+use std::{convert::Infallible, str::FromStr};
 use tracing::instrument;
+use utoipa::ToSchema;
 
-#[derive(Debug, PartialEq, Clone)]
+pub mod builtins;
+pub mod diagnostic;
+pub mod eval;
+pub mod fetch;
+pub mod html;
+pub mod limits;
+pub mod module;
+mod print;
+pub mod repl;
+pub mod typecheck;
+pub mod value;
+pub mod vm;
+use diagnostic::RawSpans;
+pub use diagnostic::{ParseDiagnostic, Position, Span, Spans};
+pub use eval::{eval, Env, EvalError};
+use indexmap::IndexMap;
+pub use print::{escape, format_script};
+pub use typecheck::{TypeEnv, TypeError};
+pub use value::FromJsonError;
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(into = "serde_json::Value", try_from = "serde_json::Value")]
 pub enum Value {
     Number(i64),
     Bool(bool),
     Str(String),
+    List(Vec<Value>),
+    /// Fields keep the order they were written or scraped in. Boxed to keep `Value` small.
+    Record(Box<IndexMap<String, Value>>),
+    Null,
 }
 
 impl Typed for Value {
     type TypeRepr = TypeRepr;
-    type E = ();
+    type E = Infallible;
 
     fn get_type(s: Self) -> Result<TypeRepr, Self::E> {
         match s {
             Self::Number(_) => Ok(TypeRepr::Number),
             Self::Bool(_) => Ok(TypeRepr::Bool),
             Self::Str(_) => Ok(TypeRepr::Str),
+            // a list read from JSON may mix types, its elements are typed `Any` then
+            Self::List(xs) => Ok(TypeRepr::List(Box::new(
+                xs.into_iter()
+                    .map(|x| Value::get_type(x).unwrap_or_else(|e| match e {}))
+                    .try_fold(TypeRepr::Any, |t, u| t.unify(&u))
+                    .unwrap_or(TypeRepr::Any),
+            ))),
+            Self::Record(fields) => Ok(TypeRepr::Record(Box::new(
+                (*fields)
+                    .into_iter()
+                    .map(|(k, v)| (k, Value::get_type(v).unwrap_or_else(|e| match e {})))
+                    .collect(),
+            ))),
+            Self::Null => Ok(TypeRepr::Null),
         }
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, ToSchema)]
 pub enum TypeRepr {
     Number,
     Bool,
     Str,
+    #[schema(value_type = Object)]
+    List(Box<TypeRepr>),
+    #[schema(value_type = Object)]
+    Record(Box<IndexMap<String, TypeRepr>>),
+    Null,
+    /// Statically unknown, e.g. the elements of `[]` or data parsed from JSON. Checked at
+    /// runtime instead.
+    Any,
 }
 
 trait Typed {
@@ -49,68 +99,84 @@ trait Typed {
 pub enum Expr {
     Value(Value),
     Binop(Oper, Box<Expr>, Box<Expr>),
+    Unop(Unop, Box<Expr>),
+    Var(String),
     //  let name = e1 in e2
     Let(String, Box<Expr>, Box<Expr>),
     If(Box<Expr>, Box<Expr>, Box<Expr>),
+    /// name(arg, ...), a function from the [`builtins::Registry`]
+    Call(String, Vec<Expr>),
+    /// [e1, e2, ...]
+    List(Vec<Expr>),
+    /// {name: e1, "bio": e2, ...}
+    Record(Vec<(String, Expr)>),
+    /// xs[0]
+    Index(Box<Expr>, Box<Expr>),
+    /// profile.name
+    Field(Box<Expr>, String),
+    /// |acc, x| acc + x, only allowed as argument of `map`, `filter` and `fold`
+    Lambda(Vec<String>, Box<Expr>),
 }
 
 impl Typed for Expr {
     type TypeRepr = TypeRepr;
-    type E = ();
+    type E = TypeError;
 
     fn get_type(s: Self) -> Result<Self::TypeRepr, Self::E> {
-        match s {
-            Self::Value(v) => Ok(Value::get_type(v)?),
-            Self::Binop(o, _, _) => Ok(Oper::get_type(o)?), // TODO typecheck args
-            Self::Let(_, _, e2) => Ok(Expr::get_type(*e2)?),
-            Self::If(_, _, e2) => Ok(Expr::get_type(*e2)?),
-        }
-    }
-}
-// pub enum TypeCheckResult {
-//     Failed(String),
-//     Succeeded
-// }
-impl Oper {
-    pub fn get_types_to_check(&self) -> (TypeRepr, TypeRepr) {
-        match self {
-            Oper::Add | Oper::Sub | Oper::Mul | Oper::Div => (TypeRepr::Number, TypeRepr::Number),
-            Oper::Eq | Oper::Neq => (TypeRepr::Bool, TypeRepr::Bool),
-        }
+        s.infer(&TypeEnv::new())
     }
 }
+
 pub trait TypeCheck {
     type R;
 
     fn typecheck(&self) -> Self::R;
 }
 
-impl TypeCheck for Expr {
-    type R = Result<(), ()>;
-
-    fn typecheck(&self) -> Self::R {
-        match self {
-            Self::Value(_) => Ok(()),
-            Self::If(condition, e1, e2) => {
-                if Expr::get_type(*condition.clone())? == TypeRepr::Bool
-                    && Expr::get_type(*e1.clone())? == Expr::get_type(*e2.clone())?
-                {
-                    Ok(())
-                } else {
-                    Err(())
-                }
-            }
-            Self::Binop(op, e1, e2) => {
-                if op.get_types_to_check()
-                    == (Expr::get_type(*e1.clone())?, Expr::get_type(*e2.clone())?)
-                {
-                    Ok(())
-                } else {
-                    Err(())
-                }
-            }
-            Self::Let(_, _, _) => Ok(()),
+/// An expression and the spans of its nodes.
+type Parsed = (Expr, RawSpans);
+
+/// Run `f`, which parses a single node and returns the spans of its children, skipping leading
+/// whitespace and recording the span of the node.
+fn spanned<'a, E, F>(mut f: F) -> impl FnMut(&'a str) -> IResult<&'a str, Parsed, E>
+where
+    E: ParseError<&'a str>,
+    F: Parser<&'a str, (Expr, Vec<RawSpans>), E>,
+{
+    move |i: &'a str| {
+        let (i, _) = multispace0(i)?;
+        let (rest, (expr, children)) = f.parse(i)?;
+        let spans = RawSpans {
+            start: i.len(),
+            end: rest.len(),
+            children,
+        };
+        Ok((rest, (expr, spans)))
+    }
+}
+
+/// Parse a complete scrape script, recording the span of every node.
+///
+/// # Example
+///
+/// ```rust
+/// use seekr::scrape::{parse_script, Position};
+/// let (_, spans) = parse_script("let foo = 2 in\n  foo * 21").unwrap();
+/// // the body of the let
+/// let body = &spans.children[1];
+/// assert_eq!(body.span.start, Position { line: 2, column: 3 });
+/// assert_eq!(body.span.end, Position { line: 2, column: 11 });
+///
+/// let diagnostic = parse_script("let foo = 2 foo").unwrap_err();
+/// assert_eq!(diagnostic.expected, vec!["in"]);
+/// ```
+pub fn parse_script(src: &str) -> Result<(Expr, Spans), ParseDiagnostic> {
+    match terminated(expr::<VerboseError<&str>>, pair(multispace0, eof))(src) {
+        Ok((_, (expr, spans))) => Ok((expr, spans.resolve(src))),
+        Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => {
+            Err(ParseDiagnostic::from_verbose_error(src, e))
         }
+        Err(nom::Err::Incomplete(_)) => unreachable!("complete parsers never return Incomplete"),
     }
 }
 
@@ -121,8 +187,7 @@ impl TypeCheck for Expr {
 ///
 /// ```rust
 /// use seekr::scrape::{Expr, parse_parens, Value, Oper};
-/// use std::collections::HashMap;
-/// let result = parse_parens(HashMap::new(), "(4+20)");
+/// let result = parse_parens("(4+20)");
 /// assert_eq!(
 ///     result,
 ///     Ok((
@@ -135,9 +200,21 @@ impl TypeCheck for Expr {
 ///     ))
 /// );
 /// ```
+pub fn parse_parens(i: &str) -> IResult<&str, Expr> {
+    map(parens, |(e, _)| e)(i)
+}
 
-pub fn parse_parens(envo: HashMap<String, Expr>, i: &str) -> IResult<&str, Expr> {
-    delimited(char('('), |i| parse_expr(envo.clone(), i), char(')')).parse(i)
+fn parens<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    i: &'a str,
+) -> IResult<&'a str, Parsed, E> {
+    let (i, _) = multispace0(i)?;
+    context(
+        "parenthesized expression",
+        preceded(
+            char('('),
+            cut(terminated(expr, preceded(multispace0, char(')')))),
+        ),
+    )(i)
 }
 
 /// Expr : 4 * 20
@@ -148,34 +225,272 @@ pub fn parse_parens(envo: HashMap<String, Expr>, i: &str) -> IResult<&str, Expr>
 ///      4 * (20 + 6) --> 4  * (20 + 6)
 ///  therefore parse_parens parses an expression inside parens.
 #[instrument]
-pub fn parse_factor(envo: HashMap<String, Expr>, i: &str) -> IResult<&str, Expr> {
+pub fn parse_factor(i: &str) -> IResult<&str, Expr> {
+    map(factor, |(e, _)| e)(i)
+}
+
+/// An atom followed by any number of indexing and field accesses, e.g. `profile.links[0]`.
+fn factor<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    i: &'a str,
+) -> IResult<&'a str, Parsed, E> {
+    let (mut i, (mut e, mut spans)) = atom(i)?;
+    loop {
+        let index = delimited(
+            char('['),
+            context("index", cut(expr)),
+            cut(preceded(multispace0, char(']'))),
+        );
+        let field = preceded(char('.'), context("field", cut(identifier)));
+        let (rest, postfix) = match alt((map(index, Ok), map(field, Err)))(i) {
+            Ok(res) => res,
+            Err(nom::Err::Error(_)) => return Ok((i, (e, spans))),
+            Err(err) => return Err(err),
+        };
+        let (node, children) = match postfix {
+            Ok((idx, idx_spans)) => (Expr::Index(Box::new(e), Box::new(idx)), Some(idx_spans)),
+            Err(name) => (Expr::Field(Box::new(e), name.to_string()), None),
+        };
+        spans = RawSpans {
+            start: spans.start,
+            end: rest.len(),
+            children: std::iter::once(spans).chain(children).collect(),
+        };
+        e = node;
+        i = rest;
+    }
+}
+
+fn atom<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    i: &'a str,
+) -> IResult<&'a str, Parsed, E> {
     alt((
-        map(parse_value, Expr::Value),
-        |i| parse_parens(envo.clone(), i),
-        |i| {
-            // NOTE really weird type stuff
-            let (i, _) = multispace0(i)?;
-            let (i, name): (&str, &str) = alpha1(i)?;
-            if let Some(e) = envo.get(name) {
-                Ok((i, e.to_owned()))
-            } else {
-                fail(i)
-            }
+        spanned(map(literal, |v| (Expr::Value(v), vec![]))),
+        parens,
+        list,
+        record,
+        spanned(map(preceded(char('!'), cut(factor)), |(e, spans)| {
+            (Expr::Unop(Unop::Not, Box::new(e)), vec![spans])
+        })),
+        call,
+        spanned(map(identifier, |name| {
+            (Expr::Var(name.to_string()), vec![])
+        })),
+    ))(i)
+}
+
+/// `[e1, e2, ...]`, the spans of the elements are the children.
+fn list<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    i: &'a str,
+) -> IResult<&'a str, Parsed, E> {
+    spanned(map(
+        preceded(
+            char('['),
+            context(
+                "list",
+                cut(terminated(
+                    separated_list0(preceded(multispace0, char(',')), expr),
+                    preceded(multispace0, char(']')),
+                )),
+            ),
+        ),
+        |elems: Vec<Parsed>| {
+            let (elems, spans) = elems.into_iter().unzip();
+            (Expr::List(elems), spans)
+        },
+    ))(i)
+}
+
+/// `{name: e1, "bio": e2}`, keys are identifiers or strings. The spans of the values are the
+/// children.
+fn record<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    i: &'a str,
+) -> IResult<&'a str, Parsed, E> {
+    let key = preceded(multispace0, alt((map(identifier, str::to_string), string)));
+    let field = pair(terminated(key, preceded(multispace0, char(':'))), expr);
+    spanned(map(
+        preceded(
+            char('{'),
+            context(
+                "record",
+                cut(terminated(
+                    separated_list0(preceded(multispace0, char(',')), field),
+                    preceded(multispace0, char('}')),
+                )),
+            ),
+        ),
+        |fields: Vec<(String, Parsed)>| {
+            let (fields, spans) = fields
+                .into_iter()
+                .map(|(k, (e, spans))| ((k, e), spans))
+                .unzip();
+            (Expr::Record(fields), spans)
+        },
+    ))(i)
+}
+
+/// `|x, y| body`, the span of the body is the only child.
+fn lambda<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    i: &'a str,
+) -> IResult<&'a str, Parsed, E> {
+    spanned(map(
+        preceded(
+            char('|'),
+            context(
+                "lambda",
+                cut(pair(
+                    terminated(
+                        separated_list1(
+                            preceded(multispace0, char(',')),
+                            preceded(multispace0, identifier),
+                        ),
+                        preceded(multispace0, char('|')),
+                    ),
+                    expr,
+                )),
+            ),
+        ),
+        |(params, (body, spans)): (Vec<&str>, Parsed)| {
+            (
+                Expr::Lambda(
+                    params.into_iter().map(str::to_string).collect(),
+                    Box::new(body),
+                ),
+                vec![spans],
+            )
         },
-    ))
-    .parse(i)
-}
-
-pub fn parse_term_aux(envo: HashMap<String, Expr>, i: &str, factor: Expr) -> IResult<&str, Expr> {
-    if let Ok((i, (op, remainder))) =
-        pair(map_res(alt((tag("*"), tag("/"))), FromStr::from_str), |i| {
-            parse_factor(envo.clone(), i)
-        })(i)
-    {
-        let res = Expr::Binop(op, Box::new(factor), Box::new(remainder));
-        Ok(parse_term_aux(envo.clone(), i, res.clone()).unwrap_or((i, res)))
-    } else {
-        Ok((i, factor))
+    ))(i)
+}
+
+/// `name(arg, ...)`, the spans of the arguments are the children. Definitions of an imported
+/// module are called by a qualified name, e.g. `util.normalize(x)`, see [`module`].
+fn call<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    i: &'a str,
+) -> IResult<&'a str, Parsed, E> {
+    spanned(map(
+        pair(
+            terminated(
+                recognize(pair(identifier, opt(pair(char('.'), identifier)))),
+                pair(multispace0, char('(')),
+            ),
+            context(
+                "call",
+                cut(terminated(
+                    separated_list0(preceded(multispace0, char(',')), alt((lambda, expr))),
+                    preceded(multispace0, char(')')),
+                )),
+            ),
+        ),
+        |(name, args): (&str, Vec<Parsed>)| {
+            let (args, spans) = args.into_iter().unzip();
+            (Expr::Call(name.to_string(), args), spans)
+        },
+    ))(i)
+}
+
+/// Words that can not be used as variable names.
+pub const KEYWORDS: [&str; 10] = [
+    "let", "in", "if", "then", "else", "true", "false", "null", "def", "import",
+];
+
+/// An identifier starts with a letter or `_` followed by letters, digits and `_`, e.g.
+/// `user_name2`. Keywords are not identifiers.
+pub fn parse_identifier(i: &str) -> IResult<&str, &str> {
+    identifier(i)
+}
+
+fn identifier<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    i: &'a str,
+) -> IResult<&'a str, &'a str, E> {
+    context(
+        "identifier",
+        verify(
+            recognize(pair(
+                alt((alpha1, tag("_"))),
+                many0_count(alt((alphanumeric1, tag("_")))),
+            )),
+            |name: &str| !KEYWORDS.contains(&name),
+        ),
+    )(i)
+}
+
+/// Succeeds if `i` does not continue with a character that could be part of an identifier, so
+/// keywords are not matched at the start of a longer name like `trueish` or `letter`.
+fn word_end<'a, E: ParseError<&'a str>>(i: &'a str) -> IResult<&'a str, (), E> {
+    not(satisfy(|c| c.is_alphanumeric() || c == '_'))(i)
+}
+
+/// A keyword surrounded by whitespace, the context shows up in the expected tokens of a
+/// diagnostic.
+fn keyword<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    kw: &'static str,
+) -> impl FnMut(&'a str) -> IResult<&'a str, &'a str, E> {
+    context(
+        kw,
+        delimited(multispace0, terminated(tag(kw), word_end), multispace0),
+    )
+}
+
+/// Binary operators grouped by precedence level, from the loosest to the tightest binding one.
+/// Every level is left associative, e.g. `1-2-3` is `(1-2)-3`. Within a level longer operators
+/// come first so `<=` is not read as `<` followed by `=`.
+pub const PRECEDENCE: [&[&str]; 6] = [
+    &["||"],
+    &["&&"],
+    &["==", "!="],
+    &["<=", ">=", "<", ">"],
+    &["+", "-"],
+    &["*", "/"],
+];
+
+/// Level of `*` and `/` in [`PRECEDENCE`].
+const TERM_LEVEL: usize = PRECEDENCE.len() - 1;
+
+/// Parse one of the operators of a precedence level, surrounded by optional whitespace.
+fn oper<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    ops: &'static [&'static str],
+) -> impl Fn(&'a str) -> IResult<&'a str, Oper, E> {
+    move |i| {
+        let (i, _) = multispace0(i)?;
+        for op in ops {
+            let res: IResult<&str, Oper, E> = map_opt(tag(*op), |s| Oper::from_str(s).ok())(i);
+            if let Ok((i, oper)) = res {
+                let (i, _) = multispace0(i)?;
+                return Ok((i, oper));
+            }
+        }
+        context("operator", fail)(i)
+    }
+}
+
+/// Parse a chain of binary operations of the precedence `level` or tighter.
+/// Expr : 1 + 2 * 3 < 4
+///            ^^^^^ operands of `+` (level 4) are parsed at level 5
+///        ^^^^^^^^^ operands of `<` (level 3) are parsed at level 4
+/// Past the last level the operands are factors.
+#[instrument]
+fn binop<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    level: usize,
+    i: &'a str,
+) -> IResult<&'a str, Parsed, E> {
+    let Some(ops) = PRECEDENCE.get(level) else {
+        return factor(i);
+    };
+    let (mut i, (mut lhs, mut lhs_spans)) = binop(level + 1, i)?;
+    loop {
+        let (rest, op) = match oper(ops)(i) {
+            Ok(res) => res,
+            Err(nom::Err::Error(_)) => return Ok((i, (lhs, lhs_spans))),
+            Err(e) => return Err(e),
+        };
+        // once an operator is read the right operand is mandatory
+        let (rest, (rhs, rhs_spans)) = cut(context("expression", |i| binop(level + 1, i)))(rest)?;
+        lhs = Expr::Binop(op, Box::new(lhs), Box::new(rhs));
+        lhs_spans = RawSpans {
+            start: lhs_spans.start,
+            end: rhs_spans.end,
+            children: vec![lhs_spans, rhs_spans],
+        };
+        i = rest;
     }
 }
 
@@ -189,24 +504,10 @@ pub fn parse_term_aux(envo: HashMap<String, Expr>, i: &str, factor: Expr) -> IRe
 /// A term is multiplication and division of to factors.
 /// In `[parse_factor]` is explained why the remainder is a factor and not an expression.
 #[instrument]
-pub fn parse_term(envo: HashMap<String, Expr>, i: &str) -> IResult<&str, Expr> {
-    let (i, factor) = parse_factor(envo.clone(), i)?;
-    parse_term_aux(envo.clone(), i, factor)
+pub fn parse_term(i: &str) -> IResult<&str, Expr> {
+    map(|i| binop(TERM_LEVEL, i), |(e, _)| e)(i)
 }
 
-#[instrument]
-pub fn parse_expr_aux(envo: HashMap<String, Expr>, i: &str, initial: Expr) -> IResult<&str, Expr> {
-    if let Ok((i, (op, remainder))) =
-        pair(map_res(alt((tag("+"), tag("-"))), FromStr::from_str), |i| {
-            parse_term(envo.clone(), i)
-        })(i)
-    {
-        let res = Expr::Binop(op, Box::new(initial), Box::new(remainder));
-        Ok(parse_expr_aux(envo.clone(), i, res.clone()).unwrap_or((i, res)))
-    } else {
-        Ok((i, initial))
-    }
-}
 // /// ```rust
 // /// use seekr_rs::scrape::{Expr, parse_parens, Value, Oper};
 // /// ```
@@ -224,38 +525,63 @@ pub fn parse_expr_aux(envo: HashMap<String, Expr>, i: &str, initial: Expr) -> IR
 // }
 
 #[instrument]
-pub fn parse_expr(envo: HashMap<String, Expr>, i: &str) -> IResult<&str, Expr> {
-    alt((
-        |i| {
-            let (i, initial) = parse_term(envo.clone(), i)?;
-            parse_expr_aux(envo.clone(), i, initial)
-        },
-        |i| {
-            let (i, _) = delimited(multispace0, tag("let"), multispace1)(i)?;
-            let (i, name) = alpha1(i)?;
-            let (i, _) = delimited(multispace0, tag("="), multispace0)(i)?;
-            let (i, e1) = parse_expr(envo.clone(), i)?;
-            let (i, _) = delimited(multispace1, tag("in"), multispace1)(i)?;
-            let mut envomut = envo.clone();
-            envomut.insert(name.to_string(), e1.clone());
-            // let envo = envomut.clone();
-            let (i, e2) = parse_expr(envomut, i)?;
-            // TODO subst
-
-            Ok((i, Expr::Let(name.to_string(), Box::new(e1), Box::new(e2))))
-        },
-        |i| {
-            let (i, _) = delimited(multispace0, tag("if"), multispace1)(i)?;
-            let (i, cond) = parse_expr(envo.clone(), i)?;
-            let (i, _) = delimited(multispace1, tag("then"), multispace1)(i)?;
-            let (i, e1) = parse_expr(envo.clone(), i)?;
-            let (i, _) = delimited(multispace1, tag("else"), multispace1)(i)?;
-            let (i, e2) = parse_expr(envo.clone(), i)?;
-
-            Ok((i, Expr::If(Box::new(cond), Box::new(e1), Box::new(e2))))
-        },
-    ))
-    .parse(i)
+pub fn parse_expr(i: &str) -> IResult<&str, Expr> {
+    map(expr, |(e, _)| e)(i)
+}
+
+fn expr<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    i: &'a str,
+) -> IResult<&'a str, Parsed, E> {
+    context(
+        "expression",
+        alt((
+            |i| binop(0, i),
+            spanned(|i| {
+                let (i, _) = terminated(tag("let"), pair(word_end, multispace1))(i)?;
+                // after the keyword the rest of the let is mandatory
+                let (i, (name, (e1, s1), (e2, s2))) = context(
+                    "let",
+                    cut(|i| {
+                        let (i, name) = identifier(i)?;
+                        let (i, _) =
+                            context("=", delimited(multispace0, char('='), multispace0))(i)?;
+                        let (i, e1) = expr(i)?;
+                        let (i, _) = keyword("in")(i)?;
+                        let (i, e2) = expr(i)?;
+                        Ok((i, (name, e1, e2)))
+                    }),
+                )(i)?;
+                Ok((
+                    i,
+                    (
+                        Expr::Let(name.to_string(), Box::new(e1), Box::new(e2)),
+                        vec![s1, s2],
+                    ),
+                ))
+            }),
+            spanned(|i| {
+                let (i, _) = terminated(tag("if"), pair(word_end, multispace1))(i)?;
+                let (i, ((cond, sc), (e1, s1), (e2, s2))) = context(
+                    "if",
+                    cut(|i| {
+                        let (i, cond) = expr(i)?;
+                        let (i, _) = keyword("then")(i)?;
+                        let (i, e1) = expr(i)?;
+                        let (i, _) = keyword("else")(i)?;
+                        let (i, e2) = expr(i)?;
+                        Ok((i, (cond, e1, e2)))
+                    }),
+                )(i)?;
+                Ok((
+                    i,
+                    (
+                        Expr::If(Box::new(cond), Box::new(e1), Box::new(e2)),
+                        vec![sc, s1, s2],
+                    ),
+                ))
+            }),
+        )),
+    )(i)
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -269,22 +595,29 @@ pub enum Oper {
     /// 4 / 20
     Div,
     /// 4 == 4
-    Eq, // TODO
+    Eq,
     /// 4 != 4
-    Neq, // TODO
+    Neq,
+    /// 4 < 20
+    Lt,
+    /// 4 <= 20
+    Le,
+    /// 4 > 20
+    Gt,
+    /// 4 >= 20
+    Ge,
+    /// true && false
+    And,
+    /// true || false
+    Or,
 }
 
-impl Typed for Oper {
-    type TypeRepr = TypeRepr;
-    type E = ();
-
-    fn get_type(s: Self) -> Result<Self::TypeRepr, Self::E> {
-        match s {
-            Self::Add | Self::Sub | Self::Mul | Self::Div => Ok(TypeRepr::Number),
-            Self::Eq | Self::Neq => Ok(TypeRepr::Bool),
-        }
-    }
+#[derive(Debug, PartialEq, Clone)]
+pub enum Unop {
+    /// !true
+    Not,
 }
+
 impl FromStr for Oper {
     type Err = &'static str;
 
@@ -295,25 +628,92 @@ impl FromStr for Oper {
             "-" => Ok(Self::Sub),
             "*" => Ok(Self::Mul),
             "/" => Ok(Self::Div),
+            "==" => Ok(Self::Eq),
+            "!=" => Ok(Self::Neq),
+            "<" => Ok(Self::Lt),
+            "<=" => Ok(Self::Le),
+            ">" => Ok(Self::Gt),
+            ">=" => Ok(Self::Ge),
+            "&&" => Ok(Self::And),
+            "||" => Ok(Self::Or),
             _ => Err("Invalid Operator"),
         }
     }
 }
 
-// original: !"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\]^_`abcdefghijklmnopqrstuvwxyz{|}~
-// modified: ! #$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[ ]^_`abcdefghijklmnopqrstuvwxyz{|}~
-fn parse_str<'a, E: ParseError<&'a str>>(i: &'a str) -> IResult<&'a str, String, E> {
-    Ok(escaped_transform(
-        one_of(
-            r#" ! #$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[ ]^_`abcdefghijklmnopqrstuvwxyz{|}~"#,
+/// A piece of a string literal.
+enum Fragment<'a> {
+    Literal(&'a str),
+    Escaped(char),
+}
+
+/// `\\`, `\"`, `\n`, `\t`, `\r` or `\u{1F600}` with one to six hex digits, after the backslash.
+fn escape_sequence<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    i: &'a str,
+) -> IResult<&'a str, char, E> {
+    let unicode = preceded(
+        char('u'),
+        delimited(
+            char('{'),
+            map_opt(
+                take_while_m_n(1, 6, |c: char| c.is_ascii_hexdigit()),
+                |hex| u32::from_str_radix(hex, 16).ok().and_then(char::from_u32),
+            ),
+            char('}'),
         ),
-        '\\',
+    );
+    context(
+        "escape",
         alt((
-            value("\\", tag("\\")),
-            value("\"", tag("\"")),
-            value("\n", tag("n")),
+            value('\\', char('\\')),
+            value('"', char('"')),
+            value('\n', char('n')),
+            value('\t', char('t')),
+            value('\r', char('r')),
+            unicode,
         )),
-    )(i)?)
+    )(i)
+}
+
+/// The contents of a string literal up to the closing quote. Any character but `"` and `\` stands
+/// for itself, those two are escaped.
+fn parse_str<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    i: &'a str,
+) -> IResult<&'a str, String, E> {
+    fold_many0(
+        alt((
+            map(is_not("\"\\"), Fragment::Literal),
+            map(
+                preceded(char('\\'), cut(escape_sequence)),
+                Fragment::Escaped,
+            ),
+        )),
+        String::new,
+        |mut s, fragment| {
+            match fragment {
+                Fragment::Literal(l) => s.push_str(l),
+                Fragment::Escaped(c) => s.push(c),
+            }
+            s
+        },
+    )(i)
+}
+
+/// `r"..."` or `r#"..."#` with any number of `#`, the contents are taken as they are. The
+/// string ends at the first `"` followed by as many `#` as it started with.
+fn raw_string<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    i: &'a str,
+) -> IResult<&'a str, String, E> {
+    let (i, hashes) = terminated(preceded(char('r'), many0_count(char('#'))), char('"'))(i)?;
+    let closing = format!("\"{}", "#".repeat(hashes));
+    let result = context(
+        "raw string",
+        cut(map(
+            terminated(take_until(closing.as_str()), tag(closing.as_str())),
+            str::to_string,
+        )),
+    )(i);
+    result
 }
 
 fn string<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
@@ -321,24 +721,42 @@ fn string<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
 ) -> IResult<&'a str, String, E> {
     context(
         "string",
-        preceded(char('\"'), cut(terminated(parse_str, char('\"')))),
+        alt((
+            preceded(char('\"'), cut(terminated(parse_str, char('\"')))),
+            raw_string,
+        )),
     )(i)
 }
 
 pub fn parse_value(i: &str) -> IResult<&str, Value> {
+    literal(i)
+}
+
+fn literal<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    i: &'a str,
+) -> IResult<&'a str, Value, E> {
     // No need for env since values are always literals
     alt((
-        map(
-            pair(opt(char('-')), map_res(digit1, |s: &str| s.parse::<i64>())),
-            |(sign, number)| Value::Number(if sign.is_some() { -number } else { number }),
+        context(
+            "number",
+            // the sign is parsed with the digits, `i64::MIN` has no positive counterpart
+            map(
+                map_opt(recognize(pair(opt(char('-')), digit1)), |s: &str| {
+                    s.parse::<i64>().ok()
+                }),
+                Value::Number,
+            ),
         ),
         map(
-            map_res(alt((tag("true"), tag("false"))), FromStr::from_str),
+            terminated(
+                alt((value(true, tag("true")), value(false, tag("false")))),
+                word_end,
+            ),
             Value::Bool,
         ),
+        value(Value::Null, terminated(tag("null"), word_end)),
         map(string, Value::Str),
-    ))
-    .parse(i)
+    ))(i)
 }
 
 mod test;
@@ -0,0 +1,549 @@
+//! Scripts split into modules. A module starts with `import` and `def` items, followed by an
+//! optional body expression:
+//!
+//! ```text
+//! import "text utils" as text
+//! def greeting(name) = "hello " + text.normalize(name)
+//! greeting(name)
+//! ```
+//!
+//! Modules are linked by inlining: every call of a definition is replaced by its body, with the
+//! arguments bound by `let`. Variables of an inlined body are renamed to `module.def/name`, names
+//! the parser never produces, so they can not capture variables of the caller. Evaluation, the VM
+//! and the type checker then work on a single expression, which is how types are checked across
+//! modules. Recursion can not be inlined and is rejected.
+
+use nom::{
+    branch::alt,
+    bytes::complete::tag,
+    character::complete::{char, multispace0, multispace1},
+    combinator::{cut, eof, map, opt},
+    error::{context, ContextError, ParseError, VerboseError},
+    multi::{many0, separated_list0},
+    sequence::{delimited, pair, preceded, terminated},
+    IResult,
+};
+use std::collections::HashMap;
+use thiserror::Error;
+use tracing::instrument;
+
+use super::{
+    builtins::{Registry, HIGHER_ORDER},
+    diagnostic::RawSpans,
+    expr, identifier, keyword, string, word_end, ParseDiagnostic, Position, Span, Spans, TypeEnv,
+    TypeError, TypeRepr, KEYWORDS,
+};
+use super::{Expr, Value};
+
+/// Inlining stops with [`ModuleError::TooLarge`] past this many expression nodes, definitions
+/// calling each other twice double the size with every level.
+pub const MAX_EXPANDED_NODES: usize = 100_000;
+
+/// `import "name"` or `import "name" as alias`. Without an alias the name must be an identifier.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Import {
+    pub name: String,
+    pub alias: String,
+    pub span: Span,
+}
+
+/// `def name(params) = body`
+#[derive(Debug, PartialEq, Clone)]
+pub struct Def {
+    pub name: String,
+    pub params: Vec<String>,
+    pub body: Expr,
+    /// Spans of the body.
+    pub spans: Spans,
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Module {
+    pub imports: Vec<Import>,
+    pub defs: Vec<Def>,
+    /// A module only providing definitions has no body, it evaluates to `null`.
+    pub body: Option<(Expr, Spans)>,
+}
+
+#[derive(Debug, PartialEq, Clone, Error)]
+pub enum ModuleError {
+    #[error("{module}: {diagnostic}")]
+    Parse {
+        module: String,
+        diagnostic: ParseDiagnostic,
+    },
+
+    #[error("{module}: {error}")]
+    Type { module: String, error: TypeError },
+
+    #[error("{module}: no script named {name:?} to import")]
+    NotFound {
+        module: String,
+        name: String,
+        span: Span,
+    },
+
+    #[error("{module}: import {name:?} needs an alias, e.g. import {name:?} as m")]
+    Alias {
+        module: String,
+        name: String,
+        span: Span,
+    },
+
+    #[error("{module}: {name} is defined twice")]
+    Duplicate {
+        module: String,
+        name: String,
+        span: Span,
+    },
+
+    #[error("{module}: {name} is a builtin and can not be redefined")]
+    Reserved {
+        module: String,
+        name: String,
+        span: Span,
+    },
+
+    #[error("import cycle: {}", .0.join(" -> "))]
+    Cycle(Vec<String>),
+
+    #[error("recursive definition: {}", .0.join(" -> "))]
+    Recursive(Vec<String>),
+
+    #[error("inlining definitions takes more than {} nodes", MAX_EXPANDED_NODES)]
+    TooLarge,
+}
+
+/// Where the sources of imported modules come from, e.g. the stored scripts of a user.
+pub trait Loader {
+    fn source(&self, name: &str) -> Option<String>;
+}
+
+impl Loader for HashMap<String, String> {
+    fn source(&self, name: &str) -> Option<String> {
+        self.get(name).cloned()
+    }
+}
+
+enum Item {
+    Import(String, Option<String>, RawSpans),
+    Def(String, Vec<String>, Expr, RawSpans, RawSpans),
+}
+
+/// `import "name" (as alias)?` or `def name(params) = body`. The spans of a def are its own and
+/// the ones of its body.
+fn item<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    i: &'a str,
+) -> IResult<&'a str, Item, E> {
+    let (i, _) = multispace0(i)?;
+    let start = i.len();
+    let span = |rest: &str| RawSpans {
+        start,
+        end: rest.len(),
+        children: vec![],
+    };
+    if let Ok((i, _)) = terminated(tag::<_, _, E>("import"), word_end)(i) {
+        let (rest, (name, alias)) = context(
+            "import",
+            cut(pair(
+                preceded(multispace0, string),
+                opt(preceded(keyword("as"), identifier)),
+            )),
+        )(i)?;
+        return Ok((
+            rest,
+            Item::Import(name, alias.map(str::to_string), span(rest)),
+        ));
+    }
+    let (i, _) = terminated(tag("def"), pair(word_end, multispace1))(i)?;
+    let (rest, (name, params, (body, spans))) = context(
+        "def",
+        cut(|i| {
+            let (i, name) = identifier(i)?;
+            let (i, params) = delimited(
+                preceded(multispace0, char('(')),
+                separated_list0(
+                    preceded(multispace0, char(',')),
+                    preceded(multispace0, identifier),
+                ),
+                preceded(multispace0, char(')')),
+            )(i)?;
+            let (i, _) = context("=", delimited(multispace0, char('='), multispace0))(i)?;
+            let (i, body) = expr(i)?;
+            Ok((i, (name, params, body)))
+        }),
+    )(i)?;
+    Ok((
+        rest,
+        Item::Def(
+            name.to_string(),
+            params.into_iter().map(str::to_string).collect(),
+            body,
+            spans,
+            span(rest),
+        ),
+    ))
+}
+
+/// Parse a module: its items, then the optional body.
+///
+/// # Example
+///
+/// ```rust
+/// use seekr::scrape::module::parse_module;
+/// let module = parse_module("import \"util\"\ndef double(x) = x * 2\ndouble(util.three())").unwrap();
+/// assert_eq!(module.imports[0].alias, "util");
+/// assert_eq!(module.defs[0].params, ["x"]);
+/// assert!(module.body.is_some());
+/// ```
+pub fn parse_module(src: &str) -> Result<Module, ParseDiagnostic> {
+    let body = alt((map(pair(multispace0, eof), |_| None), map(expr, Some)));
+    let parsed = terminated(
+        pair(many0(item::<VerboseError<&str>>), body),
+        pair(multispace0, eof),
+    )(src);
+    let (items, body) = match parsed {
+        Ok((_, res)) => res,
+        Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => {
+            return Err(ParseDiagnostic::from_verbose_error(src, e))
+        }
+        Err(nom::Err::Incomplete(_)) => unreachable!("complete parsers never return Incomplete"),
+    };
+    let mut module = Module {
+        imports: vec![],
+        defs: vec![],
+        body: body.map(|(e, spans)| (e, spans.resolve(src))),
+    };
+    for item in items {
+        match item {
+            Item::Import(name, alias, span) => module.imports.push(Import {
+                alias: alias.unwrap_or_else(|| name.clone()),
+                name,
+                span: span.resolve(src).span,
+            }),
+            Item::Def(name, params, body, spans, span) => module.defs.push(Def {
+                name,
+                params,
+                body,
+                spans: spans.resolve(src),
+                span: span.resolve(src).span,
+            }),
+        }
+    }
+    Ok(module)
+}
+
+fn is_identifier(name: &str) -> bool {
+    matches!(identifier::<VerboseError<&str>>(name), Ok(("", _))) && !KEYWORDS.contains(&name)
+}
+
+/// Parse a module and reject names defined twice, definitions of the higher-order builtins,
+/// which take functions by name, and imports that can not be referred to.
+fn parse(name: &str, src: &str) -> Result<Module, ModuleError> {
+    let module = parse_module(src).map_err(|diagnostic| ModuleError::Parse {
+        module: name.to_string(),
+        diagnostic,
+    })?;
+    let mut seen = HashMap::new();
+    for import in &module.imports {
+        if !is_identifier(&import.alias) {
+            return Err(ModuleError::Alias {
+                module: name.to_string(),
+                name: import.name.clone(),
+                span: import.span,
+            });
+        }
+        if seen.insert(&import.alias, ()).is_some() {
+            return Err(ModuleError::Duplicate {
+                module: name.to_string(),
+                name: import.alias.clone(),
+                span: import.span,
+            });
+        }
+    }
+    for def in &module.defs {
+        if HIGHER_ORDER.contains(&def.name.as_str()) {
+            return Err(ModuleError::Reserved {
+                module: name.to_string(),
+                name: def.name.clone(),
+                span: def.span,
+            });
+        }
+        if seen.insert(&def.name, ()).is_some() {
+            return Err(ModuleError::Duplicate {
+                module: name.to_string(),
+                name: def.name.clone(),
+                span: def.span,
+            });
+        }
+    }
+    Ok(module)
+}
+
+/// A module with everything it imports, directly or not.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Linked {
+    main: String,
+    modules: HashMap<String, Module>,
+}
+
+impl Linked {
+    /// Parse the module `name` and load its imports through `loader`. Modules imported more
+    /// than once are loaded once, import cycles are an error.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::collections::HashMap;
+    /// use seekr::scrape::{eval, module::{Linked, ModuleError}, Env, Value};
+    /// let sources = HashMap::from([(
+    ///     "util".to_string(),
+    ///     "def twice(s) = s + s".to_string(),
+    /// )]);
+    /// let linked = Linked::load("main", "import \"util\"\nutil.twice(\"ab\")", &sources).unwrap();
+    /// let (expr, _) = linked.expand().unwrap();
+    /// assert_eq!(eval(&expr, &Env::new()), Ok(Value::Str("abab".to_string())));
+    ///
+    /// let sources = HashMap::from([("a".to_string(), "import \"main\"".to_string())]);
+    /// assert_eq!(
+    ///     Linked::load("main", "import \"a\"\n1", &sources),
+    ///     Err(ModuleError::Cycle(vec!["main".to_string(), "a".to_string(), "main".to_string()]))
+    /// );
+    /// ```
+    #[instrument(skip(src, loader))]
+    pub fn load(name: &str, src: &str, loader: &impl Loader) -> Result<Self, ModuleError> {
+        let mut linked = Self {
+            main: name.to_string(),
+            modules: HashMap::new(),
+        };
+        let main = parse(name, src)?;
+        linked.visit(name, &main, &mut vec![name.to_string()], loader)?;
+        linked.modules.insert(name.to_string(), main);
+        Ok(linked)
+    }
+
+    /// Load the imports of `module`, depth first. `stack` holds the modules being loaded.
+    fn visit(
+        &mut self,
+        name: &str,
+        module: &Module,
+        stack: &mut Vec<String>,
+        loader: &impl Loader,
+    ) -> Result<(), ModuleError> {
+        for import in &module.imports {
+            if let Some(start) = stack.iter().position(|m| *m == import.name) {
+                let mut cycle = stack[start..].to_vec();
+                cycle.push(import.name.clone());
+                return Err(ModuleError::Cycle(cycle));
+            }
+            if self.modules.contains_key(&import.name) {
+                continue;
+            }
+            let src = loader
+                .source(&import.name)
+                .ok_or_else(|| ModuleError::NotFound {
+                    module: name.to_string(),
+                    name: import.name.clone(),
+                    span: import.span,
+                })?;
+            let imported = parse(&import.name, &src)?;
+            stack.push(import.name.clone());
+            self.visit(&import.name, &imported, stack, loader)?;
+            stack.pop();
+            self.modules.insert(import.name.clone(), imported);
+        }
+        Ok(())
+    }
+
+    /// The body of the main module with all definitions inlined. Code of an inlined definition
+    /// has the span of its call.
+    pub fn expand(&self) -> Result<(Expr, Spans), ModuleError> {
+        let main = &self.modules[&self.main];
+        let (mut expr, mut spans) = main.body.clone().unwrap_or_else(|| {
+            let start = Position { line: 1, column: 1 };
+            (
+                Expr::Value(Value::Null),
+                Spans {
+                    span: Span { start, end: start },
+                    children: vec![],
+                },
+            )
+        });
+        Expander::new(self).expand(&self.main, &mut expr, &mut spans)?;
+        Ok((expr, spans))
+    }
+
+    /// Typecheck every definition on its own, with parameters of type `Any`, then the expanded
+    /// body with the variables of `env`. Errors in a definition are reported in its module,
+    /// arguments not fitting a definition at the call.
+    pub fn check(&self, env: &TypeEnv, registry: &Registry) -> Result<TypeRepr, ModuleError> {
+        let mut names: Vec<&String> = self.modules.keys().collect();
+        names.sort();
+        for name in names {
+            for def in &self.modules[name].defs {
+                let (mut body, mut spans) = (def.body.clone(), def.spans.clone());
+                let mut expander = Expander::new(self);
+                expander.stack.push(format!("{}.{}", name, def.name));
+                expander.expand(name, &mut body, &mut spans)?;
+                let params = def
+                    .params
+                    .iter()
+                    .map(|p| (p.clone(), TypeRepr::Any))
+                    .collect();
+                body.infer_with(Some(&spans), &params, registry)
+                    .map_err(|error| ModuleError::Type {
+                        module: name.clone(),
+                        error,
+                    })?;
+            }
+        }
+        let (expr, spans) = self.expand()?;
+        expr.infer_with(Some(&spans), env, registry)
+            .map_err(|error| ModuleError::Type {
+                module: self.main.clone(),
+                error,
+            })
+    }
+}
+
+/// The boxed subexpressions of a node, in the order of the children of its [`Spans`].
+fn children_mut(expr: &mut Expr) -> Vec<&mut Expr> {
+    match expr {
+        Expr::Value(_) | Expr::Var(_) => vec![],
+        Expr::Unop(_, e) | Expr::Field(e, _) | Expr::Lambda(_, e) => vec![e],
+        Expr::Binop(_, e1, e2) | Expr::Let(_, e1, e2) | Expr::Index(e1, e2) => vec![e1, e2],
+        Expr::If(e1, e2, e3) => vec![e1, e2, e3],
+        Expr::Call(_, es) | Expr::List(es) => es.iter_mut().collect(),
+        Expr::Record(fields) => fields.iter_mut().map(|(_, e)| e).collect(),
+    }
+}
+
+/// Spans for an expression that are all `span`.
+fn uniform(expr: &mut Expr, span: Span) -> Spans {
+    Spans {
+        span,
+        children: children_mut(expr)
+            .into_iter()
+            .map(|e| uniform(e, span))
+            .collect(),
+    }
+}
+
+/// Prefix every variable bound or used in an inlined body.
+fn rename(expr: &mut Expr, prefix: &str) {
+    let renamed = |name: &mut String| *name = format!("{}/{}", prefix, name);
+    match expr {
+        Expr::Var(name) | Expr::Let(name, ..) => renamed(name),
+        Expr::Lambda(params, _) => params.iter_mut().for_each(renamed),
+        _ => (),
+    }
+    for child in children_mut(expr) {
+        rename(child, prefix);
+    }
+}
+
+fn size(expr: &mut Expr) -> usize {
+    1 + children_mut(expr).into_iter().map(size).sum::<usize>()
+}
+
+struct Expander<'a> {
+    linked: &'a Linked,
+    /// Definitions being inlined, as `module.def`.
+    stack: Vec<String>,
+    size: usize,
+}
+
+impl<'a> Expander<'a> {
+    fn new(linked: &'a Linked) -> Self {
+        Self {
+            linked,
+            stack: vec![],
+            size: 0,
+        }
+    }
+
+    /// The module and definition a call in `module` refers to, `None` for builtins. Local
+    /// definitions shadow builtins of the same name, except the higher-order ones `parse`
+    /// rejects.
+    fn resolve(&self, module: &str, name: &str) -> Option<(&'a str, &'a Def)> {
+        let (module, name) = match name.split_once('.') {
+            Some((alias, name)) => {
+                let import = self.linked.modules[module]
+                    .imports
+                    .iter()
+                    .find(|i| i.alias == alias)?;
+                (import.name.as_str(), name)
+            }
+            None => (module, name),
+        };
+        let (module, m) = self.linked.modules.get_key_value(module)?;
+        let def = m.defs.iter().find(|d| d.name == name)?;
+        Some((module.as_str(), def))
+    }
+
+    fn expand(
+        &mut self,
+        module: &str,
+        expr: &mut Expr,
+        spans: &mut Spans,
+    ) -> Result<(), ModuleError> {
+        for (child, child_spans) in children_mut(expr).into_iter().zip(&mut spans.children) {
+            self.expand(module, child, child_spans)?;
+        }
+        let Expr::Call(name, args) = expr else {
+            return Ok(());
+        };
+        let Some((def_module, def)) = self.resolve(module, name) else {
+            return Ok(());
+        };
+        let qualified = format!("{}.{}", def_module, def.name);
+        if let Some(start) = self.stack.iter().position(|d| *d == qualified) {
+            let mut cycle = self.stack[start..].to_vec();
+            cycle.push(qualified);
+            return Err(ModuleError::Recursive(cycle));
+        }
+        if args.len() != def.params.len() {
+            return Err(ModuleError::Type {
+                module: module.to_string(),
+                error: TypeError::Arity {
+                    name: name.clone(),
+                    expected: def.params.len(),
+                    found: args.len(),
+                    span: Some(spans.span),
+                },
+            });
+        }
+
+        let mut body = def.body.clone();
+        rename(&mut body, &qualified);
+        self.size += size(&mut body);
+        if self.size > MAX_EXPANDED_NODES {
+            return Err(ModuleError::TooLarge);
+        }
+        let mut body_spans = uniform(&mut body, spans.span);
+        self.stack.push(qualified.clone());
+        self.expand(def_module, &mut body, &mut body_spans)?;
+        self.stack.pop();
+
+        // let module.def/param = arg in ... in body
+        let bindings = def
+            .params
+            .iter()
+            .zip(args.drain(..).zip(spans.children.drain(..)));
+        for (param, (arg, arg_spans)) in bindings.rev() {
+            body = Expr::Let(
+                format!("{}/{}", qualified, param),
+                Box::new(arg),
+                Box::new(body),
+            );
+            body_spans = Spans {
+                span: spans.span,
+                children: vec![arg_spans, body_spans],
+            };
+        }
+        *expr = body;
+        *spans = body_spans;
+        Ok(())
+    }
+}
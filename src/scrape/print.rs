@@ -0,0 +1,370 @@
+//! Turns expressions back into source, e.g. to show a stored script.
+//!
+//! Parentheses are only written where the parser would otherwise build a different tree, and
+//! `let` and `if` are broken into indented lines, so printing is stable: printing the parsed
+//! output again gives the same text.
+
+use std::fmt::{self, Write};
+
+use super::{
+    module::{parse_module, Module},
+    Expr, Oper, ParseDiagnostic, Unop, Value, KEYWORDS, PRECEDENCE,
+};
+
+const INDENT: &str = "  ";
+
+/// How tightly an expression binds, a child that binds looser than its position requires is
+/// parenthesized.
+/// - `let` and `if` extend as far to the right as possible and are only allowed where a full
+///   expression is
+/// - binary operators of [`PRECEDENCE`] level `l` bind with `l + 1`
+/// - `!e` is a factor but can not be indexed, `!xs[0]` negates `xs[0]`
+/// - literals, variables, calls, lists, records and postfix accesses
+const EXPR: usize = 0;
+const UNARY: usize = PRECEDENCE.len() + 1;
+const ATOM: usize = UNARY + 1;
+
+impl Oper {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Self::Add => "+",
+            Self::Sub => "-",
+            Self::Mul => "*",
+            Self::Div => "/",
+            Self::Eq => "==",
+            Self::Neq => "!=",
+            Self::Lt => "<",
+            Self::Le => "<=",
+            Self::Gt => ">",
+            Self::Ge => ">=",
+            Self::And => "&&",
+            Self::Or => "||",
+        }
+    }
+
+    /// Index of the operator in [`PRECEDENCE`].
+    pub fn level(&self) -> usize {
+        let symbol = self.symbol();
+        PRECEDENCE
+            .iter()
+            .position(|ops| ops.contains(&symbol))
+            .expect("every operator has a precedence")
+    }
+}
+
+impl fmt::Display for Oper {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.symbol())
+    }
+}
+
+impl Expr {
+    fn binding(&self) -> usize {
+        match self {
+            Self::Let(..) | Self::If(..) | Self::Lambda(..) => EXPR,
+            Self::Binop(op, ..) => op.level() + 1,
+            Self::Unop(..) => UNARY,
+            _ => ATOM,
+        }
+    }
+
+    /// Whether the expression is printed on more than one line.
+    fn is_multiline(&self) -> bool {
+        match self {
+            Self::Let(..) => true,
+            Self::If(c, e1, e2) => [c, e1, e2].iter().any(|e| e.contains_block()),
+            _ => self.contains_block(),
+        }
+    }
+
+    /// Whether a `let` or `if` is nested anywhere in the expression.
+    fn contains_block(&self) -> bool {
+        match self {
+            Self::Let(..) | Self::If(..) => true,
+            Self::Value(_) | Self::Var(_) => false,
+            Self::Binop(_, e1, e2) | Self::Index(e1, e2) => {
+                e1.contains_block() || e2.contains_block()
+            }
+            Self::Unop(_, e) | Self::Field(e, _) | Self::Lambda(_, e) => e.contains_block(),
+            Self::Call(_, es) | Self::List(es) => es.iter().any(Expr::contains_block),
+            Self::Record(fields) => fields.iter().any(|(_, e)| e.contains_block()),
+        }
+    }
+}
+
+/// Prints source that parses back into the same expression.
+///
+/// # Example
+///
+/// ```rust
+/// use seekr::scrape::parse_expr;
+/// let (_, expr) = parse_expr("((1 + 2) * (3 * 4)) - (5 - 6)").unwrap();
+/// assert_eq!(expr.to_string(), "(1 + 2) * (3 * 4) - (5 - 6)");
+///
+/// let (_, expr) = parse_expr("let a = 1 in if a > (if true then 0 else 1) then a else 2").unwrap();
+/// assert_eq!(
+///     expr.to_string(),
+///     "let a = 1 in\nif a > (if true then 0 else 1) then\n  a\nelse\n  2"
+/// );
+/// ```
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Printer { f, indent: 0 }.expr(self, EXPR)
+    }
+}
+
+/// Imports, definitions and the body, separated by blank lines. Definitions with a body on more
+/// than one line start it indented on the next line.
+///
+/// ```rust
+/// use seekr::scrape::module::parse_module;
+/// let module = parse_module("import \"github followers\" as gh def f(x)=let y=x in y gh.f(1)");
+/// assert_eq!(
+///     module.unwrap().to_string(),
+///     "import \"github followers\" as gh\n\ndef f(x) =\n  let y = x in\n  y\n\ngh.f(1)"
+/// );
+/// ```
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut printer = Printer { f, indent: 0 };
+        for (i, import) in self.imports.iter().enumerate() {
+            if i > 0 {
+                printer.newline()?;
+            }
+            write!(printer.f, "import {}", escape(&import.name))?;
+            if import.alias != import.name {
+                write!(printer.f, " as {}", import.alias)?;
+            }
+        }
+        let mut first = self.imports.is_empty();
+        let mut section = |printer: &mut Printer| {
+            if !std::mem::take(&mut first) {
+                printer.f.write_str("\n\n")?;
+            }
+            Ok(())
+        };
+        for def in &self.defs {
+            section(&mut printer)?;
+            write!(printer.f, "def {}({}) =", def.name, def.params.join(", "))?;
+            if def.body.is_multiline() {
+                printer.indented(&def.body)?;
+            } else {
+                printer.f.write_char(' ')?;
+                printer.expr(&def.body, EXPR)?;
+            }
+        }
+        if let Some((body, _)) = &self.body {
+            section(&mut printer)?;
+            printer.expr(body, EXPR)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse a script and print it again, see [`Module`]'s `Display`.
+pub fn format_script(src: &str) -> Result<String, ParseDiagnostic> {
+    Ok(format!("{}\n", parse_module(src)?))
+}
+
+struct Printer<'a, 'b> {
+    f: &'a mut fmt::Formatter<'b>,
+    indent: usize,
+}
+
+impl Printer<'_, '_> {
+    fn newline(&mut self) -> fmt::Result {
+        self.f.write_char('\n')?;
+        for _ in 0..self.indent {
+            self.f.write_str(INDENT)?;
+        }
+        Ok(())
+    }
+
+    /// Print `e` where an expression binding at least as tight as `binding` is expected.
+    fn expr(&mut self, e: &Expr, binding: usize) -> fmt::Result {
+        if e.binding() < binding {
+            self.f.write_char('(')?;
+            self.expr(e, EXPR)?;
+            return self.f.write_char(')');
+        }
+        match e {
+            Expr::Value(v) => literal(self.f, v),
+            Expr::Var(name) => self.f.write_str(name),
+            Expr::Binop(op, e1, e2) => {
+                // operators are left associative, an operand on the right of the same level
+                // needs parentheses
+                let level = op.level() + 1;
+                self.expr(e1, level)?;
+                write!(self.f, " {} ", op)?;
+                self.expr(e2, level + 1)
+            }
+            Expr::Unop(Unop::Not, e) => {
+                self.f.write_char('!')?;
+                self.expr(e, UNARY)
+            }
+            Expr::Let(name, e1, e2) => {
+                write!(self.f, "let {} = ", name)?;
+                self.expr(e1, EXPR)?;
+                self.f.write_str(" in")?;
+                self.newline()?;
+                self.expr(e2, EXPR)
+            }
+            Expr::If(..) => self.conditional(e, false),
+            Expr::Call(name, args) => {
+                self.f.write_str(name)?;
+                self.sequence('(', args, ')', |p, arg| p.expr(arg, EXPR))
+            }
+            Expr::List(elems) => self.sequence('[', elems, ']', |p, e| p.expr(e, EXPR)),
+            Expr::Record(fields) => self.sequence('{', fields, '}', |p, (k, e)| {
+                key(p.f, k)?;
+                p.f.write_str(": ")?;
+                p.expr(e, EXPR)
+            }),
+            Expr::Index(e, idx) => {
+                self.expr(e, ATOM)?;
+                self.f.write_char('[')?;
+                self.expr(idx, EXPR)?;
+                self.f.write_char(']')
+            }
+            Expr::Field(e, name) => {
+                self.expr(e, ATOM)?;
+                write!(self.f, ".{}", name)
+            }
+            Expr::Lambda(params, body) => {
+                write!(self.f, "|{}| ", params.join(", "))?;
+                self.expr(body, EXPR)
+            }
+        }
+    }
+
+    /// `if c then e1 else e2` on one line if none of its parts is multi-line, otherwise with the
+    /// branches indented. An `if` in the else branch continues the chain as `else if`, in the
+    /// same layout.
+    fn conditional(&mut self, e: &Expr, chained: bool) -> fmt::Result {
+        let Expr::If(c, e1, e2) = e else {
+            return self.expr(e, EXPR);
+        };
+        if !chained && !e.is_multiline() {
+            self.f.write_str("if ")?;
+            self.expr(c, EXPR)?;
+            self.f.write_str(" then ")?;
+            self.expr(e1, EXPR)?;
+            self.f.write_str(" else ")?;
+            return self.expr(e2, EXPR);
+        }
+        self.f.write_str("if ")?;
+        self.expr(c, EXPR)?;
+        self.f.write_str(" then")?;
+        self.indented(e1)?;
+        self.newline()?;
+        self.f.write_str("else")?;
+        if matches!(**e2, Expr::If(..)) {
+            self.f.write_char(' ')?;
+            self.conditional(e2, true)
+        } else {
+            self.indented(e2)
+        }
+    }
+
+    fn indented(&mut self, e: &Expr) -> fmt::Result {
+        self.indent += 1;
+        let res = self.newline().and_then(|_| self.expr(e, EXPR));
+        self.indent -= 1;
+        res
+    }
+
+    fn sequence<T>(
+        &mut self,
+        open: char,
+        items: &[T],
+        close: char,
+        mut item: impl FnMut(&mut Self, &T) -> fmt::Result,
+    ) -> fmt::Result {
+        self.f.write_char(open)?;
+        for (i, x) in items.iter().enumerate() {
+            if i > 0 {
+                self.f.write_str(", ")?;
+            }
+            item(self, x)?;
+        }
+        self.f.write_char(close)
+    }
+}
+
+/// A value as it is written in a script, unlike [`Value`]'s `Display` which writes strings
+/// unquoted.
+fn literal(f: &mut impl Write, v: &Value) -> fmt::Result {
+    match v {
+        Value::Number(n) => write!(f, "{}", n),
+        Value::Bool(b) => write!(f, "{}", b),
+        Value::Null => f.write_str("null"),
+        Value::Str(s) => quoted(f, s),
+        Value::List(xs) => {
+            f.write_char('[')?;
+            for (i, x) in xs.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                literal(f, x)?;
+            }
+            f.write_char(']')
+        }
+        Value::Record(fields) => {
+            f.write_char('{')?;
+            for (i, (k, x)) in fields.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                key(f, k)?;
+                f.write_str(": ")?;
+                literal(f, x)?;
+            }
+            f.write_char('}')
+        }
+    }
+}
+
+fn quoted(f: &mut impl Write, s: &str) -> fmt::Result {
+    f.write_str(&escape(s))
+}
+
+/// Quote a string as a scrape literal, [`parse_value`](super::parse_value) reads it back as `s`.
+/// Control characters without a short escape are written as `\u{...}`.
+///
+/// ```rust
+/// use seekr::scrape::{escape, parse_value, Value};
+/// assert_eq!(escape("Ада \"Lovelace\"\t\u{0}"), r#""Ада \"Lovelace\"\t\u{0}""#);
+/// assert_eq!(parse_value(&escape("東京\r\n")), Ok(("", Value::Str("東京\r\n".to_string()))));
+/// ```
+pub fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c if c.is_control() => out.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Record keys are written bare if they are identifiers.
+fn key(f: &mut impl Write, k: &str) -> fmt::Result {
+    let mut chars = k.chars();
+    let identifier = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !KEYWORDS.contains(&k);
+    if identifier {
+        f.write_str(k)
+    } else {
+        quoted(f, k)
+    }
+}
@@ -0,0 +1,294 @@
+//! Interactive evaluation of scrape expressions, see `seekr repl`.
+
+use rustyline::{
+    completion::Completer,
+    error::ReadlineError,
+    highlight::Highlighter,
+    hint::Hinter,
+    history::DefaultHistory,
+    validate::{ValidationContext, ValidationResult, Validator},
+    Editor, Helper,
+};
+use std::path::PathBuf;
+
+use super::{
+    builtins::Registry, eval::eval_limited, limits::Limits, parse_identifier, parse_script, Env,
+    Expr, TypeEnv, TypeRepr, Value,
+};
+
+pub const HELP: &str = "\
+<expr>              show the AST, type and value of an expression
+:let <name> = <expr> bind a value for the following lines
+:type <expr>        show the type of an expression
+:ast <expr>         show the syntax tree of an expression
+:load <file>        run the lines of a file as if they were typed
+:help               show this help
+:quit               leave, as does Ctrl-D";
+
+/// A line of input, expressions may span several lines.
+#[derive(Debug, PartialEq)]
+enum Command<'a> {
+    Eval(&'a str),
+    Let(&'a str, &'a str),
+    Type(&'a str),
+    Ast(&'a str),
+    Load(&'a str),
+    Help,
+    Quit,
+}
+
+fn parse_command(input: &str) -> Result<Command<'_>, String> {
+    let input = input.trim();
+    let Some(command) = input.strip_prefix(':') else {
+        return Ok(Command::Eval(input));
+    };
+    let (name, rest) = command
+        .split_once(char::is_whitespace)
+        .map_or((command, ""), |(name, rest)| (name, rest.trim()));
+    match name {
+        "let" => {
+            let (var, src) = rest
+                .split_once('=')
+                .ok_or_else(|| "usage: :let <name> = <expr>".to_string())?;
+            let var = var.trim();
+            match parse_identifier(var) {
+                Ok(("", _)) => Ok(Command::Let(var, src)),
+                _ => Err(format!("not a variable name: {:?}", var)),
+            }
+        }
+        "type" | "t" => Ok(Command::Type(rest)),
+        "ast" => Ok(Command::Ast(rest)),
+        "load" | "l" if !rest.is_empty() => Ok(Command::Load(rest)),
+        "load" | "l" => Err("usage: :load <file>".to_string()),
+        "help" | "h" | "?" => Ok(Command::Help),
+        "quit" | "q" => Ok(Command::Quit),
+        _ => Err(format!("unknown command :{}, see :help", name)),
+    }
+}
+
+/// Whether `input` is a whole entry, or an expression that continues on the next line, e.g.
+/// `let x = 1 in`.
+pub fn is_complete(input: &str) -> bool {
+    let src = match parse_command(input) {
+        // the expression of `:let name =` follows on the next line
+        Ok(Command::Let(_, src)) if src.trim().is_empty() => return false,
+        Ok(Command::Eval(src) | Command::Let(_, src) | Command::Type(src) | Command::Ast(src)) => {
+            src
+        }
+        _ => return true,
+    };
+    if src.trim().is_empty() {
+        return true;
+    }
+    match parse_script(src) {
+        Ok(_) => true,
+        Err(diagnostic) => !diagnostic.is_at_end() && !is_unclosed(src),
+    }
+}
+
+/// Whether a bracket or string is still open at the end of `src`. Lists do not fail at the end
+/// of `[1,`, the parser backtracks to the comma.
+fn is_unclosed(src: &str) -> bool {
+    let mut depth = 0i32;
+    let mut rest = src;
+    while let Some(c) = rest.chars().next() {
+        rest = &rest[c.len_utf8()..];
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            '"' => loop {
+                let mut chars = rest.chars();
+                match chars.next() {
+                    None => return true,
+                    Some('"') => {
+                        rest = chars.as_str();
+                        break;
+                    }
+                    Some('\\') => {
+                        chars.next();
+                    }
+                    Some(_) => (),
+                }
+                rest = chars.as_str();
+            },
+            // raw strings have no escapes, they end at a quote followed by their hashes
+            'r' => {
+                let hashes = rest.len() - rest.trim_start_matches('#').len();
+                if let Some(body) = rest[hashes..].strip_prefix('"') {
+                    let closing = format!("\"{}", "#".repeat(hashes));
+                    match body.find(&closing) {
+                        Some(end) => rest = &body[end + closing.len()..],
+                        None => return true,
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    depth > 0
+}
+
+/// The state of a REPL: the functions that can be called and the `:let` bindings so far.
+///
+/// # Example
+///
+/// ```rust
+/// use seekr::scrape::{builtins::Registry, limits::Limits, repl::Session};
+/// let mut session = Session::new(Registry::standard().clone(), Limits::default());
+/// assert_eq!(session.run(":let name = \"Ada\""), Ok("name: Str = \"Ada\"".to_string()));
+/// assert_eq!(session.run(":type upper(name)"), Ok("Str".to_string()));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Session {
+    registry: Registry,
+    limits: Limits,
+    env: Env,
+    types: TypeEnv,
+}
+
+impl Session {
+    pub fn new(registry: Registry, limits: Limits) -> Self {
+        Self {
+            registry,
+            limits,
+            env: Env::new(),
+            types: TypeEnv::new(),
+        }
+    }
+
+    /// Run a complete entry and return what to show, errors are rendered for the terminal.
+    pub fn run(&mut self, input: &str) -> Result<String, String> {
+        match parse_command(input)? {
+            Command::Eval("") => Ok(String::new()),
+            Command::Eval(src) => {
+                let (expr, t, value) = self.eval(src)?;
+                Ok(format!(
+                    "ast:   {:?}\ntype:  {}\nvalue: {}",
+                    expr,
+                    t,
+                    Expr::Value(value)
+                ))
+            }
+            Command::Let(name, src) => {
+                let (_, t, value) = self.eval(src)?;
+                let out = format!("{}: {} = {}", name, t, Expr::Value(value.clone()));
+                self.types.insert(name.to_string(), t);
+                self.env.insert(name.to_string(), value);
+                Ok(out)
+            }
+            Command::Type(src) => self.check(src).map(|(_, t)| t.to_string()),
+            Command::Ast(src) => self.parse(src).map(|expr| format!("{:#?}", expr)),
+            Command::Load(path) => {
+                let src = std::fs::read_to_string(path)
+                    .map_err(|e| format!("error: can not read {}: {}", path, e))?;
+                self.load(&src)
+            }
+            Command::Help => Ok(HELP.to_string()),
+            Command::Quit => Ok(String::new()),
+        }
+    }
+
+    /// Run the entries of a file, the way they would be typed line by line. Stops at the first
+    /// error.
+    pub fn load(&mut self, src: &str) -> Result<String, String> {
+        let mut out = vec![];
+        let mut entry = String::new();
+        for line in src.lines() {
+            if !entry.is_empty() {
+                entry.push('\n');
+            }
+            entry.push_str(line);
+            if is_complete(&entry) {
+                out.push(self.run(&entry)?);
+                entry.clear();
+            }
+        }
+        if !entry.trim().is_empty() {
+            out.push(self.run(&entry)?);
+        }
+        out.retain(|o| !o.is_empty());
+        Ok(out.join("\n"))
+    }
+
+    fn parse(&self, src: &str) -> Result<Expr, String> {
+        parse_script(src)
+            .map(|(expr, _)| expr)
+            .map_err(|diagnostic| diagnostic.render(src))
+    }
+
+    fn check(&self, src: &str) -> Result<(Expr, TypeRepr), String> {
+        let (expr, spans) = parse_script(src).map_err(|diagnostic| diagnostic.render(src))?;
+        let t = expr
+            .infer_with(Some(&spans), &self.types, &self.registry)
+            .map_err(|e| format!("error: {}", e))?;
+        Ok((expr, t))
+    }
+
+    fn eval(&self, src: &str) -> Result<(Expr, TypeRepr, Value), String> {
+        let (expr, t) = self.check(src)?;
+        let (value, _) = eval_limited(&expr, &self.env, &self.registry, &self.limits);
+        let value = value.map_err(|e| format!("error: {}", e))?;
+        Ok((expr, t, value))
+    }
+}
+
+/// Continues the input on the next line while an expression is incomplete.
+struct InputHelper;
+
+impl Validator for InputHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        Ok(if is_complete(ctx.input()) {
+            ValidationResult::Valid(None)
+        } else {
+            ValidationResult::Incomplete
+        })
+    }
+}
+
+impl Completer for InputHelper {
+    type Candidate = String;
+}
+
+impl Hinter for InputHelper {
+    type Hint = String;
+}
+
+impl Highlighter for InputHelper {}
+
+impl Helper for InputHelper {}
+
+/// Read entries from the terminal until `:quit` or Ctrl-D. History is kept in `history` if
+/// given.
+pub fn run(mut session: Session, history: Option<PathBuf>) -> anyhow::Result<()> {
+    let mut editor = Editor::<InputHelper, DefaultHistory>::new()?;
+    editor.set_helper(Some(InputHelper));
+    if let Some(path) = &history {
+        // there is no history on the first run
+        let _ = editor.load_history(path);
+    }
+    println!("seekr {}, :help for help", env!("CARGO_PKG_VERSION"));
+    loop {
+        let input = match editor.readline("> ") {
+            Ok(input) => input,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+        if input.trim().is_empty() {
+            continue;
+        }
+        editor.add_history_entry(input.as_str())?;
+        if parse_command(&input) == Ok(Command::Quit) {
+            break;
+        }
+        match session.run(&input) {
+            Ok(out) if out.is_empty() => (),
+            Ok(out) => println!("{}", out),
+            Err(e) => eprintln!("{}", e.trim_end()),
+        }
+    }
+    if let Some(path) = &history {
+        editor.save_history(path)?;
+    }
+    Ok(())
+}
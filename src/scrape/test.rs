@@ -1,6 +1,5 @@
 #![cfg(test)]
 use super::*;
-use std::collections::hash_map::HashMap;
 #[test]
 fn test_parse_value() {
     assert_eq!(parse_value("true"), Ok(("", Value::Bool(true))));
@@ -11,8 +10,55 @@ fn test_parse_value() {
             Ok(("", Value::Number(i)))
         );
     }
+    for i in [i64::MIN, i64::MAX] {
+        assert_eq!(parse_value(&i.to_string()), Ok(("", Value::Number(i))));
+    }
+    assert!(parse_value("9223372036854775808").is_err());
+    assert!(parse_value("-9223372036854775809").is_err());
+}
+use seekr_macro::{expr, scrape, value};
+
+fn var(name: &str) -> Box<Expr> {
+    Box::new(Expr::Var(name.to_string()))
+}
+
+#[test]
+fn test_parse_identifier() {
+    for name in [
+        "foo",
+        "user_name2",
+        "_private",
+        "x1_y2",
+        "letter",
+        "iffy",
+        "trueish",
+    ] {
+        assert_eq!(parse_identifier(name), Ok(("", name)));
+        assert_eq!(parse_expr(name), Ok(("", *var(name))));
+    }
+    for keyword in KEYWORDS {
+        assert!(parse_identifier(keyword).is_err());
+    }
+    assert!(parse_identifier("2fast").is_err());
+    assert_eq!(parse_identifier("foo-bar"), Ok(("-bar", "foo")));
+
+    assert_eq!(
+        parse_expr(r#"let user_name2 = "greg" in user_name2"#),
+        Ok((
+            "",
+            Expr::Let("user_name2".to_string(), value!("greg"), var("user_name2"))
+        ))
+    );
+    // variables stay symbolic, also when they are not bound by a let
+    assert_eq!(
+        parse_expr("username+1"),
+        Ok(("", Expr::Binop(Oper::Add, var("username"), value!(1))))
+    );
+    assert_eq!(
+        parse_expr("if letter then iffy else falsey"),
+        Ok(("", Expr::If(var("letter"), var("iffy"), var("falsey"))))
+    );
 }
-use seekr_macro::value;
 use tracing_test::traced_test;
 #[traced_test]
 #[test]
@@ -24,7 +70,7 @@ fn test_parse_expr() {
 
     // value!(int_one!());
     assert_eq!(
-        parse_expr(HashMap::new(), "1*3+-2"),
+        parse_expr("1*3+-2"),
         Ok((
             "",
             Expr::Binop(
@@ -36,7 +82,7 @@ fn test_parse_expr() {
     );
 
     assert_eq!(
-        parse_expr(HashMap::new(), "1*3+2"),
+        parse_expr("1*3+2"),
         Ok((
             "",
             Expr::Binop(
@@ -48,7 +94,7 @@ fn test_parse_expr() {
     );
 
     assert_eq!(
-        parse_expr(HashMap::new(), "1*3+(2)"),
+        parse_expr("1*3+(2)"),
         Ok((
             "",
             Expr::Binop(
@@ -58,23 +104,20 @@ fn test_parse_expr() {
             )
         ))
     );
+    assert_eq!(parse_expr("true"), Ok(("", Expr::Value(Value::Bool(true)))));
     assert_eq!(
-        parse_expr(HashMap::new(), "true"),
-        Ok(("", Expr::Value(Value::Bool(true))))
-    );
-    assert_eq!(
-        parse_expr(HashMap::new(), "false"),
+        parse_expr("false"),
         Ok(("", Expr::Value(Value::Bool(false))))
     );
     for i in -1000..1000 {
         assert_eq!(
-            parse_expr(HashMap::new(), format!("{}", i).as_str()),
+            parse_expr(format!("{}", i).as_str()),
             Ok(("", Expr::Value(Value::Number(i))))
         );
     }
 
     assert_eq!(
-        parse_expr(HashMap::new(), "1+2+5"),
+        parse_expr("1+2+5"),
         Ok((
             "",
             Expr::Binop(
@@ -86,7 +129,7 @@ fn test_parse_expr() {
     );
 
     assert_eq!(
-        parse_expr(HashMap::new(), "1*2*5"),
+        parse_expr("1*2*5"),
         Ok((
             "",
             Expr::Binop(
@@ -98,7 +141,7 @@ fn test_parse_expr() {
     );
 
     assert_eq!(
-        parse_expr(HashMap::new(), "1*2*5-2"),
+        parse_expr("1*2*5-2"),
         Ok((
             "",
             Expr::Binop(
@@ -114,7 +157,7 @@ fn test_parse_expr() {
     );
 
     assert_eq!(
-        parse_expr(HashMap::new(), "1*2*5-2+1"),
+        parse_expr("1*2*5-2+1"),
         Ok((
             "",
             Expr::Binop(
@@ -134,7 +177,7 @@ fn test_parse_expr() {
     );
 
     assert_eq!(
-        parse_expr(HashMap::new(), "(1+2)*3"),
+        parse_expr("(1+2)*3"),
         Ok((
             "",
             Expr::Binop(
@@ -146,7 +189,7 @@ fn test_parse_expr() {
     );
 
     assert_eq!(
-        parse_expr(HashMap::new(), "(1+2+3)*3"),
+        parse_expr("(1+2+3)*3"),
         Ok((
             "",
             Expr::Binop(
@@ -162,28 +205,28 @@ fn test_parse_expr() {
     );
 
     assert_eq!(
-        parse_expr(HashMap::new(), "let foo=2 in 3"),
+        parse_expr("let foo=2 in 3"),
         Ok(("", Expr::Let("foo".to_string(), value!(2), value!(3))))
     );
 
     assert_eq!(
-        parse_expr(HashMap::new(), "let foo=2 in foo"),
-        Ok(("", Expr::Let("foo".to_string(), value!(2), value!(2))))
+        parse_expr("let foo=2 in foo"),
+        Ok(("", Expr::Let("foo".to_string(), value!(2), var("foo"))))
     );
     assert_eq!(
-        parse_expr(HashMap::new(), "let foo=2 in foo+3"),
+        parse_expr("let foo=2 in foo+3"),
         Ok((
             "",
             Expr::Let(
                 "foo".to_string(),
                 value!(2),
-                Box::new(Expr::Binop(Oper::Add, value!(2), value!(3)))
+                Box::new(Expr::Binop(Oper::Add, var("foo"), value!(3)))
             )
         ))
     );
 
     assert_eq!(
-        parse_expr(HashMap::new(), "let foo=2 in let foo = foo+3 in foo-1"),
+        parse_expr("let foo=2 in let foo = foo+3 in foo-1"),
         Ok((
             "",
             Expr::Let(
@@ -191,35 +234,31 @@ fn test_parse_expr() {
                 value!(2),
                 Box::new(Expr::Let(
                     "foo".to_string(),
-                    Box::new(Expr::Binop(Oper::Add, value!(2), value!(3))),
-                    Box::new(Expr::Binop(
-                        Oper::Sub,
-                        Box::new(Expr::Binop(Oper::Add, value!(2), value!(3))),
-                        value!(1)
-                    ))
+                    Box::new(Expr::Binop(Oper::Add, var("foo"), value!(3))),
+                    Box::new(Expr::Binop(Oper::Sub, var("foo"), value!(1)))
                 ))
             )
         ))
     );
 
     assert_eq!(
-        parse_expr(HashMap::new(), "if true then 1 else 2"),
+        parse_expr("if true then 1 else 2"),
         Ok(("", Expr::If(value!(true), value!(1), value!(2),)))
     );
 
     assert_eq!(
-        parse_expr(HashMap::new(), "let foo = if true then 1 else 2 in foo"),
+        parse_expr("let foo = if true then 1 else 2 in foo"),
         Ok((
             "",
             Expr::Let(
                 "foo".to_string(),
                 Box::new(Expr::If(value!(true), value!(1), value!(2))),
-                Box::new(Expr::If(value!(true), value!(1), value!(2))),
+                var("foo"),
             )
         ))
     );
     assert_eq!(
-        parse_expr(HashMap::new(), "1+2*3"),
+        parse_expr("1+2*3"),
         Ok((
             "",
             Expr::Binop(
@@ -239,59 +278,2228 @@ fn test_parse_expr() {
 
     for t1 in t {
         assert_eq!(
-            parse_expr(HashMap::new(), &format!(r#""{}""#, t1)),
+            parse_expr(&format!(r#""{}""#, t1)),
             Ok(("", Expr::Value(Value::Str(t1.to_string()))))
         );
     }
 
     assert_eq!(
-        parse_expr(HashMap::new(), r#""Hello World""#),
-        Ok(("", Expr::Value(Value::Str("Hello World".to_string()))))
+        parse_expr(r#""Hello World""#),
+        Ok(("", *value!("Hello World")))
     );
+    assert_eq!(parse_expr(r#""+2*3""#), Ok(("", *value!("+2*3"))));
+
+    assert_eq!(parse_expr(r#""\"""#), Ok(("", *value!("\""))));
+
+    assert_eq!(parse_expr(r#""+2*3""#), Ok(("", *value!("+2*3"))));
+
     assert_eq!(
-        parse_expr(HashMap::new(), r#""+2*3""#),
-        Ok(("", Expr::Value(Value::Str("+2*3".to_string()))))
+        parse_expr(
+            r#""! #$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~""#
+        ),
+        Ok(("", 
+
+                Expr::Value(Value::Str( r#"! #$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\]^_`abcdefghijklmnopqrstuvwxyz{|}~"# .to_string()))))
     );
+}
+
+#[test]
+fn test_strings() {
+    let parsed = parse_value;
+    let s = |s: &str| Value::Str(s.to_string());
 
+    // anything but quotes and backslashes stands for itself
+    for text in [
+        "",
+        "Ада Лавлейс",
+        "東京都",
+        "Zoë Ångström",
+        "👩‍💻",
+        "two\nlines",
+    ] {
+        assert_eq!(parsed(&format!("\"{}\"", text)), Ok(("", s(text))));
+    }
     assert_eq!(
-        parse_expr(HashMap::new(), r#""\"""#),
-        Ok(("", Expr::Value(Value::Str(r#"""#.to_string()))))
+        parsed(r#""tab\tcr\rnl\nquote\"backslash\\""#),
+        Ok(("", s("tab\tcr\rnl\nquote\"backslash\\")))
     );
+    assert_eq!(
+        parsed(r#""\u{41}\u{1F600}\u{0}\u{10FFFF}""#),
+        Ok(("", s("A\u{1F600}\u{0}\u{10FFFF}")))
+    );
+    for invalid in [
+        r#""\q""#,
+        r#""\u{}""#,
+        r#""\u{D800}""#,
+        r#""\u{110000}""#,
+        r#""\u{1234567}""#,
+        r#""\u41""#,
+        r#""open"#,
+    ] {
+        assert!(parse_script(invalid).is_err(), "{}", invalid);
+    }
+    let diagnostic = parse_script(r#""\q""#).unwrap_err();
+    assert_eq!(diagnostic.context, ["string"]);
+    assert!(diagnostic.expected.contains(&"escape".to_string()));
+
+    // raw strings
+    assert_eq!(parsed(r#"r"C:\new\tab""#), Ok(("", s(r#"C:\new\tab"#))));
+    assert_eq!(parsed(r#"r"""#), Ok(("", s(""))));
+    assert_eq!(parsed(r###"r#"say "hi""#"###), Ok(("", s(r#"say "hi""#))));
+    assert_eq!(parsed(r####"r##"a "# b"##"####), Ok(("", s(r##"a "# b"##))));
+    assert!(parse_script(r##"r#"open""##).is_err());
+    // `r` alone is still a variable
+    assert_eq!(parse_expr("r + 1"), Ok(("", *expr!(r + 1))));
+
+    assert_eq!(escape(""), r#""""#);
+    assert_eq!(escape("a\"b\\c\u{7f}"), r#""a\"b\\c\u{7f}""#);
+}
+
+#[traced_test]
+#[test]
+fn test_parse_operators() {
+    let parse = |s: &str| {
+        let (rest, expr) = parse_expr(s).unwrap();
+        assert_eq!(rest, "", "{}", s);
+        expr
+    };
+    let binop = |op, e1: Box<Expr>, e2: Box<Expr>| Box::new(Expr::Binop(op, e1, e2));
+    let not = |e: Box<Expr>| Box::new(Expr::Unop(Unop::Not, e));
+
+    for (s, op) in [
+        ("==", Oper::Eq),
+        ("!=", Oper::Neq),
+        ("<", Oper::Lt),
+        ("<=", Oper::Le),
+        (">", Oper::Gt),
+        (">=", Oper::Ge),
+        ("&&", Oper::And),
+        ("||", Oper::Or),
+    ] {
+        assert_eq!(
+            parse(&format!("1{}2", s)),
+            *binop(op.clone(), value!(1), value!(2))
+        );
+        assert_eq!(
+            parse(&format!("1 {} 2", s)),
+            *binop(op, value!(1), value!(2))
+        );
+    }
 
+    // comparison binds looser than arithmetic
+    assert_eq!(
+        parse("1+2<3*4"),
+        *binop(
+            Oper::Lt,
+            binop(Oper::Add, value!(1), value!(2)),
+            binop(Oper::Mul, value!(3), value!(4))
+        )
+    );
+    assert_eq!(
+        parse("1 - 2 >= 3"),
+        *binop(Oper::Ge, binop(Oper::Sub, value!(1), value!(2)), value!(3))
+    );
+    // equality binds looser than ordering
+    assert_eq!(
+        parse("1<2 == 3>4"),
+        *binop(
+            Oper::Eq,
+            binop(Oper::Lt, value!(1), value!(2)),
+            binop(Oper::Gt, value!(3), value!(4))
+        )
+    );
+    // logic binds looser than comparison, && tighter than ||
+    assert_eq!(
+        parse("1==2 && 3!=4"),
+        *binop(
+            Oper::And,
+            binop(Oper::Eq, value!(1), value!(2)),
+            binop(Oper::Neq, value!(3), value!(4))
+        )
+    );
+    assert_eq!(
+        parse("true || false && false"),
+        *binop(
+            Oper::Or,
+            value!(true),
+            binop(Oper::And, value!(false), value!(false))
+        )
+    );
+    assert_eq!(
+        parse("true && false || false"),
+        *binop(
+            Oper::Or,
+            binop(Oper::And, value!(true), value!(false)),
+            value!(false)
+        )
+    );
     assert_eq!(
-        parse_expr(HashMap::new(), r#""+2*3""#),
-        Ok(("", Expr::Value(Value::Str("+2*3".to_string()))))
+        parse("(true || false) && false"),
+        *binop(
+            Oper::And,
+            binop(Oper::Or, value!(true), value!(false)),
+            value!(false)
+        )
     );
 
+    // all levels are left associative
     assert_eq!(
-        parse_expr(
-            HashMap::new(),
-            r#""! #$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~""#
-        ),
-        Ok(("", 
+        parse("1<2<3"),
+        *binop(Oper::Lt, binop(Oper::Lt, value!(1), value!(2)), value!(3))
+    );
+    assert_eq!(
+        parse("1==2!=3"),
+        *binop(Oper::Neq, binop(Oper::Eq, value!(1), value!(2)), value!(3))
+    );
+    assert_eq!(
+        parse("true&&false&&true"),
+        *binop(
+            Oper::And,
+            binop(Oper::And, value!(true), value!(false)),
+            value!(true)
+        )
+    );
+    assert_eq!(
+        parse("true||false||true"),
+        *binop(
+            Oper::Or,
+            binop(Oper::Or, value!(true), value!(false)),
+            value!(true)
+        )
+    );
 
-                Expr::Value(Value::Str( r#"! #$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\]^_`abcdefghijklmnopqrstuvwxyz{|}~"# .to_string()))))
+    // ! binds tighter than every binary operator
+    assert_eq!(parse("!true"), *not(value!(true)));
+    assert_eq!(parse("!!true"), *not(not(value!(true))));
+    assert_eq!(
+        parse("!true && false"),
+        *binop(Oper::And, not(value!(true)), value!(false))
+    );
+    assert_eq!(parse("!(1<2)"), *not(binop(Oper::Lt, value!(1), value!(2))));
+    assert_eq!(parse("1 != -2"), *binop(Oper::Neq, value!(1), value!(-2)));
+
+    assert_eq!(
+        parse("if 1 < 2 && !false then 1 else 2"),
+        Expr::If(
+            binop(
+                Oper::And,
+                binop(Oper::Lt, value!(1), value!(2)),
+                not(value!(false))
+            ),
+            value!(1),
+            value!(2)
+        )
+    );
+    assert_eq!(
+        parse("let foo=2 in foo >= 2"),
+        Expr::Let(
+            "foo".to_string(),
+            value!(2),
+            binop(Oper::Ge, var("foo"), value!(2))
+        )
     );
 }
 
 #[traced_test]
 #[test]
 fn test_typecheck() {
-    let (_, res) = parse_expr(HashMap::new(), "1+2+5").unwrap();
+    let (_, res) = parse_expr("1+2+5").unwrap();
     assert_eq!(res.typecheck(), Ok(()));
 
-    let (_, res) = parse_expr(HashMap::new(), r#""hello world""#).unwrap();
+    let (_, res) = parse_expr(r#""hello world""#).unwrap();
     assert_eq!(res.typecheck(), Ok(()));
 
-    let (_, res) = parse_expr(HashMap::new(), r#""hello world"+1"#).unwrap();
-    assert_eq!(res.typecheck(), Err(()));
+    let (_, res) = parse_expr(r#""hello world"+1"#).unwrap();
+    assert_eq!(
+        res.typecheck(),
+        Err(TypeError::Mismatch {
+            expected: TypeRepr::Str,
+            found: TypeRepr::Number,
+            span: None
+        })
+    );
 
-    let (_, res) = parse_expr(HashMap::new(), r#"if "hello world" then 1 else 2"#).unwrap();
-    assert_eq!(res.typecheck(), Err(()));
+    let (_, res) = parse_expr(r#"if "hello world" then 1 else 2"#).unwrap();
+    assert_eq!(
+        res.typecheck(),
+        Err(TypeError::Mismatch {
+            expected: TypeRepr::Bool,
+            found: TypeRepr::Str,
+            span: None
+        })
+    );
 
-    let (_, res) = parse_expr(HashMap::new(), r#"if false then 1 else 2"#).unwrap();
+    let (_, res) = parse_expr(r#"if false then 1 else 2"#).unwrap();
     assert_eq!(res.typecheck(), Ok(()));
 
-    let (_, res) = parse_expr(HashMap::new(), r#"if false then false else 2"#).unwrap();
-    assert_eq!(res.typecheck(), Err(()));
+    let (_, res) = parse_expr(r#"if false then false else 2"#).unwrap();
+    assert_eq!(
+        res.typecheck(),
+        Err(TypeError::Mismatch {
+            expected: TypeRepr::Bool,
+            found: TypeRepr::Number,
+            span: None
+        })
+    );
+}
+
+#[traced_test]
+#[test]
+fn test_infer() {
+    let infer_str = |s: &str| {
+        let (rest, expr) = parse_expr(s).unwrap();
+        assert_eq!(rest, "");
+        expr.infer(&TypeEnv::new())
+    };
+    let mismatch = |expected, found| {
+        Err(TypeError::Mismatch {
+            expected,
+            found,
+            span: None,
+        })
+    };
+
+    assert_eq!(infer_str("1*3+-2"), Ok(TypeRepr::Number));
+    assert_eq!(infer_str(r#""a"+"b""#), Ok(TypeRepr::Str));
+    assert_eq!(infer_str("true"), Ok(TypeRepr::Bool));
+    assert_eq!(infer_str(r#"let foo="a" in foo+"b""#), Ok(TypeRepr::Str));
+    assert_eq!(
+        infer_str("let foo=if true then 1 else 2 in foo*3"),
+        Ok(TypeRepr::Number)
+    );
+    assert_eq!(infer_str(r#"if true then "a" else "b""#), Ok(TypeRepr::Str));
+
+    // arguments of binary operators are checked, not only the operator
+    assert_eq!(
+        infer_str("true*2"),
+        mismatch(TypeRepr::Number, TypeRepr::Bool)
+    );
+    assert_eq!(
+        infer_str("2-false"),
+        mismatch(TypeRepr::Number, TypeRepr::Bool)
+    );
+    assert_eq!(
+        infer_str(r#"1+"a""#),
+        mismatch(TypeRepr::Number, TypeRepr::Str)
+    );
+    assert_eq!(
+        infer_str(r#"(1+"a")*2"#),
+        mismatch(TypeRepr::Number, TypeRepr::Str)
+    );
+    assert_eq!(
+        infer_str(r#"1+(if true then 1 else "a")"#),
+        mismatch(TypeRepr::Number, TypeRepr::Str)
+    );
+
+    // types flow through let bindings, both the bound expression and the body are checked
+    assert_eq!(
+        infer_str(r#"let foo="a" in foo*2"#),
+        mismatch(TypeRepr::Number, TypeRepr::Str)
+    );
+    assert_eq!(
+        infer_str("let foo=true+1 in 2"),
+        mismatch(TypeRepr::Number, TypeRepr::Bool)
+    );
+
+    // the condition and both branches of an if are checked
+    assert_eq!(
+        infer_str("if 1 then 2 else 3"),
+        mismatch(TypeRepr::Bool, TypeRepr::Number)
+    );
+    assert_eq!(
+        infer_str("if true then 1 else true"),
+        mismatch(TypeRepr::Number, TypeRepr::Bool)
+    );
+    assert_eq!(
+        infer_str("if true then true+1 else 2"),
+        mismatch(TypeRepr::Number, TypeRepr::Bool)
+    );
+
+    let eq = |e1, e2| Expr::Binop(Oper::Eq, e1, e2);
+    assert_eq!(
+        eq(value!(1), value!(2)).infer(&TypeEnv::new()),
+        Ok(TypeRepr::Bool)
+    );
+    assert_eq!(
+        eq(value!(true), value!(false)).infer(&TypeEnv::new()),
+        Ok(TypeRepr::Bool)
+    );
+    assert_eq!(
+        eq(value!(true), value!(1)).infer(&TypeEnv::new()),
+        mismatch(TypeRepr::Bool, TypeRepr::Number)
+    );
+
+    assert_eq!(infer_str("1 < 2 && !false"), Ok(TypeRepr::Bool));
+    assert_eq!(infer_str(r#""a" >= "b""#), Ok(TypeRepr::Bool));
+    assert_eq!(
+        infer_str(r#""a" < 1"#),
+        mismatch(TypeRepr::Str, TypeRepr::Number)
+    );
+    assert_eq!(
+        infer_str("true < false"),
+        mismatch(TypeRepr::Number, TypeRepr::Bool)
+    );
+    assert_eq!(
+        infer_str("1 && true"),
+        mismatch(TypeRepr::Bool, TypeRepr::Number)
+    );
+    assert_eq!(infer_str("!1"), mismatch(TypeRepr::Bool, TypeRepr::Number));
+
+    // unbound variables are reported at check time, inputs are declared in the env
+    let unbound = |name: &str| {
+        Err(TypeError::Unbound {
+            name: name.to_string(),
+            span: None,
+        })
+    };
+    assert_eq!(infer_str("foo"), unbound("foo"));
+    assert_eq!(infer_str("let foo=1 in bar"), unbound("bar"));
+    assert_eq!(infer_str("let foo=foo in 1"), unbound("foo"));
+    assert_eq!(infer_str("(let foo=1 in foo)+foo"), unbound("foo"));
+    let (_, expr) = parse_expr(r#"user_name + "@example.com""#).unwrap();
+    let env = TypeEnv::from([("user_name".to_string(), TypeRepr::Str)]);
+    assert_eq!(expr.infer(&env), Ok(TypeRepr::Str));
+    assert_eq!(expr.infer(&TypeEnv::new()), unbound("user_name"));
+    // inner bindings shadow outer ones and the env
+    let (_, expr) = parse_expr("let user_name=1 in user_name*2").unwrap();
+    assert_eq!(expr.infer(&env), Ok(TypeRepr::Number));
+}
+
+#[test]
+fn test_eval() {
+    let eval_str = |s: &str| {
+        let (rest, expr) = parse_expr(s).unwrap();
+        assert_eq!(rest, "");
+        eval(&expr, &Env::new())
+    };
+
+    assert_eq!(eval_str("1*3+-2"), Ok(Value::Number(1)));
+    assert_eq!(eval_str("(1+2+3)*3"), Ok(Value::Number(18)));
+    assert_eq!(eval_str("7/2"), Ok(Value::Number(3)));
+    assert_eq!(eval_str("-7/2"), Ok(Value::Number(-3)));
+    assert_eq!(
+        eval_str("let foo=2 in let foo = foo+3 in foo-1"),
+        Ok(Value::Number(4))
+    );
+    assert_eq!(eval_str("if false then 1 else 2"), Ok(Value::Number(2)));
+    assert_eq!(eval_str("1+2 == 3"), Ok(Value::Bool(true)));
+    assert_eq!(eval_str("1 != 1"), Ok(Value::Bool(false)));
+    assert_eq!(eval_str("-1 < 1 && 2 <= 2"), Ok(Value::Bool(true)));
+    assert_eq!(eval_str("1 > 2 || 3 >= 4"), Ok(Value::Bool(false)));
+    assert_eq!(eval_str(r#""abc" < "abd""#), Ok(Value::Bool(true)));
+    assert_eq!(eval_str("!(1 < 2)"), Ok(Value::Bool(false)));
+    assert_eq!(
+        eval_str("!1"),
+        Err(EvalError::InvalidOperand {
+            op: Unop::Not,
+            value: Value::Number(1)
+        })
+    );
+    assert_eq!(
+        eval_str(r#""hello "+"world""#),
+        Ok(Value::Str("hello world".to_string()))
+    );
+
+    assert_eq!(eval_str("1/0"), Err(EvalError::DivisionByZero));
+    assert_eq!(eval_str("1/(2-2)"), Err(EvalError::DivisionByZero));
+    assert_eq!(
+        eval_str("9223372036854775807+1"),
+        Err(EvalError::Overflow(Oper::Add))
+    );
+    assert_eq!(
+        eval_str("-9223372036854775807-2"),
+        Err(EvalError::Overflow(Oper::Sub))
+    );
+    assert_eq!(
+        eval_str("4294967296*4294967296"),
+        Err(EvalError::Overflow(Oper::Mul))
+    );
+    assert_eq!(
+        eval_str("(-9223372036854775807-1)/-1"),
+        Err(EvalError::Overflow(Oper::Div))
+    );
+    assert_eq!(
+        eval_str(r#""hello"+1"#),
+        Err(EvalError::InvalidOperands {
+            op: Oper::Add,
+            lhs: Value::Str("hello".to_string()),
+            rhs: Value::Number(1),
+        })
+    );
+    assert_eq!(
+        eval_str(r#""hello"*"world""#),
+        Err(EvalError::InvalidOperands {
+            op: Oper::Mul,
+            lhs: Value::Str("hello".to_string()),
+            rhs: Value::Str("world".to_string()),
+        })
+    );
+    assert_eq!(
+        eval_str("if 1 then 2 else 3"),
+        Err(EvalError::NonBoolCondition(Value::Number(1)))
+    );
+
+    // short circuit: the branch not taken is never evaluated
+    assert_eq!(eval_str("if true then 1 else 1/0"), Ok(Value::Number(1)));
+    assert_eq!(eval_str("if false then 1/0 else 2"), Ok(Value::Number(2)));
+    assert_eq!(eval_str("false && 1/0 == 1"), Ok(Value::Bool(false)));
+    assert_eq!(eval_str("true || 1/0 == 1"), Ok(Value::Bool(true)));
+    assert_eq!(eval_str("true && 1/0 == 1"), Err(EvalError::DivisionByZero));
+    // but let bindings are strict
+    assert_eq!(eval_str("let foo=1/0 in 2"), Err(EvalError::DivisionByZero));
+
+    assert_eq!(
+        eval_str("foo"),
+        Err(EvalError::UnboundVariable("foo".to_string()))
+    );
+    assert_eq!(
+        eval_str("(let foo=1 in foo)+foo"),
+        Err(EvalError::UnboundVariable("foo".to_string()))
+    );
+    assert_eq!(
+        eval_str("let foo=1 in let bar=foo+1 in let foo=10 in foo+bar"),
+        Ok(Value::Number(12))
+    );
+    let (_, expr) = parse_expr(r#"user_name + "@example.com""#).unwrap();
+    assert_eq!(
+        eval(
+            &expr,
+            &Env::from([("user_name".to_string(), Value::Str("greg".to_string()))])
+        ),
+        Ok(Value::Str("greg@example.com".to_string()))
+    );
+
+    // the body of a let is evaluated in an extended copy of the outer env
+    let env = Env::from([("foo".to_string(), Value::Number(1))]);
+    let expr = Expr::Binop(
+        Oper::Add,
+        Box::new(Expr::Let("foo".to_string(), value!(2), var("foo"))),
+        var("foo"),
+    );
+    assert_eq!(eval(&expr, &env), Ok(Value::Number(3)));
+    assert_eq!(env.get("foo"), Some(&Value::Number(1)));
+}
+
+/// Replace the free occurrences of `name` in `expr` by `value`.
+fn subst(expr: &Expr, name: &str, value: &Value) -> Expr {
+    let s = |e: &Expr| Box::new(subst(e, name, value));
+    match expr {
+        Expr::Var(n) if n == name => Expr::Value(value.clone()),
+        Expr::Value(_) | Expr::Var(_) => expr.clone(),
+        Expr::Binop(op, e1, e2) => Expr::Binop(op.clone(), s(e1), s(e2)),
+        Expr::Unop(op, e) => Expr::Unop(op.clone(), s(e)),
+        Expr::If(c, e1, e2) => Expr::If(s(c), s(e1), s(e2)),
+        // the binding shadows `name` in the body
+        Expr::Let(n, e1, e2) if n == name => Expr::Let(n.clone(), s(e1), e2.clone()),
+        Expr::Let(n, e1, e2) => Expr::Let(n.clone(), s(e1), s(e2)),
+        Expr::Call(f, args) => Expr::Call(
+            f.clone(),
+            args.iter().map(|e| subst(e, name, value)).collect(),
+        ),
+        Expr::List(elems) => Expr::List(elems.iter().map(|e| subst(e, name, value)).collect()),
+        Expr::Record(fields) => Expr::Record(
+            fields
+                .iter()
+                .map(|(k, e)| (k.clone(), subst(e, name, value)))
+                .collect(),
+        ),
+        Expr::Index(e, idx) => Expr::Index(s(e), s(idx)),
+        Expr::Field(e, field) => Expr::Field(s(e), field.clone()),
+        // the parameters shadow `name` in the body
+        Expr::Lambda(params, _) if params.iter().any(|p| p == name) => expr.clone(),
+        Expr::Lambda(params, body) => Expr::Lambda(params.clone(), s(body)),
+    }
+}
+
+/// Reference interpretation of a parsed `Expr` used to check `eval`. Let bindings are
+/// substituted instead of looked up in an env. Arithmetic is done in i128 and range checked
+/// afterwards instead of relying on the checked i64 operations.
+fn eval_reference(expr: &Expr) -> Result<Value, EvalError> {
+    match expr {
+        Expr::Value(v) => Ok(v.clone()),
+        Expr::Var(name) => Err(EvalError::UnboundVariable(name.to_string())),
+        Expr::Let(name, e1, e2) => {
+            let value = eval_reference(e1)?;
+            eval_reference(&subst(e2, name, &value))
+        }
+        Expr::If(c, e1, e2) => match eval_reference(c)? {
+            Value::Bool(true) => eval_reference(e1),
+            Value::Bool(false) => eval_reference(e2),
+            v => Err(EvalError::NonBoolCondition(v)),
+        },
+        Expr::Call(name, args) if builtins::HIGHER_ORDER.contains(&name.as_str()) => {
+            let (xs, init, Expr::Lambda(params, body)) = (match args.as_slice() {
+                [xs, init, lambda] => (xs, Some(init), lambda),
+                [xs, lambda] => (xs, None, lambda),
+                _ => unreachable!(),
+            }) else {
+                unreachable!()
+            };
+            let Value::List(xs) = eval_reference(xs)? else {
+                unreachable!()
+            };
+            // bind the parameters one after the other
+            let apply = |args: &[Value]| {
+                let body = params
+                    .iter()
+                    .zip(args)
+                    .fold(*body.clone(), |body, (p, v)| subst(&body, p, v));
+                eval_reference(&body)
+            };
+            match (name.as_str(), init) {
+                ("map", _) => xs
+                    .iter()
+                    .map(|x| apply(std::slice::from_ref(x)))
+                    .collect::<Result<_, _>>()
+                    .map(Value::List),
+                ("filter", _) => {
+                    let mut kept = vec![];
+                    for x in xs {
+                        match apply(std::slice::from_ref(&x))? {
+                            Value::Bool(true) => kept.push(x),
+                            Value::Bool(false) => (),
+                            v => return Err(EvalError::NonBoolCondition(v)),
+                        }
+                    }
+                    Ok(Value::List(kept))
+                }
+                (_, Some(init)) => {
+                    let mut acc = eval_reference(init)?;
+                    for x in xs {
+                        acc = apply(&[acc, x])?;
+                    }
+                    Ok(acc)
+                }
+                _ => unreachable!(),
+            }
+        }
+        Expr::List(elems) => Ok(Value::List(
+            elems.iter().map(eval_reference).collect::<Result<_, _>>()?,
+        )),
+        Expr::Record(fields) => Ok(Value::Record(Box::new(
+            fields
+                .iter()
+                .map(|(k, e)| Ok((k.clone(), eval_reference(e)?)))
+                .collect::<Result<_, EvalError>>()?,
+        ))),
+        Expr::Index(e, idx) => match (eval_reference(e)?, eval_reference(idx)?) {
+            (Value::List(xs), Value::Number(index)) if (0..xs.len() as i64).contains(&index) => {
+                Ok(xs[index as usize].clone())
+            }
+            (Value::List(xs), Value::Number(index)) => Err(EvalError::IndexOutOfRange {
+                index,
+                len: xs.len(),
+            }),
+            (value, index) => Err(EvalError::InvalidIndex { value, index }),
+        },
+        Expr::Field(e, name) => match eval_reference(e)? {
+            Value::Record(fields) if fields.contains_key(name) => Ok(fields[name].clone()),
+            value => Err(EvalError::NoField {
+                name: name.clone(),
+                value,
+            }),
+        },
+        Expr::Lambda(..) => Err(EvalError::UnexpectedLambda),
+        Expr::Call(name, args) => {
+            let args = args
+                .iter()
+                .map(eval_reference)
+                .collect::<Result<Vec<_>, _>>()?;
+            match (name.as_str(), args.as_slice()) {
+                ("len", [Value::Str(s)]) => Ok(Value::Number(s.len() as i64)),
+                ("upper", [Value::Str(s)]) => Ok(Value::Str(s.to_ascii_uppercase())),
+                ("contains", [Value::Str(a), Value::Str(b)]) => Ok(Value::Bool(a.contains(b))),
+                ("to_str", [Value::Number(n)]) => Ok(Value::Str(format!("{}", n))),
+                ("to_str", [Value::Bool(b)]) => Ok(Value::Str(format!("{}", b))),
+                ("to_str", [Value::Str(s)]) => Ok(Value::Str(s.clone())),
+                ("to_str", [v]) => Ok(Value::Str(serde_json::Value::from(v.clone()).to_string())),
+                _ => Err(EvalError::InvalidArguments {
+                    name: name.clone(),
+                    args,
+                }),
+            }
+        }
+        Expr::Unop(Unop::Not, e) => match eval_reference(e)? {
+            Value::Bool(b) => Ok(Value::Bool(!b)),
+            value => Err(EvalError::InvalidOperand {
+                op: Unop::Not,
+                value,
+            }),
+        },
+        Expr::Binop(op, e1, e2) => {
+            let lhs = eval_reference(e1)?;
+            match (op, &lhs) {
+                (Oper::And, Value::Bool(false)) => return Ok(lhs),
+                (Oper::Or, Value::Bool(true)) => return Ok(lhs),
+                _ => (),
+            }
+            let rhs = eval_reference(e2)?;
+            match (op, &lhs, &rhs) {
+                (Oper::Add, Value::Str(a), Value::Str(b)) => Ok(Value::Str(format!("{}{}", a, b))),
+                (Oper::Eq, a, b) => Ok(Value::Bool(a == b)),
+                (Oper::Neq, a, b) => Ok(Value::Bool(a != b)),
+                (Oper::And | Oper::Or, Value::Bool(_), Value::Bool(b)) => Ok(Value::Bool(*b)),
+                (Oper::Lt, Value::Number(a), Value::Number(b)) => Ok(Value::Bool(a < b)),
+                (Oper::Le, Value::Number(a), Value::Number(b)) => Ok(Value::Bool(a <= b)),
+                (Oper::Gt, Value::Number(a), Value::Number(b)) => Ok(Value::Bool(a > b)),
+                (Oper::Ge, Value::Number(a), Value::Number(b)) => Ok(Value::Bool(a >= b)),
+                (Oper::Lt, Value::Str(a), Value::Str(b)) => Ok(Value::Bool(a < b)),
+                (Oper::Le, Value::Str(a), Value::Str(b)) => Ok(Value::Bool(a <= b)),
+                (Oper::Gt, Value::Str(a), Value::Str(b)) => Ok(Value::Bool(a > b)),
+                (Oper::Ge, Value::Str(a), Value::Str(b)) => Ok(Value::Bool(a >= b)),
+                (
+                    Oper::Add | Oper::Sub | Oper::Mul | Oper::Div,
+                    Value::Number(a),
+                    Value::Number(b),
+                ) => {
+                    let (a, b) = (*a as i128, *b as i128);
+                    let result = match op {
+                        Oper::Add => a + b,
+                        Oper::Sub => a - b,
+                        Oper::Mul => a * b,
+                        Oper::Div if b == 0 => return Err(EvalError::DivisionByZero),
+                        Oper::Div => a / b,
+                        _ => unreachable!(),
+                    };
+                    i64::try_from(result)
+                        .map(Value::Number)
+                        .map_err(|_| EvalError::Overflow(op.clone()))
+                }
+                _ => Err(EvalError::InvalidOperands {
+                    op: op.clone(),
+                    lhs,
+                    rhs,
+                }),
+            }
+        }
+    }
+}
+
+/// Scrape source text the parser accepts. Binary operations are fully parenthesized so the
+/// generated structure does not depend on precedence, see `test_parse_operators` for that.
+fn source_strategy() -> impl Strategy<Value = String> {
+    let leaf = prop_oneof![
+        any::<i64>().prop_map(|n| n.to_string()),
+        (-10i64..10).prop_map(|n| n.to_string()),
+        any::<bool>().prop_map(|b| b.to_string()),
+        "[a-z ]{1,8}".prop_map(|s| format!(r#""{}""#, s)),
+        prop::sample::select(vec!["foo", "bar", "baz_1"]).prop_map(|s| s.to_string()),
+    ];
+    leaf.prop_recursive(6, 64, 3, |inner| {
+        prop_oneof![
+            (
+                inner.clone(),
+                prop::sample::select(vec![
+                    "+", "-", "*", "/", "==", "!=", "<", "<=", ">", ">=", "&&", "||"
+                ]),
+                inner.clone()
+            )
+                .prop_map(|(a, op, b)| format!("({}{}{})", a, op, b)),
+            (
+                prop::sample::select(vec!["foo", "bar", "baz_1"]),
+                inner.clone(),
+                inner.clone()
+            )
+                .prop_map(|(name, e1, e2)| format!("(let {}={} in {})", name, e1, e2)),
+            (
+                prop::sample::select(vec!["foo", "bar"]),
+                inner.clone(),
+                inner.clone(),
+                prop::sample::select(vec!["+", "*"]),
+            )
+                .prop_map(|(name, e1, e2, op)| {
+                    format!("(let {name}={e1} in ({name}{op}{e2}))")
+                }),
+            inner.clone().prop_map(|e| format!("!{}", e)),
+            (
+                prop::sample::select(vec!["len", "upper", "to_str"]),
+                inner.clone()
+            )
+                .prop_map(|(f, e)| format!("{}({})", f, e)),
+            (inner.clone(), inner.clone()).prop_map(|(a, b)| format!("contains({}, {})", a, b)),
+            (prop::collection::vec(inner.clone(), 0..4), -1i64..4).prop_map(|(xs, i)| format!(
+                "[{}][{}]",
+                xs.join(", "),
+                i
+            )),
+            (
+                inner.clone(),
+                inner.clone(),
+                prop::sample::select(vec!["a", "b", "c"])
+            )
+                .prop_map(|(a, b, k)| format!("{{a: {}, \"b\": {}}}.{}", a, b, k)),
+            (
+                prop::collection::vec(inner.clone(), 0..4),
+                prop::sample::select(vec!["map", "filter"]),
+                prop::sample::select(vec!["foo", "bar"]),
+                inner.clone(),
+                prop::sample::select(vec!["+", "=="]),
+            )
+                .prop_map(|(xs, f, x, e, op)| {
+                    format!("{}([{}], |{x}| ({x}{}{}))", f, xs.join(","), op, e)
+                }),
+            (prop::collection::vec(inner.clone(), 0..4), inner.clone()).prop_map(|(xs, init)| {
+                format!("fold([{}], {}, |foo, bar| (foo + bar))", xs.join(","), init)
+            }),
+            (any::<bool>(), inner.clone(), inner.clone())
+                .prop_map(|(c, e1, e2)| format!("(if {} then {} else {})", c, e1, e2)),
+            (inner.clone(), inner.clone(), inner)
+                .prop_map(|(c, e1, e2)| format!("(if {} then {} else {})", c, e1, e2)),
+        ]
+    })
+}
+
+use proptest::prelude::*;
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(2000))]
+    #[test]
+    fn proptest_eval(source in source_strategy()) {
+        let (rest, expr) = parse_expr(&source).unwrap();
+        prop_assert_eq!(rest, "");
+        prop_assert_eq!(eval(&expr, &Env::new()), eval_reference(&expr));
+    }
+
+    /// Well typed expressions can only fail at runtime because of arithmetic and indexing.
+    #[test]
+    fn proptest_typecheck_sound(source in source_strategy()) {
+        let (_, expr) = parse_expr(&source).unwrap();
+        if let Ok(t) = expr.infer(&TypeEnv::new()) {
+            match eval(&expr, &Env::new()) {
+                Ok(v) => {
+                    let found = Value::get_type(v).unwrap();
+                    prop_assert!(found.unify(&t).is_some(), "{} is not {}", found, t);
+                }
+                Err(e) => prop_assert!(
+                    matches!(
+                        e,
+                        EvalError::DivisionByZero
+                            | EvalError::Overflow(_)
+                            | EvalError::IndexOutOfRange { .. }
+                    ),
+                    "{:?}",
+                    e
+                ),
+            }
+        }
+    }
+}
+
+#[test]
+fn test_parse_script() {
+    let (expr, spans) = parse_script("let foo = 2\nin foo + 1").unwrap();
+    assert_eq!(
+        expr,
+        Expr::Let(
+            "foo".to_string(),
+            Box::new(Expr::Value(Value::Number(2))),
+            Box::new(Expr::Binop(
+                Oper::Add,
+                var("foo"),
+                Box::new(Expr::Value(Value::Number(1)))
+            ))
+        )
+    );
+    let pos = |line, column| Position { line, column };
+    assert_eq!(
+        spans.span,
+        Span {
+            start: pos(1, 1),
+            end: pos(2, 11)
+        }
+    );
+    assert_eq!(spans.children.len(), 2);
+    assert_eq!(spans.children[0].span.start, pos(1, 11));
+    let body = &spans.children[1];
+    assert_eq!((body.span.start, body.span.end), (pos(2, 4), pos(2, 11)));
+    assert_eq!(
+        body.children.iter().map(|c| c.span).collect::<Vec<_>>(),
+        [
+            Span {
+                start: pos(2, 4),
+                end: pos(2, 7)
+            },
+            Span {
+                start: pos(2, 10),
+                end: pos(2, 11)
+            }
+        ]
+    );
+}
+
+#[test]
+fn test_parse_diagnostic() {
+    let err = |src| parse_script(src).unwrap_err();
+
+    let e = err("let foo = 2 foo");
+    assert_eq!(e.expected, ["in"]);
+    assert_eq!(e.context, ["let"]);
+    assert_eq!(e.message, "expected in");
+    assert_eq!(
+        e.span.start,
+        Position {
+            line: 1,
+            column: 13
+        }
+    );
+    assert_eq!(
+        e.span.end,
+        Position {
+            line: 1,
+            column: 16
+        }
+    );
+    assert_eq!(
+        e.render("let foo = 2 foo"),
+        "error: expected in\n \
+         --> 1:13\n  \
+         |\n\
+         1 | let foo = 2 foo\n  \
+         |             ^^^\n  \
+         = while parsing let\n"
+    );
+
+    let e = err("(1+2");
+    assert_eq!(e.expected, ["')'"]);
+    assert_eq!(e.span.start, Position { line: 1, column: 5 });
+
+    let e = err("1 +");
+    assert_eq!(e.expected, ["expression"]);
+
+    let e = err("1 2");
+    assert_eq!(e.expected, ["end of input"]);
+    assert_eq!(e.span.start, Position { line: 1, column: 3 });
+
+    let e = err("if true\nthen 1\nelse");
+    assert_eq!(e.span.start.line, 3);
+    assert_eq!(e.context, ["if"]);
+    assert_eq!(e.to_string(), "3:5: expected expression");
+}
+
+#[test]
+fn test_infer_spanned() {
+    let src = "let foo = 1 in\nif foo then 1 else 2";
+    let (expr, spans) = parse_script(src).unwrap();
+    assert_eq!(
+        expr.infer_spanned(&spans, &TypeEnv::new()),
+        Err(TypeError::Mismatch {
+            expected: TypeRepr::Bool,
+            found: TypeRepr::Number,
+            span: Some(Span {
+                start: Position { line: 2, column: 4 },
+                end: Position { line: 2, column: 7 }
+            })
+        })
+    );
+
+    let (expr, spans) = parse_script("1 + bar").unwrap();
+    assert_eq!(
+        expr.infer_spanned(&spans, &TypeEnv::new()),
+        Err(TypeError::Unbound {
+            name: "bar".to_string(),
+            span: Some(Span {
+                start: Position { line: 1, column: 5 },
+                end: Position { line: 1, column: 8 }
+            })
+        })
+    );
+}
+
+#[test]
+fn test_builtins() {
+    let run = |src: &str| eval(&parse_script(src).unwrap().0, &Env::new());
+    let string = |s: &str| Ok(Value::Str(s.to_string()));
+
+    assert_eq!(run(r#"len("seekr")"#), Ok(Value::Number(5)));
+    assert_eq!(run(r#"lower("SeeKr")"#), string("seekr"));
+    assert_eq!(run(r#"upper("seekr")"#), string("SEEKR"));
+    assert_eq!(run(r#"trim("  seekr ")"#), string("seekr"));
+    assert_eq!(run(r#"split("a,b,c", ",")[1]"#), string("b"));
+    assert_eq!(run(r#"contains("seekr", "ee")"#), Ok(Value::Bool(true)));
+    assert_eq!(run(r#"replace("a.b.c", ".", "/")"#), string("a/b/c"));
+    assert_eq!(
+        run(r#"regex_match("user42", "^[a-z]+[0-9]+$")"#),
+        Ok(Value::Bool(true))
+    );
+    assert!(matches!(
+        run(r#"regex_match("user42", "(")"#),
+        Err(EvalError::Builtin { name, .. }) if name == "regex_match"
+    ));
+    assert_eq!(
+        run(r#"md5(lower(trim(" Foo@Example.com ")))"#),
+        string("b48def645758b95537d4424c84d1a9ff")
+    );
+    assert_eq!(
+        run(r#"sha256("abc")"#),
+        string("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+    );
+    assert_eq!(run(r#"url_encode("a b&c")"#), string("a%20b%26c"));
+    assert_eq!(run(r#"url_decode("a%20b%26c")"#), string("a b&c"));
+    assert_eq!(run("to_str(1 + 2)"), string("3"));
+    assert_eq!(run("to_str(true)"), string("true"));
+    assert_eq!(run(r#"to_num(" 42 ") * 2"#), Ok(Value::Number(84)));
+    assert!(matches!(
+        run(r#"to_num("4x2")"#),
+        Err(EvalError::Builtin { .. })
+    ));
+    assert_eq!(
+        run("nope(1)"),
+        Err(EvalError::UnknownFunction("nope".to_string()))
+    );
+    assert_eq!(
+        run("len(1)"),
+        Err(EvalError::InvalidArguments {
+            name: "len".to_string(),
+            args: vec![Value::Number(1)]
+        })
+    );
+}
+
+#[test]
+fn test_infer_builtins() {
+    let infer = |src: &str| {
+        let (expr, spans) = parse_script(src).unwrap();
+        expr.infer_spanned(&spans, &TypeEnv::new())
+    };
+    let pos = |column| Position { line: 1, column };
+
+    assert_eq!(infer(r#"len(upper("a")) + 1"#), Ok(TypeRepr::Number));
+    assert_eq!(infer("to_str(1 == 2)"), Ok(TypeRepr::Str));
+    assert_eq!(infer(r#"contains ( "a" , "b" )"#), Ok(TypeRepr::Bool));
+    assert_eq!(
+        infer(r#"replace("a", 1, "b")"#),
+        Err(TypeError::Mismatch {
+            expected: TypeRepr::Str,
+            found: TypeRepr::Number,
+            span: Some(Span {
+                start: pos(14),
+                end: pos(15)
+            })
+        })
+    );
+    assert_eq!(
+        infer(r#"md5("a", "b")"#),
+        Err(TypeError::Arity {
+            name: "md5".to_string(),
+            expected: 1,
+            found: 2,
+            span: Some(Span {
+                start: pos(1),
+                end: pos(14)
+            })
+        })
+    );
+    assert!(matches!(
+        infer("nope()"),
+        Err(TypeError::UnknownFunction { name, .. }) if name == "nope"
+    ));
+
+    // a call is not a variable
+    assert_eq!(
+        parse_expr("len (foo)"),
+        Ok(("", Expr::Call("len".to_string(), vec![*var("foo")])))
+    );
+    assert_eq!(parse_script("len(1,)").unwrap_err().context, ["call"]);
+}
+
+#[test]
+fn test_collections() {
+    let run = |src: &str| eval(&parse_script(src).unwrap().0, &Env::new());
+    let infer = |src: &str| parse_script(src).unwrap().0.infer(&TypeEnv::new());
+    let list = |t| TypeRepr::List(Box::new(t));
+    let numbers = |ns: &[i64]| Ok(Value::List(ns.iter().copied().map(Value::Number).collect()));
+
+    assert_eq!(
+        parse_expr("xs[0].name"),
+        Ok((
+            "",
+            Expr::Field(
+                Box::new(Expr::Index(var("xs"), value!(0))),
+                "name".to_string()
+            )
+        ))
+    );
+    assert_eq!(
+        parse_expr(r#"{name: "a", "full name": null}"#),
+        Ok((
+            "",
+            Expr::Record(vec![
+                ("name".to_string(), *value!("a")),
+                ("full name".to_string(), Expr::Value(Value::Null)),
+            ])
+        ))
+    );
+    assert!(parse_identifier("null").is_err());
+    assert_eq!(parse_script("[1, 2").unwrap_err().expected, ["']'"]);
+
+    assert_eq!(run("[1, 2 + 3][1]"), Ok(Value::Number(5)));
+    assert_eq!(run("[[1], [2, 3]][1][0]"), Ok(Value::Number(2)));
+    assert_eq!(
+        run("[1][1]"),
+        Err(EvalError::IndexOutOfRange { index: 1, len: 1 })
+    );
+    assert_eq!(
+        run(r#"{a: 1, b: {c: "x"}}.b.c"#),
+        Ok(Value::Str("x".to_string()))
+    );
+    assert_eq!(
+        run(r#"{"user name": 1}["user name"]"#),
+        Ok(Value::Number(1))
+    );
+    assert!(matches!(run("{a: 1}.b"), Err(EvalError::NoField { name, .. }) if name == "b"));
+    assert_eq!(run("null == null"), Ok(Value::Bool(true)));
+    assert_eq!(run("{a: 1} != null"), Ok(Value::Bool(true)));
+    assert_eq!(run("[1, 2] == [1, 2]"), Ok(Value::Bool(true)));
+
+    assert_eq!(run("map([1, 2, 3], |x| x * 2)"), numbers(&[2, 4, 6]));
+    assert_eq!(run("filter([1, 2, 3, 4], |x| x > 2)"), numbers(&[3, 4]));
+    assert_eq!(
+        run("fold([1, 2, 3], 10, |acc, x| acc + x)"),
+        Ok(Value::Number(16))
+    );
+    assert_eq!(
+        run(r#"map(split("a b", " "), |s| upper(s))"#),
+        Ok(Value::List(vec![
+            Value::Str("A".to_string()),
+            Value::Str("B".to_string())
+        ]))
+    );
+    // lambdas close over the environment
+    assert_eq!(run("let n = 10 in map([1], |x| x + n)"), numbers(&[11]));
+    assert_eq!(
+        run("map([1], 2)"),
+        Err(EvalError::ExpectedLambda {
+            name: "map".to_string(),
+            params: 1
+        })
+    );
+
+    assert_eq!(infer("[1, 2]"), Ok(list(TypeRepr::Number)));
+    assert_eq!(infer("[]"), Ok(list(TypeRepr::Any)));
+    assert_eq!(infer("[[], [1]]"), Ok(list(list(TypeRepr::Number))));
+    assert_eq!(infer("[1, 2][0] + 1"), Ok(TypeRepr::Number));
+    assert_eq!(infer(r#"{a: 1, b: "x"}.b"#), Ok(TypeRepr::Str));
+    assert_eq!(infer("null"), Ok(TypeRepr::Null));
+    assert_eq!(infer("map([1], |x| x == 1)"), Ok(list(TypeRepr::Bool)));
+    assert_eq!(
+        infer(r#"fold(["a"], 0, |acc, s| acc + len(s))"#),
+        Ok(TypeRepr::Number)
+    );
+    assert!(matches!(
+        infer("[1, true]"),
+        Err(TypeError::Mismatch {
+            expected: TypeRepr::Number,
+            found: TypeRepr::Bool,
+            ..
+        })
+    ));
+    assert!(matches!(
+        infer("{a: 1}.b"),
+        Err(TypeError::NoField { name, .. }) if name == "b"
+    ));
+    assert!(matches!(
+        infer("filter([1], |x| x)"),
+        Err(TypeError::Mismatch {
+            expected: TypeRepr::Bool,
+            ..
+        })
+    ));
+    assert!(matches!(
+        infer("fold([1], 0, |x| x)"),
+        Err(TypeError::ExpectedLambda { params: 2, .. })
+    ));
+    assert!(matches!(
+        infer("len(|x| x)"),
+        Err(TypeError::UnexpectedLambda { .. })
+    ));
+    // statically unknown data is checked at runtime
+    let env = TypeEnv::from([("data".to_string(), TypeRepr::Any)]);
+    let (_, expr) = parse_expr("data.users[0].age + 1").unwrap();
+    assert_eq!(expr.infer(&env), Ok(TypeRepr::Number));
+    // as long as the operation is possible for some type
+    for (src, ok) in [
+        ("data < 1", true),
+        ("\"a\" >= data", true),
+        ("data <= data", true),
+        ("data > true", false),
+        ("true < data", false),
+        ("data < [1]", false),
+        ("data >= null", false),
+    ] {
+        let (_, expr) = parse_expr(src).unwrap();
+        assert_eq!(expr.infer(&env).is_ok(), ok, "{src}");
+    }
+}
+
+#[test]
+fn test_json() {
+    let json = serde_json::json!({
+        "name": "Ada",
+        "links": ["https://example.com", null],
+        "age": 36,
+        "verified": true,
+    });
+    let value = Value::try_from(json.clone()).unwrap();
+    let Value::Record(fields) = &value else {
+        panic!("{:?}", value)
+    };
+    // fields keep the order of the document
+    assert_eq!(
+        fields.keys().collect::<Vec<_>>(),
+        ["name", "links", "age", "verified"]
+    );
+    assert_eq!(
+        fields["links"],
+        Value::List(vec![
+            Value::Str("https://example.com".to_string()),
+            Value::Null
+        ])
+    );
+    assert_eq!(serde_json::Value::from(value.clone()), json);
+    assert_eq!(serde_json::to_value(&value).unwrap(), json);
+    assert_eq!(serde_json::from_value::<Value>(json).unwrap(), value);
+    assert_eq!(
+        value.to_string(),
+        r#"{"name":"Ada","links":["https://example.com",null],"age":36,"verified":true}"#
+    );
+    assert!(matches!(
+        Value::try_from(serde_json::json!([1.5])),
+        Err(FromJsonError::NotAnInteger(_))
+    ));
+    assert_eq!(
+        Value::get_type(value).unwrap().to_string(),
+        "{name: Str, links: [Any], age: Number, verified: Bool}"
+    );
+}
+
+#[test]
+fn test_html() {
+    let env = Env::from([
+        (
+            "html".to_string(),
+            Value::Str(include_str!("fixtures/profile.html").to_string()),
+        ),
+        (
+            "body".to_string(),
+            Value::Str(include_str!("fixtures/user.json").to_string()),
+        ),
+    ]);
+    let run = |src: &str| eval(&parse_script(src).unwrap().0, &env);
+    let strings = |ss: &[&str]| {
+        Ok(Value::List(
+            ss.iter().map(|s| Value::Str(s.to_string())).collect(),
+        ))
+    };
+
+    assert_eq!(
+        run(r#"map(select(html, "h1.name"), |n| text(n))"#),
+        strings(&["Ada Lovelace"])
+    );
+    assert_eq!(
+        run(r#"map(select(html, "div.bio > p"), |p| text(p))"#),
+        strings(&[
+            "Mathematician and writer.",
+            "Works on the Analytical Engine."
+        ])
+    );
+    // nodes can be selected from again
+    assert_eq!(
+        run(r#"map(select(select(html, "div.bio")[0], "a"), |a| attr(a, "href"))"#),
+        strings(&["https://example.com/engine"])
+    );
+    assert_eq!(
+        run(r#"map(select(html, "a[rel=me]"), |a| attr(a, "href"))"#),
+        strings(&["https://example.com/ada", "https://social.example/@ada"])
+    );
+    assert_eq!(
+        run(r#"attr(select(html, "img.avatar")[0], "title")"#),
+        Ok(Value::Null)
+    );
+    assert_eq!(run(r#"select(html, "table")"#), Ok(Value::List(vec![])));
+    assert!(matches!(
+        run(r#"select(html, "div >")"#),
+        Err(EvalError::Builtin { name, .. }) if name == "select"
+    ));
+
+    assert_eq!(
+        run(r#"json_path(body, "$.data.user.login")"#),
+        strings(&["ada"])
+    );
+    assert_eq!(
+        run(r#"json_path(body, "$.data.user.repositories[*].stars")"#),
+        Ok(Value::List(vec![Value::Number(42), Value::Number(7)]))
+    );
+    assert_eq!(
+        run(r#"json_path(body, "$.data.user")[0].followers + 1"#),
+        Ok(Value::Number(1816))
+    );
+    // values work as documents too
+    assert_eq!(
+        run(r#"json_path({user: {login: "ada"}}, "$.user.login")"#),
+        strings(&["ada"])
+    );
+
+    let types = TypeEnv::from([
+        ("html".to_string(), TypeRepr::Str),
+        ("body".to_string(), TypeRepr::Str),
+    ]);
+    let (_, expr) = parse_expr(r#"map(select(html, "a"), |a| text(a))"#).unwrap();
+    assert_eq!(
+        expr.infer(&types),
+        Ok(TypeRepr::List(Box::new(TypeRepr::Str)))
+    );
+    let (_, expr) = parse_expr(r#"json_path(body, "$.data")[0].user.login"#).unwrap();
+    assert_eq!(expr.infer(&types), Ok(TypeRepr::Any));
+}
+
+/// Answers every request with its own url and headers, for checking what reaches the network.
+struct Echo;
+
+impl fetch::Transport for Echo {
+    fn send(&self, request: &fetch::Request) -> Result<fetch::Response, fetch::FetchError> {
+        Ok(fetch::Response {
+            status: 200,
+            headers: request.headers.clone(),
+            body: request.url.clone(),
+        })
+    }
+}
+
+fn fetch_registry(transport: impl fetch::Transport + 'static) -> builtins::Registry {
+    let mut registry = builtins::Registry::standard().clone();
+    fetch::register(&mut registry, std::sync::Arc::new(transport));
+    registry
+}
+
+#[test]
+fn test_fetch_replay() {
+    let replay = fetch::Replay::load("src/scrape/fixtures/exchanges.json").unwrap();
+    let registry = fetch_registry(replay);
+    let run = |src: &str| eval::eval_with(&parse_script(src).unwrap().0, &Env::new(), &registry);
+
+    assert_eq!(
+        run(r#"json_path(fetch("https://api.example.com/users/ada", {}).body, "$.followers")"#),
+        Ok(Value::List(vec![Value::Number(1815)]))
+    );
+    assert_eq!(
+        run(
+            r#"let r = fetch("https://api.example.com/search", {method: "post", body: "q=ada"})
+               in [r.status, r.headers, r.body]"#
+        ),
+        Ok(Value::List(vec![
+            Value::Number(404),
+            Value::Record(Default::default()),
+            Value::Str("not found".to_string())
+        ]))
+    );
+    assert_eq!(
+        run(r#"fetch("https://api.example.com/users/bob", null)"#),
+        Err(EvalError::Fetch(fetch::FetchError::NotRecorded {
+            method: "GET".to_string(),
+            url: "https://api.example.com/users/bob".to_string()
+        }))
+    );
+    assert!(matches!(
+        run(r#"fetch("https://api.example.com/users/ada", {verb: "GET"})"#),
+        Err(EvalError::Fetch(fetch::FetchError::InvalidOptions(_)))
+    ));
+
+    let (expr, spans) = parse_script(r#"fetch("https://example.com", {}).status + 1"#).unwrap();
+    assert_eq!(
+        expr.infer_with(Some(&spans), &TypeEnv::new(), &registry),
+        Ok(TypeRepr::Number)
+    );
+    // not available without a transport
+    assert!(matches!(
+        expr.infer_spanned(&spans, &TypeEnv::new()),
+        Err(TypeError::UnknownFunction { .. })
+    ));
+}
+
+#[test]
+fn test_fetch_record() {
+    let path = std::env::temp_dir().join(format!("seekr-exchanges-{}.json", std::process::id()));
+    let recorder = fetch::Recorder::new(Echo, &path);
+    let request = fetch::Request::get("https://example.com/ada");
+    let response = fetch::Transport::send(&recorder, &request).unwrap();
+
+    let replay = fetch::Replay::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(fetch::Transport::send(&replay, &request), Ok(response));
+}
+
+#[test]
+fn test_fetch_policy() {
+    let policy = fetch::Policy {
+        allowed_hosts: vec!["example.com".to_string(), "*.example.org".to_string()],
+        rate_limit: None,
+        max_body: 30,
+        timeout: std::time::Duration::from_secs(1),
+        user_agent: "seekr-test".to_string(),
+        max_redirects: 10,
+    };
+    assert!(policy.allows("example.com"));
+    assert!(policy.allows("api.example.org"));
+    assert!(!policy.allows("example.org"));
+    assert!(!policy.allows("www.example.com"));
+    assert!(!policy.allows("badexample.org"));
+
+    let registry = fetch_registry(fetch::PolicyTransport::new(policy.clone(), Echo));
+    let run = |src: &str| eval::eval_with(&parse_script(src).unwrap().0, &Env::new(), &registry);
+    let denied = |host: &str| {
+        Err(EvalError::Fetch(fetch::FetchError::DeniedHost {
+            host: host.to_string(),
+        }))
+    };
+
+    assert_eq!(
+        run(r#"fetch("https://example.com/", {}).headers["User-Agent"]"#),
+        Ok(Value::Str("seekr-test".to_string()))
+    );
+    assert_eq!(
+        run(r#"fetch("https://example.com/", {headers: {"user-agent": "me"}}).headers"#),
+        Ok(Value::Record(Box::new(
+            [("user-agent".to_string(), Value::Str("me".to_string()))].into()
+        )))
+    );
+    assert_eq!(
+        run(r#"fetch("https://evil.example/", {})"#),
+        denied("evil.example")
+    );
+    assert_eq!(
+        run(r#"fetch("http://localhost:8000/admin", {})"#),
+        denied("localhost")
+    );
+    assert!(matches!(
+        run(r#"fetch("file:///etc/passwd", {})"#),
+        Err(EvalError::Fetch(fetch::FetchError::InvalidUrl { .. }))
+    ));
+    // the echoed url is the body
+    assert_eq!(
+        run(r#"fetch("https://example.com/a/very/long/path/to/somewhere", {})"#),
+        Err(EvalError::Fetch(fetch::FetchError::BodyTooLarge {
+            limit: 30
+        }))
+    );
+
+    let interval = std::time::Duration::from_millis(50);
+    let transport = fetch::PolicyTransport::new(
+        fetch::Policy {
+            rate_limit: Some(interval),
+            ..policy
+        },
+        Echo,
+    );
+    let start = std::time::Instant::now();
+    for url in [
+        "https://example.com/1",
+        "https://example.com/2",
+        "https://api.example.org/",
+    ] {
+        fetch::Transport::send(&transport, &fetch::Request::get(url)).unwrap();
+    }
+    // only the second request to example.com waits
+    let elapsed = start.elapsed();
+    assert!(
+        elapsed >= interval && elapsed < 2 * interval,
+        "{:?}",
+        elapsed
+    );
+}
+
+#[test]
+fn test_fetch_redirects() {
+    use limits::{LimitKind, Limits};
+
+    let exchange = |method: &str, url: &str, status: u16, location: Option<&str>| {
+        let mut request = fetch::Request::get(url);
+        request.method = method.to_string();
+        if method == "POST" {
+            request.body = Some("q=ada".to_string());
+        }
+        fetch::Exchange {
+            request,
+            response: fetch::Response {
+                status,
+                headers: location
+                    .map(|l| ("Location".to_string(), l.to_string()))
+                    .into_iter()
+                    .collect(),
+                body: url.to_string(),
+            },
+        }
+    };
+    let replay = fetch::Replay::new(vec![
+        exchange(
+            "GET",
+            "https://example.com/moved",
+            302,
+            Some("http://10.0.0.1/admin"),
+        ),
+        exchange("GET", "https://example.com/a", 301, Some("/b")),
+        exchange(
+            "GET",
+            "https://example.com/b",
+            307,
+            Some("https://api.example.org/c"),
+        ),
+        exchange("GET", "https://api.example.org/c", 200, None),
+        exchange("GET", "https://example.com/loop", 302, Some("loop")),
+        exchange("POST", "https://example.com/form", 303, Some("/done")),
+        exchange("GET", "https://example.com/done", 200, None),
+    ]);
+    let policy = fetch::Policy {
+        allowed_hosts: vec!["example.com".to_string(), "*.example.org".to_string()],
+        rate_limit: None,
+        max_redirects: 3,
+        ..fetch::Policy::default()
+    };
+    let registry = fetch_registry(fetch::PolicyTransport::new(policy, replay));
+    let run = |src: &str, max_fetches: usize| {
+        let limits = Limits {
+            max_fetches,
+            ..Limits::default()
+        };
+        let (value, usage) = eval::eval_limited(
+            &parse_script(src).unwrap().0,
+            &Env::new(),
+            &registry,
+            &limits,
+        );
+        value.map(|value| (value, usage.fetches))
+    };
+
+    // every hop is checked against the policy
+    assert_eq!(
+        run(r#"fetch("https://example.com/moved", {})"#, 20),
+        Err(EvalError::Fetch(fetch::FetchError::DeniedHost {
+            host: "10.0.0.1".to_string()
+        }))
+    );
+    // relative locations, other allowed hosts, and every hop is a fetch
+    assert_eq!(
+        run(r#"fetch("https://example.com/a", {}).body"#, 20),
+        Ok((Value::Str("https://api.example.org/c".to_string()), 3))
+    );
+    assert_eq!(
+        run(r#"fetch("https://example.com/a", {})"#, 2),
+        Err(EvalError::LimitExceeded {
+            kind: LimitKind::Fetches
+        })
+    );
+    assert_eq!(
+        run(r#"fetch("https://example.com/loop", {})"#, 20),
+        Err(EvalError::Fetch(fetch::FetchError::TooManyRedirects {
+            limit: 3
+        }))
+    );
+    // a 303 continues with a GET without the body
+    assert_eq!(
+        run(
+            r#"fetch("https://example.com/form", {method: "POST", body: "q=ada"}).body"#,
+            20
+        ),
+        Ok((Value::Str("https://example.com/done".to_string()), 2))
+    );
+}
+
+#[test]
+fn test_limits() {
+    use limits::{LimitKind, Limits};
+    let registry = fetch_registry(Echo);
+    let run = |src: &str, limits: &Limits| {
+        eval::eval_limited(
+            &parse_script(src).unwrap().0,
+            &Env::new(),
+            &registry,
+            limits,
+        )
+    };
+    let exceeded = |kind| Err(EvalError::LimitExceeded { kind });
+
+    let (result, usage) = run("1 + 2 * 3", &Limits::default());
+    assert_eq!(result, Ok(Value::Number(7)));
+    assert_eq!((usage.fuel, usage.depth), (5, 3));
+
+    let fold = r#"fold(split("a,b,c,d,e,f,g,h", ","), 0, |n, x| n + 1)"#;
+    assert_eq!(run(fold, &Limits::default()).0, Ok(Value::Number(8)));
+    let limits = Limits {
+        fuel: 20,
+        ..Limits::default()
+    };
+    let (result, usage) = run(fold, &limits);
+    assert_eq!(result, exceeded(LimitKind::Fuel));
+    assert_eq!(usage.fuel, 20);
+
+    let nested = format!("{}1{}", "(1 + ".repeat(30), ")".repeat(30));
+    assert_eq!(run(&nested, &Limits::default()).0, Ok(Value::Number(31)));
+    let limits = Limits {
+        max_depth: 20,
+        ..Limits::default()
+    };
+    let (result, usage) = run(&nested, &limits);
+    assert_eq!(result, exceeded(LimitKind::Depth));
+    assert_eq!(usage.depth, 20);
+
+    // doubling a string ten times
+    let doubling = format!(
+        "{} in s",
+        (0..10).fold("let s = \"ab\"".to_string(), |src, _| src
+            + " in let s = s + s")
+    );
+    let (result, usage) = run(&doubling, &Limits::default());
+    assert_eq!(result, Ok(Value::Str("ab".repeat(1024))));
+    assert_eq!(usage.alloc, (1..=10).map(|i| 2 << i).sum::<usize>());
+    let limits = Limits {
+        max_alloc: 1000,
+        ..Limits::default()
+    };
+    assert_eq!(run(&doubling, &limits).0, exceeded(LimitKind::Alloc));
+    let limits = Limits {
+        max_alloc: 5,
+        ..Limits::default()
+    };
+    assert_eq!(
+        run("map([1, 2, 3], |x| [x, x])", &limits).0,
+        exceeded(LimitKind::Alloc)
+    );
+
+    let fetches = r#"map(["a", "b", "c"], |p| fetch("https://example.com/" + p, {}).status)"#;
+    let (result, usage) = run(fetches, &Limits::default());
+    assert_eq!(result, Ok(Value::List(vec![Value::Number(200); 3])));
+    assert_eq!(usage.fetches, 3);
+    let limits = Limits {
+        max_fetches: 2,
+        ..Limits::default()
+    };
+    assert_eq!(run(fetches, &limits).0, exceeded(LimitKind::Fetches));
+
+    let limits = Limits {
+        timeout_ms: 0,
+        ..Limits::default()
+    };
+    assert_eq!(run(fold, &limits).0, exceeded(LimitKind::Time));
+
+    // trusted evaluation is not limited
+    assert_eq!(
+        eval::eval_with(&parse_script(&nested).unwrap().0, &Env::new(), &registry),
+        Ok(Value::Number(31))
+    );
+}
+
+#[test]
+fn test_limits_config() {
+    use limits::{Limits, LimitsConfig};
+    let config: LimitsConfig = toml::from_str(
+        r#"
+        [default]
+        fuel = 1000
+
+        [users.ada]
+        max_fetches = 100
+        "#,
+    )
+    .unwrap();
+    assert_eq!(
+        config.for_user("bob"),
+        &Limits {
+            fuel: 1000,
+            ..Limits::default()
+        }
+    );
+    assert_eq!(
+        config.for_user("ada"),
+        &Limits {
+            max_fetches: 100,
+            ..Limits::default()
+        }
+    );
+    assert_eq!(
+        toml::from_str::<LimitsConfig>("").unwrap(),
+        LimitsConfig::default()
+    );
+}
+
+#[test]
+fn test_print() {
+    let print = |src: &str| parse_script(src).unwrap().0.to_string();
+    assert_eq!(print("1+2*3"), "1 + 2 * 3");
+    assert_eq!(print("(1+2)*3"), "(1 + 2) * 3");
+    assert_eq!(print("1-(2-3)"), "1 - (2 - 3)");
+    assert_eq!(print("(1-2)-3"), "1 - 2 - 3");
+    assert_eq!(print("1 - -2"), "1 - -2");
+    assert_eq!(print("(a || b) && !(c == d)"), "(a || b) && !(c == d)");
+    assert_eq!(print("!(!a).b"), "!(!a).b");
+    assert_eq!(print("(xs[0]).name"), "xs[0].name");
+    assert_eq!(
+        print("({\"a b\": 1, if_: \"x\\\"y\\n\"})"),
+        r#"{"a b": 1, if_: "x\"y\n"}"#
+    );
+    assert_eq!(
+        print("fold(xs,0,|acc,x|acc+x)"),
+        "fold(xs, 0, |acc, x| acc + x)"
+    );
+    assert_eq!(print("(let a = 1 in a) + 1"), "(let a = 1 in\na) + 1");
+    assert_eq!(
+        print("let a = 1 in let b = if a > 0 then a else 0 in [a, b]"),
+        "let a = 1 in\nlet b = if a > 0 then a else 0 in\n[a, b]"
+    );
+    assert_eq!(
+        print("if a then let b = 1 in b else if c then 2 else if d then 3 else 4"),
+        "if a then\n  let b = 1 in\n  b\nelse if c then\n  2\nelse if d then\n  3\nelse\n  4"
+    );
+}
+
+#[test]
+fn test_format_script() {
+    let src = r#"import "github followers" as gh
+import "text"
+def badge(user) = let n = gh.count(fetch("https://api.github.com/users/" + user, {}).body) in if n > 1000 then text.shout(to_str(n)) else to_str(n)
+def links(profile)=filter(profile.links, |l| l != null)
+let p = {name: name, "bio": r"C:\path"} in
+[badge(p.name), len(links({links: ["a", null]}))]"#;
+    let formatted = format_script(src).unwrap();
+    assert_eq!(
+        formatted,
+        r#"import "github followers" as gh
+import "text"
+
+def badge(user) =
+  let n = gh.count(fetch("https://api.github.com/users/" + user, {}).body) in
+  if n > 1000 then text.shout(to_str(n)) else to_str(n)
+
+def links(profile) = filter(profile.links, |l| l != null)
+
+let p = {name: name, bio: "C:\\path"} in
+[badge(p.name), len(links({links: ["a", null]}))]
+"#
+    );
+    // formatting is stable and keeps the meaning
+    assert_eq!(format_script(&formatted).unwrap(), formatted);
+    let parsed = |src: &str| {
+        let module = module::parse_module(src).unwrap();
+        (
+            module.imports.len(),
+            module.defs.len(),
+            module.body.unwrap().0,
+        )
+    };
+    assert_eq!(parsed(&formatted), parsed(src));
+    assert!(format_script("def f(x) =").is_err());
+}
+
+fn identifier_strategy() -> impl Strategy<Value = String> {
+    "[a-z_][a-z0-9_]{0,6}".prop_filter("keyword", |s| !KEYWORDS.contains(&s.as_str()))
+}
+
+/// Expressions as the parser builds them, e.g. lists are never `Expr::Value`.
+fn expr_strategy() -> impl Strategy<Value = Expr> {
+    let leaf = prop_oneof![
+        any::<i64>().prop_map(|n| Expr::Value(Value::Number(n))),
+        any::<bool>().prop_map(|b| Expr::Value(Value::Bool(b))),
+        Just(Expr::Value(Value::Null)),
+        "(?s).{0,8}".prop_map(|s| Expr::Value(Value::Str(s))),
+        identifier_strategy().prop_map(Expr::Var),
+    ];
+    let ops = prop::sample::select(vec![
+        Oper::Add,
+        Oper::Sub,
+        Oper::Mul,
+        Oper::Div,
+        Oper::Eq,
+        Oper::Neq,
+        Oper::Lt,
+        Oper::Le,
+        Oper::Gt,
+        Oper::Ge,
+        Oper::And,
+        Oper::Or,
+    ]);
+    leaf.prop_recursive(6, 64, 4, move |inner| {
+        let boxed = inner.clone().prop_map(Box::new);
+        let lambda = (
+            prop::collection::vec(identifier_strategy(), 1..3),
+            boxed.clone(),
+        )
+            .prop_map(|(params, body)| Expr::Lambda(params, body));
+        prop_oneof![
+            (ops.clone(), boxed.clone(), boxed.clone())
+                .prop_map(|(op, e1, e2)| Expr::Binop(op, e1, e2)),
+            boxed.clone().prop_map(|e| Expr::Unop(Unop::Not, e)),
+            (identifier_strategy(), boxed.clone(), boxed.clone())
+                .prop_map(|(name, e1, e2)| Expr::Let(name, e1, e2)),
+            (boxed.clone(), boxed.clone(), boxed.clone())
+                .prop_map(|(c, e1, e2)| Expr::If(c, e1, e2)),
+            (
+                identifier_strategy(),
+                prop::collection::vec(prop_oneof![inner.clone(), lambda], 0..3)
+            )
+                .prop_map(|(name, args)| Expr::Call(name, args)),
+            prop::collection::vec(inner.clone(), 0..3).prop_map(Expr::List),
+            prop::collection::vec(("[ -~]{1,6}", inner.clone()), 0..3).prop_map(Expr::Record),
+            (boxed.clone(), boxed.clone()).prop_map(|(e, idx)| Expr::Index(e, idx)),
+            (boxed, identifier_strategy()).prop_map(|(e, name)| Expr::Field(e, name)),
+        ]
+    })
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(2000))]
+    #[test]
+    fn proptest_print_roundtrip(expr in expr_strategy()) {
+        let src = expr.to_string();
+        let (parsed, _) = parse_script(&src).map_err(|e| {
+            TestCaseError::fail(format!("{}\n{}", src, e.render(&src)))
+        })?;
+        prop_assert_eq!(&parsed, &expr, "{}", src);
+        prop_assert_eq!(parsed.to_string(), src);
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(2000))]
+    /// Every string survives being escaped and parsed back.
+    #[test]
+    fn proptest_escape_roundtrip(s in any::<String>()) {
+        let escaped = escape(&s);
+        prop_assert_eq!(parse_value(&escaped), Ok(("", Value::Str(s))));
+    }
+}
+
+#[test]
+fn test_repl() {
+    use limits::Limits;
+    use repl::{is_complete, Session};
+    let mut session = Session::new(builtins::Registry::standard().clone(), Limits::default());
+
+    assert_eq!(
+        session.run("1 + 2"),
+        Ok(
+            "ast:   Binop(Add, Value(Number(1)), Value(Number(2)))\ntype:  Number\nvalue: 3"
+                .to_string()
+        )
+    );
+    assert_eq!(
+        session.run(":let names = split(\"ada,grace\", \",\")"),
+        Ok(r#"names: [Str] = ["ada", "grace"]"#.to_string())
+    );
+    assert_eq!(
+        session.run(":let n = len(names[1])"),
+        Ok("n: Number = 5".to_string())
+    );
+    assert_eq!(
+        session.run(":type map(names, |x| len(x) + n)"),
+        Ok("[Number]".to_string())
+    );
+    assert_eq!(session.run(":ast n"), Ok("Var(\n    \"n\",\n)".to_string()));
+    assert!(session
+        .run(":type n + names")
+        .unwrap_err()
+        .starts_with("error: "));
+    assert!(session
+        .run("1 / 0")
+        .unwrap_err()
+        .contains("division by zero"));
+    assert!(session
+        .run("(1 +")
+        .unwrap_err()
+        .starts_with("error: expected expression"));
+    assert!(session.run(":let 1x = 2").is_err());
+    assert!(session.run(":frobnicate").is_err());
+
+    assert!(is_complete("1 + 2"));
+    assert!(is_complete(":help"));
+    assert!(is_complete(""));
+    assert!(!is_complete("let x = 1 in"));
+    assert!(!is_complete(":let xs = [1,\n2,"));
+    assert!(is_complete(":let xs = [1,\n2]"));
+    assert!(!is_complete("r#\"a \"[\"\n"));
+    assert!(is_complete("r#\"a \"[\"\n]\"#"));
+    // an error in the middle can not be fixed by more lines
+    assert!(is_complete("1 2"));
+
+    let path = std::env::temp_dir().join(format!("seekr-repl-{}.scrape", std::process::id()));
+    std::fs::write(
+        &path,
+        ":let a = 1\n:let b =\n  let c = a + 1 in\n  c * 2\n\na + b\n",
+    )
+    .unwrap();
+    let out = session.run(&format!(":load {}", path.display()));
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(
+        out.unwrap().lines().collect::<Vec<_>>(),
+        [
+            "a: Number = 1",
+            "b: Number = 4",
+            "ast:   Binop(Add, Var(\"a\"), Var(\"b\"))",
+            "type:  Number",
+            "value: 5"
+        ]
+    );
+    assert!(session.run(":load /nonexistent").is_err());
+}
+
+#[test]
+fn test_vm() {
+    use vm::{compile, Op};
+    let registry = builtins::Registry::standard();
+    let compiled = |src: &str| compile(&parse_script(src).unwrap().0, registry);
+
+    let program = compiled(r#"1 + 2 * 3 == 7 && !false"#);
+    assert_eq!(program.code(), [Op::Const(0)]);
+    assert_eq!(program.constants(), [Value::Bool(true)]);
+    let program = compiled(r#"if len("ab") > 1 then [1, 2][0] else {a: 3}.a"#);
+    assert_eq!(
+        program.code(),
+        [
+            Op::Const(0),
+            Op::Call {
+                function: 0,
+                args: 1
+            },
+            Op::Const(1),
+            Op::Binop(Oper::Gt),
+            Op::JumpUnless(7),
+            Op::Const(2),
+            Op::Jump(8),
+            Op::Const(3),
+        ]
+    );
+    assert_eq!(
+        program.constants()[2..],
+        [Value::Number(1), Value::Number(3)]
+    );
+    let program = compiled("if 1 < 2 then foo else bar");
+    assert_eq!(program.code(), [Op::Global(0)]);
+    // errors are left for runtime
+    let program = compiled("1 / 0");
+    assert_eq!(program.code().len(), 3);
+    assert_eq!(program.run(&Env::new()), Err(EvalError::DivisionByZero));
+
+    let numbers = |xs: &[i64]| Value::List(xs.iter().copied().map(Value::Number).collect());
+    let env = Env::from([("xs".to_string(), numbers(&[1, 2, 3]))]);
+    let cases = [
+        ("map(xs, |x| x * x)", Ok(numbers(&[1, 4, 9]))),
+        ("filter(xs, |x| x != 2)", Ok(numbers(&[1, 3]))),
+        ("fold(xs, 0, |acc, x| acc + x)", Ok(Value::Number(6))),
+        (
+            "map(xs, |x| fold(map(xs, |y| x * y), 0, |a, b| a + b))",
+            Ok(numbers(&[6, 12, 18])),
+        ),
+        (
+            "let x = 1 in let y = (let x = 2 in x) in x + y",
+            Ok(Value::Number(3)),
+        ),
+        ("fold(xs, 0, |x, x| x)", Ok(Value::Number(3))),
+        (
+            "filter(xs, |x| x)",
+            Err(EvalError::NonBoolCondition(Value::Number(1))),
+        ),
+        (
+            "map(1, |x| x)",
+            Err(EvalError::InvalidArguments {
+                name: "map".to_string(),
+                args: vec![Value::Number(1)],
+            }),
+        ),
+        (
+            "map(xs, 1)",
+            Err(EvalError::ExpectedLambda {
+                name: "map".to_string(),
+                params: 1,
+            }),
+        ),
+        (
+            "nope(1 / 0)",
+            Err(EvalError::UnknownFunction("nope".to_string())),
+        ),
+        ("ys", Err(EvalError::UnboundVariable("ys".to_string()))),
+    ];
+    for (src, expected) in cases {
+        let expr = parse_script(src).unwrap().0;
+        assert_eq!(compile(&expr, registry).run(&env), expected, "{}", src);
+        assert_eq!(eval(&expr, &env), expected, "{}", src);
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(2000))]
+    /// The bytecode VM and the tree walker agree on values and errors.
+    #[test]
+    fn proptest_vm_differential(source in source_strategy()) {
+        let (_, expr) = parse_expr(&source).unwrap();
+        let env = Env::from([("baz_1".to_string(), Value::Number(3))]);
+        let registry = builtins::Registry::standard();
+        prop_assert_eq!(
+            vm::compile(&expr, registry).run(&env),
+            eval::eval_with(&expr, &env, registry)
+        );
+    }
+
+    #[test]
+    fn proptest_vm_differential_ast(expr in expr_strategy()) {
+        let registry = builtins::Registry::standard();
+        prop_assert_eq!(
+            vm::compile(&expr, registry).run(&Env::new()),
+            eval::eval_with(&expr, &Env::new(), registry)
+        );
+    }
+}
+
+#[test]
+fn test_modules() {
+    use module::{Linked, ModuleError};
+    use std::collections::HashMap;
+
+    let sources = HashMap::from([
+        (
+            "text".to_string(),
+            "def shout(s) = upper(s) + \"!\"\ndef greet(name) = \"hi \" + shout(name)".to_string(),
+        ),
+        (
+            "github followers".to_string(),
+            "import \"text\"\ndef badge(n) = text.shout(to_str(n))".to_string(),
+        ),
+        ("cycle a".to_string(), "import \"cycle b\" as b".to_string()),
+        ("cycle b".to_string(), "import \"cycle a\" as a".to_string()),
+        ("broken".to_string(), "def bad(x) = x + true".to_string()),
+    ]);
+    let registry = builtins::Registry::standard();
+    let run = |src: &str| {
+        let (expr, _) = Linked::load("main", src, &sources)?.expand()?;
+        Ok::<_, ModuleError>(eval(
+            &expr,
+            &Env::from([("x".to_string(), Value::Number(1))]),
+        ))
+    };
+    let check = |src: &str| Linked::load("main", src, &sources)?.check(&TypeEnv::new(), registry);
+    let s = |s: &str| Value::Str(s.to_string());
+
+    // local and qualified calls, modules imported twice are loaded once
+    assert_eq!(
+        run("import \"text\"\nimport \"github followers\" as gh\ntext.greet(\"greg\") + gh.badge(3)"),
+        Ok(Ok(s("hi GREG!3!")))
+    );
+    assert_eq!(
+        run("def f() = 2\ndef g(y) = f() * y\ng(21)"),
+        Ok(Ok(Value::Number(42)))
+    );
+    // a module without a body evaluates to null
+    assert_eq!(run("def f(x) = x"), Ok(Ok(Value::Null)));
+    // local definitions shadow builtins
+    assert_eq!(
+        run("def len(s) = 0\nlen(\"abc\")"),
+        Ok(Ok(Value::Number(0)))
+    );
+
+    // bodies of definitions can not capture the variables of their caller
+    assert_eq!(
+        run("def add(x, y) = x + y\nlet x = 10 in add(x, add(x, 1))"),
+        Ok(Ok(Value::Number(21)))
+    );
+    assert!(matches!(
+        check("def leak(y) = x + y\nleak(1)"),
+        Err(ModuleError::Type {
+            error: TypeError::Unbound { .. },
+            ..
+        })
+    ));
+    assert_eq!(
+        run("def pairs(xs) = map(xs, |x| [x, x])\nlet x = 5 in pairs([x])"),
+        Ok(Ok(Value::List(vec![Value::List(vec![
+            Value::Number(5),
+            Value::Number(5)
+        ])])))
+    );
+
+    // types are checked across modules
+    assert_eq!(
+        check("import \"text\"\nlen(text.greet(\"greg\"))"),
+        Ok(TypeRepr::Number)
+    );
+    let src = "import \"text\"\n\ntext.shout(1)";
+    match check(src) {
+        Err(ModuleError::Type {
+            module,
+            error: TypeError::Mismatch {
+                span: Some(span), ..
+            },
+        }) => {
+            assert_eq!(module, "main");
+            // the argument does not fit the definition, reported at the call
+            assert_eq!(span.start, Position { line: 3, column: 1 });
+        }
+        other => panic!("{:?}", other),
+    }
+    // errors within a definition are reported in its module
+    match check("import \"broken\"\nbroken.bad(1)") {
+        Err(ModuleError::Type {
+            module,
+            error: TypeError::Mismatch {
+                span: Some(span), ..
+            },
+        }) => {
+            assert_eq!(module, "broken");
+            assert_eq!(
+                span.start,
+                Position {
+                    line: 1,
+                    column: 18
+                }
+            );
+        }
+        other => panic!("{:?}", other),
+    }
+    assert!(matches!(
+        check("import \"text\"\ntext.missing(1)"),
+        Err(ModuleError::Type {
+            error: TypeError::UnknownFunction { .. },
+            ..
+        })
+    ));
+    assert!(matches!(
+        check("def f(a) = a\nf(1, 2)"),
+        Err(ModuleError::Type {
+            error: TypeError::Arity {
+                expected: 1,
+                found: 2,
+                ..
+            },
+            ..
+        })
+    ));
+
+    // cycles, missing modules and recursion
+    assert_eq!(
+        run("import \"cycle a\" as a\n1"),
+        Err(ModuleError::Cycle(
+            ["cycle a", "cycle b", "cycle a"].map(String::from).to_vec()
+        ))
+    );
+    assert!(matches!(
+        run("import \"nope\"\n1"),
+        Err(ModuleError::NotFound { name, .. }) if name == "nope"
+    ));
+    assert_eq!(
+        run("def f(x) = g(x)\ndef g(x) = f(x)\nf(1)"),
+        Err(ModuleError::Recursive(
+            ["main.f", "main.g", "main.f"].map(String::from).to_vec()
+        ))
+    );
+    assert!(matches!(
+        check("def f(x) = f(x)"),
+        Err(ModuleError::Recursive(_))
+    ));
+    let mut deep = "def f0(x) = x".to_string();
+    for n in 1..20 {
+        deep += &format!("\ndef f{n}(x) = f{m}(x) + f{m}(x)", m = n - 1);
+    }
+    assert_eq!(run(&(deep + "\nf19(1)")), Err(ModuleError::TooLarge));
+
+    // names
+    assert!(matches!(
+        run("import \"github followers\"\n1"),
+        Err(ModuleError::Alias { .. })
+    ));
+    assert!(matches!(
+        run("def f(x) = x\ndef f(y) = y\n1"),
+        Err(ModuleError::Duplicate { name, .. }) if name == "f"
+    ));
+    for name in builtins::HIGHER_ORDER {
+        assert!(matches!(
+            run(&format!("def {name}(f, xs) = xs\n{name}(1, [])")),
+            Err(ModuleError::Reserved { name: n, .. }) if n == name
+        ));
+    }
+    // other builtins can still be shadowed
+    assert_eq!(
+        run("def len(x) = 7\nlen(\"abc\")"),
+        Ok(Ok(Value::Number(7)))
+    );
+    match run("def f(x) =") {
+        Err(ModuleError::Parse { diagnostic, .. }) => assert_eq!(diagnostic.context, ["def"]),
+        other => panic!("{:?}", other),
+    }
+
+    // the VM runs linked scripts as well
+    let (expr, _) = Linked::load("main", "import \"text\"\ntext.greet(\"vm\")", &sources)
+        .unwrap()
+        .expand()
+        .unwrap();
+    assert_eq!(
+        vm::compile(&expr, registry).run(&Env::new()),
+        Ok(s("hi VM!"))
+    );
+}
+
+#[test]
+fn test_macros() {
+    assert_eq!(*value!("greg"), Expr::Value(Value::Str("greg".to_string())));
+    assert_eq!(
+        expr!(1 + 2 * -3),
+        Box::new(Expr::Binop(
+            Oper::Add,
+            value!(1),
+            Box::new(Expr::Binop(Oper::Mul, value!(2), value!(-3)))
+        ))
+    );
+    assert_eq!(
+        expr!({
+            let x = 2;
+            let y = "a";
+            if x > 1 && !false {
+                len(y)
+            } else if x == 0 {
+                null
+            } else {
+                value!(7)
+            }
+        }),
+        Box::new(Expr::Let(
+            "x".to_string(),
+            value!(2),
+            Box::new(Expr::Let(
+                "y".to_string(),
+                value!("a"),
+                Box::new(Expr::If(
+                    Box::new(Expr::Binop(
+                        Oper::And,
+                        Box::new(Expr::Binop(Oper::Gt, var("x"), value!(1))),
+                        Box::new(Expr::Unop(Unop::Not, value!(false)))
+                    )),
+                    Box::new(Expr::Call("len".to_string(), vec![*var("y")])),
+                    Box::new(Expr::If(
+                        Box::new(Expr::Binop(Oper::Eq, var("x"), value!(0))),
+                        Box::new(Expr::Value(Value::Null)),
+                        value!(7)
+                    ))
+                ))
+            ))
+        ))
+    );
+    assert_eq!(
+        expr!([profile.links, (xs)[0]]),
+        Box::new(Expr::List(vec![
+            Expr::Field(var("profile"), "links".to_string()),
+            Expr::Index(var("xs"), value!(0)),
+        ]))
+    );
+
+    // the parser and the macros agree
+    for (parsed, built) in [
+        (
+            scrape!(r#"let x = "a\"b" in if x == "" then -1 else len(x) * 2"#),
+            *expr!({
+                let x = "a\"b";
+                if x == "" {
+                    -1
+                } else {
+                    len(x) * 2
+                }
+            }),
+        ),
+        (scrape!("!(a || b)"), *expr!(!(a || b))),
+        (scrape!("f(1, \"s\", null)"), *expr!(f(1, "s", null))),
+    ] {
+        assert_eq!(parsed, built);
+    }
 }
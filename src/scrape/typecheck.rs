@@ -0,0 +1,363 @@
+use std::{collections::HashMap, fmt};
+use thiserror::Error;
+use tracing::instrument;
+
+use super::{
+    builtins::{ParamType, Registry, HIGHER_ORDER},
+    Expr, Oper, Span, Spans, TypeCheck, TypeRepr, Typed, Unop, Value,
+};
+
+/// Types of the variables visible to an expression, the static counterpart of [`super::Env`].
+pub type TypeEnv = HashMap<String, TypeRepr>;
+
+/// `span` is the position of the offending expression, if the parser recorded one.
+#[derive(Debug, PartialEq, Clone, Error)]
+pub enum TypeError {
+    #[error("type error: expected {expected}, found {found}")]
+    Mismatch {
+        expected: TypeRepr,
+        found: TypeRepr,
+        span: Option<Span>,
+    },
+
+    #[error("unbound variable: {name}")]
+    Unbound { name: String, span: Option<Span> },
+
+    #[error("unknown function: {name}")]
+    UnknownFunction { name: String, span: Option<Span> },
+
+    #[error("{name} takes {expected} arguments, found {found}")]
+    Arity {
+        name: String,
+        expected: usize,
+        found: usize,
+        span: Option<Span>,
+    },
+
+    #[error("no field {name} in {found}")]
+    NoField {
+        name: String,
+        found: TypeRepr,
+        span: Option<Span>,
+    },
+
+    #[error("{name} expects a lambda with {params} parameters")]
+    ExpectedLambda {
+        name: String,
+        params: usize,
+        span: Option<Span>,
+    },
+
+    #[error("a lambda can only be passed to map, filter or fold")]
+    UnexpectedLambda { span: Option<Span> },
+}
+
+impl TypeError {
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Self::Mismatch { span, .. }
+            | Self::Unbound { span, .. }
+            | Self::UnknownFunction { span, .. }
+            | Self::Arity { span, .. }
+            | Self::NoField { span, .. }
+            | Self::ExpectedLambda { span, .. }
+            | Self::UnexpectedLambda { span } => *span,
+        }
+    }
+}
+
+impl TypeRepr {
+    /// The most precise type compatible with both `self` and `other`, `Any` is compatible with
+    /// every type. `None` if the types conflict.
+    ///
+    /// ```rust
+    /// use seekr::scrape::TypeRepr;
+    /// let list = |t| TypeRepr::List(Box::new(t));
+    /// assert_eq!(list(TypeRepr::Any).unify(&list(TypeRepr::Str)), Some(list(TypeRepr::Str)));
+    /// assert_eq!(TypeRepr::Str.unify(&TypeRepr::Number), None);
+    /// ```
+    pub fn unify(&self, other: &TypeRepr) -> Option<TypeRepr> {
+        match (self, other) {
+            (TypeRepr::Any, t) | (t, TypeRepr::Any) => Some(t.clone()),
+            (TypeRepr::List(a), TypeRepr::List(b)) => Some(TypeRepr::List(Box::new(a.unify(b)?))),
+            (TypeRepr::Record(a), TypeRepr::Record(b)) if a.len() == b.len() => a
+                .iter()
+                .map(|(k, t)| Some((k.clone(), t.unify(b.get(k)?)?)))
+                .collect::<Option<_>>()
+                .map(|fields| TypeRepr::Record(Box::new(fields))),
+            (a, b) if a == b => Some(a.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for TypeRepr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeRepr::List(t) => write!(f, "[{}]", t),
+            TypeRepr::Record(fields) => {
+                write!(f, "{{")?;
+                for (n, (k, t)) in fields.iter().enumerate() {
+                    let sep = if n == 0 { "" } else { ", " };
+                    write!(f, "{}{}: {}", sep, k, t)?;
+                }
+                write!(f, "}}")
+            }
+            t => write!(f, "{:?}", t),
+        }
+    }
+}
+
+/// Fail unless `found` is compatible with `expected`, blaming the expression at `span`.
+fn expect(expected: &TypeRepr, found: TypeRepr, span: Option<Span>) -> Result<TypeRepr, TypeError> {
+    expected.unify(&found).ok_or_else(|| TypeError::Mismatch {
+        expected: expected.clone(),
+        found,
+        span,
+    })
+}
+
+/// Element type of a list, `Any` if the list is statically unknown.
+fn element(found: TypeRepr, span: Option<Span>) -> Result<TypeRepr, TypeError> {
+    match expect(&TypeRepr::List(Box::new(TypeRepr::Any)), found, span)? {
+        TypeRepr::List(t) => Ok(*t),
+        _ => Ok(TypeRepr::Any),
+    }
+}
+
+impl Expr {
+    /// Infer the type of an expression bottom up. Every subexpression is checked, the types of
+    /// `Let` bindings are carried into the body through `env`. Free variables, e.g. inputs of a
+    /// script, must be declared in `env`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use seekr::scrape::{parse_expr, TypeEnv, TypeRepr};
+    /// let (_, expr) = parse_expr("let foo=2 in if true then foo else 3").unwrap();
+    /// assert_eq!(expr.infer(&TypeEnv::new()), Ok(TypeRepr::Number));
+    ///
+    /// let (_, expr) = parse_expr("username + \"@example.com\"").unwrap();
+    /// let env = TypeEnv::from([("username".to_string(), TypeRepr::Str)]);
+    /// assert_eq!(expr.infer(&env), Ok(TypeRepr::Str));
+    /// ```
+    pub fn infer(&self, env: &TypeEnv) -> Result<TypeRepr, TypeError> {
+        self.infer_with(None, env, Registry::standard())
+    }
+
+    /// Like [`Expr::infer`], errors carry the span of the offending subexpression.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use seekr::scrape::{parse_script, Position, TypeEnv, TypeError};
+    /// let (expr, spans) = parse_script("1 + (true || false)").unwrap();
+    /// let Err(TypeError::Mismatch { span: Some(span), .. }) = expr.infer_spanned(&spans, &TypeEnv::new()) else {
+    ///     panic!()
+    /// };
+    /// assert_eq!(span.start, Position { line: 1, column: 6 });
+    /// ```
+    pub fn infer_spanned(&self, spans: &Spans, env: &TypeEnv) -> Result<TypeRepr, TypeError> {
+        self.infer_with(Some(spans), env, Registry::standard())
+    }
+
+    /// Like [`Expr::infer_spanned`], calls are checked against the signatures in `registry`.
+    #[instrument(skip(registry))]
+    pub fn infer_with(
+        &self,
+        spans: Option<&Spans>,
+        env: &TypeEnv,
+        registry: &Registry,
+    ) -> Result<TypeRepr, TypeError> {
+        let infer_at =
+            |e: &Expr, spans: Option<&Spans>, env: &TypeEnv| e.infer_with(spans, env, registry);
+        let child = |n: usize| spans.and_then(|s| s.children.get(n));
+        let span_of = |n: usize| child(n).map(|s| s.span);
+        match self {
+            Self::Value(v) => Ok(Value::get_type(v.clone()).unwrap_or_else(|e| match e {})),
+            Self::Binop(op, e1, e2) => {
+                let lhs = infer_at(e1, child(0), env)?;
+                let rhs = infer_at(e2, child(1), env)?;
+                match op {
+                    // + is overloaded for string concatenation, the left operand decides
+                    Oper::Add if lhs == TypeRepr::Str => expect(&TypeRepr::Str, rhs, span_of(1)),
+                    Oper::Add
+                        if lhs == TypeRepr::Any && matches!(rhs, TypeRepr::Str | TypeRepr::Any) =>
+                    {
+                        Ok(rhs)
+                    }
+                    Oper::Add | Oper::Sub | Oper::Mul | Oper::Div => {
+                        expect(&TypeRepr::Number, lhs, span_of(0))?;
+                        expect(&TypeRepr::Number, rhs, span_of(1))
+                    }
+                    // everything can be compared to null
+                    Oper::Eq | Oper::Neq if lhs == TypeRepr::Null || rhs == TypeRepr::Null => {
+                        Ok(TypeRepr::Bool)
+                    }
+                    Oper::Eq | Oper::Neq => {
+                        expect(&lhs, rhs, span_of(1))?;
+                        Ok(TypeRepr::Bool)
+                    }
+                    // numbers compare numerically, strings lexicographically
+                    Oper::Lt | Oper::Le | Oper::Gt | Oper::Ge => {
+                        if !matches!(lhs, TypeRepr::Str | TypeRepr::Any) {
+                            expect(&TypeRepr::Number, lhs.clone(), span_of(0))?;
+                        }
+                        // with `Any` on the left the right operand decides, and must be ordered
+                        let t = expect(&lhs, rhs, span_of(1))?;
+                        if !matches!(t, TypeRepr::Str | TypeRepr::Any) {
+                            expect(&TypeRepr::Number, t, span_of(1))?;
+                        }
+                        Ok(TypeRepr::Bool)
+                    }
+                    Oper::And | Oper::Or => {
+                        expect(&TypeRepr::Bool, lhs, span_of(0))?;
+                        expect(&TypeRepr::Bool, rhs, span_of(1))
+                    }
+                }
+            }
+            Self::Unop(Unop::Not, e) => {
+                expect(&TypeRepr::Bool, infer_at(e, child(0), env)?, span_of(0))
+            }
+            Self::Var(name) => env.get(name).cloned().ok_or_else(|| TypeError::Unbound {
+                name: name.to_string(),
+                span: spans.map(|s| s.span),
+            }),
+            Self::Let(name, e1, e2) => {
+                let mut inner = env.clone();
+                inner.insert(name.to_string(), infer_at(e1, child(0), env)?);
+                infer_at(e2, child(1), &inner)
+            }
+            Self::If(condition, e1, e2) => {
+                expect(
+                    &TypeRepr::Bool,
+                    infer_at(condition, child(0), env)?,
+                    span_of(0),
+                )?;
+                let then_type = infer_at(e1, child(1), env)?;
+                expect(&then_type, infer_at(e2, child(2), env)?, span_of(2))
+            }
+            Self::Call(name, args) if HIGHER_ORDER.contains(&name.as_str()) => {
+                let params = match name.as_str() {
+                    "fold" => 3,
+                    _ => 2,
+                };
+                if args.len() != params {
+                    return Err(TypeError::Arity {
+                        name: name.to_string(),
+                        expected: params,
+                        found: args.len(),
+                        span: spans.map(|s| s.span),
+                    });
+                }
+                let elem = element(infer_at(&args[0], child(0), env)?, span_of(0))?;
+                // the lambda is always the last argument, its parameters are bound to the
+                // accumulator, if any, and the element
+                let lambda = params - 1;
+                let body = |bindings: &[TypeRepr]| match &args[lambda] {
+                    Expr::Lambda(names, body) if names.len() == bindings.len() => {
+                        let mut inner = env.clone();
+                        inner.extend(names.iter().cloned().zip(bindings.iter().cloned()));
+                        infer_at(body, child(lambda).and_then(|s| s.children.first()), &inner)
+                    }
+                    _ => Err(TypeError::ExpectedLambda {
+                        name: name.to_string(),
+                        params: bindings.len(),
+                        span: span_of(lambda),
+                    }),
+                };
+                let body_span = child(lambda)
+                    .and_then(|s| s.children.first())
+                    .map(|s| s.span);
+                match name.as_str() {
+                    "map" => Ok(TypeRepr::List(Box::new(body(&[elem])?))),
+                    "filter" => {
+                        expect(
+                            &TypeRepr::Bool,
+                            body(std::slice::from_ref(&elem))?,
+                            body_span,
+                        )?;
+                        Ok(TypeRepr::List(Box::new(elem)))
+                    }
+                    _ => {
+                        let init = infer_at(&args[1], child(1), env)?;
+                        let acc = body(&[init.clone(), elem])?;
+                        expect(&init, acc, body_span)
+                    }
+                }
+            }
+            Self::Call(name, args) => {
+                let signature = &registry
+                    .get(name)
+                    .ok_or_else(|| TypeError::UnknownFunction {
+                        name: name.to_string(),
+                        span: spans.map(|s| s.span),
+                    })?
+                    .signature;
+                if signature.params.len() != args.len() {
+                    return Err(TypeError::Arity {
+                        name: name.to_string(),
+                        expected: signature.params.len(),
+                        found: args.len(),
+                        span: spans.map(|s| s.span),
+                    });
+                }
+                for (n, (param, arg)) in signature.params.iter().zip(args).enumerate() {
+                    let found = infer_at(arg, child(n), env)?;
+                    if let ParamType::Is(expected) = param {
+                        expect(expected, found, span_of(n))?;
+                    }
+                }
+                Ok(signature.ret.clone())
+            }
+            Self::List(elems) => {
+                let mut t = TypeRepr::Any;
+                for (n, e) in elems.iter().enumerate() {
+                    t = expect(&t, infer_at(e, child(n), env)?, span_of(n))?;
+                }
+                Ok(TypeRepr::List(Box::new(t)))
+            }
+            Self::Record(fields) => fields
+                .iter()
+                .enumerate()
+                .map(|(n, (k, e))| Ok((k.clone(), infer_at(e, child(n), env)?)))
+                .collect::<Result<_, _>>()
+                .map(|fields| TypeRepr::Record(Box::new(fields))),
+            Self::Index(e, idx) => {
+                let found = infer_at(e, child(0), env)?;
+                let idx_type = infer_at(idx, child(1), env)?;
+                match found {
+                    TypeRepr::Record(_) => {
+                        expect(&TypeRepr::Str, idx_type, span_of(1))?;
+                        Ok(TypeRepr::Any)
+                    }
+                    TypeRepr::Any if idx_type == TypeRepr::Str => Ok(TypeRepr::Any),
+                    found => {
+                        expect(&TypeRepr::Number, idx_type, span_of(1))?;
+                        element(found, span_of(0))
+                    }
+                }
+            }
+            Self::Field(e, name) => match infer_at(e, child(0), env)? {
+                TypeRepr::Record(fields) if fields.contains_key(name) => Ok(fields[name].clone()),
+                TypeRepr::Any => Ok(TypeRepr::Any),
+                found => Err(TypeError::NoField {
+                    name: name.to_string(),
+                    found,
+                    span: spans.map(|s| s.span),
+                }),
+            },
+            Self::Lambda(..) => Err(TypeError::UnexpectedLambda {
+                span: spans.map(|s| s.span),
+            }),
+        }
+    }
+}
+
+impl TypeCheck for Expr {
+    type R = Result<(), TypeError>;
+
+    fn typecheck(&self) -> Self::R {
+        self.infer(&TypeEnv::new()).map(|_| ())
+    }
+}
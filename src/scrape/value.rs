@@ -0,0 +1,71 @@
+use indexmap::IndexMap;
+use std::fmt;
+use thiserror::Error;
+
+use super::Value;
+
+#[derive(Debug, PartialEq, Error)]
+pub enum FromJsonError {
+    #[error("number can not be represented as an integer: {0}")]
+    NotAnInteger(serde_json::Number),
+}
+
+impl From<Value> for serde_json::Value {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Number(n) => n.into(),
+            Value::Bool(b) => b.into(),
+            Value::Str(s) => s.into(),
+            Value::List(xs) => xs.into_iter().map(Self::from).collect(),
+            Value::Record(fields) => (*fields)
+                .into_iter()
+                .map(|(k, v)| (k, Self::from(v)))
+                .collect(),
+            Value::Null => Self::Null,
+        }
+    }
+}
+
+/// Scrape values only have integers, fractional JSON numbers are rejected instead of silently
+/// rounded.
+impl TryFrom<serde_json::Value> for Value {
+    type Error = FromJsonError;
+
+    fn try_from(value: serde_json::Value) -> Result<Self, Self::Error> {
+        Ok(match value {
+            serde_json::Value::Null => Value::Null,
+            serde_json::Value::Bool(b) => Value::Bool(b),
+            serde_json::Value::Number(n) => {
+                Value::Number(n.as_i64().ok_or(FromJsonError::NotAnInteger(n))?)
+            }
+            serde_json::Value::String(s) => Value::Str(s),
+            serde_json::Value::Array(xs) => Value::List(
+                xs.into_iter()
+                    .map(Value::try_from)
+                    .collect::<Result<_, _>>()?,
+            ),
+            serde_json::Value::Object(fields) => Value::Record(Box::new(
+                fields
+                    .into_iter()
+                    .map(|(k, v)| Ok((k, Value::try_from(v)?)))
+                    .collect::<Result<IndexMap<_, _>, _>>()?,
+            )),
+        })
+    }
+}
+
+/// Render a value the way `to_str` shows it. Strings are shown as is, lists and records as
+/// JSON.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Number(n) => write!(f, "{}", n),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Str(s) => write!(f, "{}", s),
+            Value::Null => write!(f, "null"),
+            Value::List(_) | Value::Record(_) => {
+                write!(f, "{}", serde_json::Value::from(self.clone()))
+            }
+        }
+    }
+}
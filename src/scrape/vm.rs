@@ -0,0 +1,542 @@
+//! Compiles expressions to bytecode for a stack machine, for running the same script against
+//! many inputs.
+//!
+//! Compared to [`eval_with`](super::eval::eval_with) variables live in numbered slots instead of
+//! a cloned [`Env`] per `let`, functions are looked up once when compiling and subexpressions
+//! without variables are folded to constants. Results and errors are the same as the tree
+//! walker's, a differential test checks that on generated expressions.
+//!
+//! Programs run without [`Limits`](super::limits::Limits): steps, time and fetches are not
+//! counted, see [`Program::run`]. Stored scripts of users run through
+//! [`eval_limited`](super::eval::eval_limited), not through this module.
+
+use std::mem;
+
+use super::{
+    builtins::{Builtin, Registry, HIGHER_ORDER},
+    eval::{check_args, eval_binop, field, higher_order, index, HigherOrder},
+    Env, EvalError, Expr, Oper, Unop, Value,
+};
+
+/// Which higher order function a loop implements.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Iteration {
+    Map,
+    Filter,
+    Fold,
+}
+
+/// An instruction. Operands are indices into the tables of the [`Program`], jump targets are
+/// indices into its code.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Op {
+    /// Push a constant.
+    Const(usize),
+    /// Push the value of a local variable.
+    Load(usize),
+    /// Push a variable of the [`Env`] the program runs with.
+    Global(usize),
+    /// Pop into a local variable.
+    Store(usize),
+    Binop(Oper),
+    Not,
+    /// `&&` and `||`: jump if the left operand on the stack decides the result, keeping it.
+    ShortCircuit(Oper, usize),
+    /// Pop a condition and jump if it is false.
+    JumpUnless(usize),
+    Jump(usize),
+    /// Pop the arguments and call a function.
+    Call {
+        function: usize,
+        args: usize,
+    },
+    /// Pop elements into a list.
+    List(usize),
+    /// Pop the values of the fields named by a key table into a record.
+    Record(usize),
+    /// Pop an index and the value to index.
+    Index,
+    Field(usize),
+    /// Pop a list and start a loop over its elements, the operand names the function for errors.
+    Iterate(Iteration, usize),
+    /// Pop the initial accumulator of a `fold`.
+    Seed,
+    /// Push the next element, preceded by the accumulator in a `fold`. Once all elements are
+    /// done the result of the loop is pushed and execution continues at the target.
+    Next(usize),
+    /// Pop the result of the lambda and continue the loop at the target.
+    Collect(usize),
+    /// Fail with an error found while compiling, e.g. an unknown function.
+    Fail(usize),
+}
+
+/// A compiled expression, see [`compile`].
+#[derive(Debug, Clone)]
+pub struct Program {
+    code: Vec<Op>,
+    constants: Vec<Value>,
+    globals: Vec<String>,
+    /// Field names and the names of higher order functions.
+    names: Vec<String>,
+    keys: Vec<Vec<String>>,
+    functions: Vec<(String, Builtin)>,
+    errors: Vec<EvalError>,
+    /// Number of local variable slots.
+    slots: usize,
+}
+
+/// Compile an expression against the functions of `registry`.
+///
+/// # Example
+///
+/// ```rust
+/// use seekr::scrape::{builtins::Registry, parse_expr, vm::compile, Env, Value};
+/// let (_, expr) = parse_expr("len(name) * (2 + 3)").unwrap();
+/// let program = compile(&expr, Registry::standard());
+/// for (name, len) in [("ada", 15), ("grace", 25)] {
+///     let env = Env::from([("name".to_string(), Value::Str(name.to_string()))]);
+///     assert_eq!(program.run(&env), Ok(Value::Number(len)));
+/// }
+/// ```
+pub fn compile(expr: &Expr, registry: &Registry) -> Program {
+    let mut compiler = Compiler {
+        registry,
+        scope: vec![],
+        program: Program {
+            code: vec![],
+            constants: vec![],
+            globals: vec![],
+            names: vec![],
+            keys: vec![],
+            functions: vec![],
+            errors: vec![],
+            slots: 0,
+        },
+    };
+    compiler.expr(&fold_constants(expr));
+    compiler.program
+}
+
+/// Replace subexpressions that evaluate without variables and without errors by their value.
+/// Calls are kept, functions like `fetch` have effects. Whatever fails stays, so it fails at
+/// the same point at runtime.
+pub fn fold_constants(expr: &Expr) -> Expr {
+    let boxed = |e: &Expr| Box::new(fold_constants(e));
+    match expr {
+        Expr::Value(_) | Expr::Var(_) => expr.clone(),
+        Expr::Binop(op, e1, e2) => match (op, boxed(e1), boxed(e2)) {
+            (Oper::And, e1, _) if *e1 == Expr::Value(Value::Bool(false)) => *e1,
+            (Oper::Or, e1, _) if *e1 == Expr::Value(Value::Bool(true)) => *e1,
+            (op, e1, e2) => match (*e1, *e2) {
+                (Expr::Value(a), Expr::Value(b)) => eval_binop(op, a.clone(), b.clone())
+                    .map(Expr::Value)
+                    .unwrap_or_else(|_| {
+                        Expr::Binop(
+                            op.clone(),
+                            Box::new(Expr::Value(a)),
+                            Box::new(Expr::Value(b)),
+                        )
+                    }),
+                (e1, e2) => Expr::Binop(op.clone(), Box::new(e1), Box::new(e2)),
+            },
+        },
+        Expr::Unop(Unop::Not, e) => match *boxed(e) {
+            Expr::Value(Value::Bool(b)) => Expr::Value(Value::Bool(!b)),
+            e => Expr::Unop(Unop::Not, Box::new(e)),
+        },
+        Expr::Let(name, e1, e2) => Expr::Let(name.clone(), boxed(e1), boxed(e2)),
+        Expr::If(c, e1, e2) => match *boxed(c) {
+            Expr::Value(Value::Bool(true)) => fold_constants(e1),
+            Expr::Value(Value::Bool(false)) => fold_constants(e2),
+            c => Expr::If(Box::new(c), boxed(e1), boxed(e2)),
+        },
+        Expr::Call(name, args) => {
+            Expr::Call(name.clone(), args.iter().map(fold_constants).collect())
+        }
+        Expr::List(elems) => {
+            let elems: Vec<Expr> = elems.iter().map(fold_constants).collect();
+            match elems.iter().map(constant).collect::<Option<Vec<_>>>() {
+                Some(values) => Expr::Value(Value::List(values)),
+                None => Expr::List(elems),
+            }
+        }
+        Expr::Record(fields) => {
+            let fields: Vec<(String, Expr)> = fields
+                .iter()
+                .map(|(k, e)| (k.clone(), fold_constants(e)))
+                .collect();
+            match fields
+                .iter()
+                .map(|(k, e)| Some((k.clone(), constant(e)?)))
+                .collect::<Option<_>>()
+            {
+                Some(values) => Expr::Value(Value::Record(Box::new(values))),
+                None => Expr::Record(fields),
+            }
+        }
+        Expr::Index(e, idx) => match (*boxed(e), *boxed(idx)) {
+            (Expr::Value(v), Expr::Value(i)) => index(v.clone(), i.clone())
+                .map(Expr::Value)
+                .unwrap_or_else(|_| {
+                    Expr::Index(Box::new(Expr::Value(v)), Box::new(Expr::Value(i)))
+                }),
+            (e, idx) => Expr::Index(Box::new(e), Box::new(idx)),
+        },
+        Expr::Field(e, name) => match *boxed(e) {
+            Expr::Value(v) => field(v.clone(), name)
+                .map(Expr::Value)
+                .unwrap_or_else(|_| Expr::Field(Box::new(Expr::Value(v)), name.clone())),
+            e => Expr::Field(Box::new(e), name.clone()),
+        },
+        Expr::Lambda(params, body) => Expr::Lambda(params.clone(), boxed(body)),
+    }
+}
+
+fn constant(e: &Expr) -> Option<Value> {
+    match e {
+        Expr::Value(v) => Some(v.clone()),
+        _ => None,
+    }
+}
+
+struct Compiler<'r> {
+    registry: &'r Registry,
+    /// Names of the local variables in scope, the position is the slot.
+    scope: Vec<String>,
+    program: Program,
+}
+
+impl Compiler<'_> {
+    fn emit(&mut self, op: Op) -> usize {
+        self.program.code.push(op);
+        self.program.code.len() - 1
+    }
+
+    /// Point the jump at `at` to the next instruction.
+    fn patch(&mut self, at: usize) {
+        let target = self.program.code.len();
+        match &mut self.program.code[at] {
+            Op::ShortCircuit(_, t) | Op::JumpUnless(t) | Op::Jump(t) | Op::Next(t) => *t = target,
+            op => unreachable!("{:?} is not a jump", op),
+        }
+    }
+
+    fn fail(&mut self, error: EvalError) {
+        self.program.errors.push(error);
+        self.emit(Op::Fail(self.program.errors.len() - 1));
+    }
+
+    fn name(&mut self, name: &str) -> usize {
+        intern(&mut self.program.names, name)
+    }
+
+    /// Bring a variable into scope, returns its slot.
+    fn declare(&mut self, name: &str) -> usize {
+        self.scope.push(name.to_string());
+        self.program.slots = self.program.slots.max(self.scope.len());
+        self.scope.len() - 1
+    }
+
+    fn expr(&mut self, e: &Expr) {
+        match e {
+            Expr::Value(v) => {
+                self.program.constants.push(v.clone());
+                self.emit(Op::Const(self.program.constants.len() - 1));
+            }
+            Expr::Var(name) => match self.scope.iter().rposition(|n| n == name) {
+                Some(slot) => {
+                    self.emit(Op::Load(slot));
+                }
+                None => {
+                    let global = intern(&mut self.program.globals, name);
+                    self.emit(Op::Global(global));
+                }
+            },
+            Expr::Binop(op @ (Oper::And | Oper::Or), e1, e2) => {
+                self.expr(e1);
+                let jump = self.emit(Op::ShortCircuit(op.clone(), 0));
+                self.expr(e2);
+                self.emit(Op::Binop(op.clone()));
+                self.patch(jump);
+            }
+            Expr::Binop(op, e1, e2) => {
+                self.expr(e1);
+                self.expr(e2);
+                self.emit(Op::Binop(op.clone()));
+            }
+            Expr::Unop(Unop::Not, e) => {
+                self.expr(e);
+                self.emit(Op::Not);
+            }
+            Expr::Let(name, e1, e2) => {
+                self.expr(e1);
+                let slot = self.declare(name);
+                self.emit(Op::Store(slot));
+                self.expr(e2);
+                self.scope.pop();
+            }
+            Expr::If(c, e1, e2) => {
+                self.expr(c);
+                let jump_else = self.emit(Op::JumpUnless(0));
+                self.expr(e1);
+                let jump_end = self.emit(Op::Jump(0));
+                self.patch(jump_else);
+                self.expr(e2);
+                self.patch(jump_end);
+            }
+            Expr::Call(name, args) if HIGHER_ORDER.contains(&name.as_str()) => {
+                match higher_order(name, args) {
+                    Ok(call) => self.higher_order(name, call),
+                    Err(e) => self.fail(e),
+                }
+            }
+            Expr::Call(name, args) => {
+                let Some(builtin) = self.registry.get(name) else {
+                    return self.fail(EvalError::UnknownFunction(name.to_string()));
+                };
+                self.program
+                    .functions
+                    .push((name.to_string(), builtin.clone()));
+                let function = self.program.functions.len() - 1;
+                for arg in args {
+                    self.expr(arg);
+                }
+                self.emit(Op::Call {
+                    function,
+                    args: args.len(),
+                });
+            }
+            Expr::List(elems) => {
+                for e in elems {
+                    self.expr(e);
+                }
+                self.emit(Op::List(elems.len()));
+            }
+            Expr::Record(fields) => {
+                for (_, e) in fields {
+                    self.expr(e);
+                }
+                self.program
+                    .keys
+                    .push(fields.iter().map(|(k, _)| k.clone()).collect());
+                self.emit(Op::Record(self.program.keys.len() - 1));
+            }
+            Expr::Index(e, idx) => {
+                self.expr(e);
+                self.expr(idx);
+                self.emit(Op::Index);
+            }
+            Expr::Field(e, name) => {
+                self.expr(e);
+                let name = self.name(name);
+                self.emit(Op::Field(name));
+            }
+            Expr::Lambda(..) => self.fail(EvalError::UnexpectedLambda),
+        }
+    }
+
+    /// ```text
+    ///        <xs>
+    ///        Iterate
+    ///        <init> Seed      fold only
+    /// next:  Next end
+    ///        Store x          Store x, Store acc for fold
+    ///        <body>
+    ///        Collect next
+    /// end:
+    /// ```
+    fn higher_order(&mut self, name: &str, call: HigherOrder) {
+        let kind = match name {
+            "map" => Iteration::Map,
+            "filter" => Iteration::Filter,
+            _ => Iteration::Fold,
+        };
+        self.expr(call.xs);
+        let name = self.name(name);
+        self.emit(Op::Iterate(kind, name));
+        if let Some(init) = call.init {
+            self.expr(init);
+            self.emit(Op::Seed);
+        }
+        let next = self.emit(Op::Next(0));
+        let slots: Vec<usize> = call.params.iter().map(|p| self.declare(p)).collect();
+        for slot in slots.into_iter().rev() {
+            self.emit(Op::Store(slot));
+        }
+        self.expr(call.body);
+        self.scope.truncate(self.scope.len() - call.params.len());
+        self.emit(Op::Collect(next));
+        self.patch(next);
+    }
+}
+
+fn intern(table: &mut Vec<String>, name: &str) -> usize {
+    table.iter().position(|n| n == name).unwrap_or_else(|| {
+        table.push(name.to_string());
+        table.len() - 1
+    })
+}
+
+/// A running `map`, `filter` or `fold`.
+struct Loop {
+    kind: Iteration,
+    items: std::vec::IntoIter<Value>,
+    /// The element the lambda is called with, kept by `filter`.
+    current: Value,
+    /// Results of `map` and `filter`.
+    results: Vec<Value>,
+    /// Accumulator of `fold`.
+    acc: Value,
+}
+
+impl Program {
+    /// The instructions, for inspection.
+    pub fn code(&self) -> &[Op] {
+        &self.code
+    }
+
+    pub fn constants(&self) -> &[Value] {
+        &self.constants
+    }
+
+    /// Run the program with the variables of `env`.
+    ///
+    /// Only for trusted scripts: nothing is metered, a loop runs as long as it likes and network
+    /// functions like `fetch` are called without taking a fetch from any budget. Scripts of
+    /// users go through [`eval_limited`](super::eval::eval_limited).
+    pub fn run(&self, env: &Env) -> Result<Value, EvalError> {
+        let mut stack: Vec<Value> = Vec::with_capacity(16);
+        let mut locals = vec![Value::Null; self.slots];
+        let mut loops: Vec<Loop> = vec![];
+        let pop = |stack: &mut Vec<Value>| stack.pop().expect("stack underflow");
+
+        let mut pc = 0;
+        while let Some(op) = self.code.get(pc) {
+            pc += 1;
+            match op {
+                Op::Const(i) => stack.push(self.constants[*i].clone()),
+                Op::Load(slot) => stack.push(locals[*slot].clone()),
+                Op::Global(i) => {
+                    let name = &self.globals[*i];
+                    let value = env
+                        .get(name)
+                        .ok_or_else(|| EvalError::UnboundVariable(name.to_string()))?;
+                    stack.push(value.clone());
+                }
+                Op::Store(slot) => locals[*slot] = pop(&mut stack),
+                Op::Binop(op) => {
+                    let rhs = pop(&mut stack);
+                    let lhs = pop(&mut stack);
+                    stack.push(eval_binop(op, lhs, rhs)?);
+                }
+                Op::Not => match pop(&mut stack) {
+                    Value::Bool(b) => stack.push(Value::Bool(!b)),
+                    value => {
+                        return Err(EvalError::InvalidOperand {
+                            op: Unop::Not,
+                            value,
+                        })
+                    }
+                },
+                Op::ShortCircuit(op, target) => {
+                    let decided = matches!(
+                        (op, stack.last()),
+                        (Oper::And, Some(Value::Bool(false))) | (Oper::Or, Some(Value::Bool(true)))
+                    );
+                    if decided {
+                        pc = *target;
+                    }
+                }
+                Op::JumpUnless(target) => match pop(&mut stack) {
+                    Value::Bool(true) => (),
+                    Value::Bool(false) => pc = *target,
+                    v => return Err(EvalError::NonBoolCondition(v)),
+                },
+                Op::Jump(target) => pc = *target,
+                Op::Call { function, args } => {
+                    let (name, builtin) = &self.functions[*function];
+                    let args = stack.split_off(stack.len() - args);
+                    check_args(name, builtin, &args)?;
+                    stack.push((builtin.f)(&args)?);
+                }
+                Op::List(n) => {
+                    let elems = stack.split_off(stack.len() - n);
+                    stack.push(Value::List(elems));
+                }
+                Op::Record(keys) => {
+                    let keys = &self.keys[*keys];
+                    let values = stack.split_off(stack.len() - keys.len());
+                    stack.push(Value::Record(Box::new(
+                        keys.iter().cloned().zip(values).collect(),
+                    )));
+                }
+                Op::Index => {
+                    let idx = pop(&mut stack);
+                    let value = pop(&mut stack);
+                    stack.push(index(value, idx)?);
+                }
+                Op::Field(name) => {
+                    let value = pop(&mut stack);
+                    stack.push(field(value, &self.names[*name])?);
+                }
+                Op::Iterate(kind, name) => match pop(&mut stack) {
+                    Value::List(xs) => loops.push(Loop {
+                        kind: *kind,
+                        items: xs.into_iter(),
+                        current: Value::Null,
+                        results: vec![],
+                        acc: Value::Null,
+                    }),
+                    value => {
+                        return Err(EvalError::InvalidArguments {
+                            name: self.names[*name].clone(),
+                            args: vec![value],
+                        })
+                    }
+                },
+                Op::Seed => {
+                    let init = pop(&mut stack);
+                    loops.last_mut().expect("no loop").acc = init;
+                }
+                Op::Next(end) => {
+                    let l = loops.last_mut().expect("no loop");
+                    match (l.items.next(), l.kind) {
+                        (Some(x), Iteration::Map) => stack.push(x),
+                        (Some(x), Iteration::Filter) => {
+                            l.current = x.clone();
+                            stack.push(x);
+                        }
+                        (Some(x), Iteration::Fold) => {
+                            stack.push(mem::replace(&mut l.acc, Value::Null));
+                            stack.push(x);
+                        }
+                        (None, _) => {
+                            let l = loops.pop().expect("no loop");
+                            stack.push(match l.kind {
+                                Iteration::Map | Iteration::Filter => Value::List(l.results),
+                                Iteration::Fold => l.acc,
+                            });
+                            pc = *end;
+                        }
+                    }
+                }
+                Op::Collect(next) => {
+                    let result = pop(&mut stack);
+                    let l = loops.last_mut().expect("no loop");
+                    match (l.kind, result) {
+                        (Iteration::Map, v) => l.results.push(v),
+                        (Iteration::Filter, Value::Bool(true)) => {
+                            l.results.push(mem::replace(&mut l.current, Value::Null))
+                        }
+                        (Iteration::Filter, Value::Bool(false)) => (),
+                        (Iteration::Filter, v) => return Err(EvalError::NonBoolCondition(v)),
+                        (Iteration::Fold, v) => l.acc = v,
+                    }
+                    pc = *next;
+                }
+                Op::Fail(error) => return Err(self.errors[*error].clone()),
+            }
+        }
+        Ok(pop(&mut stack))
+    }
+}
@@ -0,0 +1,386 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, SqlitePool};
+use std::{
+    collections::HashMap,
+    sync::{Arc, OnceLock},
+};
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use crate::scrape::{
+    builtins::Registry,
+    fetch,
+    module::{parse_module, Linked, ModuleError},
+    ParseDiagnostic, TypeError, TypeRepr,
+};
+use crate::NOW;
+
+mod run;
+pub use run::{latest_results, person_types, run_script, Runner, ScrapeResult};
+
+/// A scrape script in its current version. Scripts are owned by the user that created them,
+/// like [`crate::people::Person`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct Script {
+    #[schema(example = 1u32)]
+    pub id: u32,
+
+    #[schema(example = "seekr")]
+    pub owner: String,
+
+    #[schema(example = "github followers")]
+    pub name: String,
+
+    #[schema(example = "len(name) * 2")]
+    pub source: String,
+
+    /// Starts at 1 and grows with every update or restore.
+    #[schema(example = 3u32)]
+    pub version: u32,
+
+    /// When the script was first saved, in UTC.
+    #[schema(example = "2024-01-12T18:30:00Z")]
+    pub created_at: String,
+}
+
+/// A version of a script from its history.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct ScriptVersion {
+    #[schema(example = 1u32)]
+    pub script_id: u32,
+
+    #[schema(example = 2u32)]
+    pub version: u32,
+
+    #[schema(example = "len(name)")]
+    pub source: String,
+
+    #[schema(example = "2024-01-12T18:30:00Z")]
+    pub created_at: String,
+}
+
+/// Used in requests creating or updating a script.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ScriptBuilder {
+    #[schema(example = "github followers")]
+    pub name: String,
+
+    #[schema(example = "len(name) * 2")]
+    pub source: String,
+}
+
+#[derive(Debug, Error)]
+pub enum ScriptError {
+    #[error("script not found. ID: {id:?} owner: {owner:?}")]
+    NotFound { id: u32, owner: String },
+
+    #[error("version {version} of script {id} not found")]
+    VersionNotFound { id: u32, version: u32 },
+
+    #[error("a script named {0:?} already exists")]
+    NameTaken(String),
+
+    /// Imports refer to scripts by name, renaming an imported script would break them.
+    #[error("script {name:?} is imported by {}, it can not be renamed", .by.join(", "))]
+    Imported { name: String, by: Vec<String> },
+
+    #[error("invalid script: {0}")]
+    Parse(ParseDiagnostic),
+
+    #[error("invalid script: {0}")]
+    Type(TypeError),
+
+    /// An error in an imported script, or in how the scripts fit together.
+    #[error("invalid script: {0}")]
+    Module(ModuleError),
+
+    #[error("person not found. ID: {id:?} owner: {owner:?}")]
+    PersonNotFound { id: u32, owner: String },
+
+    #[error("script run failed: {0}")]
+    Join(#[from] tokio::task::JoinError),
+
+    #[error("sqlx error")]
+    Sqlx(#[from] sqlx::Error),
+
+    #[error("not authenticated")]
+    Auth,
+}
+
+/// The functions scripts are checked against when they are saved: the standard library and
+/// `fetch`.
+pub fn registry() -> &'static Registry {
+    static REGISTRY: OnceLock<Registry> = OnceLock::new();
+    REGISTRY.get_or_init(|| {
+        let mut registry = Registry::standard().clone();
+        // checking only needs the signature of fetch, nothing is sent
+        fetch::register(&mut registry, Arc::new(fetch::Replay::new(vec![])));
+        registry
+    })
+}
+
+/// Errors of the script itself keep their diagnostics, they point into its source.
+fn module_error(name: &str, e: ModuleError) -> ScriptError {
+    match e {
+        ModuleError::Parse { module, diagnostic } if module == name => {
+            ScriptError::Parse(diagnostic)
+        }
+        ModuleError::Type { module, error } if module == name => ScriptError::Type(error),
+        e => ScriptError::Module(e),
+    }
+}
+
+/// The sources of the scripts `source` imports, directly or not. Imports are resolved against the
+/// scripts of `owner` by name. Missing scripts and cycles are left to [`Linked::load`], imported
+/// scripts that fail to parse are errors.
+pub async fn load_imports(
+    db: &SqlitePool,
+    owner: &str,
+    source: &str,
+) -> Result<HashMap<String, String>, ScriptError> {
+    let mut sources = HashMap::new();
+    // errors of the script itself are reported by `Linked::load`
+    let Ok(module) = parse_module(source) else {
+        return Ok(sources);
+    };
+    let mut pending = vec![module];
+    while let Some(module) = pending.pop() {
+        for import in module.imports {
+            if sources.contains_key(&import.name) {
+                continue;
+            }
+            let imported: Option<(String,)> =
+                sqlx::query_as("select source from scripts where owner = ? and name = ?")
+                    .bind(owner)
+                    .bind(&import.name)
+                    .fetch_optional(db)
+                    .await?;
+            if let Some((imported,)) = imported {
+                let module = parse_module(&imported).map_err(|diagnostic| {
+                    ScriptError::Module(ModuleError::Parse {
+                        module: import.name.clone(),
+                        diagnostic,
+                    })
+                })?;
+                sources.insert(import.name, imported);
+                pending.push(module);
+            }
+        }
+    }
+    Ok(sources)
+}
+
+/// The names of the other scripts of `owner` importing the script `name`.
+async fn importers(db: &SqlitePool, owner: &str, name: &str) -> Result<Vec<String>, ScriptError> {
+    Ok(list_scripts(db, owner)
+        .await?
+        .into_iter()
+        .filter(|s| s.name != name)
+        .filter(|s| parse_module(&s.source).is_ok_and(|m| m.imports.iter().any(|i| i.name == name)))
+        .map(|s| s.name)
+        .collect())
+}
+
+/// Parse a script of `owner` with its imports.
+pub async fn link(
+    db: &SqlitePool,
+    owner: &str,
+    name: &str,
+    source: &str,
+) -> Result<Linked, ScriptError> {
+    let sources = load_imports(db, owner, source).await?;
+    Linked::load(name, source, &sources).map_err(|e| module_error(name, e))
+}
+
+/// Parse and typecheck a script with the scripts it imports, returns the type of its result.
+/// Scripts run against a person and see its fields, see [`person_types`].
+pub async fn check(
+    db: &SqlitePool,
+    owner: &str,
+    name: &str,
+    source: &str,
+) -> Result<TypeRepr, ScriptError> {
+    link(db, owner, name, source)
+        .await?
+        .check(&person_types(), registry())
+        .map_err(|e| module_error(name, e))
+}
+
+fn name_taken(e: sqlx::Error, name: &str) -> ScriptError {
+    match &e {
+        sqlx::Error::Database(db) if db.is_unique_violation() => {
+            ScriptError::NameTaken(name.to_string())
+        }
+        _ => e.into(),
+    }
+}
+
+pub async fn insert_script(
+    db: &SqlitePool,
+    owner: &str,
+    script: ScriptBuilder,
+) -> Result<Script, ScriptError> {
+    check(db, owner, &script.name, &script.source).await?;
+    let mut tx = db.begin().await?;
+    let script: Script = sqlx::query_as(&format!(
+        "insert into scripts (owner, name, source, version, created_at)
+         values (?, ?, ?, 1, {NOW}) returning *"
+    ))
+    .bind(owner)
+    .bind(&script.name)
+    .bind(&script.source)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| name_taken(e, &script.name))?;
+    sqlx::query(
+        "insert into script_versions (script_id, version, source, created_at)
+         values (?, ?, ?, ?)",
+    )
+    .bind(script.id)
+    .bind(script.version)
+    .bind(&script.source)
+    .bind(&script.created_at)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(script)
+}
+
+pub async fn list_scripts(db: &SqlitePool, owner: &str) -> Result<Vec<Script>, ScriptError> {
+    Ok(
+        sqlx::query_as("select * from scripts where owner = ? order by name")
+            .bind(owner)
+            .fetch_all(db)
+            .await?,
+    )
+}
+
+pub async fn get_script(db: &SqlitePool, owner: &str, id: u32) -> Result<Script, ScriptError> {
+    sqlx::query_as("select * from scripts where id = ? and owner = ?")
+        .bind(id)
+        .bind(owner)
+        .fetch_optional(db)
+        .await?
+        .ok_or_else(|| ScriptError::NotFound {
+            id,
+            owner: owner.to_string(),
+        })
+}
+
+/// Save a new version of a script. Invalid sources are rejected and leave the script as it was,
+/// as are renames of scripts other scripts import.
+pub async fn update_script(
+    db: &SqlitePool,
+    owner: &str,
+    id: u32,
+    script: ScriptBuilder,
+) -> Result<Script, ScriptError> {
+    let current = get_script(db, owner, id).await?;
+    if current.name != script.name {
+        let by = importers(db, owner, &current.name).await?;
+        if !by.is_empty() {
+            return Err(ScriptError::Imported {
+                name: current.name,
+                by,
+            });
+        }
+    }
+    check(db, owner, &script.name, &script.source).await?;
+    let mut tx = db.begin().await?;
+    let updated: Script = sqlx::query_as(
+        "update scripts set name = ?, source = ?, version = version + 1
+         where id = ? and owner = ? returning *",
+    )
+    .bind(&script.name)
+    .bind(&script.source)
+    .bind(id)
+    .bind(owner)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| name_taken(e, &script.name))?
+    .ok_or_else(|| ScriptError::NotFound {
+        id,
+        owner: owner.to_string(),
+    })?;
+    sqlx::query(&format!(
+        "insert into script_versions (script_id, version, source, created_at)
+         values (?, ?, ?, {NOW})"
+    ))
+    .bind(updated.id)
+    .bind(updated.version)
+    .bind(&updated.source)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(updated)
+}
+
+/// Delete a script with its history and results.
+pub async fn delete_script(db: &SqlitePool, owner: &str, id: u32) -> Result<(), ScriptError> {
+    let deleted = sqlx::query("delete from scripts where id = ? and owner = ?")
+        .bind(id)
+        .bind(owner)
+        .execute(db)
+        .await?;
+    if deleted.rows_affected() == 0 {
+        return Err(ScriptError::NotFound {
+            id,
+            owner: owner.to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// All versions of a script, the newest first.
+pub async fn list_versions(
+    db: &SqlitePool,
+    owner: &str,
+    id: u32,
+) -> Result<Vec<ScriptVersion>, ScriptError> {
+    get_script(db, owner, id).await?;
+    Ok(
+        sqlx::query_as("select * from script_versions where script_id = ? order by version desc")
+            .bind(id)
+            .fetch_all(db)
+            .await?,
+    )
+}
+
+pub async fn get_version(
+    db: &SqlitePool,
+    owner: &str,
+    id: u32,
+    version: u32,
+) -> Result<ScriptVersion, ScriptError> {
+    get_script(db, owner, id).await?;
+    sqlx::query_as("select * from script_versions where script_id = ? and version = ?")
+        .bind(id)
+        .bind(version)
+        .fetch_optional(db)
+        .await?
+        .ok_or(ScriptError::VersionNotFound { id, version })
+}
+
+/// Make an earlier version current again. This adds a new version with the old source, so the
+/// history is never rewritten.
+pub async fn restore_version(
+    db: &SqlitePool,
+    owner: &str,
+    id: u32,
+    version: u32,
+) -> Result<Script, ScriptError> {
+    let old = get_version(db, owner, id, version).await?;
+    let script = get_script(db, owner, id).await?;
+    update_script(
+        db,
+        owner,
+        id,
+        ScriptBuilder {
+            name: script.name,
+            source: old.source,
+        },
+    )
+    .await
+}
+
+mod test;
@@ -0,0 +1,180 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{types::Json, FromRow, SqlitePool};
+use std::sync::Arc;
+use tracing::instrument;
+use utoipa::ToSchema;
+
+use super::{get_script, link, module_error, ScriptError, NOW};
+use crate::people::Person;
+use crate::scrape::{
+    builtins::Registry,
+    eval::eval_limited,
+    fetch::{self, HttpTransport, Policy, PolicyTransport, Transport},
+    limits::{Limits, LimitsConfig, Usage},
+    Env, TypeEnv, TypeRepr, Value,
+};
+
+/// The output of a script run against a person.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct ScrapeResult {
+    #[schema(example = 1u32)]
+    pub id: u32,
+
+    #[schema(example = "seekr")]
+    pub owner: String,
+
+    #[schema(example = 4u32)]
+    pub person_id: u32,
+
+    #[schema(example = 1u32)]
+    pub script_id: u32,
+
+    #[schema(example = "github followers")]
+    pub script_name: String,
+
+    /// The version of the script that ran.
+    #[schema(example = 3u32)]
+    pub script_version: u32,
+
+    /// The value the script returned, `None` if it failed.
+    #[schema(value_type = Option<Object>, example = json!({"followers": 12}))]
+    pub output: Option<Json<serde_json::Value>>,
+
+    #[schema(example = "fuel limit exceeded")]
+    pub error: Option<String>,
+
+    #[schema(value_type = Limits)]
+    pub limits: Json<Limits>,
+
+    #[schema(value_type = Usage)]
+    pub usage: Json<Usage>,
+
+    #[schema(example = "2024-01-14T12:00:00Z")]
+    pub created_at: String,
+}
+
+/// Runs scripts with `fetch` and the limits of their owner. Cheap to clone.
+#[derive(Debug, Clone)]
+pub struct Runner {
+    registry: Arc<Registry>,
+    limits: Arc<LimitsConfig>,
+}
+
+impl Runner {
+    pub fn new(limits: LimitsConfig, transport: Arc<dyn Transport>) -> Self {
+        let mut registry = Registry::standard().clone();
+        fetch::register(&mut registry, transport);
+        Self {
+            registry: Arc::new(registry),
+            limits: Arc::new(limits),
+        }
+    }
+
+    /// Fetch from the network within `policy`. Creates a blocking HTTP client, so it must not be
+    /// called from an async context.
+    pub fn http(limits: LimitsConfig, policy: Policy) -> Self {
+        Self::new(
+            limits,
+            Arc::new(PolicyTransport::new(policy, HttpTransport::new())),
+        )
+    }
+}
+
+/// The variables a script sees when it runs against a person, with their types.
+pub fn person_types() -> TypeEnv {
+    TypeEnv::from([
+        ("id".to_string(), TypeRepr::Number),
+        ("name".to_string(), TypeRepr::Str),
+        ("owner".to_string(), TypeRepr::Str),
+    ])
+}
+
+fn person_env(person: &Person) -> Env {
+    Env::from([
+        ("id".to_string(), Value::Number(person.id.into())),
+        ("name".to_string(), Value::Str(person.name.clone())),
+        ("owner".to_string(), Value::Str(person.owner.clone())),
+    ])
+}
+
+const SELECT_RESULTS: &str = "select r.*, s.name as script_name from scrape_results r
+     join scripts s on s.id = r.script_id";
+
+/// Run a script against a person and store the result. A run failing in the script, e.g. by
+/// exceeding its limits, is stored with its error and is not an `Err`.
+#[instrument(skip(db, runner))]
+pub async fn run_script(
+    db: &SqlitePool,
+    runner: &Runner,
+    owner: &str,
+    script_id: u32,
+    person_id: u32,
+) -> Result<ScrapeResult, ScriptError> {
+    let script = get_script(db, owner, script_id).await?;
+    let person: Person = sqlx::query_as("select * from people where id = ? and owner = ?")
+        .bind(person_id)
+        .bind(owner)
+        .fetch_optional(db)
+        .await?
+        .ok_or_else(|| ScriptError::PersonNotFound {
+            id: person_id,
+            owner: owner.to_string(),
+        })?;
+    // imported scripts may have changed since the script was saved
+    let (expr, _) = link(db, owner, &script.name, &script.source)
+        .await?
+        .expand()
+        .map_err(|e| module_error(&script.name, e))?;
+
+    let env = person_env(&person);
+    let registry = runner.registry.clone();
+    let limits = runner.limits.for_user(owner).clone();
+    let run_limits = limits.clone();
+    // fetch blocks
+    let (result, usage) =
+        tokio::task::spawn_blocking(move || eval_limited(&expr, &env, &registry, &run_limits))
+            .await?;
+    let (output, error) = match result {
+        Ok(value) => (Some(Json(serde_json::Value::from(value))), None),
+        Err(e) => (None, Some(e.to_string())),
+    };
+
+    let (id,): (u32,) = sqlx::query_as(&format!(
+        "insert into scrape_results
+         (owner, person_id, script_id, script_version, output, error, limits, usage, created_at)
+         values (?, ?, ?, ?, ?, ?, ?, ?, {NOW}) returning id"
+    ))
+    .bind(owner)
+    .bind(person_id)
+    .bind(script_id)
+    .bind(script.version)
+    .bind(output)
+    .bind(error)
+    .bind(Json(limits))
+    .bind(Json(usage))
+    .fetch_one(db)
+    .await?;
+    Ok(sqlx::query_as(&format!("{SELECT_RESULTS} where r.id = ?"))
+        .bind(id)
+        .fetch_one(db)
+        .await?)
+}
+
+/// The newest result of every script that ran against a person, by script name.
+pub async fn latest_results(
+    db: &SqlitePool,
+    owner: &str,
+    person_id: u32,
+) -> Result<Vec<ScrapeResult>, ScriptError> {
+    Ok(sqlx::query_as(&format!(
+        "{SELECT_RESULTS} where r.person_id = ? and r.owner = ? and r.id = (
+             select max(id) from scrape_results
+             where person_id = r.person_id and script_id = r.script_id
+         )
+         order by s.name"
+    ))
+    .bind(person_id)
+    .bind(owner)
+    .fetch_all(db)
+    .await?)
+}
@@ -0,0 +1,357 @@
+#![cfg(test)]
+use super::*;
+use crate::test_db;
+use sqlx::types::Json;
+
+fn builder(name: &str, source: &str) -> ScriptBuilder {
+    ScriptBuilder {
+        name: name.to_string(),
+        source: source.to_string(),
+    }
+}
+
+#[tokio::test]
+async fn test_scripts_crud() {
+    let db = test_db().await;
+
+    let script = insert_script(&db, "ferris", builder("double", "len(\"ab\") * 2"))
+        .await
+        .unwrap();
+    assert_eq!(script.version, 1);
+    assert_eq!(script.owner, "ferris");
+    assert_eq!(get_script(&db, "ferris", script.id).await.unwrap(), script);
+
+    // fetch is known to the checker
+    insert_script(
+        &db,
+        "ferris",
+        builder("status", "fetch(\"https://example.com\", {}).status"),
+    )
+    .await
+    .unwrap();
+    let names: Vec<String> = list_scripts(&db, "ferris")
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|s| s.name)
+        .collect();
+    assert_eq!(names, ["double", "status"]);
+
+    assert!(matches!(
+        insert_script(&db, "ferris", builder("double", "1")).await,
+        Err(ScriptError::NameTaken(_))
+    ));
+    // names are unique per owner only
+    insert_script(&db, "crab", builder("double", "1"))
+        .await
+        .unwrap();
+
+    let updated = update_script(&db, "ferris", script.id, builder("triple", "3 * 3"))
+        .await
+        .unwrap();
+    assert_eq!((updated.version, updated.name.as_str()), (2, "triple"));
+    assert_eq!(updated.created_at, script.created_at);
+
+    delete_script(&db, "ferris", script.id).await.unwrap();
+    assert!(matches!(
+        get_script(&db, "ferris", script.id).await,
+        Err(ScriptError::NotFound { .. })
+    ));
+    assert!(matches!(
+        delete_script(&db, "ferris", script.id).await,
+        Err(ScriptError::NotFound { .. })
+    ));
+    let (versions,): (u32,) =
+        sqlx::query_as("select count(*) from script_versions where script_id = ?")
+            .bind(script.id)
+            .fetch_one(&db)
+            .await
+            .unwrap();
+    assert_eq!(versions, 0);
+}
+
+#[tokio::test]
+async fn test_scripts_validation() {
+    let db = test_db().await;
+
+    match insert_script(&db, "ferris", builder("broken", "let x = 1 x")).await {
+        Err(ScriptError::Parse(diagnostic)) => assert_eq!(diagnostic.expected, ["in"]),
+        other => panic!("{:?}", other),
+    }
+    match insert_script(&db, "ferris", builder("broken", "1 + true")).await {
+        Err(ScriptError::Type(e)) => assert!(e.span().is_some()),
+        other => panic!("{:?}", other),
+    }
+    assert!(list_scripts(&db, "ferris").await.unwrap().is_empty());
+
+    let script = insert_script(&db, "ferris", builder("ok", "1 + 1"))
+        .await
+        .unwrap();
+    assert!(matches!(
+        update_script(&db, "ferris", script.id, builder("ok", "unknown(1)")).await,
+        Err(ScriptError::Type(TypeError::UnknownFunction { .. }))
+    ));
+    // a rejected update does not create a version
+    assert_eq!(get_script(&db, "ferris", script.id).await.unwrap(), script);
+    assert_eq!(
+        list_versions(&db, "ferris", script.id).await.unwrap().len(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn test_scripts_history() {
+    let db = test_db().await;
+
+    let script = insert_script(&db, "ferris", builder("greeting", "\"hello\""))
+        .await
+        .unwrap();
+    for source in ["\"hi\"", "upper(\"hey\")"] {
+        update_script(&db, "ferris", script.id, builder("greeting", source))
+            .await
+            .unwrap();
+    }
+    let versions = list_versions(&db, "ferris", script.id).await.unwrap();
+    assert_eq!(
+        versions
+            .iter()
+            .map(|v| (v.version, v.source.as_str()))
+            .collect::<Vec<_>>(),
+        [(3, "upper(\"hey\")"), (2, "\"hi\""), (1, "\"hello\"")]
+    );
+    assert_eq!(
+        get_version(&db, "ferris", script.id, 2).await.unwrap(),
+        versions[1]
+    );
+    assert!(matches!(
+        get_version(&db, "ferris", script.id, 7).await,
+        Err(ScriptError::VersionNotFound { version: 7, .. })
+    ));
+
+    let restored = restore_version(&db, "ferris", script.id, 1).await.unwrap();
+    assert_eq!(
+        (restored.version, restored.source.as_str()),
+        (4, "\"hello\"")
+    );
+    assert_eq!(
+        list_versions(&db, "ferris", script.id).await.unwrap().len(),
+        4
+    );
+
+    // other users neither see the script nor its history
+    assert!(matches!(
+        get_script(&db, "crab", script.id).await,
+        Err(ScriptError::NotFound { .. })
+    ));
+    assert!(matches!(
+        list_versions(&db, "crab", script.id).await,
+        Err(ScriptError::NotFound { .. })
+    ));
+    assert!(matches!(
+        update_script(&db, "crab", script.id, builder("mine", "1")).await,
+        Err(ScriptError::NotFound { .. })
+    ));
+    assert!(matches!(
+        restore_version(&db, "crab", script.id, 1).await,
+        Err(ScriptError::NotFound { .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_scripts_run() {
+    use crate::scrape::fetch::{Exchange, Replay, Request, Response};
+    use crate::scrape::limits::{Limits, LimitsConfig};
+    use std::collections::HashMap;
+
+    let db = test_db().await;
+    sqlx::query("insert into people (id, owner, name) values (4, 'ferris', 'greg')")
+        .execute(&db)
+        .await
+        .unwrap();
+    let replay = Replay::new(vec![Exchange {
+        request: Request::get("https://example.com/greg"),
+        response: Response {
+            status: 200,
+            headers: Default::default(),
+            body: "hi greg".to_string(),
+        },
+    }]);
+    let limits = LimitsConfig {
+        default: Limits::default(),
+        users: HashMap::from([(
+            "crab".to_string(),
+            Limits {
+                fuel: 1,
+                ..Limits::default()
+            },
+        )]),
+    };
+    let runner = Runner::new(limits, Arc::new(replay));
+
+    // the fields of the person are variables
+    let page = insert_script(
+        &db,
+        "ferris",
+        builder("page", "fetch(\"https://example.com/\" + name, {}).body"),
+    )
+    .await
+    .unwrap();
+    let result = run_script(&db, &runner, "ferris", page.id, 4)
+        .await
+        .unwrap();
+    assert_eq!(
+        (result.script_name.as_str(), result.script_version),
+        ("page", 1)
+    );
+    assert_eq!(result.output.unwrap().0, serde_json::json!("hi greg"));
+    assert_eq!(result.usage.fetches, 1);
+
+    let info = insert_script(&db, "ferris", builder("info", "{id: id, n: len(owner)}"))
+        .await
+        .unwrap();
+    run_script(&db, &runner, "ferris", info.id, 4)
+        .await
+        .unwrap();
+    update_script(&db, "ferris", info.id, builder("info", "id * 2"))
+        .await
+        .unwrap();
+    run_script(&db, &runner, "ferris", info.id, 4)
+        .await
+        .unwrap();
+    let latest = latest_results(&db, "ferris", 4).await.unwrap();
+    assert_eq!(
+        latest
+            .iter()
+            .map(|r| (r.script_name.as_str(), r.script_version, r.output.clone()))
+            .collect::<Vec<_>>(),
+        [
+            ("info", 2, Some(Json(serde_json::json!(8)))),
+            ("page", 1, Some(Json(serde_json::json!("hi greg")))),
+        ]
+    );
+
+    // failed runs are stored with their error and the limits of the owner
+    sqlx::query("insert into people (id, owner, name) values (5, 'crab', 'carl')")
+        .execute(&db)
+        .await
+        .unwrap();
+    let script = insert_script(&db, "crab", builder("sum", "1 + 2"))
+        .await
+        .unwrap();
+    let failed = run_script(&db, &runner, "crab", script.id, 5)
+        .await
+        .unwrap();
+    assert_eq!(failed.output, None);
+    assert_eq!(failed.error.as_deref(), Some("fuel limit exceeded"));
+    assert_eq!(failed.limits.fuel, 1);
+
+    // people and scripts of other users can not be used
+    assert!(matches!(
+        run_script(&db, &runner, "crab", script.id, 4).await,
+        Err(ScriptError::PersonNotFound { id: 4, .. })
+    ));
+    assert!(matches!(
+        run_script(&db, &runner, "crab", page.id, 5).await,
+        Err(ScriptError::NotFound { .. })
+    ));
+    assert!(latest_results(&db, "crab", 4).await.unwrap().is_empty());
+
+    delete_script(&db, "ferris", page.id).await.unwrap();
+    assert_eq!(latest_results(&db, "ferris", 4).await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_scripts_imports() {
+    use crate::scrape::{fetch::Replay, limits::LimitsConfig};
+
+    let db = test_db().await;
+    sqlx::query("insert into people (id, owner, name) values (4, 'ferris', 'greg')")
+        .execute(&db)
+        .await
+        .unwrap();
+    insert_script(
+        &db,
+        "ferris",
+        builder("util", "def normalize(s) = lower(s) + \"!\""),
+    )
+    .await
+    .unwrap();
+    let script = insert_script(
+        &db,
+        "ferris",
+        builder("greeting", "import \"util\"\nutil.normalize(name)"),
+    )
+    .await
+    .unwrap();
+    let runner = Runner::new(LimitsConfig::default(), Arc::new(Replay::default()));
+    let result = run_script(&db, &runner, "ferris", script.id, 4)
+        .await
+        .unwrap();
+    assert_eq!(result.output.unwrap().0, serde_json::json!("greg!"));
+
+    // imports resolve against scripts of the same owner only
+    assert!(matches!(
+        insert_script(&db, "crab", builder("greeting", "import \"util\"\n1")).await,
+        Err(ScriptError::Module(ModuleError::NotFound { .. }))
+    ));
+    // types are checked across scripts
+    assert!(matches!(
+        insert_script(
+            &db,
+            "ferris",
+            builder("bad", "import \"util\"\nutil.normalize(1)")
+        )
+        .await,
+        Err(ScriptError::Type(TypeError::Mismatch { .. }))
+    ));
+    let util = list_scripts(&db, "ferris").await.unwrap().remove(1);
+    assert_eq!(util.name, "util");
+    assert!(matches!(
+        update_script(
+            &db,
+            "ferris",
+            util.id,
+            builder("util", "import \"greeting\"")
+        )
+        .await,
+        Err(ScriptError::Module(ModuleError::Cycle(_)))
+    ));
+
+    // imported scripts keep their names
+    assert_eq!(
+        update_script(&db, "ferris", util.id, builder("text", "1"))
+            .await
+            .unwrap_err()
+            .to_string(),
+        "script \"util\" is imported by greeting, it can not be renamed"
+    );
+    update_script(&db, "ferris", script.id, builder("hello", "1"))
+        .await
+        .unwrap();
+    update_script(
+        &db,
+        "ferris",
+        script.id,
+        builder("greeting", &script.source),
+    )
+    .await
+    .unwrap();
+
+    // imported scripts that no longer parse are reported
+    sqlx::query("update scripts set source = 'def' where id = ?")
+        .bind(util.id)
+        .execute(&db)
+        .await
+        .unwrap();
+    assert!(matches!(
+        run_script(&db, &runner, "ferris", script.id, 4).await,
+        Err(ScriptError::Module(ModuleError::Parse { module, .. })) if module == "util"
+    ));
+
+    // a script whose import went away fails to run
+    delete_script(&db, "ferris", util.id).await.unwrap();
+    assert!(matches!(
+        run_script(&db, &runner, "ferris", script.id, 4).await,
+        Err(ScriptError::Module(ModuleError::NotFound { .. }))
+    ));
+}
@@ -0,0 +1,227 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, SqlitePool};
+use std::fmt;
+use thiserror::Error;
+use tracing::instrument;
+use utoipa::{IntoParams, ToSchema};
+
+/// Where a search hit was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum HitSource {
+    /// The name of the person.
+    Name,
+    /// An identifier of the person, aliases included.
+    Identifier,
+    /// The output of a script run against the person.
+    Result,
+}
+
+impl HitSource {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Name => "name",
+            Self::Identifier => "identifier",
+            Self::Result => "result",
+        }
+    }
+}
+
+impl TryFrom<String> for HitSource {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        [Self::Name, Self::Identifier, Self::Result]
+            .into_iter()
+            .find(|source| source.as_str() == s)
+            .ok_or_else(|| format!("unknown search source {s:?}"))
+    }
+}
+
+impl fmt::Display for HitSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A match of a search, in a person or something attached to it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct Hit {
+    #[schema(example = 4u32)]
+    pub person_id: u32,
+
+    #[schema(example = "greg")]
+    pub person_name: String,
+
+    #[sqlx(try_from = "String")]
+    pub source: HitSource,
+
+    /// The kind and platform of an identifier or the name of a script, empty for names.
+    #[schema(example = "username github")]
+    pub field: String,
+
+    /// The matched text around the match, as HTML with the matched words in `<mark>`.
+    #[schema(example = "<mark>greg</mark>")]
+    pub snippet: String,
+
+    /// Lower is better.
+    #[schema(example = -1.5)]
+    pub rank: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SearchQuery {
+    /// Words to find, all of them. `word*` matches words starting with `word` and `"two words"`
+    /// the words next to each other.
+    #[param(example = "gre* \"example com\"")]
+    pub q: String,
+
+    /// At most [`SearchQuery::MAX_LIMIT`].
+    #[serde(default = "SearchQuery::default_limit")]
+    #[param(example = 20, minimum = 1, maximum = 100)]
+    pub limit: u32,
+}
+
+impl SearchQuery {
+    pub const MAX_LIMIT: u32 = 100;
+
+    fn default_limit() -> u32 {
+        20
+    }
+
+    pub fn new(q: &str) -> Self {
+        Self {
+            q: q.to_string(),
+            limit: Self::default_limit(),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum SearchError {
+    #[error("invalid search: {0}")]
+    InvalidQuery(String),
+
+    #[error("sqlx error")]
+    Sqlx(#[from] sqlx::Error),
+
+    #[error("not authenticated")]
+    Auth,
+}
+
+/// Translate a search into an FTS5 query. Every word and phrase is quoted, so nothing the user
+/// types is taken as FTS5 syntax but the trailing `*` of a prefix.
+///
+/// ```
+/// use seekr::search::fts_query;
+///
+/// assert_eq!(fts_query(r#"gre* "ada lovelace" OR"#).unwrap(), r#""gre"* "ada lovelace" "OR""#);
+/// ```
+pub fn fts_query(q: &str) -> Result<String, SearchError> {
+    let mut terms = vec![];
+    let mut chars = q.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let mut term = String::new();
+        if c == '"' {
+            chars.next();
+            // an unclosed phrase ends with the search
+            for c in chars.by_ref() {
+                if c == '"' {
+                    break;
+                }
+                term.push(c);
+            }
+        } else {
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == '"' {
+                    break;
+                }
+                term.push(c);
+                chars.next();
+            }
+        }
+        let mut prefix = false;
+        while term.ends_with('*') {
+            term.pop();
+            prefix = true;
+        }
+        if chars.peek() == Some(&'*') {
+            chars.next();
+            prefix = true;
+        }
+        if term.trim().is_empty() {
+            continue;
+        }
+        let quoted = format!("\"{}\"", term.replace('"', "\"\""));
+        terms.push(if prefix { quoted + "*" } else { quoted });
+    }
+    if terms.is_empty() {
+        return Err(SearchError::InvalidQuery(
+            "nothing to search for".to_string(),
+        ));
+    }
+    Ok(terms.join(" "))
+}
+
+/// Mark the start and end of matches in snippets. Control characters, which names and values
+/// hardly ever contain.
+const MATCH_START: char = '\u{2}';
+const MATCH_END: char = '\u{3}';
+
+/// Escape a snippet for HTML and mark the matches.
+fn highlight(snippet: &str) -> String {
+    let mut html = String::with_capacity(snippet.len());
+    for c in snippet.chars() {
+        match c {
+            MATCH_START => html.push_str("<mark>"),
+            MATCH_END => html.push_str("</mark>"),
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            c => html.push(c),
+        }
+    }
+    html
+}
+
+/// Search the people of `owner` and their identifiers and scrape results, the best hits first.
+#[instrument(skip(db))]
+pub async fn search(
+    db: &SqlitePool,
+    owner: &str,
+    query: &SearchQuery,
+) -> Result<Vec<Hit>, SearchError> {
+    // only the text, `field` holds kinds and script names that would match every identifier
+    // or result of a kind
+    let fts = format!("{{text}} : ({})", fts_query(&query.q)?);
+    let hits: Vec<Hit> = sqlx::query_as(&format!(
+        "select search.person_id, people.name as person_name, search.source, search.field,
+             snippet(search, 0, char({}), char({}), '…', 12) as snippet,
+             bm25(search) as rank
+         from search join people on people.id = search.person_id
+         where search match ? and search.owner = ?
+         order by rank limit ?",
+        MATCH_START as u32, MATCH_END as u32
+    ))
+    .bind(fts)
+    .bind(owner)
+    .bind(query.limit.clamp(1, SearchQuery::MAX_LIMIT))
+    .fetch_all(db)
+    .await?;
+    Ok(hits
+        .into_iter()
+        .map(|hit| Hit {
+            snippet: highlight(&hit.snippet),
+            ..hit
+        })
+        .collect())
+}
+
+mod test;
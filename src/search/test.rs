@@ -0,0 +1,221 @@
+#![cfg(test)]
+use super::*;
+use crate::people::{
+    add_identifier, delete_person, insert_person, remove_identifier, update_person,
+    IdentifierBuilder, IdentifierKind, PersonBuilder, PersonPatch,
+};
+use crate::test_db;
+
+#[test]
+fn test_fts_query() {
+    assert_eq!(fts_query("greg").unwrap(), r#""greg""#);
+    assert_eq!(fts_query("  gre*  lov** ").unwrap(), r#""gre"* "lov"*"#);
+    assert_eq!(
+        fts_query(r#""ada lovelace" "analytical engine"*"#).unwrap(),
+        r#""ada lovelace" "analytical engine"*"#
+    );
+    // FTS5 syntax is taken literally
+    assert_eq!(
+        fts_query(r#"a OR b NOT c:d (e) ^f"#).unwrap(),
+        r#""a" "OR" "b" "NOT" "c:d" "(e)" "^f""#
+    );
+    assert_eq!(fts_query(r#"say"hi"#).unwrap(), r#""say" "hi""#);
+    assert_eq!(
+        fts_query(r#""unclosed phrase"#).unwrap(),
+        r#""unclosed phrase""#
+    );
+    for empty in ["", "   ", "*", r#""""#, r#"" " **"#] {
+        assert!(
+            matches!(fts_query(empty), Err(SearchError::InvalidQuery(_))),
+            "{}",
+            empty
+        );
+    }
+}
+
+#[test]
+fn test_highlight() {
+    assert_eq!(
+        highlight("\u{2}greg\u{3} <b>&'\"</b>"),
+        "<mark>greg</mark> &lt;b&gt;&amp;&#39;&quot;&lt;/b&gt;"
+    );
+}
+
+async fn found(db: &SqlitePool, owner: &str, q: &str) -> Vec<(String, HitSource, String)> {
+    search(db, owner, &SearchQuery::new(q))
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|hit| (hit.person_name, hit.source, hit.snippet))
+        .collect()
+}
+
+#[tokio::test]
+async fn test_search() {
+    let db = test_db().await;
+    let person = |name: &str| PersonBuilder {
+        name: name.to_string(),
+    };
+    let greg = insert_person(&db, "ferris", person("Greg Grüber"))
+        .await
+        .unwrap();
+    let ada = insert_person(&db, "ferris", person("Ada Lovelace"))
+        .await
+        .unwrap();
+    insert_person(&db, "crab", person("Greg Crab"))
+        .await
+        .unwrap();
+
+    let hit = |name: &str, source, snippet: &str| (name.to_string(), source, snippet.to_string());
+    assert_eq!(
+        found(&db, "ferris", "greg").await,
+        [hit(
+            "Greg Grüber",
+            HitSource::Name,
+            "<mark>Greg</mark> Grüber"
+        )]
+    );
+    // diacritics are ignored
+    assert_eq!(found(&db, "ferris", "gruber").await.len(), 1);
+    assert_eq!(found(&db, "ferris", "lov*").await.len(), 1);
+    assert_eq!(found(&db, "ferris", "\"ada lovelace\"").await.len(), 1);
+    assert!(found(&db, "ferris", "\"lovelace ada\"").await.is_empty());
+    assert!(found(&db, "ferris", "ada greg").await.is_empty());
+
+    // identifiers and aliases
+    let email = add_identifier(
+        &db,
+        "ferris",
+        ada.id,
+        IdentifierBuilder::new(IdentifierKind::Email, "ada@analytical.engine"),
+    )
+    .await
+    .unwrap();
+    add_identifier(
+        &db,
+        "ferris",
+        greg.id,
+        IdentifierBuilder::new(IdentifierKind::Alias, "the crab"),
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        found(&db, "ferris", "analytical").await,
+        [hit(
+            "Ada Lovelace",
+            HitSource::Identifier,
+            "ada@<mark>analytical</mark>.engine"
+        )]
+    );
+    assert_eq!(
+        found(&db, "ferris", "crab").await,
+        [hit(
+            "Greg Grüber",
+            HitSource::Identifier,
+            "the <mark>crab</mark>"
+        )]
+    );
+    remove_identifier(&db, "ferris", ada.id, email.id)
+        .await
+        .unwrap();
+    assert!(found(&db, "ferris", "analytical").await.is_empty());
+
+    // scrape output
+    sqlx::query(
+        "insert into scripts (id, owner, name, source, version, created_at)
+         values (1, 'ferris', 'bio', '1', 1, '')",
+    )
+    .execute(&db)
+    .await
+    .unwrap();
+    sqlx::query(
+        "insert into scrape_results
+         (owner, person_id, script_id, script_version, output, limits, usage, created_at)
+         values ('ferris', ?, 1, 1, '{\"bio\": \"writes the first program\"}', '{}', '{}', '')",
+    )
+    .bind(ada.id)
+    .execute(&db)
+    .await
+    .unwrap();
+    let hits = search(&db, "ferris", &SearchQuery::new("program"))
+        .await
+        .unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(
+        (hits[0].source, hits[0].field.as_str()),
+        (HitSource::Result, "bio")
+    );
+    sqlx::query("update scripts set name = 'biography' where id = 1")
+        .execute(&db)
+        .await
+        .unwrap();
+    let hits = search(&db, "ferris", &SearchQuery::new("program"))
+        .await
+        .unwrap();
+    assert_eq!(hits[0].field, "biography");
+
+    // renames and deletes are followed
+    update_person(
+        &db,
+        "ferris",
+        greg.id,
+        PersonPatch {
+            name: Some("Gregory".to_string()),
+        },
+    )
+    .await
+    .unwrap();
+    assert!(found(&db, "ferris", "greg").await.is_empty());
+    assert_eq!(found(&db, "ferris", "greg*").await.len(), 1);
+    delete_person(&db, "ferris", ada.id).await.unwrap();
+    assert!(found(&db, "ferris", "program").await.is_empty());
+    let (left,): (u32,) = sqlx::query_as("select count(*) from search where person_id = ?")
+        .bind(ada.id)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(left, 0);
+
+    // only the own people are found
+    assert_eq!(
+        found(&db, "crab", "greg*").await,
+        [hit("Greg Crab", HitSource::Name, "<mark>Greg</mark> Crab")]
+    );
+    assert!(found(&db, "nobody", "greg*").await.is_empty());
+}
+
+#[tokio::test]
+async fn test_search_ignores_fields() {
+    let db = test_db().await;
+    let greg = insert_person(
+        &db,
+        "ferris",
+        PersonBuilder {
+            name: "greg".to_string(),
+        },
+    )
+    .await
+    .unwrap();
+    add_identifier(
+        &db,
+        "ferris",
+        greg.id,
+        IdentifierBuilder::new(IdentifierKind::Email, "greg@example.com"),
+    )
+    .await
+    .unwrap();
+    add_identifier(
+        &db,
+        "ferris",
+        greg.id,
+        IdentifierBuilder::new(IdentifierKind::Username, "greg").platform("github"),
+    )
+    .await
+    .unwrap();
+
+    // kinds and platforms alone find nothing
+    for q in ["email", "username", "github", "email*"] {
+        assert!(found(&db, "ferris", q).await.is_empty(), "{}", q);
+    }
+    assert_eq!(found(&db, "ferris", "example").await.len(), 1);
+}
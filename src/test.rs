@@ -0,0 +1,38 @@
+#![cfg(test)]
+use super::*;
+use clap::Parser;
+
+#[tokio::test]
+async fn test_open_db() {
+    let dir = std::env::temp_dir().join(format!("seekr-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("seekr.db");
+    let _ = std::fs::remove_file(&path);
+    let args = Args::parse_from([
+        "seekr",
+        "--db-path",
+        path.to_str().unwrap(),
+        "export",
+        "--owner",
+        "ferris",
+    ]);
+
+    // a missing database is created and migrated
+    let db = open_db(&args).await.unwrap();
+    assert!(path.exists());
+    sqlx::query("insert into people (owner, name) values ('ferris', 'greg')")
+        .execute(&db)
+        .await
+        .unwrap();
+    db.close().await;
+
+    // and kept when opened again
+    let db = open_db(&args).await.unwrap();
+    let (people,): (u32,) = sqlx::query_as("select count(*) from people")
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(people, 1);
+    db.close().await;
+    std::fs::remove_dir_all(&dir).unwrap();
+}
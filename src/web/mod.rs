@@ -1,3 +1,4 @@
 pub mod auth;
 pub mod person;
 pub mod protected;
+pub mod search;
@@ -1,20 +1,28 @@
 use askama::Template;
 use axum::{
     extract::Query,
-    http::StatusCode,
-    response::{Html, IntoResponse, Response},
+    response::{IntoResponse, Response},
 };
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use utoipa::ToSchema;
 
-use crate::people::{get_person, GetPersonError, Person};
+use crate::people::{
+    get_person, list_identifiers, GetPersonError, Identifier, IdentifierError, IdentifierKind,
+};
+use crate::relationships::{list_relationships, Relationship, RelationshipError};
+use crate::scripts::{latest_results, ScrapeResult, ScriptError};
 use crate::users::AuthSession;
 
 #[derive(Debug, Template)]
 #[template(path = "person.html")]
 struct PersonTemplate<'a> {
     name: &'a str,
+    identifiers: Vec<Identifier>,
+    /// relationships from and to the person
+    relationships: Vec<Relationship>,
+    /// latest result of every script run against the person
+    results: Vec<ScrapeResult>,
 }
 
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
@@ -27,6 +35,12 @@ pub struct GetPersonProtectedQuery {
 pub enum GetPersonProtectedError {
     #[error("getting person: {0}")]
     GetPerson(#[from] GetPersonError),
+    #[error("getting identifiers: {0}")]
+    Identifiers(#[from] IdentifierError),
+    #[error("getting relationships: {0}")]
+    Relationships(#[from] RelationshipError),
+    #[error("getting scrape results: {0}")]
+    Results(#[from] ScriptError),
     #[error("unknown")]
     Unknown,
 }
@@ -43,7 +57,18 @@ pub mod get {
         query: Query<GetPersonProtectedQuery>,
         auth_session: AuthSession,
     ) -> Result<impl IntoResponse, GetPersonProtectedError> {
-        let person = get_person(auth_session, query.id).await?;
-        Ok(PersonTemplate { name: &person.name }.into_response())
+        let db = auth_session.backend.get_pool();
+        let user = auth_session.user.ok_or(GetPersonError::Auth)?;
+        let person = get_person(&db, &user.username, query.id).await?;
+        let identifiers = list_identifiers(&db, &person.owner, person.id).await?;
+        let relationships = list_relationships(&db, &person.owner, person.id).await?;
+        let results = latest_results(&db, &person.owner, person.id).await?;
+        Ok(PersonTemplate {
+            name: &person.name,
+            identifiers,
+            relationships,
+            results,
+        }
+        .into_response())
     }
 }
@@ -1,4 +1,4 @@
-use crate::web::person;
+use crate::web::{person, search};
 use askama::Template;
 use axum::{http::StatusCode, response::IntoResponse, routing::get, Router};
 
@@ -14,6 +14,7 @@ pub fn router() -> Router<()> {
     Router::new()
         .route("/", get(self::get::protected))
         .route("/person", get(person::get::person_protected))
+        .route("/search", get(search::get::search_protected))
 }
 
 mod get {
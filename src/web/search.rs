@@ -0,0 +1,54 @@
+use askama::Template;
+use axum::{
+    extract::Query,
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+
+use crate::search::{search, Hit, SearchError, SearchQuery};
+use crate::users::AuthSession;
+
+#[derive(Debug, Template)]
+#[template(path = "search.html")]
+struct SearchTemplate<'a> {
+    q: &'a str,
+    hits: Vec<Hit>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchPageQuery {
+    #[serde(default)]
+    pub q: String,
+}
+
+/// Errors are shown on the page, like those of the person page.
+pub struct SearchPageError(SearchError);
+
+impl IntoResponse for SearchPageError {
+    fn into_response(self) -> Response {
+        format!("error: {}", self.0).into_response()
+    }
+}
+
+pub mod get {
+    use super::*;
+    pub async fn search_protected(
+        Query(query): Query<SearchPageQuery>,
+        auth_session: AuthSession,
+    ) -> Result<impl IntoResponse, SearchPageError> {
+        let user = auth_session
+            .user
+            .ok_or(SearchPageError(SearchError::Auth))?;
+        let db = auth_session.backend.get_pool();
+        let hits = if query.q.trim().is_empty() {
+            vec![]
+        } else {
+            match search(&db, &user.username, &SearchQuery::new(&query.q)).await {
+                Ok(hits) => hits,
+                Err(SearchError::InvalidQuery(_)) => vec![],
+                Err(e) => return Err(SearchPageError(e)),
+            }
+        };
+        Ok(SearchTemplate { q: &query.q, hits }.into_response())
+    }
+}